@@ -0,0 +1,116 @@
+//! ANSI terminal output.
+//!
+//! `--target ansi` prints the document straight to the terminal instead of
+//! writing a file: titles are bold and underlined, BOLD and ITALICS use the
+//! matching escape codes, list items get bullets, and links and media show
+//! where they point. Handy for a quick preview without opening a browser.
+//!
+//! Like the other text backends it renders a tree that went through
+//! `LolcodeSemanticAnalyzer::resolve_tree`.
+
+use crate::meta;
+use crate::parser::ASTNode;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+
+/// Renders a resolved tree with ANSI escape codes.
+pub fn render(tree: &ASTNode) -> String {
+    let mut out = String::new();
+    node(tree, &mut out);
+
+    // tidy up the spacing between blocks
+    let mut tidy = String::with_capacity(out.len());
+    for line in out.lines() {
+        let line = line.trim_end();
+        if line.is_empty() && (tidy.is_empty() || tidy.ends_with("\n\n")) {
+            continue;
+        }
+        tidy.push_str(line);
+        tidy.push('\n');
+    }
+    tidy.truncate(tidy.trim_end().len());
+    tidy.push('\n');
+    tidy
+}
+
+// inline pieces are separated by spaces, like the HTML output
+fn push_inline(out: &mut String, text: &str) {
+    if !out.is_empty() && !out.ends_with([' ', '\n']) {
+        out.push(' ');
+    }
+    out.push_str(text);
+}
+
+fn inline_children(children: &[ASTNode]) -> String {
+    let mut text = String::new();
+    for child in children.iter() {
+        node(child, &mut text);
+    }
+    text.trim().to_string()
+}
+
+fn node(node_ref: &ASTNode, out: &mut String) {
+    match node_ref {
+        ASTNode::Program { children } | ASTNode::HeadSection { children } => {
+            for child in children.iter() {
+                node(child, out);
+            }
+        }
+        ASTNode::ParagrafSection { children } => {
+            out.push_str("\n\n");
+            for child in children.iter() {
+                node(child, out);
+            }
+            out.push_str("\n\n");
+        }
+        ASTNode::ListSection { children } => {
+            out.push_str("\n\n");
+            for child in children.iter() {
+                node(child, out);
+            }
+            out.push_str("\n\n");
+        }
+        ASTNode::Item { content } => {
+            out.push_str(&format!("\n  • {}", inline_children(content)));
+        }
+        ASTNode::Title { content } => {
+            out.push_str(&format!("\n{}{}{}{}\n\n", BOLD, UNDERLINE, meta::title_text(content), RESET));
+        }
+        ASTNode::Text { content } => push_inline(out, content),
+        ASTNode::Bold { content } => {
+            push_inline(out, &format!("{}{}{}", BOLD, inline_children(content), RESET))
+        }
+        ASTNode::Italics { content } => {
+            push_inline(out, &format!("{}{}{}", ITALIC, inline_children(content), RESET))
+        }
+        ASTNode::Newline => out.push('\n'),
+        ASTNode::Link { target, text } if text == target => {
+            push_inline(out, &format!("{}{}{}", UNDERLINE, text, RESET))
+        }
+        ASTNode::Link { target, text } => push_inline(
+            out,
+            &format!("{}{}{} {}({}){}", UNDERLINE, text, RESET, DIM, target, RESET),
+        ),
+        ASTNode::Image { url, attributes } => push_inline(
+            out,
+            &format!(
+                "{}[image: {}]{}",
+                DIM,
+                attributes.alt.as_deref().unwrap_or(url),
+                RESET
+            ),
+        ),
+        ASTNode::Video { url, .. } => push_inline(out, &format!("{}[video: {}]{}", DIM, url, RESET)),
+        ASTNode::Sound { url, .. } => push_inline(out, &format!("{}[audio: {}]{}", DIM, url, RESET)),
+        // variables and #IZ DEFINED are gone after resolving and metadata isn't shown
+        ASTNode::MetaSection { .. }
+        | ASTNode::VariableDeclaration { .. }
+        | ASTNode::VariableAssignment { .. }
+        | ASTNode::VariableReference { .. }
+        | ASTNode::IfDefined { .. } => {}
+    }
+}
@@ -0,0 +1,59 @@
+//! Arena storage for the AST.
+//!
+//! The parse tree borrows everything from an `AstArena`: child lists are slices
+//! and text is `&str`, both carved out of large shared chunks instead of one
+//! `Vec` or `String` allocation per node. The whole tree is freed at once when
+//! the arena is dropped, and walking it again (semantic pass, codegen, linting)
+//! is just following references.
+//!
+//! ```text
+//! let arena = AstArena::new();
+//! let mut parser = LolcodeParser::new(source, &arena);
+//! parser.parse()?;
+//! let tree = parser.parse_tree.unwrap();
+//! ```
+
+use crate::parser::ASTNode;
+use typed_arena::Arena;
+
+/// Owns the nodes and text of one or more parse trees.
+pub struct AstArena<'t> {
+    nodes: Arena<ASTNode<'t>>,
+    text: Arena<u8>,
+}
+
+impl Default for AstArena<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'t> AstArena<'t> {
+    /// Creates an empty arena.
+    pub fn new() -> Self {
+        Self {
+            nodes: Arena::new(),
+            text: Arena::new(),
+        }
+    }
+
+    /// Moves a node into the arena.
+    pub fn alloc(&'t self, node: ASTNode<'t>) -> &'t ASTNode<'t> {
+        self.nodes.alloc(node)
+    }
+
+    /// Moves a list of nodes into the arena as one contiguous slice.
+    // takes a finished Vec: building the nodes while extending would allocate
+    // in the arena during the extend, which typed_arena doesn't allow
+    pub fn alloc_nodes(&'t self, nodes: Vec<ASTNode<'t>>) -> &'t [ASTNode<'t>] {
+        self.nodes.alloc_extend(nodes)
+    }
+
+    /// Copies a string into the arena.
+    pub fn alloc_str(&'t self, text: &str) -> &'t str {
+        if text.is_empty() {
+            return "";
+        }
+        self.text.alloc_str(text)
+    }
+}
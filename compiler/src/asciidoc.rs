@@ -0,0 +1,164 @@
+//! AsciiDoc output.
+//!
+//! `--target asciidoc` writes an `.adoc` file for Antora and other AsciiDoc
+//! pipelines. The document header is built from the first TITLE and the META
+//! block (each entry becomes a `:key: value` attribute). Later titles become
+//! `==` sections, links to other pages become `xref:`s, and media use the
+//! `image:`, `video::` and `audio::` macros.
+//!
+//! Works on a tree resolved by `LolcodeSemanticAnalyzer::resolve_tree`.
+
+use crate::media::{self, VideoHost};
+use crate::meta;
+use crate::parser::ASTNode;
+use crate::site;
+
+/// Renders a resolved tree as AsciiDoc.
+pub fn render(tree: &ASTNode) -> String {
+    let meta = meta::document_meta(tree);
+    let mut renderer = AsciiDocRenderer::default();
+
+    // document header: title line followed by attributes
+    if let Some(title) = meta.title() {
+        renderer.out.push_str(&format!("= {}\n", title));
+        renderer.skip_title = meta.get("title").is_none();
+    }
+    for (key, value) in &meta.entries {
+        if key != "title" {
+            renderer.out.push_str(&format!(":{}: {}\n", key, value));
+        }
+    }
+    renderer.out.push('\n');
+
+    renderer.node(tree);
+    renderer.flush_line();
+
+    // no more than one blank line in a row
+    let mut out = String::with_capacity(renderer.out.len());
+    for line in renderer.out.trim_end().lines() {
+        if line.is_empty() && out.ends_with("\n\n") {
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+#[derive(Default)]
+struct AsciiDocRenderer {
+    out: String,
+    line: String,
+    skip_title: bool,
+}
+
+impl AsciiDocRenderer {
+    fn flush_line(&mut self) {
+        let line = self.line.trim();
+        if !line.is_empty() {
+            self.out.push_str(line);
+            self.out.push('\n');
+        }
+        self.line.clear();
+    }
+
+    fn push_word(&mut self, text: &str) {
+        if !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        self.line.push_str(text);
+    }
+
+    // block macros (video::, audio::) need a paragraph of their own
+    fn block(&mut self, text: &str) {
+        self.flush_line();
+        self.out.push('\n');
+        self.out.push_str(text);
+        self.out.push_str("\n\n");
+    }
+
+    fn inline(&mut self, children: &[ASTNode]) -> String {
+        let saved = std::mem::take(&mut self.line);
+        for child in children.iter() {
+            self.node(child);
+        }
+        std::mem::replace(&mut self.line, saved).trim().to_string()
+    }
+
+    fn node(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Program { children } | ASTNode::HeadSection { children } => {
+                for child in children.iter() {
+                    self.node(child);
+                }
+            }
+            ASTNode::ParagrafSection { children } | ASTNode::ListSection { children } => {
+                self.flush_line();
+                self.out.push('\n');
+                for child in children.iter() {
+                    self.node(child);
+                }
+                self.flush_line();
+                self.out.push('\n');
+            }
+            ASTNode::Item { content } => {
+                let item = self.inline(content);
+                self.flush_line();
+                self.out.push_str(&format!("* {}\n", item));
+            }
+            ASTNode::Title { content } => {
+                if self.skip_title {
+                    self.skip_title = false;
+                } else {
+                    self.flush_line();
+                    self.out.push_str(&format!("\n== {}\n\n", meta::title_text(content)));
+                }
+            }
+            ASTNode::Text { content } => self.push_word(content),
+            ASTNode::Bold { content } => {
+                let text = self.inline(content);
+                self.push_word(&format!("**{}**", text));
+            }
+            ASTNode::Italics { content } => {
+                let text = self.inline(content);
+                self.push_word(&format!("__{}__", text));
+            }
+            // a trailing + is a hard line break
+            ASTNode::Newline => {
+                if !self.line.is_empty() {
+                    self.line.push_str(" +");
+                }
+                self.flush_line();
+            }
+            ASTNode::Link { target, text } => {
+                let label = if text == target { String::new() } else { text.to_string() };
+                if site::is_external(target) {
+                    self.push_word(&format!("{}[{}]", target, label));
+                } else {
+                    self.push_word(&format!("xref:{}[{}]", target, label));
+                }
+            }
+            ASTNode::Image { url, attributes } => {
+                let mut attrs = vec![attributes.alt.clone().unwrap_or_default()];
+                if let Some(width) = attributes.width {
+                    attrs.push(width.to_string());
+                }
+                if let Some(height) = attributes.height {
+                    attrs.push(height.to_string());
+                }
+                self.push_word(&format!("image:{}[{}]", url, attrs.join(",")));
+            }
+            ASTNode::Video { url, .. } => match media::detect_video_host(url) {
+                Some((VideoHost::YouTube, id)) => self.block(&format!("video::{}[youtube]", id)),
+                Some((VideoHost::Vimeo, id)) => self.block(&format!("video::{}[vimeo]", id)),
+                None => self.block(&format!("video::{}[]", url)),
+            },
+            ASTNode::Sound { url, .. } => self.block(&format!("audio::{}[]", url)),
+            ASTNode::MetaSection { .. }
+            | ASTNode::VariableDeclaration { .. }
+            | ASTNode::VariableAssignment { .. }
+            | ASTNode::VariableReference { .. }
+            | ASTNode::IfDefined { .. } => {}
+        }
+    }
+}
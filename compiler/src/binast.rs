@@ -0,0 +1,478 @@
+//! Compact binary format for checked parse trees.
+//!
+//! Tools that run several backends or linters over the same document can
+//! compile it once, save the tree with `encode`, and load it again with
+//! `decode` instead of lexing and parsing the source every time.
+//!
+//! Layout (all integers are LEB128 varints, strings are a length and UTF-8 bytes):
+//!
+//! ```text
+//! "LOLAST" version
+//! depth                       levels of children below the root
+//! name count, names...        interner, in symbol order
+//! node                        the root, children written depth first
+//! ```
+//!
+//! Every node is a tag byte followed by its fields. Decoding checks every length,
+//! tag and symbol, so a truncated or foreign file gives an error, not a panic.
+//! The depth in the header lets `decode` turn down a tree deeper than the
+//! caller's nesting limit before reading it, and bounds the recursion after.
+
+use crate::arena::AstArena;
+use crate::intern::{Interner, Symbol};
+use crate::parser::{ASTNode, CaptionTrack, MediaAttributes};
+
+/// Magic bytes at the start of every encoded tree.
+const MAGIC: &[u8] = b"LOLAST";
+
+/// Format version. Bump it whenever the layout or the node tags change.
+const VERSION: u8 = 5;
+
+// node tags
+const PROGRAM: u8 = 0;
+const HEAD: u8 = 1;
+const META: u8 = 2;
+const PARAGRAF: u8 = 3;
+const LIST: u8 = 4;
+const DECLARATION: u8 = 5;
+const ASSIGNMENT: u8 = 6;
+const REFERENCE: u8 = 7;
+const TITLE: u8 = 8;
+const TEXT: u8 = 9;
+const BOLD: u8 = 10;
+const ITALICS: u8 = 11;
+const ITEM: u8 = 12;
+const NEWLINE: u8 = 13;
+const SOUND: u8 = 14;
+const VIDEO: u8 = 15;
+const IMAGE: u8 = 16;
+const LINK: u8 = 17;
+const IF_DEFINED: u8 = 18;
+
+// media flag bits
+const AUTOPLAY: u8 = 1;
+const LOOPING: u8 = 2;
+const MUTED: u8 = 4;
+const LAZY: u8 = 8;
+
+/// Encodes a tree together with the interner its variable symbols came from.
+pub fn encode(tree: &ASTNode, interner: &Interner) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    write_len(&mut out, depth(tree));
+
+    write_len(&mut out, interner.len());
+    for name in interner.names() {
+        write_str(&mut out, name);
+    }
+
+    write_node(&mut out, tree);
+    out
+}
+
+/// Decodes a tree written by `encode`, allocating it in `arena`.
+///
+/// `max_nesting` is the deepest section nesting accepted, as for the parser
+/// (`parser::DEFAULT_MAX_NESTING` unless the build set `--max-nesting`); a
+/// tree saved from a build with a higher limit needs the same one here.
+/// Returns the tree and a new interner that resolves its symbols.
+pub fn decode<'t>(bytes: &[u8], arena: &'t AstArena<'t>, max_nesting: usize) -> Result<(&'t ASTNode<'t>, Interner), String> {
+    let mut reader = Reader { bytes, pos: 0, depth: 0, max_depth: 0 };
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("not a LOLCODE AST file".to_string());
+    }
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(format!("unsupported AST format version {} (expected {})", version, VERSION));
+    }
+    // the sections, plus the program node and one level of inline content
+    let limit = max_nesting.saturating_add(2);
+    reader.max_depth = reader.len()?;
+    if reader.max_depth > limit {
+        return Err(format!(
+            "tree in AST file is nested {} levels deep, more than the {} a max nesting of {} allows",
+            reader.max_depth, limit, max_nesting
+        ));
+    }
+
+    let mut interner = Interner::new();
+    for _ in 0..reader.len()? {
+        let name = reader.string()?;
+        interner.intern(&name);
+    }
+
+    let tree = reader.node(arena, &interner)?;
+    if reader.pos != bytes.len() {
+        return Err(format!("{} unexpected bytes after the tree", bytes.len() - reader.pos));
+    }
+    Ok((arena.alloc(tree), interner))
+}
+
+// levels of children the decoder goes down to read `node`
+fn depth(node: &ASTNode) -> usize {
+    let has_children = matches!(
+        node,
+        ASTNode::Program { .. }
+            | ASTNode::HeadSection { .. }
+            | ASTNode::ParagrafSection { .. }
+            | ASTNode::ListSection { .. }
+            | ASTNode::IfDefined { .. }
+            | ASTNode::Title { .. }
+            | ASTNode::Bold { .. }
+            | ASTNode::Italics { .. }
+            | ASTNode::Item { .. }
+    );
+    if !has_children {
+        return 0;
+    }
+    1 + node.children().iter().map(depth).max().unwrap_or(0)
+}
+
+fn write_node(out: &mut Vec<u8>, node: &ASTNode) {
+    match node {
+        ASTNode::Program { children } => write_children(out, PROGRAM, children),
+        ASTNode::HeadSection { children } => write_children(out, HEAD, children),
+        ASTNode::ParagrafSection { children } => write_children(out, PARAGRAF, children),
+        ASTNode::ListSection { children } => write_children(out, LIST, children),
+        ASTNode::Bold { content } => write_children(out, BOLD, content),
+        ASTNode::Italics { content } => write_children(out, ITALICS, content),
+        ASTNode::Item { content } => write_children(out, ITEM, content),
+        ASTNode::Title { content } => write_children(out, TITLE, content),
+        ASTNode::MetaSection { entries } => {
+            out.push(META);
+            write_len(out, entries.len());
+            for (key, value) in entries {
+                write_str(out, key);
+                write_str(out, value);
+            }
+        }
+        ASTNode::VariableDeclaration { name } => {
+            out.push(DECLARATION);
+            write_len(out, name.index());
+        }
+        ASTNode::VariableAssignment { name, value } => {
+            out.push(ASSIGNMENT);
+            match name {
+                Some(name) => {
+                    out.push(1);
+                    write_len(out, name.index());
+                }
+                None => out.push(0),
+            }
+            write_str(out, value);
+        }
+        ASTNode::VariableReference { name } => {
+            out.push(REFERENCE);
+            write_len(out, name.index());
+        }
+        ASTNode::Text { content } => {
+            out.push(TEXT);
+            write_str(out, content);
+        }
+        ASTNode::Newline => out.push(NEWLINE),
+        ASTNode::Sound { url, attributes } => write_media(out, SOUND, url, attributes),
+        ASTNode::Video { url, attributes } => write_media(out, VIDEO, url, attributes),
+        ASTNode::Image { url, attributes } => write_media(out, IMAGE, url, attributes),
+        ASTNode::Link { target, text } => {
+            out.push(LINK);
+            write_str(out, target);
+            write_str(out, text);
+        }
+        ASTNode::IfDefined { flag, children } => {
+            out.push(IF_DEFINED);
+            write_str(out, flag);
+            write_len(out, children.len());
+            for child in children.iter() {
+                write_node(out, child);
+            }
+        }
+    }
+}
+
+fn write_children(out: &mut Vec<u8>, tag: u8, children: &[ASTNode]) {
+    out.push(tag);
+    write_len(out, children.len());
+    for child in children {
+        write_node(out, child);
+    }
+}
+
+fn write_media(out: &mut Vec<u8>, tag: u8, url: &str, attributes: &MediaAttributes) {
+    out.push(tag);
+    write_str(out, url);
+
+    let mut flags = 0;
+    if attributes.autoplay {
+        flags |= AUTOPLAY;
+    }
+    if attributes.looping {
+        flags |= LOOPING;
+    }
+    if attributes.muted {
+        flags |= MUTED;
+    }
+    if attributes.lazy {
+        flags |= LAZY;
+    }
+    out.push(flags);
+
+    write_opt_str(out, attributes.poster.as_deref());
+    write_opt_str(out, attributes.preload.as_deref());
+    write_len(out, attributes.captions.len());
+    for track in &attributes.captions {
+        write_str(out, &track.src);
+        write_str(out, &track.lang);
+        write_opt_str(out, track.label.as_deref());
+    }
+    write_opt_u32(out, attributes.width);
+    write_opt_u32(out, attributes.height);
+    write_len(out, attributes.srcset.len());
+    for candidate in &attributes.srcset {
+        write_str(out, candidate);
+    }
+    write_opt_str(out, attributes.alt.as_deref());
+    write_len(out, attributes.url_references.len());
+    for (offset, name) in &attributes.url_references {
+        write_len(out, *offset);
+        write_len(out, name.index());
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    write_varint(out, len as u64);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_len(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_opt_str(out: &mut Vec<u8>, s: Option<&str>) {
+    match s {
+        Some(s) => {
+            out.push(1);
+            write_str(out, s);
+        }
+        None => out.push(0),
+    }
+}
+
+fn write_opt_u32(out: &mut Vec<u8>, n: Option<u32>) {
+    match n {
+        Some(n) => {
+            out.push(1);
+            write_varint(out, u64::from(n));
+        }
+        None => out.push(0),
+    }
+}
+
+/// Bounds-checked cursor over the encoded bytes.
+struct Reader<'b> {
+    bytes: &'b [u8],
+    pos: usize,
+    depth: usize,
+    // the depth the header gives, so a crafted file can't overflow the stack
+    max_depth: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, n: usize) -> Result<&'b [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.bytes.len());
+        match end {
+            Some(end) => {
+                let slice = &self.bytes[self.pos..end];
+                self.pos = end;
+                Ok(slice)
+            }
+            None => Err("AST file is truncated".to_string()),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("malformed number in AST file".to_string())
+    }
+
+    // lengths can't be longer than what is left, which also stops a corrupt
+    // length from reserving huge vectors
+    fn len(&mut self) -> Result<usize, String> {
+        let len = self.varint()?;
+        if len > (self.bytes.len() - self.pos) as u64 {
+            return Err("AST file is truncated".to_string());
+        }
+        Ok(len as usize)
+    }
+
+    fn flag(&mut self) -> Result<bool, String> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(format!("invalid flag {} in AST file", other)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "invalid UTF-8 in AST file".to_string())
+    }
+
+    fn opt_string(&mut self) -> Result<Option<String>, String> {
+        if self.flag()? { self.string().map(Some) } else { Ok(None) }
+    }
+
+    fn opt_u32(&mut self) -> Result<Option<u32>, String> {
+        if !self.flag()? {
+            return Ok(None);
+        }
+        let n = self.varint()?;
+        u32::try_from(n).map(Some).map_err(|_| "number too large in AST file".to_string())
+    }
+
+    fn symbol(&mut self, interner: &Interner) -> Result<Symbol, String> {
+        let index = self.varint()?;
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| interner.symbol_at(index))
+            .ok_or_else(|| format!("unknown variable #{} in AST file", index))
+    }
+
+    fn arena_str<'t>(&mut self, arena: &'t AstArena<'t>) -> Result<&'t str, String> {
+        Ok(arena.alloc_str(&self.string()?))
+    }
+
+    fn children<'t>(&mut self, arena: &'t AstArena<'t>, interner: &Interner) -> Result<&'t [ASTNode<'t>], String> {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(format!("tree in AST file is nested deeper than the {} levels its header gives", self.max_depth));
+        }
+        let count = self.len()?;
+        let mut children = Vec::with_capacity(count);
+        for _ in 0..count {
+            children.push(self.node(arena, interner)?);
+        }
+        self.depth -= 1;
+        Ok(arena.alloc_nodes(children))
+    }
+
+    fn node<'t>(&mut self, arena: &'t AstArena<'t>, interner: &Interner) -> Result<ASTNode<'t>, String> {
+        let node = match self.byte()? {
+            PROGRAM => ASTNode::Program { children: self.children(arena, interner)? },
+            HEAD => ASTNode::HeadSection { children: self.children(arena, interner)? },
+            PARAGRAF => ASTNode::ParagrafSection { children: self.children(arena, interner)? },
+            LIST => ASTNode::ListSection { children: self.children(arena, interner)? },
+            BOLD => ASTNode::Bold { content: self.children(arena, interner)? },
+            ITALICS => ASTNode::Italics { content: self.children(arena, interner)? },
+            ITEM => ASTNode::Item { content: self.children(arena, interner)? },
+            META => {
+                let count = self.len()?;
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    entries.push((self.string()?, self.string()?));
+                }
+                ASTNode::MetaSection { entries }
+            }
+            DECLARATION => ASTNode::VariableDeclaration { name: self.symbol(interner)? },
+            ASSIGNMENT => {
+                let name = if self.flag()? { Some(self.symbol(interner)?) } else { None };
+                ASTNode::VariableAssignment { name, value: self.arena_str(arena)? }
+            }
+            REFERENCE => ASTNode::VariableReference { name: self.symbol(interner)? },
+            TITLE => ASTNode::Title { content: self.children(arena, interner)? },
+            TEXT => ASTNode::Text { content: self.arena_str(arena)? },
+            NEWLINE => ASTNode::Newline,
+            SOUND => {
+                let (url, attributes) = self.media(arena, interner)?;
+                ASTNode::Sound { url, attributes }
+            }
+            VIDEO => {
+                let (url, attributes) = self.media(arena, interner)?;
+                ASTNode::Video { url, attributes }
+            }
+            IMAGE => {
+                let (url, attributes) = self.media(arena, interner)?;
+                ASTNode::Image { url, attributes }
+            }
+            LINK => ASTNode::Link {
+                target: self.arena_str(arena)?,
+                text: self.arena_str(arena)?,
+            },
+            IF_DEFINED => ASTNode::IfDefined {
+                flag: self.arena_str(arena)?,
+                children: self.children(arena, interner)?,
+            },
+            tag => return Err(format!("unknown node tag {} in AST file", tag)),
+        };
+        Ok(node)
+    }
+
+    fn media<'t>(
+        &mut self,
+        arena: &'t AstArena<'t>,
+        interner: &Interner,
+    ) -> Result<(&'t str, Box<MediaAttributes>), String> {
+        let url = self.arena_str(arena)?;
+        let flags = self.byte()?;
+        let mut attributes = MediaAttributes {
+            autoplay: flags & AUTOPLAY != 0,
+            looping: flags & LOOPING != 0,
+            muted: flags & MUTED != 0,
+            lazy: flags & LAZY != 0,
+            poster: self.opt_string()?,
+            preload: self.opt_string()?,
+            ..MediaAttributes::default()
+        };
+        for _ in 0..self.len()? {
+            attributes.captions.push(CaptionTrack {
+                src: self.string()?,
+                lang: self.string()?,
+                label: self.opt_string()?,
+            });
+        }
+        attributes.width = self.opt_u32()?;
+        attributes.height = self.opt_u32()?;
+        for _ in 0..self.len()? {
+            attributes.srcset.push(self.string()?);
+        }
+        attributes.alt = self.opt_string()?;
+        // codegen inserts the values at these offsets, so they have to be in order
+        // and between characters of the URL
+        let mut previous = 0;
+        for _ in 0..self.len()? {
+            let offset = self.varint()?;
+            let offset = usize::try_from(offset)
+                .ok()
+                .filter(|offset| *offset >= previous && url.is_char_boundary(*offset))
+                .ok_or_else(|| format!("invalid URL reference offset {} in AST file", offset))?;
+            previous = offset;
+            attributes.url_references.push((offset, self.symbol(interner)?));
+        }
+        Ok((url, Box::new(attributes)))
+    }
+}
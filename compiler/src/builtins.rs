@@ -0,0 +1,129 @@
+//! Built-in variables.
+//!
+//! A few variables exist in every document without being declared:
+//!
+//! ```text
+//! Generated on #LEMME SEE TODAYZ_DATE #MKAY      Generated on 2026-10-17
+//! at #LEMME SEE NAO #MKAY                        at 2026-10-17 14:03 UTC
+//! ```
+//!
+//! They expand to the time of the build, in UTC: the clock, `SOURCE_DATE_EPOCH`
+//! when it is set, or a fixed time given with `--build-time`. A variable of the
+//! same name declared in the document hides the built-in one.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Names of the built-in variables.
+pub const NAMES: [&str; 2] = ["TODAYZ_DATE", "NAO"];
+
+/// The moment a build happened, which the built-in variables show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildTime {
+    /// Seconds since 1970-01-01 00:00 UTC
+    pub unix_seconds: i64,
+}
+
+impl BuildTime {
+    /// The current time, or `SOURCE_DATE_EPOCH` when it is set, so reproducible
+    /// builds get the same output every time.
+    pub fn now() -> Self {
+        if let Some(fixed) = Self::from_env() {
+            return fixed;
+        }
+        let unix_seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+        Self { unix_seconds }
+    }
+
+    /// The time in `SOURCE_DATE_EPOCH`, if it is set.
+    pub fn from_env() -> Option<Self> {
+        let epoch = std::env::var("SOURCE_DATE_EPOCH").ok()?;
+        Some(Self { unix_seconds: epoch.trim().parse().ok()? })
+    }
+
+    /// Reads `YYYY-MM-DD`, `YYYY-MM-DDTHH:MM[:SS]` (a trailing `Z` is allowed)
+    /// or seconds since the epoch. Times are UTC.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Ok(unix_seconds) = text.parse() {
+            return Some(Self { unix_seconds });
+        }
+
+        let text = text.strip_suffix('Z').unwrap_or(text);
+        let (date, time) = match text.split_once(['T', ' ']) {
+            Some((date, time)) => (date, Some(time)),
+            None => (text, None),
+        };
+        if !crate::meta::is_valid_date(date) {
+            return None;
+        }
+        let year: i64 = date[..4].parse().ok()?;
+        let month: i64 = date[5..7].parse().ok()?;
+        let day: i64 = date[8..].parse().ok()?;
+
+        let mut seconds_of_day = 0;
+        if let Some(time) = time {
+            let parts: Vec<&str> = time.split(':').collect();
+            if !(2..=3).contains(&parts.len()) || parts.iter().any(|p| p.len() != 2) {
+                return None;
+            }
+            let values: Vec<i64> = parts.iter().map(|p| p.parse().ok()).collect::<Option<_>>()?;
+            let (hour, minute, second) = (values[0], values[1], values.get(2).copied().unwrap_or(0));
+            if hour > 23 || minute > 59 || second > 59 {
+                return None;
+            }
+            seconds_of_day = hour * 3600 + minute * 60 + second;
+        }
+        Some(Self { unix_seconds: days_from_civil(year, month, day) * 86400 + seconds_of_day })
+    }
+
+    /// The date as `YYYY-MM-DD`.
+    pub fn date(&self) -> String {
+        let (year, month, day) = civil_from_days(self.unix_seconds.div_euclid(86400));
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    /// Date and time as `YYYY-MM-DD HH:MM UTC`.
+    pub fn date_time(&self) -> String {
+        let seconds_of_day = self.unix_seconds.rem_euclid(86400);
+        format!("{} {:02}:{:02} UTC", self.date(), seconds_of_day / 3600, seconds_of_day % 3600 / 60)
+    }
+
+    /// The value of the built-in variable `name`, if there is one by that name.
+    /// With `ignore_case` the name may be written in any case.
+    pub fn value(&self, name: &str, ignore_case: bool) -> Option<String> {
+        let builtin = NAMES
+            .iter()
+            .find(|builtin| if ignore_case { builtin.eq_ignore_ascii_case(name) } else { **builtin == name })?;
+        match *builtin {
+            "TODAYZ_DATE" => Some(self.date()),
+            _ => Some(self.date_time()),
+        }
+    }
+}
+
+// days since the epoch -> (year, month, day), after Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// the inverse of civil_from_days
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
@@ -0,0 +1,277 @@
+//! Build cache for incremental project builds.
+//!
+//! After a project build the compiler writes `.lolcache` at the project root,
+//! recording a hash and the length of every page's source, the page's metadata
+//! and what was found in it. The next build skips pages whose source hash and
+//! length still match (a changed source would have to collide on both), as
+//! long as the settings that affect output (the config hash) are unchanged and
+//! the old output file is still there. The metadata is kept so the feed can
+//! still list skipped pages without parsing them again, the diagnostics so a
+//! skipped page reports the same warnings as a compiled one. It also lists
+//! every file builds of the project wrote, so `lolcompiler clean` removes
+//! those and nothing else.
+//!
+//! The manifest is a plain text file:
+//!
+//! ```text
+//! lolcompiler-cache 4
+//! config <hash>
+//! output <path>
+//! page <hash> <length> <page name>
+//! meta <key> <value>
+//! title <value>
+//! diagnostic <warning|error> <code> <line>,<col> <message>
+//! label <line>,<col> <message>
+//! ```
+//!
+//! `output` paths are relative to the directory of the manifest and never leave
+//! it: absolute ones and ones with `..` are dropped when the manifest is read,
+//! so a manifest that came with a checkout can't point `clean` elsewhere. `meta`,
+//! `title` and `diagnostic` lines belong to the `page` line above them,
+//! `label` lines to the `diagnostic` above them. A diagnostic without a
+//! position has `-` in its place. Values are escaped so they stay on one line,
+//! keys so they stay one word.
+
+use crate::diagnostics::{self, Diagnostic, Label, Severity};
+use crate::meta::DocumentMeta;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the manifest file at the project root.
+pub const CACHE_FILE: &str = ".lolcache";
+
+/// First line of the manifest. Bump the number when the format changes.
+const HEADER: &str = "lolcompiler-cache 4";
+
+/// Hashes bytes with 64-bit FNV-1a.
+///
+/// Unlike `DefaultHasher` this is stable across Rust versions, so a manifest
+/// written by one build of the compiler can be read by the next.
+pub fn hash_bytes(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in data {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// What tells one version of a page's source from another.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceKey {
+    /// `hash_bytes` of the source
+    pub hash: u64,
+    /// Length of the source in bytes
+    pub len: u64,
+}
+
+impl SourceKey {
+    /// The key of a source.
+    pub fn of(source: &[u8]) -> Self {
+        Self {
+            hash: hash_bytes(source),
+            len: source.len() as u64,
+        }
+    }
+}
+
+/// What the cache remembers about one page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedPage {
+    /// Key of the page's source
+    pub source: SourceKey,
+    /// Metadata collected when the page was last compiled
+    pub meta: DocumentMeta,
+    /// Warnings found when the page was last compiled
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// The manifest of a previous build.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuildCache {
+    /// Hash of every setting that changes the output
+    pub config: u64,
+    /// Page name -> cached page
+    pub pages: HashMap<String, CachedPage>,
+    /// Every file builds wrote that was still there at the end of the last
+    /// one, outputs and artifacts of pages and the feed, in sorted order
+    pub outputs: Vec<PathBuf>,
+}
+
+impl BuildCache {
+    /// Creates an empty cache for the given config hash.
+    pub fn new(config: u64) -> Self {
+        Self {
+            config,
+            pages: HashMap::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Returns the cached page if its source still has the same key.
+    pub fn fresh(&self, name: &str, source: SourceKey) -> Option<&CachedPage> {
+        self.pages.get(name).filter(|page| page.source == source)
+    }
+
+    /// Reads a manifest. Missing, unreadable or outdated manifests give `None`,
+    /// which just means everything is rebuilt.
+    pub fn load(path: &Path) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        let mut lines = text.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+
+        let root = path.parent().unwrap_or(Path::new(""));
+        let mut cache = BuildCache::default();
+        let mut current: Option<(String, CachedPage)> = None;
+        for line in lines {
+            let (kind, rest) = line.split_once(' ')?;
+            match kind {
+                "config" => cache.config = u64::from_str_radix(rest, 16).ok()?,
+                "output" => {
+                    let output = PathBuf::from(unescape(rest));
+                    if stays_inside(&output) {
+                        cache.outputs.push(root.join(output));
+                    }
+                }
+                "page" => {
+                    if let Some((name, page)) = current.take() {
+                        cache.pages.insert(name, page);
+                    }
+                    let mut fields = rest.splitn(3, ' ');
+                    let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+                    let len = fields.next()?.parse().ok()?;
+                    let name = fields.next()?;
+                    let page = CachedPage {
+                        source: SourceKey { hash, len },
+                        meta: DocumentMeta::default(),
+                        diagnostics: Vec::new(),
+                    };
+                    current = Some((name.to_string(), page));
+                }
+                "meta" => {
+                    let (key, value) = rest.split_once(' ')?;
+                    let (_, page) = current.as_mut()?;
+                    page.meta.entries.push((unescape(key), unescape(value)));
+                }
+                "title" => {
+                    let (_, page) = current.as_mut()?;
+                    page.meta.first_title = Some(unescape(rest));
+                }
+                "diagnostic" => {
+                    let mut fields = rest.splitn(4, ' ');
+                    let severity = match fields.next()? {
+                        "warning" => Severity::Warning,
+                        "error" => Severity::Error,
+                        _ => return None,
+                    };
+                    // codes are static, so only known ones can be read back
+                    let code = fields.next()?;
+                    let code = diagnostics::CODES.iter().chain(&diagnostics::ERROR_CODES).find(|known| **known == code)?;
+                    let span = match fields.next()? {
+                        "-" => None,
+                        position => Some(parse_position(position)?),
+                    };
+                    let mut diagnostic = Diagnostic::new(severity, code, unescape(fields.next()?));
+                    diagnostic.span = span;
+                    let (_, page) = current.as_mut()?;
+                    page.diagnostics.push(diagnostic);
+                }
+                "label" => {
+                    let (position, message) = rest.split_once(' ')?;
+                    let (line, col) = parse_position(position)?;
+                    let (_, page) = current.as_mut()?;
+                    let diagnostic = page.diagnostics.last_mut()?;
+                    diagnostic.labels.push(Label { line, col, message: unescape(message) });
+                }
+                _ => return None,
+            }
+        }
+        if let Some((name, page)) = current {
+            cache.pages.insert(name, page);
+        }
+        Some(cache)
+    }
+
+    /// Writes the manifest, pages in name order so it diffs cleanly.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut text = format!("{}\nconfig {:016x}\n", HEADER, self.config);
+        let root = path.parent().unwrap_or(Path::new(""));
+        // files of a build with inputs elsewhere aren't the project's to clean
+        for relative in self.outputs.iter().filter_map(|output| output.strip_prefix(root).ok()) {
+            if stays_inside(relative) {
+                text.push_str(&format!("output {}\n", escape(&relative.to_string_lossy())));
+            }
+        }
+
+        let mut names: Vec<&String> = self.pages.keys().collect();
+        names.sort();
+        for name in names {
+            let page = &self.pages[name];
+            text.push_str(&format!("page {:016x} {} {}\n", page.source.hash, page.source.len, name));
+            for (key, value) in &page.meta.entries {
+                text.push_str(&format!("meta {} {}\n", escape_key(key), escape(value)));
+            }
+            if let Some(title) = &page.meta.first_title {
+                text.push_str(&format!("title {}\n", escape(title)));
+            }
+            for diagnostic in &page.diagnostics {
+                let severity = match diagnostic.severity {
+                    Severity::Warning => "warning",
+                    Severity::Error => "error",
+                };
+                let position = diagnostic.span.map_or_else(|| "-".to_string(), |(line, col)| format!("{},{}", line, col));
+                text.push_str(&format!("diagnostic {} {} {} {}\n", severity, diagnostic.code, position, escape(&diagnostic.message)));
+                for label in &diagnostic.labels {
+                    text.push_str(&format!("label {},{} {}\n", label.line, label.col, escape(&label.message)));
+                }
+            }
+        }
+
+        fs::write(path, text)
+    }
+}
+
+/// True if the relative path `path` names something inside the directory it is
+/// relative to: not absolute, and no `..` to climb out.
+pub fn stays_inside(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+// keep a value on one line
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+// keep a key one word; META keys can have spaces
+fn escape_key(key: &str) -> String {
+    escape(key).replace(' ', "\\s")
+}
+
+// "3,5" -> (3, 5)
+fn parse_position(position: &str) -> Option<(usize, usize)> {
+    let (line, col) = position.split_once(',')?;
+    Some((line.parse().ok()?, col.parse().ok()?))
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('s') => out.push(' '),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
@@ -0,0 +1,414 @@
+//! Classic LOLCODE front end.
+//!
+//! `--classic` reads real LOLCODE 1.2 programs instead of the document language
+//! and builds the same tree from them, so existing snippets render as documents
+//! without rewriting. Everything the program prints becomes one paragraph:
+//!
+//! ```text
+//! HAI 1.2                      #HAI
+//! CAN HAS STDIO?               #MAEK PARAGRAF
+//! I HAS A name ITZ "Ceiling"   #I HAZ name #IT IZ Ceiling #MKAY
+//! VISIBLE "HAI " name "!"      HAI #LEMME SEE name #MKAY ! #GIMMEH NEWLINE
+//! KTHXBYE                      #OIC #KTHXBYE
+//! ```
+//!
+//! Supported are `BTW` and `OBTW`/`TLDR` comments, `CAN HAS <lib>?` (ignored),
+//! `VISIBLE` with YARN, NUMBR and NUMBAR literals, `WIN`, `FAIL`, `NOOB` and
+//! variables (a trailing `!` leaves out the line break; pieces are separated by
+//! a space, as in the document language), and `I HAS A <var>
+//! [ITZ <literal>]`. YARN escapes `:)`, `:>`, `:"`, `::` and `:{var}` work as in
+//! LOLCODE. Anything that computes (operators, `R`, loops, conditionals, input)
+//! has no place in a document and is a syntax error.
+
+use crate::arena::AstArena;
+use crate::diagnostics::CompileError;
+use crate::intern::Interner;
+use crate::parser::ASTNode;
+
+/// Classic keywords that compute something, for a clearer error than "unexpected word".
+const UNSUPPORTED: [&str; 24] = [
+    "R", "SUM", "DIFF", "PRODUKT", "QUOSHUNT", "MOD", "BIGGR", "SMALLR", "BOTH", "EITHER", "WON", "NOT",
+    "ALL", "ANY", "SMOOSH", "MAEK", "IS", "O", "WTF?", "IM", "GIMMEH", "HOW", "FOUND", "GTFO",
+];
+
+/// Parses a classic LOLCODE program into a document tree allocated in `arena`.
+/// Returns the tree and the interner holding its variable names.
+pub fn parse<'t>(source: &str, arena: &'t AstArena<'t>) -> Result<(&'t ASTNode<'t>, Interner), CompileError> {
+    let tokens = tokenize(source)?;
+    let mut parser = ClassicParser {
+        tokens,
+        pos: 0,
+        arena,
+        interner: Interner::new(),
+    };
+    let tree = parser.program()?;
+    Ok((arena.alloc(tree), parser.interner))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Word(String),
+    Yarn(Vec<Piece>),
+    Number(String),
+    Bang,
+    // a line break or a comma
+    Break,
+    Eof,
+}
+
+// YARN literals are text with `:{var}` references in between
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Var(String),
+}
+
+#[derive(Debug, Clone)]
+struct ClassicToken {
+    kind: Kind,
+    line: usize,
+    col: usize,
+}
+
+fn error(line: usize, col: usize, message: String) -> CompileError {
+    CompileError::Syntax { line, col, message, labels: Vec::new() }
+}
+
+/// Splits the program into tokens, dropping comments.
+fn tokenize(source: &str) -> Result<Vec<ClassicToken>, CompileError> {
+    let chars: Vec<char> = source.strip_prefix('\u{FEFF}').unwrap_or(source).chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut col) = (0, 1, 1);
+
+    while i < chars.len() {
+        let c = chars[i];
+        let (start_line, start_col) = (line, col);
+        match c {
+            '\n' | ',' => {
+                tokens.push(ClassicToken { kind: Kind::Break, line, col });
+                i += 1;
+                if c == '\n' {
+                    line += 1;
+                    col = 1;
+                } else {
+                    col += 1;
+                }
+            }
+            '!' => {
+                tokens.push(ClassicToken { kind: Kind::Bang, line, col });
+                i += 1;
+                col += 1;
+            }
+            '"' => {
+                let (pieces, end) = yarn(&chars, i + 1, line, col)?;
+                col += end - i;
+                i = end;
+                tokens.push(ClassicToken { kind: Kind::Yarn(pieces), line: start_line, col: start_col });
+            }
+            _ if c.is_whitespace() => {
+                i += 1;
+                col += 1;
+            }
+            _ => {
+                let end = (i..chars.len())
+                    .find(|&j| chars[j].is_whitespace() || matches!(chars[j], ',' | '!' | '"'))
+                    .unwrap_or(chars.len());
+                let word: String = chars[i..end].iter().collect();
+                col += end - i;
+                i = end;
+
+                if word == "BTW" {
+                    // comment to the end of the line
+                    while i < chars.len() && chars[i] != '\n' {
+                        i += 1;
+                    }
+                } else if word == "OBTW" {
+                    // comment up to the next TLDR
+                    let rest: String = chars[i..].iter().collect();
+                    let Some(offset) = rest.find("TLDR") else {
+                        return Err(error(start_line, start_col, "Unclosed comment block - missing TLDR".to_string()));
+                    };
+                    let skipped = &rest[..offset + "TLDR".len()];
+                    line += skipped.matches('\n').count();
+                    col = match skipped.rfind('\n') {
+                        Some(newline) => skipped[newline + 1..].chars().count() + 1,
+                        None => col + skipped.chars().count(),
+                    };
+                    i += skipped.chars().count();
+                } else {
+                    let is_number = word.trim_start_matches('-').chars().all(|c| c.is_ascii_digit() || c == '.')
+                        && word.chars().any(|c| c.is_ascii_digit());
+                    let kind = if is_number { Kind::Number(word) } else { Kind::Word(word) };
+                    tokens.push(ClassicToken { kind, line: start_line, col: start_col });
+                }
+            }
+        }
+    }
+    tokens.push(ClassicToken { kind: Kind::Eof, line, col });
+    Ok(tokens)
+}
+
+/// Reads a YARN literal starting after its opening quote. Returns its pieces and
+/// the index just past the closing quote.
+fn yarn(chars: &[char], start: usize, line: usize, col: usize) -> Result<(Vec<Piece>, usize), CompileError> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut i = start;
+    loop {
+        match chars.get(i) {
+            None | Some('\n') => return Err(error(line, col, "Unclosed YARN literal - missing closing quote".to_string())),
+            Some('"') => break,
+            Some(':') => {
+                match chars.get(i + 1) {
+                    Some(')') => text.push('\n'),
+                    Some('>') => text.push('\t'),
+                    Some('"') => text.push('"'),
+                    Some(':') => text.push(':'),
+                    // the bell has nothing to ring in a document
+                    Some('o') => {}
+                    Some('{') => {
+                        let Some(close) = (i + 2..chars.len()).find(|&j| chars[j] == '}') else {
+                            return Err(error(line, col, "Unclosed :{ in YARN literal".to_string()));
+                        };
+                        if !text.is_empty() {
+                            pieces.push(Piece::Text(std::mem::take(&mut text)));
+                        }
+                        pieces.push(Piece::Var(chars[i + 2..close].iter().collect()));
+                        i = close + 1;
+                        continue;
+                    }
+                    _ => return Err(error(line, col, "Unknown escape in YARN literal".to_string())),
+                }
+                i += 2;
+            }
+            Some(&c) => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok((pieces, i + 1))
+}
+
+struct ClassicParser<'t> {
+    tokens: Vec<ClassicToken>,
+    pos: usize,
+    arena: &'t AstArena<'t>,
+    interner: Interner,
+}
+
+impl<'t> ClassicParser<'t> {
+    fn current(&self) -> &ClassicToken {
+        // tokenize always ends with Eof, and the parser never moves past it
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn advance(&mut self) {
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+    }
+
+    fn syntax_error(&self, message: String) -> CompileError {
+        let token = self.current();
+        error(token.line, token.col, message)
+    }
+
+    fn at_word(&self, word: &str) -> bool {
+        matches!(&self.current().kind, Kind::Word(w) if w == word)
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), CompileError> {
+        if !self.at_word(word) {
+            return Err(self.syntax_error(format!("Expected '{}' but found {}", word, describe(&self.current().kind))));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn skip_breaks(&mut self) {
+        while self.current().kind == Kind::Break {
+            self.advance();
+        }
+    }
+
+    fn end_of_statement(&mut self) -> Result<(), CompileError> {
+        match self.current().kind {
+            Kind::Break => {
+                self.advance();
+                Ok(())
+            }
+            Kind::Eof => Ok(()),
+            ref other => Err(self.syntax_error(format!("Expected end of line but found {}", describe(other)))),
+        }
+    }
+
+    // program ::= HAI [version] statements KTHXBYE
+    fn program(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        self.skip_breaks();
+        self.expect_word("HAI")?;
+        if matches!(self.current().kind, Kind::Number(_)) {
+            self.advance();
+        }
+        self.end_of_statement()?;
+
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_breaks();
+            if self.at_word("KTHXBYE") {
+                self.advance();
+                break;
+            }
+            if self.current().kind == Kind::Eof {
+                return Err(self.syntax_error("Expected 'KTHXBYE' before the end of the file".to_string()));
+            }
+            self.statement(&mut nodes)?;
+        }
+        self.skip_breaks();
+        if self.current().kind != Kind::Eof {
+            return Err(self.syntax_error("Unexpected tokens after KTHXBYE".to_string()));
+        }
+
+        let paragraf = ASTNode::ParagrafSection { children: self.arena.alloc_nodes(nodes) };
+        Ok(ASTNode::Program { children: self.arena.alloc_nodes(vec![paragraf]) })
+    }
+
+    fn statement(&mut self, nodes: &mut Vec<ASTNode<'t>>) -> Result<(), CompileError> {
+        let Kind::Word(word) = self.current().kind.clone() else {
+            return Err(self.syntax_error(format!("Expected a statement but found {}", describe(&self.current().kind))));
+        };
+        match word.as_str() {
+            "VISIBLE" => self.visible(nodes)?,
+            "I" => self.declaration(nodes)?,
+            "CAN" => {
+                // libraries don't mean anything in a document
+                self.advance();
+                self.expect_word("HAS")?;
+                while !matches!(self.current().kind, Kind::Break | Kind::Eof) {
+                    self.advance();
+                }
+            }
+            _ if UNSUPPORTED.contains(&word.as_str()) => {
+                return Err(self.syntax_error(format!("'{}' is not supported in classic mode", word)));
+            }
+            _ => {
+                self.advance();
+                if self.at_word("R") {
+                    return Err(self.syntax_error("'R' is not supported in classic mode, give the value with ITZ".to_string()));
+                }
+                self.pos -= 1;
+                return Err(self.syntax_error(format!("Unexpected '{}'", word)));
+            }
+        }
+        self.end_of_statement()
+    }
+
+    // VISIBLE <expr>+ [!]
+    fn visible(&mut self, nodes: &mut Vec<ASTNode<'t>>) -> Result<(), CompileError> {
+        self.advance();
+        let start = nodes.len();
+        loop {
+            match self.current().kind.clone() {
+                Kind::Yarn(pieces) => {
+                    for piece in pieces {
+                        match piece {
+                            Piece::Text(text) => self.push_text(nodes, &text),
+                            Piece::Var(name) => nodes.push(ASTNode::VariableReference { name: self.interner.intern(&name) }),
+                        }
+                    }
+                }
+                Kind::Number(number) => nodes.push(ASTNode::Text { content: self.arena.alloc_str(&number) }),
+                Kind::Word(word) if word == "WIN" || word == "FAIL" => {
+                    nodes.push(ASTNode::Text { content: self.arena.alloc_str(&word) });
+                }
+                Kind::Word(word) if word == "NOOB" => {}
+                Kind::Word(word) if UNSUPPORTED.contains(&word.as_str()) => {
+                    return Err(self.syntax_error(format!("'{}' is not supported in classic mode", word)));
+                }
+                Kind::Word(name) => nodes.push(ASTNode::VariableReference { name: self.interner.intern(&name) }),
+                _ => break,
+            }
+            self.advance();
+        }
+        if nodes.len() == start && !matches!(self.current().kind, Kind::Break | Kind::Eof | Kind::Bang) {
+            return Err(self.syntax_error("Expected something to print after VISIBLE".to_string()));
+        }
+
+        // a trailing ! keeps the next VISIBLE on the same line
+        if self.current().kind == Kind::Bang {
+            self.advance();
+        } else {
+            nodes.push(ASTNode::Newline);
+        }
+        Ok(())
+    }
+
+    // `:)` in a YARN is a line break of its own. Output puts a space after every
+    // piece, so the spaces at the ends of a piece are left to it.
+    fn push_text(&mut self, nodes: &mut Vec<ASTNode<'t>>, text: &str) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                nodes.push(ASTNode::Newline);
+            }
+            let line = line.trim();
+            if !line.is_empty() {
+                nodes.push(ASTNode::Text { content: self.arena.alloc_str(line) });
+            }
+        }
+    }
+
+    // I HAS A <var> [ITZ <literal>]
+    fn declaration(&mut self, nodes: &mut Vec<ASTNode<'t>>) -> Result<(), CompileError> {
+        self.advance();
+        self.expect_word("HAS")?;
+        self.expect_word("A")?;
+        let Kind::Word(name) = self.current().kind.clone() else {
+            return Err(self.syntax_error("Expected variable name after I HAS A".to_string()));
+        };
+        self.advance();
+        nodes.push(ASTNode::VariableDeclaration { name: self.interner.intern(&name) });
+
+        if !self.at_word("ITZ") {
+            return Ok(());
+        }
+        self.advance();
+        let value = match self.current().kind.clone() {
+            Kind::Yarn(pieces) => {
+                let mut value = String::new();
+                for piece in pieces {
+                    match piece {
+                        Piece::Text(text) => value.push_str(&text),
+                        Piece::Var(_) => {
+                            return Err(self.syntax_error("Variables inside an ITZ value are not supported in classic mode".to_string()));
+                        }
+                    }
+                }
+                value
+            }
+            Kind::Number(number) => number,
+            Kind::Word(word) if word == "WIN" || word == "FAIL" => word,
+            Kind::Word(word) if word == "NOOB" => String::new(),
+            ref other => {
+                return Err(self.syntax_error(format!("Expected a literal value after ITZ but found {}", describe(other))));
+            }
+        };
+        self.advance();
+        nodes.push(ASTNode::VariableAssignment { name: None, value: self.arena.alloc_str(&value) });
+        Ok(())
+    }
+}
+
+// for error messages
+fn describe(kind: &Kind) -> String {
+    match kind {
+        Kind::Word(word) => format!("'{}'", word),
+        Kind::Yarn(_) => "a YARN literal".to_string(),
+        Kind::Number(number) => format!("'{}'", number),
+        Kind::Bang => "'!'".to_string(),
+        Kind::Break => "end of line".to_string(),
+        Kind::Eof => "end of file".to_string(),
+    }
+}
@@ -0,0 +1,493 @@
+//! Completion suggestions for editors.
+//!
+//! `complete` looks at the source before a cursor and suggests what can be
+//! typed there: the hashwords and keywords the grammar allows at that point
+//! and, where a variable name goes, the variables in scope. What is already
+//! typed of the word under the cursor narrows the suggestions down:
+//!
+//! ```text
+//! #MAEK PARAGRAF #I HAZ name #LEMME SEE n|     ->  name, NAO
+//! #MAEK PARAGRAF #GIMMEH |                     ->  BOLD, ITALICS, NEWLINE, LINKZ, ...
+//! ```
+//!
+//! The source doesn't have to be valid, an editor asks while it is being
+//! written; text the grammar doesn't expect is skipped. Only a lexical error
+//! before the cursor (an unclosed comment or YARN) leaves nothing to suggest.
+
+use crate::builtins;
+use crate::lexer::{self, LexicalAnalyzer, Lexer};
+use crate::parser::LanguageVersion;
+use crate::token::TokenKind;
+
+/// What kind of word a suggestion is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// A hashtag word such as `#MAEK`
+    HashWord,
+    /// A keyword such as `PARAGRAF`, or a media attribute
+    Keyword,
+    /// A variable in scope, built-in variables included
+    Variable,
+}
+
+/// One suggestion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// The word as it should be inserted
+    pub text: String,
+    pub kind: CompletionKind,
+}
+
+/// The suggestions for one cursor position.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completions {
+    /// Where the word under the cursor starts; a suggestion replaces the text
+    /// from there to the cursor
+    pub start: (usize, usize),
+    /// Suggestions in the order an editor should list them
+    pub items: Vec<Completion>,
+}
+
+// what a construct that is still open takes next
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frame {
+    Program,
+    Head,
+    Meta,
+    Paragraf,
+    List,
+    // content of an #IZ DEFINED, the same as what its enclosing section takes
+    IfDefined,
+    Title,
+    Styled,
+    Item,
+    Link,
+    Media { kind: &'static str },
+    // #IT IZ, until something follows it
+    Assignment { started: bool },
+}
+
+// how far the words of a SOUNDZ, VIDZ or PIKCHUR have got
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MediaStage {
+    Url,
+    AfterUrl,
+    Attribute,
+    // words an attribute still takes
+    Arguments(&'static str, usize),
+    AfterAttribute,
+    // ALT text runs to the #MKAY
+    Alt,
+}
+
+// a single word the grammar waits for, before the innermost frame goes on
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Next {
+    Hai,
+    Content,
+    SectionKind,
+    Style,
+    HeadStyle,
+    ListStyle,
+    // a new variable or META key after #I HAZ
+    NewName,
+    MetaAssign,
+    ReferencedName,
+    CloseReference,
+    Flag,
+    Done,
+}
+
+/// The suggestions for a cursor at (line, column), counted the way the lexer
+/// counts them. A position past the end of the source gets none.
+pub fn complete(source: &str, line: usize, col: usize) -> Completions {
+    let offsets = lexer::byte_offsets(source, vec![(line, 1), (line, col)]);
+    let (Some(&line_start), Some(&cursor)) = (offsets.get(&(line, 1)), offsets.get(&(line, col))) else {
+        return Completions { start: (line, col), items: Vec::new() };
+    };
+    let before = &source[line_start..cursor];
+    let typed = typed_word(before);
+    let start = (line, col - typed.chars().count());
+
+    let mut state = State::new();
+    let mut lexer = Lexer::new(&source[..cursor - typed.len()]);
+    loop {
+        match lexer.get_next_token() {
+            Ok(token) if token.kind == TokenKind::Eof => break,
+            Ok(token) => state.take(&token.kind, token.line, token.col),
+            Err(_) => return Completions { start, items: Vec::new() },
+        }
+    }
+
+    let items = state
+        .suggestions()
+        .into_iter()
+        .filter(|item| item.text.get(..typed.len()).is_some_and(|start| start.eq_ignore_ascii_case(typed)))
+        .collect();
+    Completions { start, items }
+}
+
+// the part of a word typed before the cursor; hashwords of two words count
+// as one, so `#I H` is completed to `#I HAZ`
+fn typed_word(before: &str) -> &str {
+    if let Some(hash) = before.rfind('#') {
+        let partial = &before[hash..];
+        if HASH_WORDS.iter().any(|word| {
+            word.len() >= partial.len() && word.as_bytes()[..partial.len()].eq_ignore_ascii_case(partial.as_bytes())
+        }) {
+            return partial;
+        }
+    }
+    let word_start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    &before[word_start..]
+}
+
+// every hashtag word a document can use, for the partial word at the cursor
+const HASH_WORDS: [&str; 13] = [
+    "#HAI", "#KTHXBYE", "#OBTW", "#TLDR", "#MAEK", "#OIC",
+    "#GIMMEH", "#MKAY", "#I HAZ", "#IT IZ", "#LEMME SEE", "#SHHH",
+    "#IZ DEFINED",
+];
+
+// the grammar position reached by the tokens before the cursor
+struct State {
+    frames: Vec<Frame>,
+    next: Next,
+    // variables declared in each open scope, the global one first
+    scopes: Vec<Vec<String>>,
+    version: LanguageVersion,
+    // the line of #HAI while a version may still follow it
+    hai_line: Option<usize>,
+    // the words of the open media element, joined like the parser joins them,
+    // and where the last piece ended
+    media_body: String,
+    media_end: Option<(usize, usize)>,
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            frames: Vec::new(),
+            next: Next::Hai,
+            scopes: vec![Vec::new()],
+            version: LanguageVersion::LATEST,
+            hai_line: None,
+            media_body: String::new(),
+            media_end: None,
+        }
+    }
+
+    fn frame(&self) -> Frame {
+        self.frames.last().copied().unwrap_or(Frame::Program)
+    }
+
+    // the section whose content an #IZ DEFINED takes
+    fn content_frame(&self) -> Frame {
+        self.frames.iter().rev().copied().find(|frame| *frame != Frame::IfDefined).unwrap_or(Frame::Program)
+    }
+
+    fn take(&mut self, kind: &TokenKind, line: usize, col: usize) {
+        if let Some(hai_line) = self.hai_line.take()
+            && let TokenKind::Text(text) = kind
+            && line == hai_line
+            && let Some(version) = LanguageVersion::parse(text)
+        {
+            self.version = version;
+            return;
+        }
+        if *kind == TokenKind::Newline {
+            self.media_end = None;
+            return;
+        }
+
+        //single words the grammar waits for; anything else goes on as content
+        match (self.next, kind) {
+            (Next::Hai, TokenKind::HashWord("#HAI")) => {
+                self.frames.push(Frame::Program);
+                self.hai_line = Some(line);
+                self.next = Next::Content;
+                return;
+            }
+            (Next::Hai | Next::Done, _) => return,
+            (Next::SectionKind, TokenKind::Keyword(keyword)) => {
+                match *keyword {
+                    "HEAD" => self.frames.push(Frame::Head),
+                    "META" => self.frames.push(Frame::Meta),
+                    "PARAGRAF" => self.open_scope(Frame::Paragraf),
+                    "LIST" => self.open_scope(Frame::List),
+                    _ => {}
+                }
+                self.next = Next::Content;
+                return;
+            }
+            (Next::Style, TokenKind::Keyword(keyword)) => {
+                match *keyword {
+                    "BOLD" | "ITALICS" => self.frames.push(Frame::Styled),
+                    "LINKZ" => self.frames.push(Frame::Link),
+                    "SOUNDZ" | "VIDZ" | "PIKCHUR" => {
+                        self.frames.push(Frame::Media { kind: keyword });
+                        self.media_body.clear();
+                        self.media_end = None;
+                    }
+                    _ => {}
+                }
+                self.next = Next::Content;
+                return;
+            }
+            (Next::HeadStyle, TokenKind::Keyword("TITLE")) => {
+                self.frames.push(Frame::Title);
+                self.next = Next::Content;
+                return;
+            }
+            (Next::ListStyle, TokenKind::Keyword("ITEM")) => {
+                self.frames.push(Frame::Item);
+                self.next = Next::Content;
+                return;
+            }
+            (Next::NewName, TokenKind::VarDef(name)) => {
+                if self.content_frame() == Frame::Meta {
+                    self.next = Next::MetaAssign;
+                } else {
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.push(name.clone());
+                    }
+                    self.next = Next::Content;
+                }
+                return;
+            }
+            (Next::ReferencedName, TokenKind::VarDef(name)) => {
+                // a reference is part of the URL it is in
+                if matches!(self.frame(), Frame::Media { .. }) {
+                    self.add_media_piece(name, line, col);
+                }
+                self.next = Next::CloseReference;
+                return;
+            }
+            (Next::Flag, TokenKind::VarDef(_)) => {
+                self.frames.push(Frame::IfDefined);
+                self.next = Next::Content;
+                return;
+            }
+            (Next::CloseReference, TokenKind::HashWord("#MKAY")) => {
+                if matches!(self.frame(), Frame::Media { .. }) {
+                    self.media_end = Some((line, col + "#MKAY".len()));
+                }
+                self.next = Next::Content;
+                return;
+            }
+            _ => self.next = Next::Content,
+        }
+
+        match kind {
+            TokenKind::HashWord(word) => self.take_hash_word(word),
+            _ => {
+                if let Some(Frame::Assignment { started }) = self.frames.last_mut() {
+                    *started = true;
+                } else if let (Frame::Media { .. }, TokenKind::Text(piece) | TokenKind::Address(piece) | TokenKind::VarDef(piece)) =
+                    (self.frame(), kind)
+                {
+                    self.add_media_piece(piece, line, col);
+                }
+            }
+        }
+    }
+
+    // pieces that touch in the source make one word, see the parser's spaced_body
+    fn add_media_piece(&mut self, piece: &str, line: usize, col: usize) {
+        if !self.media_body.is_empty() && self.media_end != Some((line, col)) {
+            self.media_body.push(' ');
+        }
+        self.media_body.push_str(piece);
+        self.media_end = Some((line, col + piece.chars().count()));
+    }
+
+    // where the words of the open media element have got
+    fn media_stage(&self, kind: &str) -> MediaStage {
+        let mut stage = MediaStage::Url;
+        for word in self.media_body.split_whitespace() {
+            let word = word.to_ascii_uppercase();
+            stage = match stage {
+                MediaStage::Url => MediaStage::AfterUrl,
+                MediaStage::AfterUrl if word == "WIT" => MediaStage::Attribute,
+                MediaStage::AfterAttribute if word == "AN" => MediaStage::Attribute,
+                MediaStage::Attribute if word == "ALT" => MediaStage::Alt,
+                MediaStage::Attribute => match media_attributes(kind).iter().find(|(name, _)| *name == word) {
+                    Some(&(name, arguments)) if arguments > 0 => MediaStage::Arguments(name, arguments),
+                    _ => MediaStage::AfterAttribute,
+                },
+                MediaStage::Arguments(_, 1) => MediaStage::AfterAttribute,
+                MediaStage::Arguments(name, left) => MediaStage::Arguments(name, left - 1),
+                stage => stage,
+            };
+        }
+        stage
+    }
+
+    fn take_hash_word(&mut self, word: &str) {
+        match word {
+            "#MAEK" => self.next = Next::SectionKind,
+            "#I HAZ" => self.next = Next::NewName,
+            "#IT IZ" => self.frames.push(Frame::Assignment { started: self.frame() == Frame::Meta }),
+            "#LEMME SEE" => self.next = Next::ReferencedName,
+            "#IZ DEFINED" => self.next = Next::Flag,
+            "#GIMMEH" => {
+                self.next = match self.content_frame() {
+                    Frame::Head => Next::HeadStyle,
+                    Frame::List => Next::ListStyle,
+                    _ => Next::Style,
+                }
+            }
+            "#OIC" => {
+                let frame = self.frame();
+                if matches!(frame, Frame::Head | Frame::Meta | Frame::Paragraf | Frame::List | Frame::IfDefined) {
+                    self.frames.pop();
+                }
+                if matches!(frame, Frame::Paragraf | Frame::List) {
+                    self.scopes.pop();
+                }
+            }
+            "#MKAY" => {
+                if matches!(
+                    self.frame(),
+                    Frame::Title | Frame::Styled | Frame::Item | Frame::Link | Frame::Media { .. } | Frame::Assignment { .. }
+                ) {
+                    self.frames.pop();
+                }
+            }
+            "#KTHXBYE" => self.next = Next::Done,
+            _ => {}
+        }
+    }
+
+    fn open_scope(&mut self, frame: Frame) {
+        self.frames.push(frame);
+        self.scopes.push(Vec::new());
+    }
+
+    // what may come next, before the typed word narrows it down
+    fn suggestions(&self) -> Vec<Completion> {
+        let v1_1 = self.version >= LanguageVersion::V1_1;
+        match self.next {
+            Next::Hai => hash_words(&["#HAI"]),
+            Next::Done | Next::NewName | Next::Flag => Vec::new(),
+            Next::SectionKind => {
+                let top_level = !self.frames.contains(&Frame::Paragraf);
+                let mut kinds = Vec::new();
+                if top_level {
+                    kinds.push("HEAD");
+                    if v1_1 {
+                        kinds.push("META");
+                    }
+                }
+                kinds.extend(["PARAGRAF", "LIST"]);
+                keywords(&kinds)
+            }
+            Next::Style => {
+                let styles: &[&str] = if v1_1 {
+                    &["BOLD", "ITALICS", "NEWLINE", "LINKZ", "SOUNDZ", "VIDZ", "PIKCHUR"]
+                } else {
+                    &["BOLD", "ITALICS", "NEWLINE", "SOUNDZ", "VIDZ"]
+                };
+                keywords(styles)
+            }
+            Next::HeadStyle => keywords(&["TITLE"]),
+            Next::ListStyle => keywords(&["ITEM"]),
+            Next::MetaAssign => hash_words(&["#IT IZ"]),
+            Next::ReferencedName => self.variables(),
+            Next::CloseReference => hash_words(&["#MKAY"]),
+            Next::Content => self.content(),
+        }
+    }
+
+    // what the innermost open construct takes
+    fn content(&self) -> Vec<Completion> {
+        let section_content = ["#MAEK", "#I HAZ", "#IT IZ", "#LEMME SEE", "#GIMMEH", "#IZ DEFINED"];
+        match self.frame() {
+            Frame::Program => {
+                let mut items = hash_words(&section_content);
+                items.extend(hash_words(&["#SHHH", "#KTHXBYE"]));
+                items
+            }
+            Frame::Paragraf => {
+                let mut items = hash_words(&section_content);
+                items.extend(hash_words(&["#SHHH", "#OIC"]));
+                items
+            }
+            Frame::IfDefined if self.content_frame() == Frame::List => hash_words(&["#GIMMEH", "#IZ DEFINED", "#OIC"]),
+            Frame::IfDefined => {
+                let mut items = hash_words(&section_content);
+                items.extend(hash_words(&["#OIC"]));
+                items
+            }
+            Frame::Head => hash_words(&["#GIMMEH", "#OIC"]),
+            Frame::Meta => hash_words(&["#I HAZ", "#OIC"]),
+            Frame::List => hash_words(&["#GIMMEH", "#IZ DEFINED", "#SHHH", "#OIC"]),
+            Frame::Title | Frame::Styled | Frame::Item => hash_words(&["#LEMME SEE", "#MKAY"]),
+            Frame::Link => hash_words(&["#MKAY"]),
+            Frame::Assignment { started: false } => {
+                let mut items = self.variables();
+                items.extend(hash_words(&["#MKAY"]));
+                items
+            }
+            Frame::Assignment { started: true } => hash_words(&["#MKAY"]),
+            Frame::Media { kind } => match self.media_stage(kind) {
+                MediaStage::Url => hash_words(&["#LEMME SEE", "#MKAY"]),
+                MediaStage::AfterUrl if self.version >= LanguageVersion::V1_1 => {
+                    let mut items = keywords(&["WIT"]);
+                    items.extend(hash_words(&["#LEMME SEE", "#MKAY"]));
+                    items
+                }
+                MediaStage::AfterUrl => hash_words(&["#LEMME SEE", "#MKAY"]),
+                MediaStage::Attribute => {
+                    let names: Vec<&str> = media_attributes(kind).iter().map(|(name, _)| *name).collect();
+                    keywords(&names)
+                }
+                MediaStage::Arguments("PRELOAD", _) => keywords(&["NONE", "METADATA", "AUTO"]),
+                MediaStage::Arguments(..) => Vec::new(),
+                MediaStage::AfterAttribute => {
+                    let mut items = keywords(&["AN"]);
+                    items.extend(hash_words(&["#MKAY"]));
+                    items
+                }
+                MediaStage::Alt => hash_words(&["#MKAY"]),
+            },
+        }
+    }
+
+    // variables of the open scopes, innermost first, then the built-in ones
+    // a document variable doesn't hide
+    fn variables(&self) -> Vec<Completion> {
+        let mut names: Vec<&str> = Vec::new();
+        for scope in self.scopes.iter().rev() {
+            for name in scope {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+        for builtin in builtins::NAMES {
+            if !names.contains(&builtin) {
+                names.push(builtin);
+            }
+        }
+        names.into_iter().map(|name| Completion { text: name.to_string(), kind: CompletionKind::Variable }).collect()
+    }
+}
+
+// the attributes one kind of media takes after WIT or AN, with the number of
+// words after each (a CAPTIONS label is optional and not counted)
+fn media_attributes(kind: &str) -> &'static [(&'static str, usize)] {
+    match kind {
+        "VIDZ" => &[("POSTER", 1), ("AUTOPLAY", 0), ("LOOP", 0), ("MUTED", 0), ("CAPTIONS", 2), ("LAZY", 0)],
+        "SOUNDZ" => &[("LOOP", 0), ("PRELOAD", 1), ("LAZY", 0)],
+        _ => &[("WIDTH", 1), ("HEIGHT", 1), ("SRCSET", 2), ("ALT", 0), ("LAZY", 0)],
+    }
+}
+
+fn hash_words(words: &[&str]) -> Vec<Completion> {
+    words.iter().map(|word| Completion { text: word.to_string(), kind: CompletionKind::HashWord }).collect()
+}
+
+fn keywords(words: &[&str]) -> Vec<Completion> {
+    words.iter().map(|word| Completion { text: word.to_string(), kind: CompletionKind::Keyword }).collect()
+}
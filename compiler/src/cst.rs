@@ -0,0 +1,306 @@
+//! Concrete syntax tree: the parse tree with every character of the source.
+//!
+//! `ASTNode` keeps what codegen needs and drops the rest; a formatter or a
+//! refactoring also needs the spacing, the comments and how each value was
+//! written. A parser made with `LolcodeParser::with_cst` builds this tree next
+//! to the AST, in two layers:
+//!
+//! - the green tree (`GreenNode`) holds the tokens and trivia from the lossless
+//!   lexer, grouped into a node for every construct. It knows no positions, so
+//!   the same subtree can be shared
+//! - the red tree (`SyntaxNode`) is a view of it with offsets and parents, made
+//!   as it is walked
+//!
+//! Nodes are named after the `ASTNode` the parser made from them; text and
+//! newlines stay single tokens. Writing out a tree gives the source back byte
+//! for byte, and parsing its `tokens` gives the AST again:
+//!
+//! ```text
+//! let root = stages::cst(source)?;
+//! let (tree, interner) = stages::parse(root.tokens(), &arena)?;
+//! ```
+
+use crate::lexer::BOM;
+use crate::parser::ASTNode;
+use crate::token::{Token, TokenKind, Trivia};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// What a node of the concrete tree is, after the `ASTNode` it gives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Program,
+    HeadSection,
+    MetaSection,
+    ParagrafSection,
+    ListSection,
+    VariableDeclaration,
+    VariableAssignment,
+    VariableReference,
+    Title,
+    Bold,
+    Italics,
+    Item,
+    Sound,
+    Video,
+    Image,
+    Link,
+    IfDefined,
+}
+
+impl NodeKind {
+    /// The kind of node for `node`, `None` for text and newlines, which are a
+    /// single token.
+    pub fn of(node: &ASTNode) -> Option<Self> {
+        Some(match node {
+            ASTNode::Program { .. } => Self::Program,
+            ASTNode::HeadSection { .. } => Self::HeadSection,
+            ASTNode::MetaSection { .. } => Self::MetaSection,
+            ASTNode::ParagrafSection { .. } => Self::ParagrafSection,
+            ASTNode::ListSection { .. } => Self::ListSection,
+            ASTNode::VariableDeclaration { .. } => Self::VariableDeclaration,
+            ASTNode::VariableAssignment { .. } => Self::VariableAssignment,
+            ASTNode::VariableReference { .. } => Self::VariableReference,
+            ASTNode::Title { .. } => Self::Title,
+            ASTNode::Bold { .. } => Self::Bold,
+            ASTNode::Italics { .. } => Self::Italics,
+            ASTNode::Item { .. } => Self::Item,
+            ASTNode::Sound { .. } => Self::Sound,
+            ASTNode::Video { .. } => Self::Video,
+            ASTNode::Image { .. } => Self::Image,
+            ASTNode::Link { .. } => Self::Link,
+            ASTNode::IfDefined { .. } => Self::IfDefined,
+            ASTNode::Text { .. } | ASTNode::Newline => return None,
+        })
+    }
+}
+
+/// A node of the green tree: its kind and everything in it, in source order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenNode<'a> {
+    pub kind: NodeKind,
+    pub children: Vec<GreenElement<'a>>,
+    // bytes of source below the node
+    len: usize,
+}
+
+/// One child of a green node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreenElement<'a> {
+    Node(Arc<GreenNode<'a>>),
+    /// A token the parser read, with the source it was read from
+    Token { kind: TokenKind, text: &'a str },
+    /// Whitespace or a comment before the token after it
+    Trivia(Trivia<'a>),
+}
+
+impl<'a> GreenNode<'a> {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement<'a>>) -> Self {
+        let len = children.iter().map(GreenElement::len).sum();
+        Self { kind, children, len }
+    }
+
+    /// Bytes of source below the node.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl GreenElement<'_> {
+    /// Bytes of source the element covers.
+    pub fn len(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.len(),
+            GreenElement::Token { text, .. } => text.len(),
+            GreenElement::Trivia(trivia) => trivia.text().len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Display for GreenNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.children.iter().try_for_each(|child| write!(f, "{}", child))
+    }
+}
+
+impl fmt::Display for GreenElement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GreenElement::Node(node) => write!(f, "{}", node),
+            GreenElement::Token { text, .. } => f.write_str(text),
+            GreenElement::Trivia(trivia) => f.write_str(trivia.text()),
+        }
+    }
+}
+
+/// A node of the red tree: a green node at its place in the document.
+/// Cloning one is cheap.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode<'a>(Arc<RedNode<'a>>);
+
+#[derive(Debug)]
+struct RedNode<'a> {
+    green: Arc<GreenNode<'a>>,
+    // byte offset in the source
+    offset: usize,
+    parent: Option<SyntaxNode<'a>>,
+}
+
+impl<'a> SyntaxNode<'a> {
+    /// The root of the tree for `green`, which starts the source.
+    pub fn new_root(green: Arc<GreenNode<'a>>) -> Self {
+        Self(Arc::new(RedNode { green, offset: 0, parent: None }))
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Arc<GreenNode<'a>> {
+        &self.0.green
+    }
+
+    /// Bytes of the source the node covers.
+    pub fn range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.len()
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode<'a>> {
+        self.0.parent.as_ref()
+    }
+
+    /// The parent, its parent and so on up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode<'a>> {
+        std::iter::successors(self.parent().cloned(), |node| node.parent().cloned())
+    }
+
+    /// The nodes right below this one, in source order.
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode<'a>> + '_ {
+        let mut offset = self.0.offset;
+        self.0.green.children.iter().filter_map(move |child| {
+            let start = offset;
+            offset += child.len();
+            match child {
+                GreenElement::Node(green) => Some(SyntaxNode(Arc::new(RedNode {
+                    green: Arc::clone(green),
+                    offset: start,
+                    parent: Some(self.clone()),
+                }))),
+                _ => None,
+            }
+        })
+    }
+
+    /// This node and every node below it, depth first.
+    pub fn descendants(&self) -> Vec<SyntaxNode<'a>> {
+        let mut nodes = vec![self.clone()];
+        for child in self.children() {
+            nodes.extend(child.descendants());
+        }
+        nodes
+    }
+
+    /// The tokens of the node, without trivia, at their lines and columns in
+    /// the document; for the root they end with `Eof`.
+    pub fn tokens(&self) -> Vec<Token> {
+        let root = self.ancestors().last().unwrap_or_else(|| self.clone());
+        let range = self.range();
+        let mut walk = Walk { offset: 0, line: 1, col: 1, tokens: Vec::new() };
+        walk.node(&root.0.green, &range);
+        walk.tokens
+    }
+}
+
+impl fmt::Display for SyntaxNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.green)
+    }
+}
+
+// the tokens in `range`, counting lines and columns from the start the way
+// the lexer does
+struct Walk {
+    offset: usize,
+    line: usize,
+    col: usize,
+    tokens: Vec<Token>,
+}
+
+impl Walk {
+    fn node(&mut self, green: &GreenNode, range: &Range<usize>) {
+        for child in &green.children {
+            match child {
+                GreenElement::Node(node) => self.node(node, range),
+                GreenElement::Token { kind, text } => {
+                    // `Eof` takes no room, right at the end
+                    if range.contains(&self.offset) || (text.is_empty() && self.offset == range.end) {
+                        self.tokens.push(Token { kind: kind.clone(), line: self.line, col: self.col });
+                    }
+                    self.advance(text);
+                }
+                GreenElement::Trivia(trivia) => self.advance(trivia.text()),
+            }
+        }
+    }
+
+    fn advance(&mut self, text: &str) {
+        // a byte order mark at the very start takes no column
+        let counted = if self.offset == 0 { text.strip_prefix(BOM).unwrap_or(text) } else { text };
+        self.offset += text.len();
+        for ch in counted.chars() {
+            if ch == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+        }
+    }
+}
+
+/// Collects the tokens and trivia the parser reads and groups them into nodes
+/// once it knows what they made.
+#[derive(Debug, Default)]
+pub(crate) struct Builder<'a> {
+    // the elements not yet below a finished node
+    children: Vec<GreenElement<'a>>,
+}
+
+impl<'a> Builder<'a> {
+    /// Where a node starting at the next token begins.
+    pub(crate) fn checkpoint(&self) -> usize {
+        self.children.len()
+    }
+
+    /// Adds a token with the trivia before it.
+    pub(crate) fn token(&mut self, leading: Vec<Trivia<'a>>, kind: TokenKind, text: &'a str) {
+        self.children.extend(leading.into_iter().map(GreenElement::Trivia));
+        self.children.push(GreenElement::Token { kind, text });
+    }
+
+    /// Puts everything since `checkpoint` below a node of `kind`. A node that
+    /// is already all of it isn't wrapped again.
+    pub(crate) fn wrap(&mut self, checkpoint: usize, kind: NodeKind) {
+        if let [GreenElement::Node(node)] = &self.children[checkpoint..]
+            && node.kind == kind
+        {
+            return;
+        }
+        let children = self.children.split_off(checkpoint);
+        self.children.push(GreenElement::Node(Arc::new(GreenNode::new(kind, children))));
+    }
+
+    /// The whole tree, once the last token has been added.
+    pub(crate) fn finish(self) -> SyntaxNode<'a> {
+        SyntaxNode::new_root(Arc::new(GreenNode::new(NodeKind::Program, self.children)))
+    }
+}
@@ -0,0 +1,274 @@
+//! Diagnostics shared by the compiler stages.
+//!
+//! An error in the source stops compilation at the stage that found it and comes
+//! back as a `CompileError`. Passes that only want to point something out (dead
+//! links, lint findings) collect `Diagnostic` values instead. Either way the caller
+//! decides how to report them.
+//!
+//! Every diagnostic has a code, shown after its message, that `#SHHH <code>`
+//! silences in a document. Before the first construct of the document the pragma
+//! covers all of it, before the first construct of a section the whole section,
+//! anywhere else the construct after it. Diagnostics that look at the source
+//! rather than the tree (invisible characters) or at variables (names, unused
+//! values) can only be silenced for the whole document.
+
+use crate::parser::ASTNode;
+use std::fmt;
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Codes of every diagnostic, the ones `#SHHH` accepts.
+pub const CODES: [&str; 12] = [
+    "missing-alt",
+    "missing-captions",
+    "invisible-character",
+    "dead-link",
+    "unchecked-link",
+    "case-collision",
+    "missing-title",
+    "duplicate-title",
+    "late-head",
+    "empty-section",
+    "empty-styled-text",
+    "unused-value",
+];
+
+/// Codes of the errors that stop compilation when they are given as
+/// diagnostics (see `CompileError::to_diagnostics`). `#SHHH` can't silence them.
+pub const ERROR_CODES: [&str; 4] = ["lexical-error", "syntax-error", "semantic-error", "io-error"];
+
+/// Exit status of the command line compiler when a lint set to `error` fails,
+/// or for a failure outside the stages below (starting worker threads).
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit status for a bad command line: unknown options, bad values, inputs
+/// that aren't `.lol` files or directories.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit status when a source can't be read or an output can't be written.
+pub const EXIT_IO: i32 = 3;
+/// Exit status for a `CompileError::Lexical`.
+pub const EXIT_LEXICAL: i32 = 4;
+/// Exit status for a `CompileError::Syntax`.
+pub const EXIT_SYNTAX: i32 = 5;
+/// Exit status for a `CompileError::Semantic`.
+pub const EXIT_SEMANTIC: i32 = 6;
+
+/// A single message produced by one of the compiler passes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Which check found it, one of `CODES`, or of `ERROR_CODES` for an
+    /// error that stopped compilation
+    pub code: &'static str,
+    pub message: String,
+    /// Where in the source it is, as (line, column), when the pass knows
+    pub span: Option<(usize, usize)>,
+    /// Other places in the source that explain it
+    pub labels: Vec<Label>,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic without a position.
+    pub fn new(severity: Severity, code: &'static str, message: String) -> Self {
+        Self {
+            severity,
+            code,
+            message,
+            span: None,
+            labels: Vec::new(),
+        }
+    }
+
+    /// Creates a warning diagnostic.
+    pub fn warning(code: &'static str, message: String) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    /// Creates an error diagnostic.
+    pub fn error(code: &'static str, message: String) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    /// The message with the position in front and a line for each label,
+    /// the way `CompileError::Syntax` shows them.
+    pub fn located_message(&self) -> String {
+        let mut message = match self.span {
+            Some((line, col)) => format!("line {}, col {}: {}", line, col, self.message),
+            None => self.message.clone(),
+        };
+        for label in &self.labels {
+            message.push_str(&format!("\n  line {}, col {}: {}", label.line, label.col, label.message));
+        }
+        message
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "Warning: ")?,
+            Severity::Error => write!(f, "Error: ")?,
+        }
+        if let Some((line, col)) = self.span {
+            write!(f, "line {}, col {}: ", line, col)?;
+        }
+        write!(f, "{} [{}]", self.message, self.code)?;
+        // the labels go under the line with the code
+        for label in &self.labels {
+            write!(f, "\n  line {}, col {}: {}", label.line, label.col, label.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Diagnostic codes silenced with `#SHHH`, collected by the parser.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Suppressions<'t> {
+    /// Codes silenced in the whole document
+    pub document: Vec<&'static str>,
+    /// Codes silenced inside one node of the tree and everything under it
+    pub nodes: Vec<(&'t ASTNode<'t>, &'static str)>,
+}
+
+impl<'t> Suppressions<'t> {
+    /// Drops the diagnostics whose code is silenced in the whole document.
+    pub fn filter(&self, diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.retain(|d| !self.document.contains(&d.code));
+    }
+
+    /// The codes silenced inside `node`: the ones in `active`, which are silenced
+    /// around it, and the ones covering `node` itself. Passes that walk the tree
+    /// call this on the way down.
+    pub fn enter(&self, node: &ASTNode, active: &[&'static str]) -> Vec<&'static str> {
+        let mut codes = active.to_vec();
+        for (covered, code) in &self.nodes {
+            if std::ptr::eq(*covered, node) {
+                codes.push(code);
+            }
+        }
+        codes
+    }
+}
+
+/// A second place in the source an error points at, such as the `#GIMMEH` a
+/// missing `#MKAY` should have closed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+/// An error that stopped compilation, by the stage that found it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    /// The lexer found something that isn't a token
+    Lexical { line: usize, col: usize, message: String },
+    /// The tokens don't follow the grammar; `labels` point at other places
+    /// that explain it
+    Syntax { line: usize, col: usize, message: String, labels: Vec<Label> },
+    /// Every problem the semantic analyzer found in the tree
+    Semantic { errors: Vec<String> },
+    /// A source couldn't be read or an output couldn't be written, for the
+    /// calls that compile files
+    Io { message: String },
+}
+
+impl CompileError {
+    /// Prints the error to stderr the way the compiler always has.
+    pub fn report(&self) {
+        match self {
+            CompileError::Semantic { errors } => {
+                eprintln!("=== Semantic Analysis Errors ===");
+                for error in errors {
+                    eprintln!("Semantic error: {}", error);
+                }
+                eprintln!("================================");
+            }
+            _ => eprintln!("{}", self),
+        }
+    }
+
+    /// The error as diagnostics, one for each problem it holds, for callers
+    /// that report everything the same way. Messages start with the position
+    /// the way warnings do.
+    pub fn to_diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            CompileError::Lexical { line, col, message } => {
+                vec![Diagnostic::error("lexical-error", format!("line {}, col {}: {}", line, col, message))]
+            }
+            CompileError::Syntax { line, col, message, labels } => {
+                let mut message = format!("line {}, col {}: {}", line, col, message);
+                for label in labels {
+                    message.push_str(&format!("\n  line {}, col {}: {}", label.line, label.col, label.message));
+                }
+                vec![Diagnostic::error("syntax-error", message)]
+            }
+            CompileError::Semantic { errors } => errors
+                .iter()
+                .map(|error| Diagnostic::error("semantic-error", error.clone()))
+                .collect(),
+            CompileError::Io { message } => vec![Diagnostic::error("io-error", message.clone())],
+        }
+    }
+
+    /// The exit status the command line compiler stops with for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CompileError::Lexical { .. } => EXIT_LEXICAL,
+            CompileError::Syntax { .. } => EXIT_SYNTAX,
+            CompileError::Semantic { .. } => EXIT_SEMANTIC,
+            CompileError::Io { .. } => EXIT_IO,
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Lexical { line, col, message } => {
+                write!(f, "Lexical error at line {}, col {}: {}", line, col, message)
+            }
+            CompileError::Syntax { line, col, message, labels } => {
+                write!(f, "Syntax error at line {}, col {}: {}", line, col, message)?;
+                for label in labels {
+                    write!(f, "\n  line {}, col {}: {}", label.line, label.col, label.message)?;
+                }
+                Ok(())
+            }
+            CompileError::Semantic { errors } => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "Semantic error: {}", error)?;
+                }
+                Ok(())
+            }
+            CompileError::Io { message } => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Prints every diagnostic to stderr.
+pub fn report(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        #[cfg(feature = "tracing")]
+        match diagnostic.severity {
+            Severity::Warning => tracing::warn!("{}", diagnostic.located_message()),
+            Severity::Error => tracing::error!("{}", diagnostic.located_message()),
+        }
+        eprintln!("{}", diagnostic);
+    }
+}
+
+/// Returns true if any of the diagnostics is an error.
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
@@ -0,0 +1,252 @@
+//! Structural differences between two revisions of a document.
+//!
+//! A text diff shows a rewrapped paragraf or a moved line break as a change.
+//! `diff` compares the parse trees instead and lists the nodes that were
+//! inserted, removed or changed, each with where it is in its tree and, when
+//! the parser's positions are at hand, in its source.
+//!
+//! Children are matched up the way a line diff matches lines: the longest run
+//! of equal nodes stays, and in between a node is paired with one of the same
+//! kind, which is compared field by field and child by child. Whatever is left
+//! over was removed or inserted as a whole.
+
+use crate::intern::Interner;
+use crate::parser::{ASTNode, MediaAttributes, Positions};
+use std::collections::HashMap;
+
+/// Matching two lists of children takes time and memory in the product of
+/// their lengths; past this, once equal ends are taken off, what is left of the
+/// old list counts as removed and of the new one as inserted.
+const MATCH_LIMIT: usize = 1 << 22;
+
+/// One revision of a document: its tree, the interner holding its variable
+/// names and, for spans, the positions the parser recorded.
+#[derive(Debug, Clone, Copy)]
+pub struct Revision<'t> {
+    pub tree: &'t ASTNode<'t>,
+    pub interner: &'t Interner,
+    pub positions: Option<&'t Positions>,
+}
+
+impl<'t> Revision<'t> {
+    pub fn new(tree: &'t ASTNode<'t>, interner: &'t Interner) -> Self {
+        Self { tree, interner, positions: None }
+    }
+}
+
+/// What happened to a node between the revisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Only in the new tree
+    Inserted,
+    /// Only in the old tree
+    Removed,
+    /// In both, with different fields (text, URL, name, ...); changes to its
+    /// children are listed on their own
+    Changed,
+}
+
+/// One difference between the revisions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<'t> {
+    pub kind: ChangeKind,
+    /// The node in the old tree, `None` if it was inserted
+    pub old: Option<&'t ASTNode<'t>>,
+    /// The node in the new tree, `None` if it was removed
+    pub new: Option<&'t ASTNode<'t>>,
+    /// Child indexes from the old root down to `old`
+    pub old_path: Option<Vec<usize>>,
+    /// Child indexes from the new root down to `new`
+    pub new_path: Option<Vec<usize>>,
+    /// Where `old` starts in the old source as (line, column), or the section
+    /// or element around it when it has no position of its own
+    pub old_span: Option<(usize, usize)>,
+    /// Where `new` starts in the new source, the same way
+    pub new_span: Option<(usize, usize)>,
+}
+
+/// The differences from `old` to `new`, in document order.
+pub fn diff<'t>(old: Revision<'t>, new: Revision<'t>) -> Vec<Change<'t>> {
+    let old = Side::new(old);
+    let new = Side::new(new);
+    let mut differ = Differ { old: &old, new: &new, changes: Vec::new() };
+    differ.node(old.root(), new.root());
+    differ.changes
+}
+
+// a revision with where its nodes start
+struct Side<'t> {
+    revision: Revision<'t>,
+    starts: HashMap<usize, (usize, usize)>,
+}
+
+impl<'t> Side<'t> {
+    fn new(revision: Revision<'t>) -> Self {
+        let starts = revision.positions.map(|positions| positions.element_starts(revision.tree)).unwrap_or_default();
+        Self { revision, starts }
+    }
+
+    fn root(&self) -> At<'t> {
+        self.at(self.revision.tree, Vec::new(), None)
+    }
+
+    // `node`, which is below something that starts at `around`
+    fn at(&self, node: &'t ASTNode<'t>, path: Vec<usize>, around: Option<(usize, usize)>) -> At<'t> {
+        let span = self.starts.get(&(node as *const ASTNode as usize)).copied().or(around);
+        At { node, path, span }
+    }
+
+    // child `index` of `parent`
+    fn child(&self, parent: &At<'t>, index: usize) -> At<'t> {
+        let mut path = parent.path.clone();
+        path.push(index);
+        self.at(&parent.node.children()[index], path, parent.span)
+    }
+}
+
+// a node and where it is
+struct At<'t> {
+    node: &'t ASTNode<'t>,
+    path: Vec<usize>,
+    span: Option<(usize, usize)>,
+}
+
+struct Differ<'s, 't> {
+    old: &'s Side<'t>,
+    new: &'s Side<'t>,
+    changes: Vec<Change<'t>>,
+}
+
+impl<'t> Differ<'_, 't> {
+    fn push(&mut self, kind: ChangeKind, old: Option<&At<'t>>, new: Option<&At<'t>>) {
+        self.changes.push(Change {
+            kind,
+            old: old.map(|at| at.node),
+            new: new.map(|at| at.node),
+            old_path: old.map(|at| at.path.clone()),
+            new_path: new.map(|at| at.path.clone()),
+            old_span: old.and_then(|at| at.span),
+            new_span: new.and_then(|at| at.span),
+        });
+    }
+
+    // two nodes of the same kind
+    fn node(&mut self, old: At<'t>, new: At<'t>) {
+        if !self.same_fields(old.node, new.node) {
+            self.push(ChangeKind::Changed, Some(&old), Some(&new));
+        }
+        self.children(&old, &new);
+    }
+
+    fn children(&mut self, old: &At<'t>, new: &At<'t>) {
+        let (a, b) = (old.node.children(), new.node.children());
+        let prefix = a.iter().zip(b).take_while(|(x, y)| self.same(x, y)).count();
+        let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| self.same(x, y)).count();
+        let (a_end, b_end) = (a.len() - suffix, b.len() - suffix);
+
+        // the equal nodes in between, with the gaps around them compared
+        let equal = longest_match(a_end - prefix, b_end - prefix, |i, j| self.same(&a[prefix + i], &b[prefix + j]));
+        let (mut i, mut j) = (prefix, prefix);
+        for (x, y) in equal.into_iter().map(|(x, y)| (prefix + x, prefix + y)).chain([(a_end, b_end)]) {
+            self.gap(old, i..x, new, j..y);
+            (i, j) = (x + 1, y + 1);
+        }
+    }
+
+    // children that didn't match anything equal: those of a kind are paired up
+    // and compared, the rest were removed or inserted
+    fn gap(&mut self, old: &At<'t>, a: std::ops::Range<usize>, new: &At<'t>, b: std::ops::Range<usize>) {
+        let (old_children, new_children) = (old.node.children(), new.node.children());
+        let paired = longest_match(a.len(), b.len(), |i, j| {
+            old_children[a.start + i].kind_name() == new_children[b.start + j].kind_name()
+        });
+        let (mut i, mut j) = (a.start, b.start);
+        for (x, y) in paired.into_iter().map(|(x, y)| (a.start + x, b.start + y)).chain([(a.end, b.end)]) {
+            for removed in i..x {
+                self.push(ChangeKind::Removed, Some(&self.old.child(old, removed)), None);
+            }
+            for inserted in j..y {
+                self.push(ChangeKind::Inserted, None, Some(&self.new.child(new, inserted)));
+            }
+            if x < a.end {
+                self.node(self.old.child(old, x), self.new.child(new, y));
+            }
+            (i, j) = (x + 1, y + 1);
+        }
+    }
+
+    // equal nodes, children and all
+    fn same(&self, a: &ASTNode, b: &ASTNode) -> bool {
+        let (x, y) = (a.children(), b.children());
+        self.same_fields(a, b) && x.len() == y.len() && x.iter().zip(y).all(|(x, y)| self.same(x, y))
+    }
+
+    // the same kind of node with equal fields, children aside; variable names
+    // are compared by name as each revision has its own interner
+    fn same_fields(&self, a: &ASTNode, b: &ASTNode) -> bool {
+        let (old, new) = (self.old.revision.interner, self.new.revision.interner);
+        match (a, b) {
+            (ASTNode::MetaSection { entries: x }, ASTNode::MetaSection { entries: y }) => x == y,
+            (ASTNode::VariableDeclaration { name: x }, ASTNode::VariableDeclaration { name: y })
+            | (ASTNode::VariableReference { name: x }, ASTNode::VariableReference { name: y }) => {
+                old.resolve(*x) == new.resolve(*y)
+            }
+            (ASTNode::VariableAssignment { name: x, value: v }, ASTNode::VariableAssignment { name: y, value: w }) => {
+                v == w && x.map(|x| old.resolve(x)) == y.map(|y| new.resolve(y))
+            }
+            (ASTNode::Text { content: x }, ASTNode::Text { content: y }) => x == y,
+            (ASTNode::Sound { url: u, attributes: x }, ASTNode::Sound { url: v, attributes: y })
+            | (ASTNode::Video { url: u, attributes: x }, ASTNode::Video { url: v, attributes: y })
+            | (ASTNode::Image { url: u, attributes: x }, ASTNode::Image { url: v, attributes: y }) => {
+                u == v && self.same_media(x, y)
+            }
+            (ASTNode::Link { target: t, text: x }, ASTNode::Link { target: u, text: y }) => t == u && x == y,
+            (ASTNode::IfDefined { flag: x, .. }, ASTNode::IfDefined { flag: y, .. }) => x == y,
+            _ => a.kind_name() == b.kind_name(),
+        }
+    }
+
+    fn same_media(&self, x: &MediaAttributes, y: &MediaAttributes) -> bool {
+        let (old, new) = (self.old.revision.interner, self.new.revision.interner);
+        let references_match = x.url_references.len() == y.url_references.len()
+            && x.url_references.iter().zip(&y.url_references).all(|((at, a), (bt, b))| {
+                at == bt && old.resolve(*a) == new.resolve(*b)
+            });
+        let without_references = |m: &MediaAttributes| MediaAttributes { url_references: Vec::new(), ..m.clone() };
+        references_match && without_references(x) == without_references(y)
+    }
+}
+
+// the longest common subsequence of two lists, by `equal` on their indexes, as
+// pairs of indexes in order; none when the lists are too long to match
+fn longest_match(n: usize, m: usize, equal: impl Fn(usize, usize) -> bool) -> Vec<(usize, usize)> {
+    if n == 0 || m == 0 || (n + 1).saturating_mul(m + 1) > MATCH_LIMIT {
+        return Vec::new();
+    }
+    // lengths[i][j]: longest match of the lists from i and j on
+    let width = m + 1;
+    let mut lengths = vec![0u32; (n + 1) * width];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i * width + j] = if equal(i, j) {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if equal(i, j) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
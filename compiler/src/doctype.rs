@@ -0,0 +1,56 @@
+//! HTML versions a page can be written for.
+//!
+//! Pages are HTML5 by default. `--doctype html4` and `--doctype xhtml1` write
+//! them for the Strict DTDs of HTML 4.01 and XHTML 1.0 instead, for systems
+//! that still validate against those, and codegen only uses elements the DTD
+//! has: sections become `<div class="section">`, sound, video and hosted
+//! players an `<object>` with a link to the media inside, the charset is
+//! given with `http-equiv`, images always get an `alt` and `srcset`, lazy
+//! loading and caption tracks are left out. XHTML 1.0 is also written in XML
+//! syntax, like `--xhtml`.
+//!
+//! Slides need HTML5 for reveal.js, so they always get it.
+
+/// The version of HTML a page is written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Doctype {
+    #[default]
+    Html5,
+    /// HTML 4.01 Strict
+    Html401,
+    /// XHTML 1.0 Strict
+    Xhtml10,
+}
+
+impl Doctype {
+    /// Parses a doctype name given on the command line.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "html5" | "html" => Some(Doctype::Html5),
+            "html4" | "html401" | "html4.01" => Some(Doctype::Html401),
+            "xhtml1" | "xhtml10" | "xhtml1.0" => Some(Doctype::Xhtml10),
+            _ => None,
+        }
+    }
+
+    /// The `<!DOCTYPE>` line a page starts with.
+    pub fn declaration(&self) -> &'static str {
+        match self {
+            Doctype::Html5 => "<!DOCTYPE html>",
+            Doctype::Html401 => {
+                "<!DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\" \"http://www.w3.org/TR/html4/strict.dtd\">"
+            }
+            Doctype::Xhtml10 => {
+                "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">"
+            }
+        }
+    }
+
+    /// True for the versions before HTML5, which have none of its elements.
+    pub fn is_legacy(&self) -> bool {
+        !matches!(self, Doctype::Html5)
+    }
+
+    /// Names accepted by `parse`, for usage messages.
+    pub const NAMES: &'static str = "html5, html4, xhtml1";
+}
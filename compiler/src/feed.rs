@@ -0,0 +1,70 @@
+//! Atom feed generation for multi-page projects.
+//!
+//! Every page whose META block has a `date` becomes an entry in `feed.xml`
+//! at the project root, newest first, so a blog written as a folder of `.lol`
+//! files can be subscribed to.
+
+use crate::meta::DocumentMeta;
+use crate::xml::escape_xml;
+
+/// Name of the feed file at the project root.
+pub const FEED_FILE: &str = "feed.xml";
+
+/// One feed entry, built from a page's metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedEntry {
+    pub title: String,
+    /// Publication date as `YYYY-MM-DD`
+    pub date: String,
+    pub description: Option<String>,
+    /// Link to the generated page, relative to the feed
+    pub href: String,
+}
+
+impl FeedEntry {
+    /// Builds an entry for a page, if its metadata has a date.
+    pub fn from_meta(meta: &DocumentMeta, href: String) -> Option<Self> {
+        let date = meta.get("date")?.to_string();
+        Some(Self {
+            title: meta.title().unwrap_or(&href).to_string(),
+            date,
+            description: meta.get("description").map(str::to_string),
+            href,
+        })
+    }
+}
+
+/// Renders an Atom feed with the entries sorted newest first.
+pub fn atom_feed(title: &str, entries: &[FeedEntry]) -> String {
+    let mut entries = entries.to_vec();
+    entries.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.href.cmp(&b.href)));
+
+    // the feed was last updated when its newest entry was
+    let updated = entries
+        .first()
+        .map(|e| e.date.clone())
+        .unwrap_or_else(|| "1970-01-01".to_string());
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("<title>{}</title>\n", escape_xml(title)));
+    xml.push_str(&format!("<id>urn:lolcode:{}</id>\n", escape_xml(title)));
+    xml.push_str(&format!("<updated>{}T00:00:00Z</updated>\n", updated));
+    xml.push_str(&format!("<link href=\"{}\" rel=\"self\"/>\n", FEED_FILE));
+
+    for entry in &entries {
+        xml.push_str("<entry>\n");
+        xml.push_str(&format!("<title>{}</title>\n", escape_xml(&entry.title)));
+        xml.push_str(&format!("<link href=\"{}\"/>\n", escape_xml(&entry.href)));
+        xml.push_str(&format!("<id>urn:lolcode:{}</id>\n", escape_xml(&entry.href)));
+        xml.push_str(&format!("<updated>{}T00:00:00Z</updated>\n", entry.date));
+        if let Some(description) = &entry.description {
+            xml.push_str(&format!("<summary>{}</summary>\n", escape_xml(description)));
+        }
+        xml.push_str("</entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
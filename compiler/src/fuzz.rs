@@ -0,0 +1,72 @@
+//! Entry point for fuzzing the compiler.
+//!
+//! `fuzz_compile` runs every stage on arbitrary bytes in memory and always returns:
+//! errors in the source come back as a `CompileError`, and nothing is printed,
+//! written, opened or exited. A cargo-fuzz target only has to call it:
+//!
+//! ```text
+//! fuzz_target!(|data: &[u8]| {
+//!     let _ = lolcompiler::fuzz::fuzz_compile(data);
+//! });
+//! ```
+//!
+//! Any panic, abort or stack overflow it runs into is a bug in the compiler.
+
+use crate::arena::AstArena;
+use crate::binast;
+use crate::diagnostics::CompileError;
+use crate::lint::{self, LintOptions};
+use crate::meta;
+use crate::parser::{LolcodeParser, Parser};
+use crate::semantic::LolcodeSemanticAnalyzer;
+use crate::target::Target;
+
+/// Every target with its own code generation (PDF prints the HTML output).
+const TARGETS: [Target; 7] = [
+    Target::Html,
+    Target::Slides,
+    Target::Gemtext,
+    Target::Ansi,
+    Target::Man,
+    Target::AsciiDoc,
+    Target::Xml,
+];
+
+/// Compiles `data` for every target and returns the HTML output.
+///
+/// Invalid UTF-8 is replaced rather than rejected so the fuzzer keeps reaching
+/// the lexer. The tree also goes through the lints, metadata extraction and a
+/// round trip through the binary AST format.
+pub fn fuzz_compile(data: &[u8]) -> Result<String, CompileError> {
+    let source = String::from_utf8_lossy(data);
+    let arena = AstArena::new();
+
+    let mut parser = LolcodeParser::new(&source, &arena);
+    parser.parse()?;
+    // a successful parse always leaves a tree
+    let Some(tree) = parser.parse_tree else {
+        return Ok(String::new());
+    };
+
+    lint::lint_source(&source, &LintOptions::default());
+    lint::lint(tree, &LintOptions::default(), &parser.suppressions);
+    meta::document_meta(tree);
+    let encoded = binast::encode(tree, &parser.interner);
+    let _ = binast::decode(&encoded, &arena, parser.max_nesting);
+
+    let mut analyzer = LolcodeSemanticAnalyzer::new();
+    analyzer.options.open_browser = false;
+    analyzer.interner = std::mem::take(&mut parser.interner);
+    analyzer.positions = std::mem::take(&mut parser.positions);
+    analyzer.check_tree(tree)?;
+
+    let mut html = String::new();
+    for target in TARGETS {
+        analyzer.options.target = target;
+        let output = analyzer.generate_output(tree);
+        if target == Target::Html {
+            html = output;
+        }
+    }
+    Ok(html)
+}
@@ -0,0 +1,124 @@
+//! Gemtext output.
+//!
+//! `--target gemtext` renders the document as `text/gemini` for publishing on
+//! Gemini capsules. Gemtext is line based: titles become `#` headings, list
+//! items become `*` lines, and links and media each get a `=>` line of their
+//! own. There is no inline styling, so BOLD and ITALICS keep only their text.
+//!
+//! The renderer works on a tree whose variables were already substituted by
+//! `LolcodeSemanticAnalyzer::resolve_tree`.
+
+use crate::meta;
+use crate::parser::ASTNode;
+
+/// Renders a resolved tree as gemtext.
+pub fn render(tree: &ASTNode) -> String {
+    let mut renderer = GemtextRenderer::default();
+    renderer.node(tree);
+    renderer.flush_line();
+
+    // no more than one blank line in a row, and none at the end
+    let mut out = String::with_capacity(renderer.out.len());
+    for line in renderer.out.trim_end().lines() {
+        if line.is_empty() && (out.is_empty() || out.ends_with("\n\n")) {
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+#[derive(Default)]
+struct GemtextRenderer {
+    out: String,
+    // inline text waiting for the end of its line
+    line: String,
+}
+
+impl GemtextRenderer {
+    fn flush_line(&mut self) {
+        let line = self.line.trim();
+        if !line.is_empty() {
+            self.out.push_str(line);
+            self.out.push('\n');
+        }
+        self.line.clear();
+    }
+
+    fn push_word(&mut self, text: &str) {
+        if !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        self.line.push_str(text);
+    }
+
+    // links have to be on a line by themselves
+    fn link_line(&mut self, href: &str, text: &str) {
+        self.flush_line();
+        if text.is_empty() || text == href {
+            self.out.push_str(&format!("=> {}\n", href));
+        } else {
+            self.out.push_str(&format!("=> {} {}\n", href, text));
+        }
+    }
+
+    fn node(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Program { children } | ASTNode::HeadSection { children } => {
+                for child in children.iter() {
+                    self.node(child);
+                }
+            }
+            ASTNode::ParagrafSection { children } => {
+                self.flush_line();
+                self.out.push('\n');
+                for child in children.iter() {
+                    self.node(child);
+                }
+                self.flush_line();
+                self.out.push('\n');
+            }
+            ASTNode::ListSection { children } => {
+                self.flush_line();
+                self.out.push('\n');
+                for child in children.iter() {
+                    self.node(child);
+                }
+                self.out.push('\n');
+            }
+            ASTNode::Item { content } => {
+                self.flush_line();
+                for child in content.iter() {
+                    self.node(child);
+                }
+                let item = self.line.trim().to_string();
+                self.line.clear();
+                self.out.push_str(&format!("* {}\n", item));
+            }
+            ASTNode::Title { content } => {
+                self.flush_line();
+                self.out.push_str(&format!("# {}\n\n", meta::title_text(content)));
+            }
+            ASTNode::Text { content } => self.push_word(content),
+            ASTNode::Bold { content } | ASTNode::Italics { content } => {
+                for child in content.iter() {
+                    self.node(child);
+                }
+            }
+            ASTNode::Newline => self.flush_line(),
+            ASTNode::Link { target, text } => self.link_line(target, text),
+            ASTNode::Image { url, attributes } => {
+                self.link_line(url, attributes.alt.as_deref().unwrap_or("Image"))
+            }
+            ASTNode::Video { url, .. } => self.link_line(url, "Video"),
+            ASTNode::Sound { url, .. } => self.link_line(url, "Audio"),
+            // variables and #IZ DEFINED are gone after resolving and metadata isn't shown
+            ASTNode::MetaSection { .. }
+            | ASTNode::VariableDeclaration { .. }
+            | ASTNode::VariableAssignment { .. }
+            | ASTNode::VariableReference { .. }
+            | ASTNode::IfDefined { .. } => {}
+        }
+    }
+}
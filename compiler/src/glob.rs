@@ -0,0 +1,191 @@
+//! Wildcard patterns for command line inputs.
+//!
+//! Shells on Unix expand `docs/*.lol` before the compiler sees it, but the
+//! Windows shells don't, and a quoted `'docs/**/*.lol'` isn't expanded anywhere.
+//! The compiler expands such inputs itself:
+//!
+//! - `*` matches any part of a name, `?` any one character
+//! - `[abc]`, `[a-z]` and `[!abc]` match one character of (or not of) a set
+//! - `**` as a whole path component matches any number of directories, none
+//!   included
+//!
+//! Like in a shell, wildcards don't match a `.` at the start of a name, and
+//! matches come back sorted so a build always sees its pages in the same order.
+//! `**` doesn't follow symbolic links to directories, so a link back up the
+//! tree can't send it round in circles.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// True if `input` has wildcards to expand.
+pub fn is_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// The directory a pattern searches: its components up to the first one with
+/// a wildcard, e.g. `docs` for `docs/**/*.lol`.
+pub fn base_dir(pattern: &str) -> PathBuf {
+    let mut base = PathBuf::new();
+    for component in Path::new(pattern).components() {
+        if is_pattern(&component.as_os_str().to_string_lossy()) {
+            break;
+        }
+        base.push(component);
+    }
+    base
+}
+
+/// Every file that matches `pattern`, in sorted order.
+pub fn expand(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let base = base_dir(pattern);
+    let rest: Vec<String> = Path::new(pattern)
+        .components()
+        .skip(base.components().count())
+        .filter(|component| *component != Component::CurDir)
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    let start = if base.as_os_str().is_empty() { PathBuf::from(".") } else { base.clone() };
+
+    let mut found = Vec::new();
+    expand_in(&start, &rest, &mut found)?;
+    //a relative pattern without a directory gives names without `./`, like a shell
+    if base.as_os_str().is_empty() {
+        for path in &mut found {
+            if let Ok(relative) = path.strip_prefix(".") {
+                *path = relative.to_path_buf();
+            }
+        }
+    }
+    found.sort();
+    found.dedup();
+    Ok(found)
+}
+
+// matches the pattern's `components` below `dir`
+fn expand_in(dir: &Path, components: &[String], found: &mut Vec<PathBuf>) -> io::Result<()> {
+    let Some((first, rest)) = components.split_first() else {
+        if dir.is_file() {
+            found.push(dir.to_path_buf());
+        }
+        return Ok(());
+    };
+    if first == "**" {
+        //none of the directories, then each one below
+        expand_in(dir, rest, found)?;
+        for entry in sorted_entries(dir)? {
+            //a link to a directory above would never end
+            let is_dir = fs::symlink_metadata(&entry).is_ok_and(|meta| meta.is_dir());
+            if is_dir && !is_hidden(&entry) {
+                expand_in(&entry, components, found)?;
+            }
+        }
+        return Ok(());
+    }
+    for entry in sorted_entries(dir)? {
+        let name = entry.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if matches(first, &name) {
+            expand_in(&entry, rest, found)?;
+        }
+    }
+    Ok(())
+}
+
+// the entries of a directory, none for something that isn't one
+fn sorted_entries(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// True if the name `text` matches the one-component pattern `pattern`.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    //a leading dot has to be written out
+    if text.first() == Some(&'.') && pattern.first() != Some(&'.') {
+        return false;
+    }
+    matches_from(&pattern, &text)
+}
+
+/// Like `matches`, but a wildcard matches a `.` at the start of a name too,
+/// the way ignore files read their patterns.
+pub fn matches_hidden(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_from(&pattern, &text)
+}
+
+// Greedy matching: a `*` first matches nothing, and when the rest fails to
+// match it takes one more character, only ever going back to the last `*`.
+// Whatever an earlier `*` would have matched instead the last one can match
+// too, so this finds every match in time linear in the text for each `*`.
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    //where the last `*` is in the pattern, and where in the text it stops
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match class_matches(&pattern[p + 1..], text[t]) {
+                Some((true, len)) => Some(1 + len),
+                Some((false, _)) => None,
+                //an unclosed `[` is just a character
+                None => (text[t] == '[').then_some(1),
+            },
+            Some(&c) => (text[t] == c).then_some(1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(len), _) => {
+                p += len;
+                t += 1;
+            }
+            (None, Some((after, stop))) => {
+                star = Some((after, stop + 1));
+                p = after;
+                t = stop + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    //only `*`s can match what's left of the text, nothing
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// whether `c` is in the set that starts after a `[`, and how many characters
+// the set takes up to and including its `]`; `None` if there is no `]`
+fn class_matches(class: &[char], c: char) -> Option<(bool, usize)> {
+    let negated = matches!(class.first(), Some('!' | '^'));
+    let start = usize::from(negated);
+    //a `]` right at the start is part of the set
+    let end = class.iter().skip(start + 1).position(|&ch| ch == ']')? + start + 1;
+    let set = &class[start..end];
+    let mut found = false;
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            found |= set[i] <= c && c <= set[i + 2];
+            i += 3;
+        } else {
+            found |= set[i] == c;
+            i += 1;
+        }
+    }
+    Some((found != negated, end + 1))
+}
@@ -0,0 +1,422 @@
+//! Golden-file tests for documents.
+//!
+//! A golden directory holds `.lol` inputs next to the output they are expected
+//! to compile to: `page.lol` is compared against `page.html` (or the extension
+//! of the chosen target) and, if it exists, `page.err` against the diagnostics
+//! the compile produced, one per line. A document that is expected to fail has
+//! only the `.err` file. Every difference comes back as a `Mismatch` with a
+//! line diff, so a downstream test is one call:
+//!
+//! ```text
+//! let report = GoldenRun::new().check_dir(Path::new("tests/golden"))?;
+//! assert!(report.mismatches.is_empty(), "{}", report);
+//! ```
+//!
+//! With `bless` set the expected files are rewritten from the actual output
+//! instead of compared.
+//!
+//! `check_paranoid` compiles a directory of hostile documents, like the
+//! `security` corpus, in `--paranoid` mode and lists anything in the output
+//! that mode shouldn't let through:
+//!
+//! ```text
+//! let mut run = GoldenRun::new();
+//! run.options.paranoid = true;
+//! assert!(run.check_dir(Path::new("security"))?.is_ok());
+//! assert_eq!(run.check_paranoid(Path::new("security"))?, Vec::<String>::new());
+//! ```
+
+use crate::arena::AstArena;
+use crate::builtins::BuildTime;
+use crate::diagnostics::{self, CompileError};
+use crate::lint::{self, LintOptions};
+use crate::parser::{DEFAULT_MAX_NESTING, LolcodeParser, Parser};
+use crate::semantic::{LolcodeSemanticAnalyzer, OutputOptions};
+use crate::sanitize;
+use crate::site;
+use crate::target::Target;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extension of the expected diagnostics file.
+pub const DIAGNOSTICS_EXTENSION: &str = "err";
+
+/// Lines of unchanged output shown around each change in a diff.
+const CONTEXT_LINES: usize = 2;
+
+/// Longest stretch of changed lines the diff lines up exactly; longer ones
+/// are shown as a whole removal and addition.
+const MAX_ALIGNED_LINES: usize = 2000;
+
+/// Settings for compiling the documents of a golden directory.
+#[derive(Debug, Clone)]
+pub struct GoldenRun {
+    /// Options for the generated output; the target picks the expected file
+    pub options: OutputOptions,
+    /// Levels for the lint rules
+    pub lint_options: LintOptions,
+    /// Deepest section nesting a document may have
+    pub max_nesting: usize,
+    /// Treat variable names that differ only in case as the same variable
+    pub case_insensitive: bool,
+    /// Global variables given from outside the documents
+    pub defines: HashMap<String, String>,
+    /// What `TODAYZ_DATE` and `NAO` show; fixed so expected files don't go stale
+    pub build_time: BuildTime,
+    /// Only warn about a HEAD section after the document's content
+    pub allow_late_head: bool,
+    /// Write the actual output to the expected files instead of comparing
+    pub bless: bool,
+}
+
+/// An expected file that didn't match what the document compiled to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// The `.lol` source
+    pub input: PathBuf,
+    /// The expected file it was compared against
+    pub expected: PathBuf,
+    /// Line diff, `-` for expected and `+` for actual lines
+    pub diff: String,
+}
+
+/// The outcome of checking a golden directory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GoldenReport {
+    /// Documents whose expected files all matched
+    pub passed: usize,
+    /// Every expected file that didn't match, in input order
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Default for GoldenRun {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GoldenRun {
+    /// Creates a run with default options. The browser is never opened.
+    pub fn new() -> Self {
+        Self {
+            options: OutputOptions {
+                open_browser: false,
+                ..OutputOptions::default()
+            },
+            lint_options: LintOptions::default(),
+            max_nesting: DEFAULT_MAX_NESTING,
+            case_insensitive: false,
+            defines: HashMap::new(),
+            build_time: BuildTime { unix_seconds: 0 },
+            allow_late_head: false,
+            bless: false,
+        }
+    }
+
+    /// Compiles every `.lol` file below `dir` and compares it with its expected files.
+    ///
+    /// Only failing to read or write files is an error; compile errors are
+    /// output like any other and compared with the `.err` file.
+    pub fn check_dir(&self, dir: &Path) -> Result<GoldenReport, String> {
+        let pages = site::collect_pages(&[dir.to_string_lossy().to_string()])?;
+        let mut report = GoldenReport::default();
+        for page in pages {
+            let mismatches = self.check_file(&page.source)?;
+            if mismatches.is_empty() {
+                report.passed += 1;
+            }
+            report.mismatches.extend(mismatches);
+        }
+        Ok(report)
+    }
+
+    /// Compiles one `.lol` file and compares it with its expected files.
+    pub fn check_file(&self, input: &Path) -> Result<Vec<Mismatch>, String> {
+        let source = fs::read_to_string(input).map_err(|e| format!("Failed to read '{}': {}", input.display(), e))?;
+        let (output, diagnostics) = self.compile(&source);
+
+        let output_path = input.with_extension(self.options.target.extension());
+        let diagnostics_path = input.with_extension(DIAGNOSTICS_EXTENSION);
+        let mut mismatches = Vec::new();
+
+        // a failed compile has no output, so its output file must not exist either
+        let expected_output = read_expected(&output_path)?;
+        match (&output, expected_output) {
+            (Some(actual), Some(expected)) => {
+                if self.bless {
+                    write_expected(&output_path, actual)?;
+                } else if *actual != expected {
+                    mismatches.push(mismatch(input, &output_path, &expected, actual));
+                }
+            }
+            (Some(actual), None) => {
+                if self.bless {
+                    write_expected(&output_path, actual)?;
+                } else if !diagnostics_path.exists() {
+                    mismatches.push(mismatch(input, &output_path, "", actual));
+                }
+            }
+            (None, Some(expected)) => {
+                if self.bless {
+                    fs::remove_file(&output_path)
+                        .map_err(|e| format!("Failed to remove '{}': {}", output_path.display(), e))?;
+                } else {
+                    mismatches.push(mismatch(input, &output_path, &expected, ""));
+                }
+            }
+            (None, None) => {}
+        }
+
+        // diagnostics are only compared when they are expected, or the compile failed
+        let expected_diagnostics = read_expected(&diagnostics_path)?;
+        if self.bless {
+            if expected_diagnostics.is_some() || output.is_none() {
+                write_expected(&diagnostics_path, &diagnostics)?;
+            }
+        } else if let Some(expected) = expected_diagnostics {
+            if diagnostics != expected {
+                mismatches.push(mismatch(input, &diagnostics_path, &expected, &diagnostics));
+            }
+        } else if output.is_none() {
+            mismatches.push(mismatch(input, &diagnostics_path, "", &diagnostics));
+        }
+
+        Ok(mismatches)
+    }
+
+    /// Compiles every `.lol` file below `dir` in `--paranoid` mode for each
+    /// HTML target and lists the `sanitize::violations` in the output, each
+    /// with the file and target it is in.
+    pub fn check_paranoid(&self, dir: &Path) -> Result<Vec<String>, String> {
+        let pages = site::collect_pages(&[dir.to_string_lossy().to_string()])?;
+        let mut found = Vec::new();
+        for target in [Target::Html, Target::Slides] {
+            let run = GoldenRun {
+                options: OutputOptions { target, paranoid: true, ..self.options.clone() },
+                ..self.clone()
+            };
+            for page in &pages {
+                let source = fs::read_to_string(&page.source)
+                    .map_err(|e| format!("Failed to read '{}': {}", page.source.display(), e))?;
+                let (output, _) = run.compile(&source);
+                for violation in sanitize::violations(output.as_deref().unwrap_or("")) {
+                    found.push(format!("{} ({}): {}", page.source.display(), target.name(), violation));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Compiles a document in memory. Returns the output, or `None` if the
+    /// compile stopped, and the diagnostics text.
+    fn compile(&self, source: &str) -> (Option<String>, String) {
+        let arena = AstArena::new();
+        let mut messages = Vec::new();
+        let output = self.compile_tree(source, &arena, &mut messages).unwrap_or_else(|error| {
+            messages.push(error.to_string());
+            None
+        });
+        let mut text = String::new();
+        for message in messages {
+            text.push_str(&message);
+            text.push('\n');
+        }
+        (output, text)
+    }
+
+    // lint findings go into `messages`, an error that stops the compile is returned
+    fn compile_tree<'t>(
+        &self,
+        source: &str,
+        arena: &'t AstArena<'t>,
+        messages: &mut Vec<String>,
+    ) -> Result<Option<String>, CompileError> {
+        let mut parser = LolcodeParser::new(source, arena);
+        parser.max_nesting = self.max_nesting;
+        parser.parse()?;
+        let Some(tree) = parser.parse_tree else {
+            return Ok(None);
+        };
+
+        let mut lints = lint::lint_source(source, &self.lint_options);
+        lints.extend(lint::lint(tree, &self.lint_options, &parser.suppressions));
+        parser.suppressions.filter(&mut lints);
+        messages.extend(lints.iter().map(|d| d.to_string()));
+        if diagnostics::has_errors(&lints) {
+            return Ok(None);
+        }
+
+        let mut analyzer = LolcodeSemanticAnalyzer::new();
+        analyzer.options = self.options.clone();
+        analyzer.interner = std::mem::take(&mut parser.interner);
+        analyzer.case_insensitive = self.case_insensitive;
+        analyzer.defines = self.defines.clone();
+        analyzer.build_time = self.build_time;
+        analyzer.allow_late_head = self.allow_late_head;
+        analyzer.positions = std::mem::take(&mut parser.positions);
+        analyzer.source_len = source.len();
+        analyzer.check_tree(tree)?;
+        parser.suppressions.filter(&mut analyzer.warnings);
+        messages.extend(analyzer.warnings.iter().map(|d| d.to_string()));
+        Ok(Some(analyzer.generate_output(tree)))
+    }
+}
+
+impl GoldenReport {
+    /// True when every expected file matched.
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for GoldenReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for mismatch in &self.mismatches {
+            writeln!(f, "{}", mismatch)?;
+        }
+        write!(f, "{} passed, {} mismatched", self.passed, self.mismatches.len())
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- {}", self.expected.display())?;
+        writeln!(f, "+++ {} (actual)", self.input.display())?;
+        write!(f, "{}", self.diff)
+    }
+}
+
+// a missing expected file reads as None, anything else that fails is an error
+fn read_expected(path: &Path) -> Result<Option<String>, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read '{}': {}", path.display(), e)),
+    }
+}
+
+fn write_expected(path: &Path, text: &str) -> Result<(), String> {
+    fs::write(path, text).map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+}
+
+fn mismatch(input: &Path, expected_path: &Path, expected: &str, actual: &str) -> Mismatch {
+    Mismatch {
+        input: input.to_path_buf(),
+        expected: expected_path.to_path_buf(),
+        diff: diff_lines(expected, actual),
+    }
+}
+
+/// Line diff of `expected` against `actual` in hunks with a little context,
+/// like `diff -u` without the file header.
+pub fn diff_lines(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // only the middle between the common start and end needs aligning
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let changes = align(&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut edits: Vec<(char, &str)> = Vec::new();
+    edits.extend(old[..prefix].iter().map(|line| (' ', *line)));
+    edits.extend(changes);
+    edits.extend(old[old.len() - suffix..].iter().map(|line| (' ', *line)));
+
+    if !edits.iter().any(|(kind, _)| *kind != ' ') {
+        // same lines, so only the line endings differ
+        return "(only the line endings differ)\n".to_string();
+    }
+    hunks(&edits)
+}
+
+// longest common subsequence of the changed middle, as ' ', '-' and '+' lines
+fn align<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)> {
+    if old.len() > MAX_ALIGNED_LINES || new.len() > MAX_ALIGNED_LINES {
+        let mut edits: Vec<(char, &str)> = old.iter().map(|line| ('-', *line)).collect();
+        edits.extend(new.iter().map(|line| ('+', *line)));
+        return edits;
+    }
+
+    // lengths[i][j]: common lines of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            edits.push(('-', old[i]));
+            i += 1;
+        } else {
+            edits.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    edits
+}
+
+// keeps the changed lines and CONTEXT_LINES around them, with a header per hunk
+fn hunks(edits: &[(char, &str)]) -> String {
+    let mut out = String::new();
+    let (mut old_line, mut new_line) = (1, 1);
+    let mut index = 0;
+    while index < edits.len() {
+        let Some(offset) = edits[index..].iter().position(|(kind, _)| *kind != ' ') else {
+            break;
+        };
+        let change = index + offset;
+        let start = change.saturating_sub(CONTEXT_LINES).max(index);
+        for (kind, _) in &edits[index..start] {
+            if *kind == ' ' {
+                old_line += 1;
+                new_line += 1;
+            }
+        }
+
+        // the hunk runs until CONTEXT_LINES * 2 unchanged lines in a row
+        let mut end = change;
+        let mut unchanged = 0;
+        while end < edits.len() && unchanged <= CONTEXT_LINES * 2 {
+            unchanged = if edits[end].0 == ' ' { unchanged + 1 } else { 0 };
+            end += 1;
+        }
+        let end = if unchanged > CONTEXT_LINES { end - (unchanged - CONTEXT_LINES) } else { end };
+
+        out.push_str(&format!("@@ -{} +{} @@\n", old_line, new_line));
+        for (kind, line) in &edits[start..end] {
+            match kind {
+                '-' => old_line += 1,
+                '+' => new_line += 1,
+                _ => {
+                    old_line += 1;
+                    new_line += 1;
+                }
+            }
+            out.push(*kind);
+            out.push_str(line);
+            out.push('\n');
+        }
+        index = end;
+    }
+    out
+}
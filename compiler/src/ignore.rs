@@ -0,0 +1,131 @@
+//! `.lolignore` files for directory builds.
+//!
+//! A directory given as input can hold drafts, templates and vendored
+//! examples that aren't pages of the site. A `.lolignore` in it, or in any
+//! directory below it, lists what to skip, in the syntax of `.gitignore`:
+//!
+//! ```text
+//! # notes that aren't ready
+//! drafts/
+//! *.draft.lol
+//! !drafts/almost-done.lol
+//! /templates
+//! vendor/**/examples
+//! ```
+//!
+//! A pattern without a `/` (other than at the end) matches a name at any
+//! depth; one with a `/` is relative to the directory of the ignore file. A
+//! trailing `/` only matches directories, and `!` takes a path back that an
+//! earlier pattern skipped, as long as its directory isn't skipped. Rules of
+//! an ignore file deeper in the tree come after those above it, and the last
+//! rule that matches a path decides. Wildcards are those of `glob`.
+
+use crate::glob;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the ignore file in a directory.
+pub const IGNORE_FILE: &str = ".lolignore";
+
+/// The rules of one ignore file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IgnoreFile {
+    /// Directory the file is in, which its patterns are relative to
+    pub dir: PathBuf,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    // path components of the pattern, `**` for any number of them
+    components: Vec<String>,
+    // `!`: takes the path back
+    negated: bool,
+    // trailing `/`: only directories match
+    dir_only: bool,
+}
+
+impl IgnoreFile {
+    /// Reads the rules of the ignore file in `dir`, if there is one.
+    pub fn load(dir: &Path) -> io::Result<Option<Self>> {
+        match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(text) => Ok(Some(Self::parse(dir, &text))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads the rules in `text`, an ignore file in `dir`.
+    pub fn parse(dir: &Path, text: &str) -> Self {
+        let rules = text.lines().filter_map(parse_rule).collect();
+        Self { dir: dir.to_path_buf(), rules }
+    }
+
+    /// Whether the last of this file's rules that matches `path` skips it,
+    /// `None` if none of them matches or `path` isn't below `dir`.
+    pub fn decide(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.dir).ok()?;
+        let components: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && matches_components(&rule.components, &components))
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// Whether `path` is skipped by the ignore files it is below, given from the
+/// outermost directory in.
+pub fn is_ignored(ignores: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .find_map(|ignore| ignore.decide(path, is_dir))
+        .unwrap_or(false)
+}
+
+// one line of an ignore file; `None` for blank lines and comments
+fn parse_rule(line: &str) -> Option<Rule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        //a `\` keeps a leading `#` or `!` as it is
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    //a slash anywhere but at the end ties the pattern to the ignore file's directory
+    let anchored = line.contains('/');
+    let mut components: Vec<String> = line
+        .trim_start_matches('/')
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .map(str::to_string)
+        .collect();
+    if components.is_empty() {
+        return None;
+    }
+    if !anchored {
+        components.insert(0, "**".to_string());
+    }
+    Some(Rule { components, negated, dir_only })
+}
+
+fn matches_components(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => (0..=path.len()).any(|skip| matches_components(rest, &path[skip..])),
+        Some((first, rest)) => path
+            .split_first()
+            .is_some_and(|(name, path)| glob::matches_hidden(first, name) && matches_components(rest, path)),
+    }
+}
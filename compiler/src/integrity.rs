@@ -0,0 +1,257 @@
+//! CSP nonces and Subresource Integrity for generated pages.
+//!
+//! A page served under a strict `Content-Security-Policy` may only run the
+//! scripts and styles the policy allows. With `--csp-nonce <nonce>` every
+//! `<style>`, `<script>` and stylesheet `<link>` the compiler puts in a page
+//! carries `nonce="<nonce>"`, injected snippets included, for a server that
+//! sends the same nonce in its `script-src`/`style-src`. With `--sri` the
+//! assets a page loads from a CDN (the reveal.js files of `--reveal-cdn`)
+//! get an `integrity` hash, so the browser refuses them if the CDN serves
+//! anything else.
+//!
+//! The hash is `sha384-` and the base64 SHA-384 of the asset, fetched with
+//! `curl` once per build. An asset that can't be fetched is linked without
+//! one, with a warning.
+
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+
+/// True if `nonce` can go in a CSP header and an attribute as it is: base64
+/// or base64url characters only.
+pub fn is_valid_nonce(nonce: &str) -> bool {
+    !nonce.is_empty() && nonce.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=' | '-' | '_'))
+}
+
+/// ` nonce="<nonce>"` for an element the compiler injects, nothing without one.
+pub fn nonce_attribute(nonce: Option<&str>) -> String {
+    nonce.map(|nonce| format!(" nonce=\"{}\"", nonce)).unwrap_or_default()
+}
+
+/// `html` with `nonce` on each `<script>`, `<style>` and stylesheet `<link>`
+/// that doesn't have one, for a snippet injected with
+/// `--inject-head`/`--inject-body`. Other links (icons, preloads) load nothing
+/// CSP checks nonces for and are left alone.
+pub fn stamp_nonce(html: &str, nonce: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::with_capacity(html.len());
+    let mut written = 0;
+    let mut at = 0;
+    while let Some(start) = lower[at..].find('<').map(|start| at + start) {
+        at = start + 1;
+        let Some(name) = ["script", "style", "link"].into_iter().find(|name| lower[at..].starts_with(name)) else {
+            continue;
+        };
+        let name_end = at + name.len();
+        if !lower[name_end..].starts_with(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/') {
+            continue;
+        }
+        let tag_end = lower[name_end..].find('>').map_or(lower.len(), |end| name_end + end);
+        let attributes = &lower[name_end..tag_end];
+        if attributes.contains("nonce=") || (name == "link" && !is_stylesheet(attributes)) {
+            continue;
+        }
+        out.push_str(&html[written..name_end]);
+        out.push_str(&nonce_attribute(Some(nonce)));
+        written = name_end;
+    }
+    out.push_str(&html[written..]);
+    out
+}
+
+// whether a `<link>` tag's (lowercased) attributes have `stylesheet` among the
+// words of its `rel`
+fn is_stylesheet(attributes: &str) -> bool {
+    let Some(start) = attributes.find("rel=").map(|start| start + "rel=".len()) else {
+        return false;
+    };
+    let value = &attributes[start..];
+    let value = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+        _ => value.split(|c: char| c.is_ascii_whitespace() || c == '/').next().unwrap_or_default(),
+    };
+    value.split_ascii_whitespace().any(|word| word == "stylesheet")
+}
+
+/// ` integrity="sha384-..." crossorigin="anonymous"` for an asset at `url`,
+/// nothing if it can't be fetched.
+pub fn integrity_attributes(url: &str) -> String {
+    match integrity(url) {
+        Some(hash) => format!(" integrity=\"{}\" crossorigin=\"anonymous\"", hash),
+        None => String::new(),
+    }
+}
+
+/// The Subresource Integrity hash of the asset at `url`. Each URL is fetched
+/// once per run.
+pub fn integrity(url: &str) -> Option<String> {
+    static HASHES: OnceLock<Mutex<HashMap<String, Option<String>>>> = OnceLock::new();
+    let hashes = HASHES.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(hash) = hashes.lock().ok()?.get(url) {
+        return hash.clone();
+    }
+    let hash = fetch(url).map(|bytes| sri_hash(&bytes));
+    if hash.is_none() {
+        log::warn!("Couldn't fetch {} for its integrity hash, linking it without one", url);
+    }
+    hashes.lock().ok()?.insert(url.to_string(), hash.clone());
+    hash
+}
+
+fn fetch(url: &str) -> Option<Vec<u8>> {
+    let output = Command::new("curl").args(["-sfL", "--max-time", "30", url]).output().ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+/// The integrity value for `bytes`: `sha384-` and their base64 SHA-384.
+pub fn sri_hash(bytes: &[u8]) -> String {
+    format!("sha384-{}", base64(&sha384(bytes)))
+}
+
+// SHA-512's round constants
+const K: [u64; 80] = [
+    0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc, 0x3956c25bf348b538,
+    0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118, 0xd807aa98a3030242, 0x12835b0145706fbe,
+    0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2, 0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235,
+    0xc19bf174cf692694, 0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5, 0x983e5152ee66dfab,
+    0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4, 0xc6e00bf33da88fc2, 0xd5a79147930aa725,
+    0x06ca6351e003826f, 0x142929670a0e6e70, 0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df, 0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+    0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30, 0xd192e819d6ef5218,
+    0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8, 0x19a4c116b8d2d0c8, 0x1e376c085141ab53,
+    0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8, 0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3, 0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+    0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b, 0xca273eceea26619c,
+    0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178, 0x06f067aa72176fba, 0x0a637dc5a2c898a6,
+    0x113f9804bef90dae, 0x1b710b35131c471b, 0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c, 0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+];
+
+// SHA-384: SHA-512 with its own starting state, cut to 48 bytes
+fn sha384(bytes: &[u8]) -> [u8; 48] {
+    let mut state: [u64; 8] = [
+        0xcbbb9d5dc1059ed8, 0x629a292a367cd507, 0x9159015a3070dd17, 0x152fecd8f70e5939,
+        0x67332667ffc00b31, 0x8eb44a8768581511, 0xdb0c2e0d64f98fa7, 0x47b5481dbefa4fa4,
+    ];
+    let mut padded = bytes.to_vec();
+    padded.push(0x80);
+    while padded.len() % 128 != 112 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&((bytes.len() as u128) * 8).to_be_bytes());
+
+    for block in padded.chunks_exact(128) {
+        let mut w = [0u64; 80];
+        for (i, word) in block.chunks_exact(8).enumerate() {
+            w[i] = u64::from_be_bytes(word.try_into().unwrap_or_default());
+        }
+        for i in 16..80 {
+            let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+            let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..80 {
+            let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 48];
+    for (chunk, word) in digest.chunks_exact_mut(8).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, byte)| n | u32::from(*byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(ALPHABET[(n >> (18 - 6 * i)) as usize & 63]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    // the SHA-384 examples of FIPS 180-4
+    #[test]
+    fn sha384_test_vectors() {
+        assert_eq!(
+            hex(&sha384(b"")),
+            "38b060a751ac96384cd9327eb1b1e36a21fdb71114be07434c0cc7bf63f6e1da274edebfe76f65fbd51ad2f14898b95b"
+        );
+        assert_eq!(
+            hex(&sha384(b"abc")),
+            "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7"
+        );
+        // 896 bits, so the padding needs a second block
+        let message = b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+        assert_eq!(
+            hex(&sha384(message)),
+            "09330c33f71147e83d192fc782cd1b4753111b173b3b05d22fa08086e3b0f712fcc7c71a557e2db966c3e9fa91746039"
+        );
+    }
+
+    #[test]
+    fn sri_hash_is_base64() {
+        assert_eq!(sri_hash(b""), "sha384-OLBgp1GsljhM2TJ+sbHjaiH9txEUvgdDTAzHv2P24donTt6/529l+9Ua0vFImLlb");
+    }
+
+    // RFC 4648's examples, one for each amount of padding
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn nonce_goes_on_scripts_styles_and_stylesheets() {
+        let html = "<script src=\"a.js\"></script><style>p{}</style><link rel=\"stylesheet\" href=\"a.css\"><link rel=\"icon\" href=\"a.png\"><LINK REL='alternate stylesheet' href=b.css><link rel=preload href=c.css>";
+        assert_eq!(
+            stamp_nonce(html, "abc"),
+            "<script nonce=\"abc\" src=\"a.js\"></script><style nonce=\"abc\">p{}</style><link nonce=\"abc\" rel=\"stylesheet\" href=\"a.css\"><link rel=\"icon\" href=\"a.png\"><LINK nonce=\"abc\" REL='alternate stylesheet' href=b.css><link rel=preload href=c.css>"
+        );
+    }
+
+    #[test]
+    fn nonce_is_not_added_twice() {
+        let html = "<script nonce=\"xyz\"></script>";
+        assert_eq!(stamp_nonce(html, "abc"), html);
+    }
+}
@@ -0,0 +1,84 @@
+//! String interning for variable names.
+//!
+//! The parser interns every variable name it sees and stores the resulting
+//! `Symbol` in the AST. The semantic analyzer takes over the same `Interner`,
+//! so its symbol table is keyed by `Symbol` and scope lookups compare integers
+//! instead of hashing and comparing strings. Each distinct name is stored once
+//! no matter how often it is declared or referenced.
+//!
+//! Keywords don't need an interner: the lexer hands out `&'static str` from its
+//! keyword tables, so they are never allocated at all.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// An interned name. Only meaningful together with the `Interner` that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Position of the name in its interner, in the order names were added.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Maps names to `Symbol`s and back.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    ids: HashMap<Arc<str>, Symbol>,
+    names: Vec<Arc<str>>,
+}
+
+impl Interner {
+    /// Creates an empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the symbol for `name`, adding it if it hasn't been seen yet.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.ids.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        let name: Arc<str> = Arc::from(name);
+        self.names.push(Arc::clone(&name));
+        self.ids.insert(name, symbol);
+        symbol
+    }
+
+    /// Looks up the symbol for `name` without adding it.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.ids.get(name).copied()
+    }
+
+    /// Returns the name behind a symbol.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the symbol came from a different interner.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+
+    /// Returns the symbol at `index`, if the interner has that many names.
+    pub fn symbol_at(&self, index: usize) -> Option<Symbol> {
+        (index < self.names.len()).then_some(Symbol(index as u32))
+    }
+
+    /// Every interned name, in symbol order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(|name| &**name)
+    }
+
+    /// Number of distinct names interned.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns true if nothing has been interned yet.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
@@ -0,0 +1,154 @@
+//! Just enough JSON writing for the machine-readable output, without a
+//! serializer dependency.
+
+use crate::intern::Interner;
+use crate::parser::{ASTNode, MediaAttributes};
+
+/// A JSON string literal, quotes included.
+pub fn string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A parse tree as JSON, for tools that want the structure of a document
+/// without linking against the compiler.
+///
+/// Every node is an object with a `kind` (`program`, `head`, `meta`,
+/// `paragraf`, `list`, `declaration`, `assignment`, `reference`, `title`,
+/// `text`, `bold`, `italics`, `item`, `newline`, `sound`, `video`, `image`,
+/// `link` or `if_defined`) and the fields of that kind of node; sections have
+/// `children` and styled text has `content`.
+pub fn ast(tree: &ASTNode, interner: &Interner) -> String {
+    let mut out = String::new();
+    node(tree, interner, &mut out);
+    out
+}
+
+fn node(node: &ASTNode, interner: &Interner, out: &mut String) {
+    match node {
+        ASTNode::Program { children } => nodes("program", "children", children, interner, out),
+        ASTNode::HeadSection { children } => nodes("head", "children", children, interner, out),
+        ASTNode::ParagrafSection { children } => nodes("paragraf", "children", children, interner, out),
+        ASTNode::ListSection { children } => nodes("list", "children", children, interner, out),
+        ASTNode::MetaSection { entries } => {
+            let entries: Vec<String> =
+                entries.iter().map(|(key, value)| format!("[{},{}]", string(key), string(value))).collect();
+            out.push_str(&format!("{{\"kind\":\"meta\",\"entries\":[{}]}}", entries.join(",")));
+        }
+        ASTNode::VariableDeclaration { name } => {
+            out.push_str(&format!("{{\"kind\":\"declaration\",\"name\":{}}}", string(interner.resolve(*name))));
+        }
+        ASTNode::VariableAssignment { name, value } => {
+            // a bare #IT IZ assigns to the variable declared last
+            let name = name.map_or_else(|| "null".to_string(), |name| string(interner.resolve(name)));
+            out.push_str(&format!("{{\"kind\":\"assignment\",\"name\":{},\"value\":{}}}", name, string(value)));
+        }
+        ASTNode::VariableReference { name } => {
+            out.push_str(&format!("{{\"kind\":\"reference\",\"name\":{}}}", string(interner.resolve(*name))));
+        }
+        ASTNode::Title { content } => nodes("title", "content", content, interner, out),
+        ASTNode::Bold { content } => nodes("bold", "content", content, interner, out),
+        ASTNode::Italics { content } => nodes("italics", "content", content, interner, out),
+        ASTNode::Item { content } => nodes("item", "content", content, interner, out),
+        ASTNode::Text { content } => out.push_str(&format!("{{\"kind\":\"text\",\"content\":{}}}", string(content))),
+        ASTNode::Newline => out.push_str("{\"kind\":\"newline\"}"),
+        ASTNode::Sound { url, attributes } => media("sound", url, attributes, interner, out),
+        ASTNode::Video { url, attributes } => media("video", url, attributes, interner, out),
+        ASTNode::Image { url, attributes } => media("image", url, attributes, interner, out),
+        ASTNode::Link { target, text } => {
+            out.push_str(&format!("{{\"kind\":\"link\",\"target\":{},\"text\":{}}}", string(target), string(text)));
+        }
+        ASTNode::IfDefined { flag, children } => {
+            out.push_str(&format!("{{\"kind\":\"if_defined\",\"flag\":{},", string(flag)));
+            list("children", children, interner, out);
+            out.push('}');
+        }
+    }
+}
+
+fn nodes(kind: &str, field: &str, children: &[ASTNode], interner: &Interner, out: &mut String) {
+    out.push_str(&format!("{{\"kind\":\"{}\",", kind));
+    list(field, children, interner, out);
+    out.push('}');
+}
+
+fn list(field: &str, children: &[ASTNode], interner: &Interner, out: &mut String) {
+    out.push_str(&format!("\"{}\":[", field));
+    for (i, child) in children.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        node(child, interner, out);
+    }
+    out.push(']');
+}
+
+// only the attributes that are set, besides the URL's variable references
+fn media(kind: &str, url: &str, attributes: &MediaAttributes, interner: &Interner, out: &mut String) {
+    let references: Vec<String> = attributes
+        .url_references
+        .iter()
+        .map(|(offset, name)| format!("[{},{}]", offset, string(interner.resolve(*name))))
+        .collect();
+    out.push_str(&format!(
+        "{{\"kind\":\"{}\",\"url\":{},\"url_references\":[{}]",
+        kind,
+        string(url),
+        references.join(",")
+    ));
+    let optional = [
+        ("poster", attributes.poster.as_deref()),
+        ("preload", attributes.preload.as_deref()),
+        ("alt", attributes.alt.as_deref()),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            out.push_str(&format!(",\"{}\":{}", key, string(value)));
+        }
+    }
+    let flags = [
+        ("autoplay", attributes.autoplay),
+        ("loop", attributes.looping),
+        ("muted", attributes.muted),
+        ("lazy", attributes.lazy),
+    ];
+    for (key, set) in flags {
+        if set {
+            out.push_str(&format!(",\"{}\":true", key));
+        }
+    }
+    for (key, value) in [("width", attributes.width), ("height", attributes.height)] {
+        if let Some(value) = value {
+            out.push_str(&format!(",\"{}\":{}", key, value));
+        }
+    }
+    if !attributes.srcset.is_empty() {
+        let srcset: Vec<String> = attributes.srcset.iter().map(|source| string(source)).collect();
+        out.push_str(&format!(",\"srcset\":[{}]", srcset.join(",")));
+    }
+    if !attributes.captions.is_empty() {
+        let captions: Vec<String> = attributes
+            .captions
+            .iter()
+            .map(|track| {
+                let label = track.label.as_deref().map_or_else(|| "null".to_string(), string);
+                format!("{{\"src\":{},\"lang\":{},\"label\":{}}}", string(&track.src), string(&track.lang), label)
+            })
+            .collect();
+        out.push_str(&format!(",\"captions\":[{}]", captions.join(",")));
+    }
+    out.push('}');
+}
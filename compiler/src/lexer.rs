@@ -3,11 +3,32 @@
 //! This module implements a character-by-character lexer that tokenizes LOLCODE source code.
 //! The lexer recognizes keywords, hashtag words, variables, text content, and handles
 //! multi-line comments.
+//!
+//! A backslash makes the lexer read markup as text: `\#` is a literal `#`, `\\`
+//! a backslash and `\BOLD` (any keyword) the plain word.
+//!
+//! Right after `#IT IZ` or `TITLE` a value can be quoted as a YARN literal,
+//! `"like  this # one"`, which keeps every character. Inside it `\"` is a quote,
+//! `\\` a backslash, `\n` a newline and `\t` a tab.
+//!
+//! `#SHHH <code>` is read with its code as one token; an unknown code is an error.
+//!
+//! `#IT IZ <name> R <value>` assigns to the variable `name`: the name comes as a
+//! `VarDef` token and the value after `R` is read like any other value. Only a
+//! name declared with `#I HAZ` earlier in the source is taken as the target;
+//! any other `<word> R ...` is the value itself.
+//!
+//! A lexer made with `Lexer::lossless` also keeps what the others read past:
+//! `next_lossless` hands back each token with the whitespace and comments
+//! before it and the exact text it was read from, for tools that have to
+//! write the source back out.
 
 //taking from other token.rs without having to repeat
-use crate::token::{Token, TokenKind};
-//exit when something illegal found
-use std::process::exit;
+use crate::token::{LosslessToken, Token, TokenKind, Trivia};
+//returned when something illegal found
+use crate::diagnostics::{CODES, CompileError};
+use std::collections::{HashMap, HashSet};
+use unicode_ident::{is_xid_continue, is_xid_start};
 
 /// Trait defining the interface for lexical analysis.
 /// 
@@ -23,7 +44,7 @@ pub trait LexicalAnalyzer {
     fn lookup(&self, s: &str) -> bool;
     
     /// Retrieves the next token from the input stream.
-    fn get_next_token(&mut self) -> Token;
+    fn get_next_token(&mut self) -> Result<Token, CompileError>;
 }
 
 /// Character-by-character lexer implementation for LOLCODE.
@@ -31,37 +52,191 @@ pub trait LexicalAnalyzer {
 /// Maintains source position (line and column), character lookahead,
 /// and builds lexemes token by token.
 pub struct Lexer<'a> {
-    src: &'a str,
-    iter: std::str::CharIndices<'a>,
-    look: Option<(usize, char)>, 
+    // the whole input, a leading BOM included
+    source: &'a str,
+    // the input not read yet, starting with the current character
+    rest: &'a str,
+    // byte offset in `source` where the last token scanned starts
+    token_start: usize,
+    // whitespace and comments read past since the last token, kept only by a
+    // lossless lexer
+    trivia: Option<Vec<Trivia<'a>>>,
     /// Current line number (1-indexed)
     pub line: usize,
     /// Current column number (1-indexed)
     pub col: usize,
     // for building a lexeme
     cur: String,
+    // the last token was `#IT IZ` or `TITLE`, so a quoted YARN literal may follow
+    yarn_allowed: bool,
+    // the last token was `#IT IZ`, so the rest of the line up to `#MKAY` is a value
+    value_mode: bool,
+    // the last token was `#I HAZ`, so a name being declared comes next
+    declaring: bool,
+    // every name declared so far; only these can be an assignment target
+    declared: HashSet<String>,
+}
+
+/// Every hashtag word, as it appears in tokens.
+const HASH_WORDS: [&str; 13] = [
+    "#HAI", "#KTHXBYE", "#OBTW", "#TLDR", "#MAEK", "#OIC",
+    "#GIMMEH", "#MKAY", "#I HAZ", "#IT IZ", "#LEMME SEE", "#SHHH",
+    "#IZ DEFINED",
+];
+
+/// Every keyword, as it appears in tokens.
+const KEYWORDS: [&str; 13] = [
+    "HEAD", "META", "TITLE", "PARAGRAF", "BOLD", "ITALICS",
+    "LIST", "ITEM", "NEWLINE", "SOUNDZ", "VIDZ", "PIKCHUR", "LINKZ",
+];
+
+/// The keywords written as hashtag words (`#HEAD`), which the lexer has
+/// always accepted; the parser decides where they fit.
+const HASH_KEYWORDS: [&str; 13] = [
+    "#HEAD", "#META", "#TITLE", "#PARAGRAF", "#BOLD", "#ITALICS",
+    "#LIST", "#ITEM", "#NEWLINE", "#SOUNDZ", "#VIDZ", "#PIKCHUR", "#LINKZ",
+];
+
+/// Hashtag words of two words, as `(first, second, word)`: the first word as
+/// read, the second one to expect after a space and the word they make.
+const TWO_WORD_HASH_WORDS: [(&str, &str, &str); 4] = [
+    ("I", "HAZ", "I HAZ"),
+    ("IT", "IZ", "IT IZ"),
+    ("LEMME", "SEE", "LEMME SEE"),
+    ("IZ", "DEFINED", "IZ DEFINED"),
+];
+
+/// Byte order mark some editors put at the start of UTF-8 files.
+pub const BOM: char = '\u{FEFF}';
+
+/// Characters that take no space: zero-width space, non-joiner and joiner, word
+/// joiner and a BOM that isn't at the start. They sneak in with copied text.
+pub const ZERO_WIDTH: [char; 5] = ['\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', BOM];
+
+/// True if the last word of `text` is a URL, so a `#` continues it.
+fn in_url(text: &str) -> bool {
+    text.rsplit(char::is_whitespace).next().is_some_and(|word| word.contains("://"))
+}
+
+/// Finds the static `#WORD` for an uppercase hashtag word without the `#`.
+// tokens point into the table so keywords are never allocated
+fn hash_word(upper: &str) -> Option<&'static str> {
+    HASH_WORDS.iter().chain(&HASH_KEYWORDS).copied().find(|w| &w[1..] == upper)
+}
+
+/// Finds the static spelling of an uppercase keyword.
+pub(crate) fn keyword(upper: &str) -> Option<&'static str> {
+    KEYWORDS.iter().copied().find(|w| *w == upper)
+}
+
+/// True if `name` lexes as a variable name: one Unicode identifier that isn't a keyword.
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_xid_start)
+        && chars.all(is_xid_continue)
+        && keyword(&name.to_ascii_uppercase()).is_none()
+}
+
+/// Byte offsets in `source` of (line, column) positions as the lexer counts them:
+/// from 1, columns in characters, a leading BOM not counted. The end of the
+/// source is a position too; positions past it are left out.
+pub(crate) fn byte_offsets(source: &str, mut wanted: Vec<(usize, usize)>) -> HashMap<(usize, usize), usize> {
+    wanted.sort_unstable();
+    wanted.dedup();
+    let mut offsets = HashMap::new();
+    let skipped = if source.starts_with(BOM) { BOM.len_utf8() } else { 0 };
+    let (mut line, mut col) = (1, 1);
+    let mut next = wanted.iter().peekable();
+    for (i, ch) in source[skipped..].char_indices().chain(std::iter::once((source.len() - skipped, '\n'))) {
+        while let Some(&&position) = next.peek() {
+            if position > (line, col) {
+                break;
+            }
+            offsets.insert(position, skipped + i);
+            next.next();
+        }
+        if ch == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+    offsets
 }
 
 impl <'a> Lexer <'a> {
     /// Creates a new lexer for the given source code.
     /// 
     /// Initializes the lexer at line 1, column 1 with the first character loaded.
+    /// A leading BOM is skipped.
     pub fn new(src: &'a str) -> Self {
-        let mut iter = src.char_indices();
-        let look = iter.next();
+        let rest = src.strip_prefix(BOM).unwrap_or(src);
         Self {
-            src,
-            iter,
-            look,
+            source: src,
+            rest,
+            token_start: 0,
+            trivia: None,
             line: 1,
             col: 1,
             cur: String::new(),
+            yarn_allowed: false,
+            value_mode: false,
+            declaring: false,
+            declared: HashSet::new(),
         }
     }
     
+    /// Creates a lexer that keeps whitespace and comments, for `next_lossless`.
+    /// A leading BOM is kept as whitespace before the first token.
+    pub fn lossless(src: &'a str) -> Self {
+        let mut lexer = Self::new(src);
+        let bom = &src[..src.len() - lexer.rest.len()];
+        let mut trivia = Vec::new();
+        if !bom.is_empty() {
+            trivia.push(Trivia::Whitespace(bom));
+        }
+        lexer.trivia = Some(trivia);
+        lexer
+    }
+
+    /// Retrieves the next token like `get_next_token`, with the whitespace and
+    /// comments before it and the source text it was read from. Only a lexer
+    /// made with `lossless` keeps the trivia; others give none.
+    pub fn next_lossless(&mut self) -> Result<LosslessToken<'a>, CompileError> {
+        let token = self.get_next_token()?;
+        let leading = self.trivia.as_mut().map(std::mem::take).unwrap_or_default();
+        let text = &self.source[self.token_start..self.offset()];
+        Ok(LosslessToken { token, leading, text })
+    }
+
+    /// Byte offset in the whole input of the current character.
+    fn offset(&self) -> usize {
+        self.source.len() - self.rest.len()
+    }
+
+    /// Keeps the source from `start` up to the current character as trivia,
+    /// if this lexer keeps it at all.
+    fn keep_trivia(&mut self, start: usize, comment: bool) {
+        let end = self.offset();
+        if let Some(trivia) = &mut self.trivia
+            && start < end
+        {
+            let text = &self.source[start..end];
+            trivia.push(if comment { Trivia::Comment(text) } else { Trivia::Whitespace(text) });
+        }
+    }
+
     /// Peeks at the current character without consuming it.
     fn peek(&self) -> Option<char> {
-        self.look.map(|(_,c)| c)
+        self.rest.chars().next()
+    }
+
+    /// The characters after the current one, for looking further ahead.
+    fn ahead(&self) -> std::str::Chars<'a> {
+        let mut chars = self.rest.chars();
+        chars.next();
+        chars
     }
 
     /// Consumes and returns the current character, advancing to the next one.
@@ -69,52 +244,72 @@ impl <'a> Lexer <'a> {
     /// Updates line and column counters based on the consumed character.
     //returns current character and moves counter to next one
     fn bump(&mut self) -> Option<char> {
-        let ch = self.look?; // grabs current character
+        let ch = self.peek()?; // grabs current character
         // advance line/col
-        if ch.1 == '\n' {
+        if ch == '\n' {
             self.line += 1;
             self.col = 1;
         } else {
             self.col += 1;
         }
-        self.look = self.iter.next(); // go to next character
-        Some(ch.1) // return character just consumed
+        self.rest = &self.rest[ch.len_utf8()..]; // go to next character
+        Some(ch) // return character just consumed
+    }
+
+    /// Consumes the next `len` bytes at once and returns them; they have to end
+    /// on a character boundary.
+    ///
+    /// Text and comments are mostly long runs without anything to look at, so
+    /// the hot loops find where the run ends with `memchr` and skip it here
+    /// instead of going one `bump` at a time.
+    fn bump_bytes(&mut self, len: usize) -> &'a str {
+        let (run, rest) = self.rest.split_at(len);
+        let bytes = run.as_bytes();
+        match memchr::memrchr(b'\n', bytes) {
+            Some(last) => {
+                self.line += memchr::memchr_iter(b'\n', bytes).count();
+                self.col = run[last + 1..].chars().count() + 1;
+            }
+            None => self.col += run.chars().count(),
+        }
+        self.rest = rest;
+        run
     }
     
-    /// Reports a lexical error and exits the program.
-    /// 
-    /// # Panics
-    /// 
-    /// Always exits with status code 1
-    fn error_exit(&self, msg: &str) -> ! {
-        eprintln!("Lexical error at line {}, col {}: {}", self.line, self.col, msg);
-        exit(1);
+    /// Builds a lexical error at the current position.
+    fn error(&self, msg: &str) -> CompileError {
+        self.error_at(self.line, self.col, msg)
+    }
+
+    /// Builds a lexical error at the given position.
+    fn error_at(&self, line: usize, col: usize, msg: &str) -> CompileError {
+        #[cfg(feature = "tracing")]
+        tracing::error!(line, col, "lexical error: {}", msg);
+        CompileError::Lexical {
+            line,
+            col,
+            message: msg.to_string(),
+        }
     }
 
     /// Checks if a string is a valid hashtag word.
     fn is_hash_word(&self, upper: &str) -> bool {
-        matches!(upper, 
-            "HAI" | "KTHXBYE" | "OBTW" | "TLDR" | "MAEK" | "OIC" | 
-            "GIMMEH" | "MKAY" | "I HAZ" | "IT IZ" | "LEMME SEE"
-        )
+        hash_word(upper).is_some()
     }
 
     /// Checks if a string is a valid language keyword.
     fn is_keyword(&self, upper: &str) -> bool {
-        matches!(upper,
-            "HEAD" | "TITLE" | "PARAGRAF" | "BOLD" | "ITALICS" | 
-            "LIST" | "ITEM" | "NEWLINE" | "SOUNDZ" | "VIDZ"
-        )
+        keyword(upper).is_some()
     }
     
     /// Skips a multi-line comment block (`#OBTW` ... `#TLDR`).
     /// 
     /// Ensures every `#OBTW` has a matching `#TLDR` closing tag.
     // ensures every #OBTW has a closing #TLDR which is technically some syntax analysis but only for comments
-    fn skip_multiline_comment(&mut self) {
+    fn skip_multiline_comment(&mut self) -> Result<(), CompileError> {
         loop {
             if self.peek().is_none() {
-                self.error_exit("Unclosed comment block - missing #TLDR");
+                return Err(self.error("Unclosed comment block - missing #TLDR"));
             }
             
             if self.peek() == Some('#') {
@@ -123,18 +318,21 @@ impl <'a> Lexer <'a> {
                 let mut word = String::new();
                 while let Some(c) = self.peek() {
                     if c.is_ascii_alphabetic() {
-                        word.push(self.bump().unwrap());
+                        self.bump();
+                        word.push(c);
                     } else {
                         break;
                     }
                 }
                 
-                if word.to_ascii_uppercase() == "TLDR" {
-                    return; // Comment block closed
+                if word.eq_ignore_ascii_case("TLDR") {
+                    return Ok(()); // Comment block closed
                 }
                 // Not TLDR, continue searching
             } else {
-                self.bump();
+                // only a `#` can close the comment
+                let len = memchr::memchr(b'#', self.rest.as_bytes()).unwrap_or(self.rest.len());
+                self.bump_bytes(len);
             }
         }
     }
@@ -142,8 +340,8 @@ impl <'a> Lexer <'a> {
     /// Reads a hashtag word token (e.g., `#HAI`, `#I HAZ`, `#LEMME SEE`).
     /// 
     /// Handles both single-word and two-word hashtag keywords.
-    /// Multi-line comments (`#OBTW` ... `#TLDR`) are skipped entirely.
-    fn read_hash_word(&mut self, start_line: usize, start_col: usize) -> Token {
+    /// Multi-line comments (`#OBTW` ... `#TLDR`) are skipped entirely and give no token.
+    fn read_hash_word(&mut self, start_line: usize, start_col: usize) -> Result<Option<Token>, CompileError> {
         //consume #
         self.get_char();
         
@@ -151,8 +349,8 @@ impl <'a> Lexer <'a> {
         self.cur.clear();
         while let Some(c) = self.peek() {
             if c.is_ascii_alphabetic() {
-                let ch = self.get_char().unwrap();
-                self.add_char(ch);
+                self.get_char();
+                self.add_char(c);
             } else {
                 break;
             }
@@ -160,87 +358,98 @@ impl <'a> Lexer <'a> {
         
         let first_word = self.cur.to_ascii_uppercase();
         
-        // Check for hashkey words with 2 words: #I HAZ, #IT IZ, #LEMME SEE
-        let full_word = if first_word == "I" && self.peek() == Some(' ') {
+        // Check for hashkey words with 2 words: #I HAZ, #IT IZ, #LEMME SEE, #IZ DEFINED
+        let mut full_word = first_word;
+        let two_word = TWO_WORD_HASH_WORDS.iter().find(|(first, _, _)| *first == full_word);
+        if let Some(&(_, expected, word)) = two_word && self.peek() == Some(' ') {
             self.get_char(); // consume space
             let mut second = String::new();
             while let Some(c) = self.peek() {
                 if c.is_ascii_alphabetic() {
-                    let ch = self.get_char().unwrap();
-                    second.push(ch);
+                    self.get_char();
+                    second.push(c);
                 } else {
                     break;
                 }
             }
-            if second.to_ascii_uppercase() == "HAZ" {
-                "I HAZ".to_string()
-            } else {
-                first_word
+            if second.eq_ignore_ascii_case(expected) {
+                full_word = word.to_string();
             }
-        } else if first_word == "IT" && self.peek() == Some(' ') {
-            self.get_char(); // consume space
-            let mut second = String::new();
-            while let Some(c) = self.peek() {
-                if c.is_ascii_alphabetic() {
-                    let ch = self.get_char().unwrap();
-                    second.push(ch);
-                } else {
-                    break;
-                }
-            }
-            if second.to_ascii_uppercase() == "IZ" {
-                "IT IZ".to_string()
-            } else {
-                first_word
-            }
-        } else if first_word == "LEMME" && self.peek() == Some(' ') {
-            self.get_char(); // consume space
-            let mut second = String::new();
-            while let Some(c) = self.peek() {
-                if c.is_ascii_alphabetic() {
-                    let ch = self.get_char().unwrap();
-                    second.push(ch);
-                } else {
-                    break;
-                }
-            }
-            if second.to_ascii_uppercase() == "SEE" {
-                "LEMME SEE".to_string()
-            } else {
-                first_word
-            }
-        } else {
-            first_word
-        };
+        }
         
         //checking if valid hashtag word using lookup
-        if !self.lookup(&full_word) {
-            self.error_exit(&format!("Unrecognized hashtag word '#{}'", full_word));
-        }
+        let Some(word) = hash_word(&full_word) else {
+            return Err(self.error(&format!("Unrecognized hashtag word '#{}'", full_word)));
+        };
         
         // OBTW...TLDR is a multi-line comment block - skip it entirely
-        if full_word == "OBTW" {
-            self.skip_multiline_comment();
-            // After comment, get next real token
-            return self.get_next_token();
+        if word == "#OBTW" {
+            self.skip_multiline_comment()?;
+            return Ok(None);
+        }
+
+        // the diagnostic code is part of the pragma
+        if word == "#SHHH" {
+            return self.read_shhh_code(start_line, start_col).map(Some);
         }
         
         //put together token
-        Token {
-            kind: TokenKind::HashWord(format!("#{}", full_word)),
+        Ok(Some(Token {
+            kind: TokenKind::HashWord(word),
             line: start_line,
             col: start_col,
+        }))
+    }
+
+    /// Reads the diagnostic code after `#SHHH` and returns the pragma as one token.
+    fn read_shhh_code(&mut self, start_line: usize, start_col: usize) -> Result<Token, CompileError> {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.get_char();
         }
+        let mut code = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            self.get_char();
+            code.push(c.to_ascii_lowercase());
+        }
+        if code.is_empty() {
+            return Err(self.error_at(start_line, start_col, "Expected a diagnostic code after #SHHH"));
+        }
+        let Some(code) = CODES.iter().copied().find(|known| *known == code) else {
+            return Err(self.error_at(start_line, start_col, &format!(
+                "Unknown diagnostic code '{}' after #SHHH, expected one of: {}",
+                code,
+                CODES.join(", ")
+            )));
+        };
+        Ok(Token {
+            kind: TokenKind::Shhh(code),
+            line: start_line,
+            col: start_col,
+        })
     }
 
     /// Reads a word token (keyword or variable name).
+    ///
+    /// Words follow the Unicode identifier rules (XID), so `café` or `名前` is one
+    /// word. Keywords are matched case-insensitively in ASCII only.
     fn read_word(&mut self, start_line: usize, start_col: usize) -> Token {
         self.cur.clear();
+        // `C\#` is one word of text
+        let mut escaped = false;
         
         while let Some(c) = self.peek() {
-            if c.is_ascii_alphanumeric() {
-                let ch = self.get_char().unwrap();
-                self.add_char(ch);
+            if is_xid_continue(c) {
+                self.get_char();
+                self.add_char(c);
+            } else if c == '\\' && matches!(self.ahead().next(), Some('#' | '\\')) {
+                self.get_char();
+                if let Some(c) = self.get_char() {
+                    self.add_char(c);
+                }
+                escaped = true;
             } else {
                 break;
             }
@@ -249,9 +458,15 @@ impl <'a> Lexer <'a> {
         let upper = self.cur.to_ascii_uppercase();
         
         // Check if it's a keyword using lookup
-        if self.lookup(&upper) {
+        if escaped {
             Token {
-                kind: TokenKind::Keyword(upper),
+                kind: TokenKind::Text(self.cur.clone()),
+                line: start_line,
+                col: start_col,
+            }
+        } else if let Some(word) = keyword(&upper) {
+            Token {
+                kind: TokenKind::Keyword(word),
                 line: start_line,
                 col: start_col,
             }
@@ -265,31 +480,283 @@ impl <'a> Lexer <'a> {
         }
     }
 
+    /// Reads what follows a backslash into `text` as plain text.
+    ///
+    /// `\#` is a literal `#`, `\\` a backslash and `\BOLD` (or any other keyword)
+    /// the word itself. Returns false for anything else, which keeps the backslash
+    /// so paths like `C:\Users` read as written.
+    fn read_escape(&mut self, text: &mut String) -> bool {
+        let Some(c) = self.peek() else {
+            return false;
+        };
+        if c == '#' || c == '\\' {
+            self.bump();
+            text.push(c);
+            return true;
+        }
+
+        // look at the whole word before deciding to take it
+        let word: String = std::iter::once(c)
+            .chain(self.ahead())
+            .take_while(|c| is_xid_continue(*c))
+            .collect();
+        if keyword(&word.to_ascii_uppercase()).is_none() {
+            return false;
+        }
+        for _ in word.chars() {
+            self.bump();
+        }
+        text.push_str(&word);
+        true
+    }
+
+    /// True if the input continues with `scheme://`.
+    fn url_ahead(&self) -> bool {
+        let Some(first) = self.peek() else {
+            return false;
+        };
+        if !first.is_ascii_alphabetic() {
+            return false;
+        }
+        let mut rest = self.ahead().peekable();
+        while rest.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')).is_some() {}
+        rest.next() == Some(':') && rest.next() == Some('/') && rest.next() == Some('/')
+    }
+
+    /// True if the value after `#IT IZ` starts with `<name> R `, naming the
+    /// variable it is assigned to. `name` has to be declared before it, so a
+    /// plain value like `Toys R Us` stays a value.
+    fn assignment_target_ahead(&self) -> bool {
+        if !self.peek().is_some_and(is_xid_start) {
+            return false;
+        }
+        let mut rest = std::iter::once(self.peek().unwrap_or_default())
+            .chain(self.ahead())
+            .peekable();
+        let mut name = String::new();
+        while let Some(c) = rest.next_if(|c| is_xid_continue(*c)) {
+            name.push(c);
+        }
+        let mut spaced = false;
+        while rest.next_if(|c| matches!(c, ' ' | '\t')).is_some() {
+            spaced = true;
+        }
+        spaced
+            && rest.next() == Some('R')
+            && matches!(rest.next(), Some(' ' | '\t' | '"'))
+            && is_variable_name(&name)
+            && self.declared.contains(&name)
+    }
+
+    /// Reads the `<name> R` of an assignment target, see `assignment_target_ahead`.
+    fn read_assignment_target(&mut self, start_line: usize, start_col: usize) -> Token {
+        self.cur.clear();
+        while let Some(c) = self.peek().filter(|c| is_xid_continue(*c)) {
+            self.get_char();
+            self.add_char(c);
+        }
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+        // the `R`
+        self.bump();
+        Token {
+            kind: TokenKind::VarDef(self.cur.clone()),
+            line: start_line,
+            col: start_col,
+        }
+    }
+
+    /// True if the input continues with a hashtag word, e.g. the `#MKAY` right
+    /// after a URL.
+    fn hash_word_ahead(&self) -> bool {
+        if self.peek() != Some('#') {
+            return false;
+        }
+        let word: String = self.ahead().take_while(|c| c.is_ascii_alphabetic()).collect();
+        let word = word.to_ascii_uppercase();
+        // two-word ones like `#I HAZ` only need their first word here
+        HASH_WORDS.iter().any(|w| w[1..].split(' ').next() == Some(word.as_str()))
+    }
+
+    /// Reads a URL up to the next whitespace or hashtag word.
+    fn read_address(&mut self, start_line: usize, start_col: usize) -> Token {
+        let mut url = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || ZERO_WIDTH.contains(&c) || self.hash_word_ahead() {
+                break;
+            }
+            self.bump();
+            url.push(c);
+        }
+        Token {
+            kind: TokenKind::Address(url),
+            line: start_line,
+            col: start_col,
+        }
+    }
+
+    /// Reads a quoted YARN literal, applying its escapes.
+    fn read_yarn(&mut self, start_line: usize, start_col: usize) -> Result<Token, CompileError> {
+        self.bump(); // consume opening quote
+        let mut yarn = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => {
+                    return Err(self.error_at(start_line, start_col, "Unclosed YARN literal - missing closing quote"));
+                }
+                Some('"') => {
+                    self.bump();
+                    break;
+                }
+                Some('\\') => {
+                    self.bump();
+                    match self.peek() {
+                        Some(c @ ('"' | '\\')) => yarn.push(c),
+                        Some('n') => yarn.push('\n'),
+                        Some('t') => yarn.push('\t'),
+                        // anything else keeps its backslash
+                        _ => {
+                            yarn.push('\\');
+                            continue;
+                        }
+                    }
+                    self.bump();
+                }
+                Some(c) => {
+                    self.bump();
+                    yarn.push(c);
+                }
+            }
+        }
+        Ok(Token {
+            kind: TokenKind::Yarn(yarn),
+            line: start_line,
+            col: start_col,
+        })
+    }
+
     /// Reads a line of plain text content.
     /// 
-    /// Stops at newlines or hashtag symbols. Empty text is skipped.
-    fn read_text_line(&mut self, start_line: usize, start_col: usize) -> Token {
+    /// Stops at newlines or hashtag symbols. Empty text gives no token.
+    /// A backslash escapes `#`, itself and keywords, see `read_escape`.
+    fn read_text_line(&mut self, start_line: usize, start_col: usize) -> Option<Token> {
         let mut text = String::new();
         
         while let Some(c) = self.peek() {
-            // Stop at newline or hashtag
-            if c == '\n' || c == '#' {
+            // everything up to the next newline, hashtag or backslash is plain text
+            let plain = memchr::memchr3(b'\n', b'#', b'\\', self.rest.as_bytes()).unwrap_or(self.rest.len());
+            if plain > 0 {
+                text.push_str(self.bump_bytes(plain));
+                continue;
+            }
+            // Stop at newline or hashtag, but not at the fragment of a URL
+            if c == '\n' || (c == '#' && (self.hash_word_ahead() || !in_url(&text))) {
                 break;
             }
-            text.push(self.bump().unwrap());
+            self.bump();
+            if c == '\\' && self.read_escape(&mut text) {
+                continue;
+            }
+            text.push(c);
         }
         
         let trimmed = text.trim().to_string();
         
         // If empty skip to next token
         if trimmed.is_empty() {
-            return self.get_next_token();
+            return None;
         }
         
-        Token {
+        Some(Token {
             kind: TokenKind::Text(trimmed),
             line: start_line,
             col: start_col,
+        })
+    }
+
+    /// Scans the next token; `get_next_token` keeps track of where YARN may start.
+    fn scan_token(&mut self) -> Result<Token, CompileError> {
+        // comments and blank text give no token, keep going until something does
+        loop {
+            let skipped_from = self.offset();
+            // Skip spaces/tabs and zero-width characters, don't ignore newlines
+            while let Some(c) = self.peek() {
+                if c == ' ' || c == '\t' || ZERO_WIDTH.contains(&c) {
+                    self.bump();
+                } else {
+                    break;
+                }
+            }
+            self.keep_trivia(skipped_from, false);
+
+            self.token_start = self.offset();
+            let start_line = self.line;
+            let start_col = self.col;
+
+            // Check for EOF
+            let ch = match self.peek() {
+                Some(c) => c,
+                None => return Ok(Token {
+                    kind: TokenKind::Eof,
+                    line: start_line,
+                    col: start_col,
+                }),
+            };
+
+            // newlines are significant
+            if ch == '\n' {
+                self.bump();
+                return Ok(Token {
+                    kind: TokenKind::Newline,
+                    line: start_line,
+                    col: start_col,
+                });
+            }
+
+            // quoted value
+            if ch == '"' && self.yarn_allowed {
+                return self.read_yarn(start_line, start_col);
+            }
+
+            // Check for hashtag tokens
+            if ch == '#' {
+                if let Some(token) = self.read_hash_word(start_line, start_col)? {
+                    return Ok(token);
+                }
+                self.keep_trivia(self.token_start, true);
+                continue;
+            }
+
+            // `#IT IZ <name> R <value>` names the variable it assigns to
+            if self.value_mode && self.assignment_target_ahead() {
+                return Ok(self.read_assignment_target(start_line, start_col));
+            }
+
+            // an unquoted value is taken as one piece, punctuation and spacing included
+            if self.value_mode && ch != '#' {
+                if let Some(Token { kind: TokenKind::Text(value), line, col }) = self.read_text_line(start_line, start_col) {
+                    return Ok(Token { kind: TokenKind::VarVal(value), line, col });
+                }
+                self.keep_trivia(self.token_start, false);
+                continue;
+            }
+
+            // URLs keep their `#fragment` and punctuation
+            if self.url_ahead() {
+                return Ok(self.read_address(start_line, start_col));
+            }
+
+            // Handle keywords and variable names
+            if is_xid_start(ch) {
+                return Ok(self.read_word(start_line, start_col));
+            }
+
+            // anything else is treated as plain text (numbers, punctuation, etc.)
+            if let Some(token) = self.read_text_line(start_line, start_col) {
+                return Ok(token);
+            }
+            self.keep_trivia(self.token_start, false);
         }
     }
 }
@@ -310,52 +777,20 @@ impl<'a> LexicalAnalyzer for Lexer<'a> {
 
     /// Retrieves the next token from the input.
     /// 
-    /// Skips whitespace (spaces and tabs) but preserves newlines as tokens.
-    /// Recognizes hashtag words, keywords, variables, and text content.
-    fn get_next_token(&mut self) -> Token {
-        // Skip spaces/tabs, don't ignore newlines
-        while let Some(c) = self.peek() {
-            if c == ' ' || c == '\t' {
-                self.bump();
-            } else {
-                break;
-            }
+    /// Skips whitespace (spaces, tabs and zero-width characters) but preserves newlines as tokens.
+    /// Recognizes hashtag words, keywords, variables, text content and YARN literals.
+    fn get_next_token(&mut self) -> Result<Token, CompileError> {
+        let token = self.scan_token()?;
+        // the value still follows an assignment target
+        let target = self.value_mode && matches!(token.kind, TokenKind::VarDef(_));
+        self.yarn_allowed = target || matches!(token.kind, TokenKind::HashWord("#IT IZ") | TokenKind::Keyword("TITLE"));
+        self.value_mode = target || token.kind == TokenKind::HashWord("#IT IZ");
+        if self.declaring
+            && let TokenKind::VarDef(name) = &token.kind
+        {
+            self.declared.insert(name.clone());
         }
-
-        let start_line = self.line;
-        let start_col = self.col;
-
-        // Check for EOF
-        let ch = match self.peek() {
-            Some(c) => c,
-            None => return Token {
-                kind: TokenKind::Eof,
-                line: start_line,
-                col: start_col,
-            },
-        };
-
-        // newlines are significant
-        if ch == '\n' {
-            self.bump();
-            return Token {
-                kind: TokenKind::Newline,
-                line: start_line,
-                col: start_col,
-            };
-        }
-
-        // Check for hashtag tokens
-        if ch == '#' {
-            return self.read_hash_word(start_line, start_col);
-        }
-
-        // Handle keywords and variable names
-        if ch.is_ascii_alphabetic() {
-            return self.read_word(start_line, start_col);
-        }
-
-        // anything else is treated as plain text (numbers, punctuation, etc.)
-        self.read_text_line(start_line, start_col)
+        self.declaring = token.kind == TokenKind::HashWord("#I HAZ");
+        Ok(token)
     }
-}
\ No newline at end of file
+}
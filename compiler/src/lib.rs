@@ -8,8 +8,124 @@
 //! 2. **Syntax Analysis** - Building an Abstract Syntax Tree
 //! 3. **Semantic Analysis** - Variable scope and usage validation
 //! 4. **Code Generation** - HTML output generation
+//!
+//! Progress messages go through the `log` crate at `info` level (details at
+//! `debug`), so nothing is printed unless the embedder installs a logger.
+//! The lexer, parser and semantic analyzer return errors in the source as a
+//! `diagnostics::CompileError`; only `Pipeline::run` prints them and exits.
+//! Each module's documentation covers what else it offers embedders, editors
+//! and test suites (`playground`, `stages`, `symbols`, `golden`, ...).
+//!
+//! `compile_str` and `compile_file` are the supported way to compile from other
+//! programs: one call each, errors back as a `CompileError`, nothing printed.
+//!
+//! ```text
+//! let html = lolcompiler::compile_str(source)?;
+//! let written = lolcompiler::compile_file(Path::new("index.lol"), &OutputOptions::default())?;
+//! ```
+//!
+//! With the `tracing` feature each stage runs inside a `tracing` span (`compile`,
+//! `lex`, `parse`, `semantic`, `codegen`, `emit`) and errors and warnings are also
+//! emitted as events, so embedders can feed compiles into their own subscriber.
 
 pub mod token;
 pub mod lexer;
+pub mod arena;
+pub mod intern;
+pub mod binast;
 pub mod parser;
-pub mod semantic;
\ No newline at end of file
+pub mod cst;
+pub mod nodes;
+pub mod query;
+pub mod diff;
+pub mod classic;
+pub mod semantic;
+pub mod symbols;
+pub mod completion;
+pub mod repl;
+pub mod builtins;
+pub mod pipeline;
+pub mod stages;
+pub mod timings;
+pub mod target;
+pub mod doctype;
+pub mod pdf;
+pub mod slides;
+pub mod gemtext;
+pub mod ansi;
+pub mod roff;
+pub mod asciidoc;
+pub mod xml;
+pub mod sanitize;
+pub mod integrity;
+pub mod diagnostics;
+pub mod links;
+pub mod lint;
+pub mod media;
+pub mod meta;
+pub mod feed;
+pub mod site;
+pub mod glob;
+pub mod ignore;
+pub mod output;
+pub mod cache;
+pub mod fuzz;
+pub mod playground;
+pub mod golden;
+pub mod print;
+pub mod json;
+#[cfg(feature = "arbitrary")]
+pub mod treegen;
+
+use arena::AstArena;
+use diagnostics::CompileError;
+use parser::{LolcodeParser, Parser};
+use semantic::{LolcodeSemanticAnalyzer, OutputOptions};
+use std::path::{Path, PathBuf};
+use target::Target;
+
+/// Compiles a document to HTML with the default options.
+pub fn compile_str(source: &str) -> Result<String, CompileError> {
+    compile(source, &OutputOptions::default()).map(|(generated, _)| generated)
+}
+
+/// Compiles the document at `path` for `options.target` and writes the output
+/// next to it, as the command line compiler does for a single page; an output
+/// that is already up to date is left alone. Returns
+/// the path of the written file: the `.pdf` for the PDF target, the `.txt`
+/// for ANSI. The browser is never opened.
+pub fn compile_file(path: &Path, options: &OutputOptions) -> Result<PathBuf, CompileError> {
+    let source = site::read_source(path, site::DEFAULT_MAX_INPUT_SIZE).map_err(|message| CompileError::Io { message })?;
+    let (generated, analyzer) = compile(&source, options)?;
+    let output_path = analyzer.output_path(&path.to_string_lossy());
+    output::write_if_changed(&output_path, generated.as_bytes()).map_err(|e| CompileError::Io {
+        message: format!("Failed to write '{}': {}", output_path.display(), e),
+    })?;
+    if options.target == Target::Pdf {
+        let pdf_path = output_path.with_extension("pdf");
+        pdf::render_pdf(&output_path, &pdf_path).map_err(|message| CompileError::Io { message })?;
+        return Ok(pdf_path);
+    }
+    Ok(output_path)
+}
+
+// every stage in memory, without the lints or links to other pages; gives the
+// analyzer back for the name of the output
+fn compile(source: &str, options: &OutputOptions) -> Result<(String, LolcodeSemanticAnalyzer), CompileError> {
+    let arena = AstArena::new();
+    let mut parser = LolcodeParser::new(source, &arena);
+    parser.parse()?;
+
+    let mut analyzer = LolcodeSemanticAnalyzer::new();
+    analyzer.options = options.clone();
+    analyzer.options.open_browser = false;
+    let Some(tree) = parser.parse_tree else {
+        return Ok((String::new(), analyzer));
+    };
+    analyzer.interner = std::mem::take(&mut parser.interner);
+    analyzer.positions = std::mem::take(&mut parser.positions);
+    analyzer.source_len = source.len();
+    analyzer.check_tree(tree)?;
+    let generated = analyzer.generate_output(tree);
+    Ok((generated, analyzer))
+}
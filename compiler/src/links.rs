@@ -0,0 +1,148 @@
+//! Link checker for embedded media.
+//!
+//! Walks the parse tree, collects every SOUNDZ/VIDZ/PIKCHUR URL and verifies that it
+//! still resolves. Remote addresses get a HEAD request (through `curl`, the same
+//! way the compiler shells out to open the browser) and local paths are checked
+//! on disk relative to the source file. Dead links come back as warnings. URLs
+//! built from variables are only known during codegen and aren't checked.
+
+use crate::diagnostics::{Diagnostic, Suppressions};
+use crate::parser::ASTNode;
+use std::path::Path;
+use std::process::Command;
+
+/// Collects the URL of every media node in the tree, in document order, except
+/// the ones built from variables.
+pub fn collect_media_urls(node: &ASTNode) -> Vec<String> {
+    let mut urls = Vec::new();
+    collect_into(node, &Suppressions::default(), &[], &mut urls);
+    urls.into_iter().map(|(url, _)| url).collect()
+}
+
+// each URL with the codes silenced where it was found
+fn collect_into(node: &ASTNode, shhh: &Suppressions, silenced: &[&'static str], urls: &mut Vec<(String, Vec<&'static str>)>) {
+    let silenced = shhh.enter(node, silenced);
+    match node {
+        ASTNode::Program { children }
+        | ASTNode::HeadSection { children }
+        | ASTNode::ParagrafSection { children }
+        | ASTNode::ListSection { children }
+        | ASTNode::IfDefined { children, .. } => {
+            for child in children.iter() {
+                collect_into(child, shhh, &silenced, urls);
+            }
+        }
+        ASTNode::Bold { content } | ASTNode::Italics { content } | ASTNode::Item { content } => {
+            for child in content.iter() {
+                collect_into(child, shhh, &silenced, urls);
+            }
+        }
+        ASTNode::Sound { url, attributes } if attributes.url_references.is_empty() => {
+            urls.push((url.to_string(), silenced.clone()));
+        }
+        ASTNode::Video { url, attributes } => {
+            if attributes.url_references.is_empty() {
+                urls.push((url.to_string(), silenced.clone()));
+            }
+            if let Some(poster) = &attributes.poster {
+                urls.push((poster.clone(), silenced.clone()));
+            }
+            for track in &attributes.captions {
+                urls.push((track.src.clone(), silenced.clone()));
+            }
+        }
+        ASTNode::Image { url, attributes } => {
+            if attributes.url_references.is_empty() {
+                urls.push((url.to_string(), silenced.clone()));
+            }
+            for source in &attributes.srcset {
+                // each entry is "<url> <descriptor>"
+                if let Some(src) = source.split_whitespace().next() {
+                    urls.push((src.to_string(), silenced.clone()));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Checks every media URL in the tree and returns a warning for each dead link.
+///
+/// `base_dir` is the directory of the source file; relative paths are resolved
+/// against it. Media covered by `#SHHH dead-link` isn't checked.
+pub fn check_links(tree: &ASTNode, base_dir: &Path, shhh: &Suppressions) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    let mut urls = Vec::new();
+    collect_into(tree, shhh, &[], &mut urls);
+
+    for (url, silenced) in urls {
+        if silenced.contains(&"dead-link") {
+            continue;
+        }
+        if url.is_empty() {
+            warnings.push(Diagnostic::warning("dead-link", "media element has an empty URL".to_string()));
+            continue;
+        }
+
+        if is_remote(&url) {
+            match head_status(&url) {
+                Some(0) => warnings.push(Diagnostic::warning("dead-link", format!(
+                    "dead link '{}' (no response)",
+                    url
+                ))),
+                Some(status) if status < 400 => {}
+                Some(status) => warnings.push(Diagnostic::warning("dead-link", format!(
+                    "dead link '{}' (HTTP {})",
+                    url, status
+                ))),
+                None if silenced.contains(&"unchecked-link") => {}
+                None => warnings.push(Diagnostic::warning("unchecked-link", format!(
+                    "could not check '{}': curl is not available",
+                    url
+                ))),
+            }
+        } else {
+            // strip a file:// scheme so local files can be written either way
+            let local = url.strip_prefix("file://").unwrap_or(&url);
+            let path = Path::new(local);
+            let full_path = if path.is_absolute() {
+                path.to_path_buf()
+            } else {
+                base_dir.join(path)
+            };
+
+            if !full_path.exists() {
+                warnings.push(Diagnostic::warning("dead-link", format!(
+                    "dead link '{}' (file not found)",
+                    url
+                )));
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Returns true for addresses that need a network request to check.
+fn is_remote(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Sends a HEAD request and returns the final HTTP status code.
+///
+/// Returns `Some(0)` when the host could not be reached and `None` when curl
+/// itself could not be run.
+fn head_status(url: &str) -> Option<u16> {
+    let output = Command::new("curl")
+        .args(["--head", "--silent", "--location", "--max-time", "10"])
+        .args(["--write-out", "\n%{http_code}"])
+        .arg(url)
+        .output()
+        .ok()?;
+
+    // the status code is the last line, after the headers
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let code = stdout.lines().last().unwrap_or("").trim();
+    Some(code.parse().unwrap_or(0))
+}
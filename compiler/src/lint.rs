@@ -0,0 +1,253 @@
+//! Lint rules for LOLCODE documents.
+//!
+//! Lints look for documents that compile fine but are probably not what the
+//! author wanted. Each rule has its own level in `LintOptions`: off, a warning,
+//! or an error that stops compilation.
+
+use crate::diagnostics::{Diagnostic, Severity, Suppressions};
+use crate::lexer::{BOM, ZERO_WIDTH};
+use crate::media;
+use crate::meta;
+use crate::parser::ASTNode;
+
+/// Levels for each lint rule. `None` turns a rule off.
+#[derive(Debug, Clone)]
+pub struct LintOptions {
+    /// Images without ALT text and videos without CAPTIONS
+    pub media_accessibility: Option<Severity>,
+    /// Zero-width characters anywhere but a BOM at the start
+    pub invisible_characters: Option<Severity>,
+    /// Documents without a TITLE or with more than one
+    pub titles: Option<Severity>,
+    /// Sections and styled text with nothing in them
+    pub empty_constructs: Option<Severity>,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self {
+            media_accessibility: Some(Severity::Warning),
+            invisible_characters: Some(Severity::Warning),
+            titles: Some(Severity::Warning),
+            empty_constructs: Some(Severity::Warning),
+        }
+    }
+}
+
+/// Parses a lint level given on the command line (`off`, `warn` or `error`).
+pub fn parse_level(level: &str) -> Option<Option<Severity>> {
+    match level.to_ascii_lowercase().as_str() {
+        "off" => Some(None),
+        "warn" | "warning" => Some(Some(Severity::Warning)),
+        "error" | "deny" => Some(Some(Severity::Error)),
+        _ => None,
+    }
+}
+
+/// Runs the enabled lint rules that look at the tree. Findings inside a node
+/// covered by `#SHHH` for their code are left out.
+pub fn lint(tree: &ASTNode, options: &LintOptions, shhh: &Suppressions) -> Vec<Diagnostic> {
+    let mut found = Vec::new();
+    if let Some(severity) = options.media_accessibility {
+        media_accessibility(tree, severity, shhh, &[], &mut found);
+    }
+    if let Some(severity) = options.titles {
+        titles(tree, severity, shhh, &mut found);
+    }
+    if let Some(severity) = options.empty_constructs {
+        let mut counts = Vec::new();
+        empty_constructs(tree, severity, shhh, &[], &mut counts, &mut found);
+    }
+    found
+}
+
+/// Runs the lint rules that look at the source text rather than the tree.
+pub fn lint_source(source: &str, options: &LintOptions) -> Vec<Diagnostic> {
+    let mut found = Vec::new();
+    if let Some(severity) = options.invisible_characters {
+        invisible_characters(source, severity, &mut found);
+    }
+    found
+}
+
+/// Flags zero-width characters. The lexer skips them between tokens, but inside
+/// text and names they make words that look the same compare different.
+///
+/// Joiners between two non-ASCII characters are accepted: emoji sequences and
+/// several scripts need them.
+fn invisible_characters(source: &str, severity: Severity, found: &mut Vec<Diagnostic>) {
+    let source = source.strip_prefix(BOM).unwrap_or(source);
+    for (index, line) in source.split('\n').enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        for (col, &c) in chars.iter().enumerate() {
+            if !ZERO_WIDTH.contains(&c) {
+                continue;
+            }
+            let joins = matches!(c, '\u{200C}' | '\u{200D}')
+                && col > 0
+                && chars.get(col + 1).is_some_and(|next| !next.is_ascii())
+                && !chars[col - 1].is_ascii();
+            if joins {
+                continue;
+            }
+            found.push(Diagnostic::new(
+                severity,
+                "invisible-character",
+                format!(
+                    "line {}, col {}: invisible character U+{:04X}",
+                    index + 1,
+                    col + 1,
+                    c as u32
+                ),
+            ));
+        }
+    }
+}
+
+/// Flags images without alt text and self-hosted videos without captions.
+///
+/// An empty `ALT` marks an image as decorative and is accepted. YouTube and
+/// Vimeo embeds are skipped since their captions live on the host.
+fn media_accessibility(
+    node: &ASTNode,
+    severity: Severity,
+    shhh: &Suppressions,
+    silenced: &[&'static str],
+    found: &mut Vec<Diagnostic>,
+) {
+    let silenced = shhh.enter(node, silenced);
+    match node {
+        ASTNode::Program { children }
+        | ASTNode::HeadSection { children }
+        | ASTNode::ParagrafSection { children }
+        | ASTNode::ListSection { children }
+        | ASTNode::IfDefined { children, .. } => {
+            for child in children.iter() {
+                media_accessibility(child, severity, shhh, &silenced, found);
+            }
+        }
+        ASTNode::Bold { content } | ASTNode::Italics { content } | ASTNode::Item { content } => {
+            for child in content.iter() {
+                media_accessibility(child, severity, shhh, &silenced, found);
+            }
+        }
+        ASTNode::Image { url, attributes } if attributes.alt.is_none() && !silenced.contains(&"missing-alt") => {
+            found.push(Diagnostic::new(severity, "missing-alt", format!("image '{}' has no ALT text", url)));
+        }
+        ASTNode::Video { url, attributes }
+            if attributes.captions.is_empty()
+                && media::detect_video_host(url).is_none()
+                && !silenced.contains(&"missing-captions") =>
+        {
+            found.push(Diagnostic::new(severity, "missing-captions", format!("video '{}' has no CAPTIONS", url)));
+        }
+        _ => {}
+    }
+}
+
+/// Flags a document without a TITLE, and every TITLE after the first, since
+/// each one becomes the page's main heading.
+///
+/// Titles under `#IZ DEFINED` count too: the lint runs before it is known
+/// which flags are defined.
+fn titles(tree: &ASTNode, severity: Severity, shhh: &Suppressions, found: &mut Vec<Diagnostic>) {
+    let mut titles = Vec::new();
+    collect_titles(tree, shhh, &[], &mut titles);
+    let Some((first, _)) = titles.first() else {
+        found.push(Diagnostic::new(severity, "missing-title", "document has no TITLE".to_string()));
+        return;
+    };
+    for (title, silenced) in &titles[1..] {
+        if silenced.contains(&"duplicate-title") {
+            continue;
+        }
+        found.push(Diagnostic::new(
+            severity,
+            "duplicate-title",
+            format!("TITLE '{}' after the document's TITLE '{}'", title, first),
+        ));
+    }
+}
+
+// every title in document order, with the codes silenced around it
+fn collect_titles(
+    node: &ASTNode,
+    shhh: &Suppressions,
+    silenced: &[&'static str],
+    titles: &mut Vec<(String, Vec<&'static str>)>,
+) {
+    let silenced = shhh.enter(node, silenced);
+    match node {
+        ASTNode::Program { children }
+        | ASTNode::HeadSection { children }
+        | ASTNode::ParagrafSection { children }
+        | ASTNode::ListSection { children }
+        | ASTNode::IfDefined { children, .. } => {
+            for child in children.iter() {
+                collect_titles(child, shhh, &silenced, titles);
+            }
+        }
+        ASTNode::Title { content } => titles.push((meta::title_text(content), silenced)),
+        _ => {}
+    }
+}
+
+/// Flags sections and styled text with nothing in them, which compile to empty
+/// elements and usually mean something was deleted by accident.
+///
+/// Lints get the tree without the parser's `Positions`, so a construct is
+/// named by its place among the ones of its kind: "PARAGRAF section 2 is empty".
+fn empty_constructs(
+    node: &ASTNode,
+    severity: Severity,
+    shhh: &Suppressions,
+    silenced: &[&'static str],
+    counts: &mut Vec<(&'static str, usize)>,
+    found: &mut Vec<Diagnostic>,
+) {
+    let silenced = shhh.enter(node, silenced);
+    let (kind, code, children) = match node {
+        ASTNode::HeadSection { children } => ("HEAD section", "empty-section", *children),
+        ASTNode::ParagrafSection { children } => ("PARAGRAF section", "empty-section", *children),
+        ASTNode::ListSection { children } => ("LIST section", "empty-section", *children),
+        ASTNode::Bold { content } => ("BOLD", "empty-styled-text", *content),
+        ASTNode::Italics { content } => ("ITALICS", "empty-styled-text", *content),
+        ASTNode::Item { content } => ("ITEM", "empty-styled-text", *content),
+        ASTNode::MetaSection { entries } => {
+            let number = count(counts, "META section");
+            if entries.is_empty() && !silenced.contains(&"empty-section") {
+                found.push(Diagnostic::new(severity, "empty-section", format!("META section {} is empty", number)));
+            }
+            return;
+        }
+        ASTNode::Program { children } | ASTNode::IfDefined { children, .. } => {
+            for child in children.iter() {
+                empty_constructs(child, severity, shhh, &silenced, counts, found);
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    let number = count(counts, kind);
+    if children.is_empty() && !silenced.contains(&code) {
+        found.push(Diagnostic::new(severity, code, format!("{} {} is empty", kind, number)));
+    }
+    for child in children.iter() {
+        empty_constructs(child, severity, shhh, &silenced, counts, found);
+    }
+}
+
+// counts one more construct of `kind` and returns its number, from 1
+fn count(counts: &mut Vec<(&'static str, usize)>, kind: &'static str) -> usize {
+    match counts.iter_mut().find(|(counted, _)| *counted == kind) {
+        Some((_, number)) => {
+            *number += 1;
+            *number
+        }
+        None => {
+            counts.push((kind, 1));
+            1
+        }
+    }
+}
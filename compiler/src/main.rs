@@ -7,7 +7,15 @@
 //! 
 //! ```bash
 //! cargo run <file.lol>
+//! cargo run <file.lol> <other.lol> <directory> ...
+//! cargo run -- rename <file.lol> <name | line:col> <new-name>
+//! cargo run -- clean [file.lol | dir]...
+//! cargo run -- repl
 //! ```
+//!
+//! Several inputs build as one project whose pages can link to each other. Every
+//! option and command, and the exit status of each kind of failure (the
+//! `diagnostics::EXIT_*` codes), is described in `README.md` at the repository root.
 //! 
 //! ## Pipeline
 //! 
@@ -17,66 +25,1116 @@
 //! 3. **Semantic Analysis** - Validates variable usage and scoping
 //! 4. **Code Generation** - Produces HTML output from the validated AST
 
-mod token;
-mod lexer;
-mod parser;
-mod semantic;
+use lolcompiler::arena::AstArena;
+use lolcompiler::binast;
+use lolcompiler::builtins::BuildTime;
+use lolcompiler::cache::{self, BuildCache, SourceKey};
+use lolcompiler::doctype::Doctype;
+use lolcompiler::feed::{self, FeedEntry};
+use lolcompiler::integrity;
+use lolcompiler::json;
+use lolcompiler::meta::{self, DocumentMeta};
+use lolcompiler::lexer;
+use lolcompiler::lint::{self, LintOptions};
+use lolcompiler::output;
+use lolcompiler::parser::DEFAULT_MAX_NESTING;
+use lolcompiler::pipeline::{Pipeline, PipelineObserver};
+use lolcompiler::semantic::{LolcodeSemanticAnalyzer, OutputOptions};
+use lolcompiler::repl::{self, Session};
+use lolcompiler::sanitize::ValuePolicy;
+use lolcompiler::site::{self, OutputNaming, Page};
+use lolcompiler::symbols;
+use lolcompiler::target::Target;
+use lolcompiler::diagnostics::{self, Diagnostic, Severity};
+use lolcompiler::timings::Timings;
+use lolcompiler::token::Token;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use rayon::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-use lexer::{LexicalAnalyzer, Lexer};
-use parser::{LolcodeParser, Parser};
-use semantic::LolcodeSemanticAnalyzer;
-use std::path::Path;
+/// Prints the library's log messages: progress on stdout, warnings and errors on stderr.
+struct ConsoleLogger;
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if record.level() <= Level::Warn {
+            eprintln!("{}", record.args());
+        } else {
+            println!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+/// Command line settings shared by every page of a build.
+struct BuildSettings {
+    check_links: bool,
+    timings: bool,
+    save_ast: bool,
+    emit: Emit,
+    max_nesting: usize,
+    case_insensitive: bool,
+    classic: bool,
+    allow_late_head: bool,
+    // in command line order, a later define of a name wins
+    defines: Vec<(String, String)>,
+    build_time: BuildTime,
+    // --build-time or SOURCE_DATE_EPOCH, the only build times that belong in the cache key
+    fixed_build_time: Option<BuildTime>,
+    max_input_size: u64,
+    options: OutputOptions,
+    lint_options: LintOptions,
+    // page name -> META lang, only read when output names use it
+    langs: HashMap<String, String>,
+    // a page with an error is reported with the others instead of ending the build
+    keep_going: bool,
+}
+
+impl BuildSettings {
+    /// Where a page's output goes.
+    fn output_path(&self, page: &Page) -> PathBuf {
+        let lang = self.langs.get(&page.name).map(String::as_str);
+        self.options.naming.output_path(&page.source, self.options.target.extension(), lang)
+    }
+
+    /// Hash of everything besides the sources that changes the output, so a
+    /// cached page is only reused for the same compiler, settings and set of
+    /// pages. Pages link to each other's output names, so with `{lang}` in
+    /// the name every page's language is part of it too.
+    fn config_hash(&self, pages: &[Page]) -> u64 {
+        let mut config = format!(
+            "{} {:?} {:?} {} {} {:?} {} {} {} {} {:?} {:?}",
+            env!("CARGO_PKG_VERSION"),
+            self.options,
+            self.lint_options,
+            self.check_links,
+            self.save_ast,
+            self.emit,
+            self.max_nesting,
+            self.case_insensitive,
+            self.classic,
+            self.allow_late_head,
+            self.fixed_build_time,
+            self.defines
+        );
+        for page in pages {
+            config.push('\n');
+            config.push_str(&page.name);
+            if let Some(lang) = self.langs.get(&page.name) {
+                config.push(' ');
+                config.push_str(lang);
+            }
+        }
+        cache::hash_bytes(config.as_bytes())
+    }
+}
+
+/// What `--emit` asks each page's build to write.
+#[derive(Debug, Clone, Copy)]
+struct Emit {
+    /// The page itself, in the `--target` format
+    page: bool,
+    /// Every token, one per line
+    tokens: bool,
+    /// The parse tree as JSON
+    ast_json: bool,
+    /// Scopes and variables as JSON
+    symbols: bool,
+}
+
+impl Emit {
+    /// Reads the value of `--emit`: a comma-separated list of kinds.
+    fn parse(list: &str) -> Option<Self> {
+        let mut emit = Self { page: false, tokens: false, ast_json: false, symbols: false };
+        for kind in list.split(',') {
+            match kind.trim() {
+                "html" => emit.page = true,
+                "tokens" => emit.tokens = true,
+                "ast-json" => emit.ast_json = true,
+                "symbols" => emit.symbols = true,
+                _ => return None,
+            }
+        }
+        Some(emit)
+    }
+
+    /// Extensions of the files besides the page that are asked for.
+    fn extensions(&self) -> Vec<&'static str> {
+        let mut extensions = Vec::new();
+        if self.tokens {
+            extensions.push("tokens");
+        }
+        if self.ast_json {
+            extensions.push("ast.json");
+        }
+        if self.symbols {
+            extensions.push("symbols.json");
+        }
+        extensions
+    }
+}
+
+/// Keeps the tokens of a page as text for `--emit tokens`.
+#[derive(Default)]
+struct TokenDump {
+    text: String,
+}
+
+impl PipelineObserver for TokenDump {
+    fn after_lexing(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            self.text.push_str(&format!("{}:{} {:?}\n", token.line, token.col, token.kind));
+        }
+    }
+}
+
+/// Reads the value of `--define`: `NAME` or `NAME=value`.
+fn parse_define(define: &str) -> (String, String) {
+    // a bare flag for #IZ DEFINED is true
+    let (name, value) = define.split_once('=').unwrap_or((define, "WIN"));
+    if !lexer::is_variable_name(name) {
+        eprintln!("Error: --define expects NAME or NAME=value, with NAME a variable name");
+        std::process::exit(diagnostics::EXIT_USAGE);
+    }
+    (name.to_string(), value.to_string())
+}
+
+/// Reads the value of `--output-ext`, with or without a leading dot.
+fn parse_output_ext(extension: &str) -> String {
+    let extension = extension.trim_start_matches('.');
+    if extension.is_empty() || extension.contains(['/', '\\']) {
+        eprintln!("Error: --output-ext expects an extension such as htm or xhtml");
+        std::process::exit(diagnostics::EXIT_USAGE);
+    }
+    extension.to_string()
+}
+
+/// Reads the value of `--output-name`.
+fn parse_output_name(template: String) -> String {
+    //every page needs a name of its own, next to its source
+    if !template.contains("{stem}") || template.contains(['/', '\\']) {
+        eprintln!("Error: --output-name expects a file name template with {{stem}}, e.g. {{stem}}.{{lang}}.{{ext}}");
+        std::process::exit(diagnostics::EXIT_USAGE);
+    }
+    template
+}
+
+/// `lolcompiler clean`: removes what builds of the given pages left next to
+/// them, the files the build cache lists or else the outputs for one target.
+fn clean(mut args: impl Iterator<Item = String>) {
+    let mut naming = OutputNaming::default();
+    let mut target = Target::default();
+    let mut dry_run = false;
+    let mut verbosity = LevelFilter::Info;
+    let mut inputs = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" | "-n" => dry_run = true,
+            "--quiet" | "-q" => verbosity = LevelFilter::Warn,
+            "--output-ext" => naming.extension = Some(parse_output_ext(&args.next().unwrap_or_default())),
+            "--output-name" => naming.template = Some(parse_output_name(args.next().unwrap_or_default())),
+            "--target" => {
+                let name = args.next().unwrap_or_default();
+                target = Target::parse(&name).unwrap_or_else(|| {
+                    eprintln!("Error: --target expects one of: {}", Target::NAMES);
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            _ if arg.starts_with('-') => {
+                eprintln!("Usage: lolcompiler clean [--dry-run] [--quiet] [--target <name>] [--output-ext <ext>] [--output-name <template>] [file.lol | dir]...");
+                std::process::exit(diagnostics::EXIT_USAGE);
+            }
+            _ => inputs.push(arg),
+        }
+    }
+    if inputs.is_empty() {
+        inputs.push(".".to_string());
+    }
+
+    log::set_logger(&LOGGER).expect("logger is only set once");
+    log::set_max_level(verbosity);
+
+    let pages = site::collect_pages(&inputs).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(diagnostics::EXIT_USAGE);
+    });
+    let langs = if naming.uses_lang() {
+        site::page_langs(&pages, site::DEFAULT_MAX_INPUT_SIZE)
+    } else {
+        HashMap::new()
+    };
+    let root = site::project_root(&inputs);
+    let cache = BuildCache::load(&root.join(cache::CACHE_FILE));
+    let files = site::generated_files(&pages, &root, &naming, &langs, target, cache.as_ref());
+    for file in &files {
+        if dry_run {
+            println!("{}", file.display());
+            continue;
+        }
+        if let Err(e) = std::fs::remove_file(file) {
+            eprintln!("Error: Failed to remove '{}': {}", file.display(), e);
+            std::process::exit(diagnostics::EXIT_IO);
+        }
+        log::debug!("Removed {}", file.display());
+    }
+    if !dry_run {
+        log::info!("Removed {} generated files", files.len());
+    }
+}
+
+/// `lolcompiler rename`: renames one variable of a document and rewrites the
+/// file, leaving other variables of the same name alone.
+fn rename(mut args: impl Iterator<Item = String>) {
+    let mut case_insensitive = false;
+    let mut defines = HashMap::new();
+    let mut verbosity = LevelFilter::Info;
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ignore-case" => case_insensitive = true,
+            "--quiet" | "-q" => verbosity = LevelFilter::Warn,
+            "--define" | "-D" => {
+                let (name, value) = parse_define(&args.next().unwrap_or_default());
+                defines.insert(name, value);
+            }
+            _ if arg.starts_with('-') => {
+                eprintln!("Error: unknown option '{}'", arg);
+                std::process::exit(diagnostics::EXIT_USAGE);
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [input, variable, new_name] = <[String; 3]>::try_from(positional).unwrap_or_else(|_| {
+        eprintln!("Usage: lolcompiler rename [--ignore-case] [--quiet] [--define NAME[=value]]... <file.lol> <name | line:col> <new-name>");
+        std::process::exit(diagnostics::EXIT_USAGE);
+    });
+
+    log::set_logger(&LOGGER).expect("logger is only set once");
+    log::set_max_level(verbosity);
+
+    let path = Path::new(&input);
+    let source = site::read_source(path, site::DEFAULT_MAX_INPUT_SIZE).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(diagnostics::EXIT_IO);
+    });
+    let mut analyzer = LolcodeSemanticAnalyzer::new();
+    analyzer.case_insensitive = case_insensitive;
+    analyzer.defines = defines;
+    //LINKZ may name the pages next to the file
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    analyzer.page_links = site::collect_pages(&[dir.display().to_string()])
+        .unwrap_or_default()
+        .into_iter()
+        .map(|page| (page.name, String::new()))
+        .collect();
+    let table = symbols::check_source(&source, &mut analyzer).unwrap_or_else(|e| {
+        e.report();
+        std::process::exit(e.exit_code());
+    });
+
+    //a position picks the variable there, a name has to pick exactly one variable
+    let position = variable
+        .split_once(':')
+        .and_then(|(line, col)| Some((line.parse().ok()?, col.parse().ok()?)));
+    let (line, col) = position.unwrap_or_else(|| {
+        let found = table.find_references(&variable);
+        match found.as_slice() {
+            [uses] => uses[0].span.0,
+            [] => {
+                eprintln!("Error: there is no variable '{}' in '{}'", variable, input);
+                std::process::exit(diagnostics::EXIT_USAGE);
+            }
+            _ => {
+                let places: Vec<String> = found
+                    .iter()
+                    .map(|uses| match uses[0].declared_at {
+                        Some((line, col)) => format!("{}:{}", line, col),
+                        None => "a define or built-in variable".to_string(),
+                    })
+                    .collect();
+                eprintln!(
+                    "Error: '{}' names more than one variable ({}), give the position of the one to rename as line:col",
+                    variable,
+                    places.join(", ")
+                );
+                std::process::exit(diagnostics::EXIT_USAGE);
+            }
+        }
+    });
+    let edits = table.rename(line, col, &new_name).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(diagnostics::EXIT_USAGE);
+    });
+    let old_name = table.symbol_at(line, col).map(|symbol| symbol.name).unwrap_or_default();
+    if let Err(e) = std::fs::write(path, symbols::apply_edits(&source, &edits)) {
+        eprintln!("Error: Failed to write '{}': {}", input, e);
+        std::process::exit(diagnostics::EXIT_IO);
+    }
+    log::info!("Renamed '{}' to '{}' in {} places", old_name, new_name, edits.len());
+}
+
+/// `lolcompiler repl`: compiles what is typed one entry at a time.
+fn repl(mut args: impl Iterator<Item = String>) {
+    use std::io::{BufRead, Write};
+
+    let mut session = Session::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ignore-case" => session.case_insensitive = true,
+            "--define" | "-D" => {
+                let (name, value) = parse_define(&args.next().unwrap_or_default());
+                session.variables.insert(name, value);
+            }
+            _ => {
+                eprintln!("Usage: lolcompiler repl [--ignore-case] [--define NAME[=value]]...");
+                std::process::exit(diagnostics::EXIT_USAGE);
+            }
+        }
+    }
+    let defines = session.variables.clone();
+
+    let (mut show_tokens, mut show_ast, mut show_html) = (false, false, true);
+    let mut entry = String::new();
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("{}", if entry.is_empty() { "lol> " } else { "...> " });
+        let _ = std::io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            println!();
+            return;
+        };
+
+        //commands only go at the start of an entry
+        if entry.is_empty() {
+            match line.trim().split_once(' ').unwrap_or((line.trim(), "")) {
+                ("", _) => continue,
+                (":quit" | ":q", _) => return,
+                (":reset", _) => {
+                    session.variables = defines.clone();
+                    continue;
+                }
+                (":vars", _) => {
+                    let mut names: Vec<_> = session.variables.iter().collect();
+                    names.sort();
+                    for (name, value) in names {
+                        println!("{} = {}", name, value);
+                    }
+                    continue;
+                }
+                (":show", kinds) => {
+                    let kinds: Vec<&str> = kinds.split(',').map(str::trim).collect();
+                    if let Some(unknown) = kinds.iter().find(|kind| !matches!(**kind, "tokens" | "ast" | "html")) {
+                        eprintln!("Error: unknown kind '{}', expected tokens, ast or html", unknown);
+                        continue;
+                    }
+                    show_tokens = kinds.contains(&"tokens");
+                    show_ast = kinds.contains(&"ast");
+                    show_html = kinds.contains(&"html");
+                    continue;
+                }
+                (command, _) if command.starts_with(':') => {
+                    eprintln!("Error: unknown command '{}', expected :show, :vars, :reset or :quit", command);
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        entry.push_str(&line);
+        entry.push('\n');
+        if !line.trim().is_empty() && !repl::is_complete(&entry) {
+            continue;
+        }
+        match session.eval(&std::mem::take(&mut entry)) {
+            Ok(compiled) => {
+                diagnostics::report(&compiled.warnings);
+                if show_tokens {
+                    println!("-- tokens");
+                    for token in &compiled.tokens {
+                        println!("{}:{} {:?}", token.line, token.col, token.kind);
+                    }
+                }
+                if show_ast {
+                    println!("-- ast");
+                    println!("{}", compiled.ast);
+                }
+                if show_html {
+                    if show_tokens || show_ast {
+                        println!("-- html");
+                    }
+                    print!("{}", compiled.html);
+                }
+            }
+            Err(e) => e.report(),
+        }
+    }
+}
+
+/// What compiling one page leaves behind for the project-wide steps.
+struct PageResult {
+    meta: DocumentMeta,
+    diagnostics: Vec<Diagnostic>,
+    timings: Option<Timings>,
+    /// Key of the page's source, for the build cache
+    source: SourceKey,
+    /// Files the page has after the build, for the `--json` summary
+    outputs: Vec<PathBuf>,
+    /// Whether the page was reused from the build cache
+    up_to_date: bool,
+    /// Exit status of the error that stopped the page, if one did; the error
+    /// itself is in `diagnostics`
+    failed: Option<i32>,
+}
+
+impl PageResult {
+    /// A page that didn't compile, with everything that was found in it.
+    fn failed(code: i32, diagnostics: Vec<Diagnostic>, source: SourceKey) -> Self {
+        Self {
+            meta: DocumentMeta::default(),
+            diagnostics,
+            timings: None,
+            source,
+            outputs: Vec::new(),
+            up_to_date: false,
+            failed: Some(code),
+        }
+    }
+}
 
 /// Entry point for the LOLCODE compiler.
 /// 
-/// Reads a `.lol` file, validates it through lexical, syntax, and semantic analysis,
-/// then generates an HTML file and opens it in the browser.
+/// Reads the `.lol` files, validates each through lexical, syntax, and semantic analysis,
+/// then generates an HTML file per page and opens it in the browser.
 fn main() {
-    //one input file to test
-    let input = std::env::args().nth(1).unwrap_or_else(||{
-        eprintln!("Usage: lolcompiler <file.lol>");
-        std::process::exit(1);
+    if std::env::args().nth(1).as_deref() == Some("rename") {
+        rename(std::env::args().skip(2));
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("clean") {
+        clean(std::env::args().skip(2));
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("repl") {
+        repl(std::env::args().skip(2));
+        return;
+    }
+
+    //flags first, then the input files to test
+    let mut check_links = false;
+    let mut timings = false;
+    let mut save_ast = false;
+    let mut emit = Emit { page: true, tokens: false, ast_json: false, symbols: false };
+    let mut max_nesting = DEFAULT_MAX_NESTING;
+    let mut case_insensitive = false;
+    let mut classic = false;
+    let mut allow_late_head = false;
+    let mut fixed_build_time = BuildTime::from_env();
+    let mut reproducible = false;
+    let mut defines = Vec::new();
+    let mut max_input_size = site::DEFAULT_MAX_INPUT_SIZE;
+    let mut force = false;
+    let mut jobs = 0;
+    let mut json = false;
+    let mut verbosity = LevelFilter::Info;
+    let mut options = OutputOptions::default();
+    let mut lint_options = LintOptions::default();
+    let mut inputs = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check-links" => check_links = true,
+            "--lazy-media" => options.lazy_media = true,
+            "--source-map" => options.source_map = true,
+            "--debug-annotations" => options.debug_annotations = true,
+            "--raw-values" => {
+                options.value_policy = args.next().as_deref().and_then(ValuePolicy::allow).unwrap_or_else(|| {
+                    eprintln!("Error: --raw-values expects a comma-separated list of tag names, e.g. b,i,br");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            "--csp-nonce" => {
+                let nonce = args.next().filter(|nonce| integrity::is_valid_nonce(nonce)).unwrap_or_else(|| {
+                    eprintln!("Error: --csp-nonce expects a nonce of base64 characters");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+                options.csp_nonce = Some(nonce);
+            }
+            "--sri" => options.subresource_integrity = true,
+            "--reveal-cdn" => options.reveal_cdn = true,
+            "--paranoid" => options.paranoid = true,
+            "--xhtml" => options.xhtml = true,
+            "--social-cards" => options.social_cards = true,
+            "--base-url" => {
+                let url = args.next().filter(|url| url.starts_with("https://") || url.starts_with("http://"));
+                let url = url.unwrap_or_else(|| {
+                    eprintln!("Error: --base-url expects an http:// or https:// URL");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+                // pages are found below it, so it is a directory
+                options.base_url = Some(if url.ends_with('/') { url } else { format!("{}/", url) });
+            }
+            flag @ ("--inject-head" | "--inject-body") => {
+                let Some(path) = args.next() else {
+                    eprintln!("Error: {} expects an HTML file", flag);
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                };
+                let snippet = site::read_source(Path::new(&path), site::DEFAULT_MAX_INPUT_SIZE).unwrap_or_else(|e| {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(diagnostics::EXIT_IO);
+                });
+                // each ends up on its own lines before the closing tag
+                let snippet = format!("{}\n", snippet.trim_end());
+                if flag == "--inject-head" {
+                    options.head_snippet = Some(snippet);
+                } else {
+                    options.body_snippet = Some(snippet);
+                }
+            }
+            "--doctype" => {
+                let name = args.next().unwrap_or_default();
+                options.doctype = Doctype::parse(&name).unwrap_or_else(|| {
+                    eprintln!("Error: --doctype expects one of: {}", Doctype::NAMES);
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            "--quiet" | "-q" => verbosity = LevelFilter::Warn,
+            "--verbose" | "-v" => verbosity = LevelFilter::Debug,
+            "--json" => json = true,
+            "--timings" => timings = true,
+            "--save-ast" => save_ast = true,
+            "--emit" => {
+                emit = args.next().as_deref().and_then(Emit::parse).unwrap_or_else(|| {
+                    eprintln!("Error: --emit expects a comma-separated list of: html, tokens, ast-json, symbols");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            "--force" => force = true,
+            "--ignore-case" => case_insensitive = true,
+            "--classic" => classic = true,
+            "--allow-late-head" => allow_late_head = true,
+            "--reproducible" => reproducible = true,
+            "--define" | "-D" => defines.push(parse_define(&args.next().unwrap_or_default())),
+            "--build-time" => {
+                fixed_build_time = Some(args.next().as_deref().and_then(BuildTime::parse).unwrap_or_else(|| {
+                    eprintln!("Error: --build-time expects YYYY-MM-DD, YYYY-MM-DDTHH:MM[:SS] or seconds since 1970");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                }));
+            }
+            "--jobs" | "-j" => {
+                jobs = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| {
+                    eprintln!("Error: --jobs expects a number of threads");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            "--max-nesting" => {
+                max_nesting = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| {
+                    eprintln!("Error: --max-nesting expects a number of levels");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            "--max-input-size" => {
+                let megabytes: u64 = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| {
+                    eprintln!("Error: --max-input-size expects a size in MB");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+                max_input_size = megabytes.saturating_mul(1024 * 1024);
+            }
+            "--output-ext" => options.naming.extension = Some(parse_output_ext(&args.next().unwrap_or_default())),
+            "--output-name" => options.naming.template = Some(parse_output_name(args.next().unwrap_or_default())),
+            "--target" => {
+                let name = args.next().unwrap_or_default();
+                options.target = Target::parse(&name).unwrap_or_else(|| {
+                    eprintln!("Error: --target expects one of: {}", Target::NAMES);
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            "--lint-media" => {
+                let level = args.next().unwrap_or_default();
+                lint_options.media_accessibility = lint::parse_level(&level).unwrap_or_else(|| {
+                    eprintln!("Error: --lint-media expects off, warn or error");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            "--lint-empty" => {
+                let level = args.next().unwrap_or_default();
+                lint_options.empty_constructs = lint::parse_level(&level).unwrap_or_else(|| {
+                    eprintln!("Error: --lint-empty expects off, warn or error");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            "--lint-title" => {
+                let level = args.next().unwrap_or_default();
+                lint_options.titles = lint::parse_level(&level).unwrap_or_else(|| {
+                    eprintln!("Error: --lint-title expects off, warn or error");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            "--lint-invisible" => {
+                let level = args.next().unwrap_or_default();
+                lint_options.invisible_characters = lint::parse_level(&level).unwrap_or_else(|| {
+                    eprintln!("Error: --lint-invisible expects off, warn or error");
+                    std::process::exit(diagnostics::EXIT_USAGE);
+                });
+            }
+            _ if arg.starts_with('-') => {
+                eprintln!("Error: unknown option '{}'", arg);
+                std::process::exit(diagnostics::EXIT_USAGE);
+            }
+            _ => inputs.push(arg),
+        }
+    }
+    if inputs.is_empty() {
+        eprintln!("Usage: lolcompiler [--target <name>] [--output-ext <ext>] [--output-name <template>] [--check-links] [--quiet | --verbose] [--json] [--lazy-media] [--source-map] [--debug-annotations] [--raw-values <tags>] [--csp-nonce <nonce>] [--reveal-cdn] [--sri] [--paranoid] [--xhtml] [--doctype <version>] [--social-cards] [--base-url <url>] [--inject-head <file>] [--inject-body <file>] [--timings] [--save-ast] [--emit <kinds>] [--max-nesting <n>] [--max-input-size <MB>] [--ignore-case] [--classic] [--allow-late-head] [--define NAME[=value]]... [--build-time <time>] [--reproducible] [--jobs <n>] [--force] [--lint-media <off|warn|error>] [--lint-invisible <off|warn|error>] [--lint-title <off|warn|error>] [--lint-empty <off|warn|error>] <file.lol | dir>...");
+        std::process::exit(diagnostics::EXIT_USAGE);
+    }
+    //the reveal.js files are the only ones a page loads from a CDN
+    if options.subresource_integrity && !options.reveal_cdn {
+        eprintln!("Error: --sri needs --reveal-cdn");
+        std::process::exit(diagnostics::EXIT_USAGE);
+    }
+    if options.paranoid && options.value_policy != ValuePolicy::EscapeAll {
+        eprintln!("Error: --paranoid can't be combined with --raw-values");
+        std::process::exit(diagnostics::EXIT_USAGE);
+    }
+    //the summary has to be the only thing on stdout
+    if json {
+        if options.target == Target::Ansi {
+            eprintln!("Error: --json can't be combined with --target ansi");
+            std::process::exit(diagnostics::EXIT_USAGE);
+        }
+        verbosity = verbosity.min(LevelFilter::Warn);
+        options.open_browser = false;
+    }
+
+    //nothing in the output may depend on when or where it was built
+    if reproducible {
+        if options.target == Target::Pdf {
+            eprintln!("Error: --reproducible can't be combined with --target pdf");
+            std::process::exit(diagnostics::EXIT_USAGE);
+        }
+        fixed_build_time = fixed_build_time.or(Some(BuildTime { unix_seconds: 0 }));
+    }
+
+    log::set_logger(&LOGGER).expect("logger is only set once");
+    log::set_max_level(verbosity);
+
+    //find every page, error if an input isn't a .lol file
+    let pages = site::collect_pages(&inputs).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(diagnostics::EXIT_USAGE);
     });
 
-    //make sure its a .lol file, error if not
-    if Path::new(&input).extension().and_then(|s| s.to_str()) != Some("lol") {
-        eprintln!("Error: input file must have a .lol extension");
-        std::process::exit(1);
+    //one browser tab is plenty when building a whole project
+    if pages.len() > 1 {
+        options.open_browser = false;
     }
+    let settings = BuildSettings {
+        check_links,
+        timings,
+        save_ast,
+        emit,
+        max_nesting,
+        case_insensitive,
+        classic,
+        allow_late_head,
+        defines,
+        build_time: fixed_build_time.unwrap_or_else(BuildTime::now),
+        fixed_build_time,
+        max_input_size,
+        //names with the page's language need every language before any page links to another
+        langs: if options.naming.uses_lang() { site::page_langs(&pages, max_input_size) } else { HashMap::new() },
+        keep_going: pages.len() > 1,
+        options,
+        lint_options,
+    };
+    let options = &settings.options;
 
-    //read file to a string
-    let source = std::fs::read_to_string(&input).unwrap_or_else(|e| {
-        eprintln!("Failed to read '{}': {}", input, e);
-        std::process::exit(1);
+    //project builds reuse pages from the last build unless something changed;
+    //terminal output isn't a file to keep and link checks should always run
+    let root = site::project_root(&inputs);
+    let cache_path = root.join(cache::CACHE_FILE);
+    let use_cache = pages.len() > 1 && options.target != Target::Ansi;
+    let config = settings.config_hash(&pages);
+    let last = if use_cache { BuildCache::load(&cache_path) } else { None };
+    //files the last builds wrote stay recorded for `clean` while they exist
+    let recorded: Vec<PathBuf> = last.as_ref().map_or_else(Vec::new, |cache| cache.outputs.clone());
+    let previous = last.filter(|cache| cache.config == config && !force && !check_links);
+
+    //pages don't depend on each other, so they compile on a thread pool (0 threads = one per core)
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .unwrap_or_else(|e| {
+            eprintln!("Error: failed to start worker threads: {}", e);
+            std::process::exit(diagnostics::EXIT_FAILURE);
+        });
+    let results: Vec<PageResult> = pool.install(|| {
+        pages
+            .par_iter()
+            .map(|page| build_page(page, &pages, &settings, previous.as_ref()))
+            .collect()
     });
-    
-    //Testing task 1: Lexical Analysis
-    //test that all tokens are valid
-    let mut lexer = Lexer::new(&source);
-    loop {
-        let tok = lexer.get_next_token();
-        if let token::TokenKind::Eof = tok.kind {
-            break;
+
+    let mut manifest = BuildCache::new(config);
+    if use_cache {
+        //a page that failed has no output to reuse
+        for (page, result) in pages.iter().zip(&results).filter(|(_, result)| result.failed.is_none()) {
+            manifest.pages.insert(
+                page.name.clone(),
+                cache::CachedPage {
+                    source: result.source,
+                    meta: result.meta.clone(),
+                    diagnostics: result.diagnostics.clone(),
+                },
+            );
+        }
+        manifest.outputs = recorded;
+        manifest.outputs.extend(results.iter().flat_map(|result| result.outputs.iter().cloned()));
+    }
+
+    //warnings in page order, prefixed with the page once there is more than one
+    let mut entries = Vec::new();
+    let mut total_timings = Timings::new();
+    for (page, result) in pages.iter().zip(&results) {
+        if pages.len() > 1 {
+            for diagnostic in &result.diagnostics {
+                eprintln!("{}: {}", page.source.display(), diagnostic);
+            }
+        } else {
+            diagnostics::report(&result.diagnostics);
+        }
+        if let Some(page_timings) = &result.timings {
+            total_timings.merge(page_timings);
+        }
+        let lang = settings.langs.get(&page.name).map(String::as_str);
+        let href = options.naming.href(&page.name, options.target.extension(), lang);
+        if let Some(entry) = FeedEntry::from_meta(&result.meta, href) {
+            entries.push(entry);
+        }
+    }
+
+    //the first page in order that failed decides the exit status
+    let failed = results.iter().find_map(|result| result.failed);
+    if pages.len() > 1 {
+        report_summary(&pages, &results);
+    }
+
+    //site mode: dated pages go into a feed next to them, once they all built
+    let mut feed_path = None;
+    if pages.len() > 1 && !entries.is_empty() && failed.is_none() {
+        //where the project is on disk is no business of a reproducible build
+        let resolved = if reproducible { Some(root.clone()) } else { root.canonicalize().ok() };
+        let title = resolved
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+            .unwrap_or_else(|| "LOLCODE Feed".to_string());
+        let path = root.join(feed::FEED_FILE);
+        let feed = feed::atom_feed(&title, &entries);
+        //an unchanged feed is left alone so readers and syncs don't see a new file
+        let changed = output::write_if_changed(&path, feed.as_bytes()).unwrap_or_else(|e| {
+            eprintln!("Failed to write feed: {}", e);
+            std::process::exit(diagnostics::EXIT_IO);
+        });
+        if changed {
+            log::info!("Feed generated successfully: {}", path.display());
+        }
+        feed_path = Some(path);
+    }
+
+    //saved after the feed so the feed is recorded too
+    if use_cache {
+        manifest.outputs.extend(feed_path.iter().cloned());
+        manifest.outputs.retain(|output| output.is_file());
+        manifest.outputs.sort();
+        manifest.outputs.dedup();
+        //a missing manifest only costs a full rebuild next time
+        if let Err(e) = manifest.save(&cache_path) {
+            log::warn!("Warning: failed to write build cache '{}': {}", cache_path.display(), e);
         }
-        // token was valid (lexer would exit on invalid tokens)
     }
 
-    //Testing task 2: Syntax Analysis
-    let mut parser = LolcodeParser::new(&source);
-    
-    //parse the source to build abstract syntax tree
-    parser.parse();
+    //on stderr, so the pages' output and the summary keep stdout to themselves
+    if settings.timings {
+        for line in &total_timings.lines {
+            eprintln!("{}", line);
+        }
+        eprint!("{}", total_timings.report_totals());
+    }
 
-    //Testing task 3: Semantic Analysis
-    //get the parse tree from the parser
-    if let Some(ref tree) = parser.parse_tree {
-        let mut semantic_analyzer = LolcodeSemanticAnalyzer::new();
-        semantic_analyzer.analyze_tree(tree, &input);
+    if json {
+        println!("{}", json_summary(&pages, &results, feed_path.as_deref()));
+    }
+    if let Some(code) = failed {
+        std::process::exit(code);
+    }
+    //if we reach here, every page made it through all stages
+    if !json && verbosity >= LevelFilter::Info {
+        println!("valid");
+    }
+}
+
+/// Prints how many errors and warnings each page of a project build had, and
+/// the totals, after the diagnostics themselves.
+///
+/// ```text
+/// site/about.lol: 1 error, 0 warnings
+/// site/index.lol: 0 errors, 2 warnings
+/// 1 error, 2 warnings across 3 files
+/// ```
+///
+/// Pages without any are left out; nothing is printed when no page had any.
+fn report_summary(pages: &[Page], results: &[PageResult]) {
+    let (mut errors, mut warnings) = (0, 0);
+    for (page, result) in pages.iter().zip(results) {
+        let (page_errors, page_warnings) = count_diagnostics(&result.diagnostics);
+        if page_errors + page_warnings > 0 {
+            eprintln!("{}: {}", page.source.display(), counts(page_errors, page_warnings));
+        }
+        errors += page_errors;
+        warnings += page_warnings;
+    }
+    if errors + warnings > 0 {
+        eprintln!("{} across {} files", counts(errors, warnings), pages.len());
+    }
+}
+
+// errors and warnings among the diagnostics
+fn count_diagnostics(diagnostics: &[Diagnostic]) -> (usize, usize) {
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    (errors, diagnostics.len() - errors)
+}
+
+fn counts(errors: usize, warnings: usize) -> String {
+    format!(
+        "{} error{}, {} warning{}",
+        errors,
+        if errors == 1 { "" } else { "s" },
+        warnings,
+        if warnings == 1 { "" } else { "s" }
+    )
+}
+
+/// The `--json` summary of a finished build: every page with the files it
+/// produced and what was found in it, the feed if there is one, and the totals.
+///
+/// ```text
+/// {"pages":[{"page":"index","source":"site/index.lol","outputs":["site/index.html"],"up_to_date":false,"failed":false,"errors":0,"warnings":1,"diagnostics":[{"severity":"warning","code":"missing-title","message":"..."}]}],"feed":"site/feed.xml","errors":0,"warnings":1,"files":1}
+/// ```
+///
+/// `outputs` lists the files that exist after the build whether or not this
+/// build wrote them; `up_to_date` pages were reused from the build cache. A
+/// page that `failed` has its errors among its diagnostics, and the build
+/// writes no feed. A diagnostic that knows where it is in the source also has
+/// `line`, `col` and `labels`, each a `line`, `col` and `message`.
+fn json_summary(pages: &[Page], results: &[PageResult], feed: Option<&Path>) -> String {
+    let (mut errors, mut warnings) = (0, 0);
+    let mut json = String::from("{\"pages\":[");
+    for (i, (page, result)) in pages.iter().zip(results).enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let (page_errors, page_warnings) = count_diagnostics(&result.diagnostics);
+        errors += page_errors;
+        warnings += page_warnings;
+        let outputs: Vec<String> = result.outputs.iter().map(|output| json_path(output)).collect();
+        let found: Vec<String> = result.diagnostics.iter().map(json_diagnostic).collect();
+        json.push_str(&format!(
+            "{{\"page\":{},\"source\":{},\"outputs\":[{}],\"up_to_date\":{},\"failed\":{},\"errors\":{},\"warnings\":{},\"diagnostics\":[{}]}}",
+            json::string(&page.name),
+            json_path(&page.source),
+            outputs.join(","),
+            result.up_to_date,
+            result.failed.is_some(),
+            page_errors,
+            page_warnings,
+            found.join(",")
+        ));
+    }
+    json.push_str("],\"feed\":");
+    json.push_str(&feed.map_or_else(|| "null".to_string(), json_path));
+    json.push_str(&format!(",\"errors\":{},\"warnings\":{},\"files\":{}}}", errors, warnings, pages.len()));
+    json
+}
+
+fn json_diagnostic(diagnostic: &Diagnostic) -> String {
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let mut json = format!(
+        "{{\"severity\":\"{}\",\"code\":{},\"message\":{}",
+        severity,
+        json::string(diagnostic.code),
+        json::string(&diagnostic.message)
+    );
+    if let Some((line, col)) = diagnostic.span {
+        json.push_str(&format!(",\"line\":{},\"col\":{}", line, col));
+    }
+    if !diagnostic.labels.is_empty() {
+        let labels: Vec<String> = diagnostic
+            .labels
+            .iter()
+            .map(|label| format!("{{\"line\":{},\"col\":{},\"message\":{}}}", label.line, label.col, json::string(&label.message)))
+            .collect();
+        json.push_str(&format!(",\"labels\":[{}]", labels.join(",")));
+    }
+    json.push('}');
+    json
+}
+
+fn json_path(path: &Path) -> String {
+    json::string(&path.to_string_lossy())
+}
+
+/// Reuses a page from the build cache if it is unchanged, otherwise compiles it.
+fn build_page(page: &Page, pages: &[Page], settings: &BuildSettings, previous: Option<&BuildCache>) -> PageResult {
+    //read file to a string
+    let source = match site::read_source(&page.source, settings.max_input_size) {
+        Ok(source) => source,
+        Err(e) if settings.keep_going => {
+            return PageResult::failed(diagnostics::EXIT_IO, vec![Diagnostic::error("io-error", e)], SourceKey::default());
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(diagnostics::EXIT_IO);
+        }
+    };
+    let source_key = SourceKey::of(source.as_bytes());
+
+    //the old output has to still be there to be reused
+    let output = settings.output_path(page);
+    if let Some(cached) = previous.and_then(|cache| cache.fresh(&page.name, source_key))
+        && output.exists()
+    {
+        log::info!("Up to date: {}", output.display());
+        return PageResult {
+            meta: cached.meta.clone(),
+            diagnostics: cached.diagnostics.clone(),
+            timings: None,
+            source: source_key,
+            outputs: page_outputs(page, settings),
+            up_to_date: true,
+            failed: None,
+        };
+    }
+
+    compile_page(page, pages, settings, &source, source_key)
+}
+
+/// Runs one page through the whole pipeline and writes its output file.
+fn compile_page(page: &Page, pages: &[Page], settings: &BuildSettings, source: &str, source_key: SourceKey) -> PageResult {
+    let input = page.source.to_string_lossy().to_string();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.options = settings.options.clone();
+    pipeline.lint_options = settings.lint_options.clone();
+    pipeline.check_links = settings.check_links;
+    pipeline.max_nesting = settings.max_nesting;
+    pipeline.case_insensitive = settings.case_insensitive;
+    pipeline.classic = settings.classic;
+    pipeline.allow_late_head = settings.allow_late_head;
+    pipeline.write_output = settings.emit.page;
+    pipeline.defines = settings.defines.iter().cloned().collect();
+    pipeline.build_time = settings.build_time;
+    pipeline.page_links = site::page_links(
+        pages,
+        page,
+        &settings.options.naming,
+        settings.options.target.extension(),
+        &settings.langs,
+    );
+    let lang = settings.langs.get(&page.name).map(String::as_str);
+    pipeline.page_href = Some(settings.options.naming.href(&page.name, settings.options.target.extension(), lang));
+    let page_timings = settings.timings.then(|| Rc::new(RefCell::new(Timings::new())));
+    if let Some(page_timings) = &page_timings {
+        pipeline.add_observer(Box::new(Rc::clone(page_timings)));
+    }
+    let tokens = settings.emit.tokens.then(|| Rc::new(RefCell::new(TokenDump::default())));
+    if let Some(tokens) = &tokens {
+        pipeline.add_observer(Box::new(Rc::clone(tokens)));
+    }
+
+    let arena = AstArena::new();
+    let tree = if settings.keep_going {
+        match pipeline.try_run(source, &input, &arena) {
+            Ok(tree) => tree,
+            Err(code) => return PageResult::failed(code, std::mem::take(&mut pipeline.diagnostics), source_key),
+        }
     } else {
-        eprintln!("Error: No parse tree generated");
-        std::process::exit(1);
+        pipeline.run(source, &input, &arena)
+    };
+    if settings.save_ast {
+        write_artifact(&page.source.with_extension("lolast"), &binast::encode(tree, &pipeline.interner), "AST");
+    }
+    if let Some(tokens) = &tokens {
+        write_artifact(&page.source.with_extension("tokens"), tokens.borrow().text.as_bytes(), "tokens");
+    }
+    if settings.emit.ast_json {
+        write_artifact(&page.source.with_extension("ast.json"), json::ast(tree, &pipeline.interner).as_bytes(), "AST JSON");
+    }
+    if settings.emit.symbols {
+        write_artifact(&page.source.with_extension("symbols.json"), pipeline.symbols.to_json().as_bytes(), "symbols");
+    }
+    //the tree still has the title's variable references, the page has their values
+    let mut meta = meta::document_meta(tree);
+    if meta.first_title.is_some()
+        && let Some(title) = pipeline.title.take()
+    {
+        meta.first_title = Some(title);
     }
+    PageResult {
+        meta,
+        diagnostics: std::mem::take(&mut pipeline.diagnostics),
+        timings: page_timings.map(|t| t.borrow().clone()),
+        source: source_key,
+        outputs: page_outputs(page, settings),
+        up_to_date: false,
+        failed: None,
+    }
+}
+
+// writes one of the files a page's build leaves besides the page
+fn write_artifact(path: &Path, contents: &[u8], what: &str) {
+    output::write_if_changed(path, contents).unwrap_or_else(|e| {
+        eprintln!("Failed to write {} file: {}", what, e);
+        std::process::exit(diagnostics::EXIT_IO);
+    });
+}
 
-    //if we reach here, both lexical and syntax analysis succeeded
-    println!("valid");
+/// The files a page's build leaves next to its source.
+fn page_outputs(page: &Page, settings: &BuildSettings) -> Vec<PathBuf> {
+    let target = settings.options.target;
+    let mut outputs = Vec::new();
+    //terminal output has no file
+    if settings.emit.page && target != Target::Ansi {
+        outputs.push(settings.output_path(page));
+    }
+    if settings.emit.page && target == Target::Pdf {
+        outputs.push(settings.output_path(page).with_extension("pdf"));
+    }
+    let mut extensions = settings.emit.extensions();
+    if settings.save_ast {
+        extensions.insert(0, "lolast");
+    }
+    for extension in extensions {
+        let path = page.source.with_extension(extension);
+        //a cached page only has the file if an earlier build wrote it
+        if path.exists() {
+            outputs.push(path);
+        }
+    }
+    outputs
 }
@@ -0,0 +1,130 @@
+//! Helpers for embedding media in the generated HTML.
+//!
+//! A plain `<video src>` only works for direct links to video files. Hosted
+//! players such as YouTube and Vimeo have to be embedded with an `<iframe>`
+//! pointing at the host's embed URL instead.
+
+use crate::parser::{ASTNode, MediaAttributes};
+
+/// Styles for the `lol-embed` wrapper placed around every video and iframe.
+///
+/// The wrapper keeps a 16:9 box at the full width of its container, and the
+/// embed fills the box, so players shrink with the page instead of overflowing
+/// narrow screens.
+pub const RESPONSIVE_EMBED_CSS: &str = "\
+.lol-embed { position: relative; width: 100%; max-width: 960px; aspect-ratio: 16 / 9; }
+.lol-embed > iframe, .lol-embed > video { position: absolute; inset: 0; width: 100%; height: 100%; border: 0; }
+.lol-embed > video { object-fit: contain; background: #000; }
+";
+
+/// Returns true if the tree contains a video, which gets the `lol-embed` wrapper.
+pub fn has_embeds(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::Program { children }
+        | ASTNode::HeadSection { children }
+        | ASTNode::ParagrafSection { children }
+        | ASTNode::ListSection { children }
+        | ASTNode::IfDefined { children, .. } => children.iter().any(has_embeds),
+        ASTNode::Bold { content } | ASTNode::Italics { content } | ASTNode::Item { content } => {
+            content.iter().any(has_embeds)
+        }
+        ASTNode::Video { .. } => true,
+        _ => false,
+    }
+}
+
+/// Video hosts that need an iframe embed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoHost {
+    YouTube,
+    Vimeo,
+}
+
+/// Detects a YouTube or Vimeo link and returns the host and video id.
+pub fn detect_video_host(url: &str) -> Option<(VideoHost, String)> {
+    // drop the scheme and a leading www./m. so the host checks stay simple
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let rest = rest
+        .strip_prefix("www.")
+        .or_else(|| rest.strip_prefix("m."))
+        .unwrap_or(rest);
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let host = host.to_ascii_lowercase();
+
+    let id = match host.as_str() {
+        "youtube.com" | "youtube-nocookie.com" => {
+            let id = if let Some(query) = path.strip_prefix("watch?") {
+                query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("v="))
+                    .map(first_segment)
+            } else {
+                path.strip_prefix("embed/")
+                    .or_else(|| path.strip_prefix("shorts/"))
+                    .or_else(|| path.strip_prefix("live/"))
+                    .map(first_segment)
+            };
+            id.map(|id| (VideoHost::YouTube, id))
+        }
+        "youtu.be" => Some((VideoHost::YouTube, first_segment(path))),
+        "vimeo.com" => Some((VideoHost::Vimeo, first_segment(path))),
+        "player.vimeo.com" => path
+            .strip_prefix("video/")
+            .map(|id| (VideoHost::Vimeo, first_segment(id))),
+        _ => None,
+    }?;
+
+    // an id has to be there and vimeo ids are always numeric
+    match id {
+        (_, ref video_id) if video_id.is_empty() => None,
+        (VideoHost::Vimeo, ref video_id) if !video_id.chars().all(|c| c.is_ascii_digit()) => None,
+        found => Some(found),
+    }
+}
+
+/// Returns the part of a path before any further `/`, `?`, `&` or `#`.
+fn first_segment(path: &str) -> String {
+    path.split(['/', '?', '&', '#']).next().unwrap_or("").to_string()
+}
+
+/// Builds the embed URL for a hosted video, carrying over the playback attributes.
+pub fn embed_url(host: VideoHost, id: &str, attributes: &MediaAttributes) -> String {
+    let mut params = Vec::new();
+    match host {
+        VideoHost::YouTube => {
+            if attributes.autoplay {
+                params.push("autoplay=1".to_string());
+            }
+            if attributes.muted {
+                params.push("mute=1".to_string());
+            }
+            if attributes.looping {
+                // youtube only loops when the video is also its own playlist
+                params.push("loop=1".to_string());
+                params.push(format!("playlist={}", id));
+            }
+        }
+        VideoHost::Vimeo => {
+            if attributes.autoplay {
+                params.push("autoplay=1".to_string());
+            }
+            if attributes.muted {
+                params.push("muted=1".to_string());
+            }
+            if attributes.looping {
+                params.push("loop=1".to_string());
+            }
+        }
+    }
+
+    let base = match host {
+        VideoHost::YouTube => format!("https://www.youtube.com/embed/{}", id),
+        VideoHost::Vimeo => format!("https://player.vimeo.com/video/{}", id),
+    };
+
+    if params.is_empty() {
+        base
+    } else {
+        format!("{}?{}", base, params.join("&amp;"))
+    }
+}
@@ -0,0 +1,125 @@
+//! Document metadata.
+//!
+//! Metadata comes from the `#MAEK META ... #OIC` block, where each entry is
+//! written like a variable:
+//!
+//! ```text
+//! #MAEK META
+//!     #I HAZ date #IT IZ 2026-10-01 #MKAY
+//!     #I HAZ description #IT IZ My first post #MKAY
+//! #OIC
+//! ```
+//!
+//! The page title is taken from the first `#GIMMEH TITLE` when the META block
+//! doesn't give one. Metadata inside `#IZ DEFINED` is not collected, since it
+//! depends on the defines of a build. Variable references in a title only have
+//! a value in a resolved tree; the pipeline fills them in for a build.
+//!
+//! The `description`, `keywords` and `author` entries also go in the head of
+//! an HTML page as `<meta name>` tags, for search engines. With social cards
+//! on, the title, the description and an `image` entry make its Open Graph
+//! and Twitter card tags.
+
+use crate::parser::ASTNode;
+
+/// META entries written into the head of an HTML page as `<meta name>` tags.
+pub const HEAD_ENTRIES: [&str; 3] = ["description", "keywords", "author"];
+
+/// Metadata collected from one document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentMeta {
+    /// Entries from the META block, keys in lowercase, in source order
+    pub entries: Vec<(String, String)>,
+    /// Text of the first TITLE in the document
+    pub first_title: Option<String>,
+}
+
+impl DocumentMeta {
+    /// Looks up a META entry. Later entries win over earlier ones.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The page title: the META `title` entry, or else the first TITLE.
+    pub fn title(&self) -> Option<&str> {
+        self.get("title").or(self.first_title.as_deref())
+    }
+
+    /// The `<meta name>` tags for the head of the page as (name, content),
+    /// for the entries of `HEAD_ENTRIES` the document has and that aren't
+    /// empty. Keywords are given as a comma-separated list.
+    pub fn head_entries(&self) -> Vec<(&'static str, String)> {
+        HEAD_ENTRIES
+            .iter()
+            .filter_map(|&name| {
+                let value = self.get(name)?.trim();
+                let content = if name == "keywords" {
+                    let keywords: Vec<&str> = value.split(',').map(str::trim).filter(|k| !k.is_empty()).collect();
+                    keywords.join(", ")
+                } else {
+                    value.to_string()
+                };
+                (!content.is_empty()).then_some((name, content))
+            })
+            .collect()
+    }
+}
+
+/// Collects the metadata of a parse tree.
+pub fn document_meta(tree: &ASTNode) -> DocumentMeta {
+    let mut meta = DocumentMeta::default();
+    collect_into(tree, &mut meta);
+    meta
+}
+
+fn collect_into(node: &ASTNode, meta: &mut DocumentMeta) {
+    match node {
+        ASTNode::Program { children }
+        | ASTNode::HeadSection { children }
+        | ASTNode::ParagrafSection { children }
+        | ASTNode::ListSection { children } => {
+            for child in children.iter() {
+                collect_into(child, meta);
+            }
+        }
+        ASTNode::MetaSection { entries } => meta.entries.extend(entries.iter().cloned()),
+        ASTNode::Title { content } if meta.first_title.is_none() => {
+            meta.first_title = Some(title_text(content));
+        }
+        _ => {}
+    }
+}
+
+/// The text of a TITLE, its pieces separated by a space. Variable references
+/// are left out: in a resolved tree they have become text.
+pub fn title_text(content: &[ASTNode]) -> String {
+    let words: Vec<&str> = content
+        .iter()
+        .filter_map(|piece| match piece {
+            ASTNode::Text { content } => Some(*content),
+            _ => None,
+        })
+        .collect();
+    words.join(" ")
+}
+
+/// Checks that a date is written as `YYYY-MM-DD`.
+pub fn is_valid_date(date: &str) -> bool {
+    let parts: Vec<&str> = date.split('-').collect();
+    if parts.len() != 3
+        || parts[0].len() != 4
+        || parts[1].len() != 2
+        || parts[2].len() != 2
+        || !parts.iter().all(|p| p.chars().all(|c| c.is_ascii_digit()))
+    {
+        return false;
+    }
+
+    let month: u32 = parts[1].parse().unwrap_or(0);
+    let day: u32 = parts[2].parse().unwrap_or(0);
+    (1..=12).contains(&month) && (1..=31).contains(&day)
+}
@@ -0,0 +1,104 @@
+//! Ids and parent links for the nodes of a parse tree.
+//!
+//! `ASTNode`s only point down, at their children. A `NodeMap` walks a tree
+//! once and numbers every node depth first, in document order, so parsing the
+//! same source always gives the same ids. A diagnostic or a cache can then name
+//! a node by its `NodeId` instead of holding on to a copy of its subtree, and
+//! find its parent, children and ancestors from the id.
+//!
+//! ```text
+//! let nodes = NodeMap::new(tree);
+//! let id = nodes.id(node).unwrap();
+//! let section = nodes.ancestors(id).find(|&a| matches!(nodes.node(a), ASTNode::ParagrafSection { .. }));
+//! ```
+
+use crate::parser::ASTNode;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A node's place in the depth-first order of its tree; the root is 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+/// Every node of a tree by id, with the links between them.
+#[derive(Debug, Clone)]
+pub struct NodeMap<'t> {
+    nodes: Vec<&'t ASTNode<'t>>,
+    parents: Vec<Option<NodeId>>,
+    // one past the last id of each node's subtree
+    ends: Vec<usize>,
+    // node address -> id
+    ids: HashMap<usize, NodeId>,
+}
+
+impl<'t> NodeMap<'t> {
+    /// Numbers the nodes of `tree`.
+    pub fn new(tree: &'t ASTNode<'t>) -> Self {
+        let mut map = Self { nodes: Vec::new(), parents: Vec::new(), ends: Vec::new(), ids: HashMap::new() };
+        map.add(tree, None);
+        map
+    }
+
+    fn add(&mut self, node: &'t ASTNode<'t>, parent: Option<NodeId>) {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(node);
+        self.parents.push(parent);
+        self.ends.push(id.0 + 1);
+        self.ids.insert(node as *const ASTNode as usize, id);
+        for child in node.children() {
+            self.add(child, Some(id));
+        }
+        self.ends[id.0] = self.nodes.len();
+    }
+
+    /// Number of nodes in the tree.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The id of `node`, `None` if it isn't in this tree.
+    pub fn id(&self, node: &ASTNode) -> Option<NodeId> {
+        self.ids.get(&(node as *const ASTNode as usize)).copied()
+    }
+
+    /// The node with id `id`.
+    ///
+    /// # Panics
+    ///
+    /// If `id` isn't from this map.
+    pub fn node(&self, id: NodeId) -> &'t ASTNode<'t> {
+        self.nodes[id.0]
+    }
+
+    /// The node `id` is below, `None` for the root.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.parents[id.0]
+    }
+
+    /// The parent of `id`, its parent and so on up to the root.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(id), |&ancestor| self.parent(ancestor))
+    }
+
+    /// The nodes right below `id`, in document order.
+    pub fn children(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let end = self.ends[id.0];
+        std::iter::successors(Some(id.0 + 1), move |&child| self.ends.get(child).copied())
+            .take_while(move |&child| child < end)
+            .map(NodeId)
+    }
+
+    /// The ids of `id` and every node below it, which follow each other.
+    pub fn subtree(&self, id: NodeId) -> Range<usize> {
+        id.0..self.ends[id.0]
+    }
+
+    /// Every node with its id, in document order.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &'t ASTNode<'t>)> + '_ {
+        self.nodes.iter().enumerate().map(|(i, node)| (NodeId(i), *node))
+    }
+}
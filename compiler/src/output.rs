@@ -0,0 +1,80 @@
+//! Writing output files without touching the ones that didn't change.
+//!
+//! A rebuild usually produces most pages exactly as they were. Rewriting them
+//! anyway gives file watchers (live reload, rsync, the watch mode of other
+//! tools) a change event for nothing, so outputs are compared with what is on
+//! disk first and only written when they differ.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Writes `contents` to `path` unless the file already holds exactly that.
+/// Returns whether the file was written.
+pub fn write_if_changed(path: &Path, contents: &[u8]) -> io::Result<bool> {
+    if fs::read(path).is_ok_and(|old| old == contents) {
+        return Ok(false);
+    }
+    fs::write(path, contents)?;
+    Ok(true)
+}
+
+/// A file that output streams into, compared with the file's old content on
+/// the way: nothing is written until the first byte that differs.
+///
+/// Call `finish` at the end, it cuts off what is left of a longer old file.
+#[derive(Debug)]
+pub struct ChangedFileWriter {
+    file: File,
+    // bytes of new content so far
+    position: u64,
+    // length of the file before any of the new content
+    old_len: u64,
+    // whether the new content differs from the old one yet
+    changed: bool,
+}
+
+impl ChangedFileWriter {
+    /// Opens `path` for the new content, creating it if it doesn't exist.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let old_len = file.metadata()?.len();
+        Ok(Self { file, position: 0, old_len, changed: false })
+    }
+
+    /// Ends the new content and returns whether the file changed.
+    pub fn finish(mut self) -> io::Result<bool> {
+        if self.position != self.old_len {
+            self.file.set_len(self.position)?;
+            self.changed = true;
+        }
+        Ok(self.changed)
+    }
+}
+
+impl Write for ChangedFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.changed {
+            let mut old = Vec::with_capacity(buf.len());
+            (&mut self.file).take(buf.len() as u64).read_to_end(&mut old)?;
+            let same = buf.iter().zip(&old).take_while(|(new, old)| new == old).count();
+            if same == buf.len() {
+                self.position += buf.len() as u64;
+                return Ok(buf.len());
+            }
+            // from here on the new content is written over the old
+            self.changed = true;
+            self.file.seek(SeekFrom::Start(self.position + same as u64))?;
+            self.file.write_all(&buf[same..])?;
+            self.position += buf.len() as u64;
+            return Ok(buf.len());
+        }
+        let written = self.file.write(buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -1,155 +1,680 @@
-use crate::token::{Token, TokenKind};
-use crate::lexer::{Lexer, LexicalAnalyzer};
-use std::process::exit;
+use crate::arena::AstArena;
+use crate::intern::{Interner, Symbol};
+use crate::cst::{self, NodeKind, SyntaxNode};
+use crate::token::{Token, TokenKind, Trivia};
+use crate::lexer::Lexer;
+use crate::diagnostics::{CompileError, Label, Suppressions};
+use std::collections::{HashMap, VecDeque};
 
 /// Parser trait for syntax analysis
 pub trait Parser {
-    fn parse(&mut self);
-    fn next_token(&mut self) -> Token;
+    fn parse(&mut self) -> Result<(), CompileError>;
+    /// Moves on to the next token and hands back the one moved past.
+    fn next_token(&mut self) -> Result<Token, CompileError>;
     fn current_token(&self) -> &Token;
+    /// The token `ahead` places past the current one, without moving on;
+    /// `0` is the current token itself.
+    fn peek_token(&mut self, ahead: usize) -> Result<&Token, CompileError>;
 }
 
 // Parse tree structure to match grammar
-#[derive(Debug, Clone)]
-pub enum ASTNode {
-    Program { children: Vec<ASTNode> },
-    HeadSection { children: Vec<ASTNode> },
-    ParagrafSection { children: Vec<ASTNode> },
-    ListSection { children: Vec<ASTNode> },
-    VariableDeclaration { name: String },
-    VariableAssignment { name: String, value: String },
-    VariableReference { name: String },
-    Title { content: String },
-    Text { content: String },
-    Bold { content: Vec<ASTNode> },
-    Italics { content: Vec<ASTNode> },
-    Item { content: Vec<ASTNode> },
+// children and text live in an AstArena, see arena.rs
+#[derive(Debug, Clone, PartialEq)]
+pub enum ASTNode<'t> {
+    Program { children: &'t [ASTNode<'t>] },
+    HeadSection { children: &'t [ASTNode<'t>] },
+    MetaSection { entries: Vec<(String, String)> },
+    ParagrafSection { children: &'t [ASTNode<'t>] },
+    ListSection { children: &'t [ASTNode<'t>] },
+    VariableDeclaration { name: Symbol },
+    VariableAssignment { name: Option<Symbol>, value: &'t str },
+    VariableReference { name: Symbol },
+    // text and variable references, like styled text
+    Title { content: &'t [ASTNode<'t>] },
+    Text { content: &'t str },
+    Bold { content: &'t [ASTNode<'t>] },
+    Italics { content: &'t [ASTNode<'t>] },
+    Item { content: &'t [ASTNode<'t>] },
     Newline,
-    Sound { url: String },
-    Video { url: String },
+    Sound { url: &'t str, attributes: Box<MediaAttributes> },
+    Video { url: &'t str, attributes: Box<MediaAttributes> },
+    Image { url: &'t str, attributes: Box<MediaAttributes> },
+    Link { target: &'t str, text: &'t str },
+    // content that is only compiled when `flag` is defined
+    IfDefined { flag: &'t str, children: &'t [ASTNode<'t>] },
+}
+
+impl<'t> ASTNode<'t> {
+    /// The nodes right below this one: the children of a section or
+    /// `#IZ DEFINED`, or the content of a styled element.
+    pub fn children(&self) -> &'t [ASTNode<'t>] {
+        match self {
+            ASTNode::Program { children }
+            | ASTNode::HeadSection { children }
+            | ASTNode::ParagrafSection { children }
+            | ASTNode::ListSection { children }
+            | ASTNode::IfDefined { children, .. } => children,
+            ASTNode::Title { content } | ASTNode::Bold { content } | ASTNode::Italics { content } | ASTNode::Item { content } => {
+                content
+            }
+            _ => &[],
+        }
+    }
+
+    /// What kind of node this is, named as in the JSON tree (`paragraf`,
+    /// `bold`, `video`, ...) and in `query` selectors.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            ASTNode::Program { .. } => "program",
+            ASTNode::HeadSection { .. } => "head",
+            ASTNode::MetaSection { .. } => "meta",
+            ASTNode::ParagrafSection { .. } => "paragraf",
+            ASTNode::ListSection { .. } => "list",
+            ASTNode::VariableDeclaration { .. } => "declaration",
+            ASTNode::VariableAssignment { .. } => "assignment",
+            ASTNode::VariableReference { .. } => "reference",
+            ASTNode::Title { .. } => "title",
+            ASTNode::Text { .. } => "text",
+            ASTNode::Bold { .. } => "bold",
+            ASTNode::Italics { .. } => "italics",
+            ASTNode::Item { .. } => "item",
+            ASTNode::Newline => "newline",
+            ASTNode::Sound { .. } => "sound",
+            ASTNode::Video { .. } => "video",
+            ASTNode::Image { .. } => "image",
+            ASTNode::Link { .. } => "link",
+            ASTNode::IfDefined { .. } => "if_defined",
+        }
+    }
+
+    /// This node and every node below it, depth first in document order.
+    pub fn descendants(&'t self) -> Vec<&'t ASTNode<'t>> {
+        self.find_all(|_| true)
+    }
+
+    /// The nodes from this one down for which `wanted` is true, depth first
+    /// in document order, e.g. every video with
+    /// `tree.find_all(|n| matches!(n, ASTNode::Video { .. }))`.
+    pub fn find_all(&'t self, wanted: impl Fn(&ASTNode<'t>) -> bool) -> Vec<&'t ASTNode<'t>> {
+        let mut found = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if wanted(node) {
+                found.push(node);
+            }
+            stack.extend(node.children().iter().rev());
+        }
+        found
+    }
+}
+
+/// Optional attributes for a media embed, written after the URL as
+/// `WIT <attribute> AN <attribute> ...`, and the variables the URL is built from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaAttributes {
+    /// Variable references in the URL as (byte offset in the URL, variable),
+    /// in order; their values are put in during codegen
+    pub url_references: Vec<(usize, Symbol)>,
+    pub poster: Option<String>,
+    pub autoplay: bool,
+    pub looping: bool,
+    pub muted: bool,
+    pub preload: Option<String>,
+    pub lazy: bool,
+    pub captions: Vec<CaptionTrack>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub srcset: Vec<String>,
+    pub alt: Option<String>,
+}
+
+/// A captions file attached to a video with `CAPTIONS <url> <lang> [<label>]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptionTrack {
+    pub src: String,
+    pub lang: String,
+    pub label: Option<String>,
+}
+
+/// Where constructs start in the source, as (line, column), for diagnostics
+/// about a tree that doesn't record positions itself. Each list is in document
+/// order, the order a depth-first walk of the tree meets the constructs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Positions {
+    /// The `#MAEK` of every section
+    pub sections: Vec<(usize, usize)>,
+    /// The `#OIC` of every section, in the order of `sections`
+    pub section_ends: Vec<(usize, usize)>,
+    /// Every variable declaration, from `#I HAZ` to the end of the name
+    pub declarations: Vec<VariableSpan>,
+    /// Every `#IT IZ ... #MKAY` assignment
+    pub assignments: Vec<VariableSpan>,
+    /// Every `#LEMME SEE ... #MKAY` variable reference
+    pub references: Vec<VariableSpan>,
+    /// The `#GIMMEH` of every TITLE, ITEM, bold or italic text, link and
+    /// media element
+    pub elements: Vec<(usize, usize)>,
+}
+
+impl Positions {
+    /// Where each section and element of `tree`, the tree these positions
+    /// were recorded for, starts, by node address.
+    pub fn element_starts(&self, tree: &ASTNode) -> HashMap<usize, (usize, usize)> {
+        let mut found = HashMap::new();
+        self.collect_starts(tree, &mut (0, 0), &mut found);
+        found
+    }
+
+    // walks the tree in the order the parser recorded positions; `next` counts
+    // the sections and elements seen so far
+    fn collect_starts(&self, node: &ASTNode, next: &mut (usize, usize), found: &mut HashMap<usize, (usize, usize)>) {
+        let key = node as *const ASTNode as usize;
+        match node {
+            ASTNode::HeadSection { .. } | ASTNode::MetaSection { .. } | ASTNode::ParagrafSection { .. } | ASTNode::ListSection { .. } => {
+                if let Some(position) = self.sections.get(next.0) {
+                    found.insert(key, *position);
+                }
+                next.0 += 1;
+            }
+            ASTNode::Title { .. }
+            | ASTNode::Item { .. }
+            | ASTNode::Bold { .. }
+            | ASTNode::Italics { .. }
+            | ASTNode::Link { .. }
+            | ASTNode::Sound { .. }
+            | ASTNode::Video { .. }
+            | ASTNode::Image { .. } => {
+                if let Some(position) = self.elements.get(next.1) {
+                    found.insert(key, *position);
+                }
+                next.1 += 1;
+            }
+            _ => {}
+        }
+        match node {
+            ASTNode::Program { children }
+            | ASTNode::HeadSection { children }
+            | ASTNode::ParagrafSection { children }
+            | ASTNode::ListSection { children }
+            | ASTNode::IfDefined { children, .. } => {
+                for child in children.iter() {
+                    self.collect_starts(child, next, found);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Where a variable declaration, assignment or reference is in the source.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VariableSpan {
+    /// The hashword that starts it
+    pub start: (usize, usize),
+    /// Just past its end
+    pub end: (usize, usize),
+    /// The variable's name, when it is written there
+    pub name: Option<(usize, usize)>,
+}
+
+/// Default limit on how deeply sections can nest inside each other.
+///
+/// Parsing and every backend recurse once per level; a debug build running on a
+/// 2 MB worker thread overflows its stack somewhere past 500 levels, so this
+/// leaves room for the deeper backends while being far beyond any real document.
+pub const DEFAULT_MAX_NESTING: usize = 256;
+
+/// A language version, written after `#HAI` as `<major>.<minor>`.
+///
+/// Grammar added since the first version needs a document to declare at least the
+/// version that brought it in. Documents without a version get `LATEST`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LanguageVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl LanguageVersion {
+    /// Sections, styled text, variables, SOUNDZ and VIDZ.
+    pub const V1_0: Self = Self { major: 1, minor: 0 };
+    /// Adds media attributes after `WIT`, PIKCHUR, LINKZ and META sections.
+    pub const V1_1: Self = Self { major: 1, minor: 1 };
+    /// Adds quoted YARN literals.
+    pub const V1_2: Self = Self { major: 1, minor: 2 };
+    /// The newest version this compiler understands.
+    pub const LATEST: Self = Self::V1_2;
+
+    /// Reads `<major>.<minor>`, e.g. `1.0`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (major, minor) = text.split_once('.')?;
+        let digits = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+        if !digits(major) || !digits(minor) {
+            return None;
+        }
+        Some(Self { major: major.parse().ok()?, minor: minor.parse().ok()? })
+    }
+}
+
+impl std::fmt::Display for LanguageVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+// where the parser gets its tokens from
+enum TokenSource<'a> {
+    Lexer(Lexer<'a>),
+    // tokens lexed earlier, see LolcodeParser::from_tokens
+    Tokens(std::vec::IntoIter<Token>),
+}
+
+// a construct that #MKAY closes, so a missing #MKAY can point back at it
+#[derive(Debug, Clone, Copy)]
+struct Opener {
+    at: (usize, usize),
+    hashword: &'static str,
+    keyword: Option<&'static str>,
+}
+
+// what a parser made with with_cst keeps for the concrete tree
+struct CstState<'a> {
+    builder: cst::Builder<'a>,
+    // trivia before the current token and the source it was read from
+    current: (Vec<Trivia<'a>>, &'a str),
+    // the same for every token read past the current one, next first
+    ahead: VecDeque<(Vec<Trivia<'a>>, &'a str)>,
 }
 
 //parser implementation
-pub struct LolcodeParser<'a> {
-    lexer: Lexer<'a>,
+pub struct LolcodeParser<'a, 't> {
+    tokens: TokenSource<'a>,
     current_tok: Token,
-    pub parse_tree: Option<ASTNode>,
+    // tokens read past the current one by peek_token, next first
+    lookahead: VecDeque<Result<Token, CompileError>>,
+    // (line, col) of the token before the current one
+    previous_at: (usize, usize),
+    // where the tree's nodes and text are allocated
+    arena: &'t AstArena<'t>,
+    pub parse_tree: Option<&'t ASTNode<'t>>,
+    /// Variable names seen so far; hand it to the semantic analyzer with the tree
+    pub interner: Interner,
+    /// Deepest section nesting accepted before a syntax error
+    pub max_nesting: usize,
+    /// Language version of the document, from `#HAI <version>`
+    pub version: LanguageVersion,
+    /// Diagnostic codes silenced with `#SHHH`, and where
+    pub suppressions: Suppressions<'t>,
+    /// Where sections and variable references start
+    pub positions: Positions,
+    /// Every syntax error found, the first of them also returned by `parse`.
+    /// A section left open by a forgotten `#OIC` is closed where it can't go
+    /// on and parsing continues, so more than one can be found
+    pub errors: Vec<CompileError>,
+    // constructs waiting for their #MKAY, innermost last
+    opened: Vec<Opener>,
+    // sections currently open
+    depth: usize,
+    // paragrafs currently open, HEAD and META can't go inside one
+    paragrafs: usize,
+    // a lexical error in the very first token, returned by parse
+    first_error: Option<CompileError>,
+    // the concrete tree as far as it is built, see with_cst
+    cst_state: Option<CstState<'a>>,
+    /// The concrete syntax tree of the document, after a successful `parse`
+    /// by a parser made with `with_cst`
+    pub cst: Option<SyntaxNode<'a>>,
 }
 
-impl<'a> LolcodeParser<'a> {
-    pub fn new(source: &'a str) -> Self {
-        let mut lexer = Lexer::new(source);
-        let first_token = lexer.get_next_token();
-        
+impl<'a, 't> LolcodeParser<'a, 't> {
+    pub fn new(source: &'a str, arena: &'t AstArena<'t>) -> Self {
+        Self::from_lexer(Lexer::new(source), arena, false)
+    }
+
+    /// Creates a parser that also builds the concrete syntax tree of the
+    /// source, kept in `cst` once `parse` succeeds.
+    pub fn with_cst(source: &'a str, arena: &'t AstArena<'t>) -> Self {
+        Self::from_lexer(Lexer::lossless(source), arena, true)
+    }
+
+    fn from_lexer(mut lexer: Lexer<'a>, arena: &'t AstArena<'t>, keep_cst: bool) -> Self {
+        let (first_token, current, first_error) = match lexer.next_lossless() {
+            Ok(token) => (token.token, (token.leading, token.text), None),
+            Err(e) => (Token { kind: TokenKind::Eof, line: 1, col: 1 }, (Vec::new(), ""), Some(e)),
+        };
+        let cst_state = keep_cst.then(|| CstState { builder: cst::Builder::default(), current, ahead: VecDeque::new() });
+
+        Self {
+            tokens: TokenSource::Lexer(lexer),
+            current_tok: first_token,
+            lookahead: VecDeque::new(),
+            previous_at: (1, 1),
+            first_error,
+            arena,
+            parse_tree: None,
+            interner: Interner::new(),
+            max_nesting: DEFAULT_MAX_NESTING,
+            version: LanguageVersion::LATEST,
+            suppressions: Suppressions::default(),
+            positions: Positions::default(),
+            errors: Vec::new(),
+            opened: Vec::new(),
+            depth: 0,
+            paragrafs: 0,
+            cst_state,
+            cst: None,
+        }
+    }
+
+    /// Creates a parser over tokens that were already lexed, e.g. by
+    /// `stages::lex`. Running out of tokens reads as the end of the file.
+    pub fn from_tokens(tokens: Vec<Token>, arena: &'t AstArena<'t>) -> Self {
+        let mut tokens = tokens.into_iter();
+        let first_token = tokens.next().unwrap_or(Token { kind: TokenKind::Eof, line: 1, col: 1 });
+
         Self {
-            lexer,
+            tokens: TokenSource::Tokens(tokens),
             current_tok: first_token,
+            lookahead: VecDeque::new(),
+            previous_at: (1, 1),
+            first_error: None,
+            arena,
             parse_tree: None,
+            interner: Interner::new(),
+            max_nesting: DEFAULT_MAX_NESTING,
+            version: LanguageVersion::LATEST,
+            suppressions: Suppressions::default(),
+            positions: Positions::default(),
+            errors: Vec::new(),
+            opened: Vec::new(),
+            depth: 0,
+            paragrafs: 0,
+            cst_state: None,
+            cst: None,
         }
     }
 
     // error reporting with line/col information
-    fn syntax_error(&self, msg: &str) -> ! {
-        eprintln!(
-            "Syntax error at line {}, col {}: {}",
-            self.current_tok.line, self.current_tok.col, msg
-        );
-        exit(1);
+    fn syntax_error(&self, msg: &str) -> CompileError {
+        self.syntax_error_with(msg, Vec::new())
+    }
+
+    // an error that also points somewhere else in the source
+    fn syntax_error_with(&self, msg: &str, labels: Vec<Label>) -> CompileError {
+        #[cfg(feature = "tracing")]
+        tracing::error!(line = self.current_tok.line, col = self.current_tok.col, "syntax error: {}", msg);
+        CompileError::Syntax {
+            line: self.current_tok.line,
+            col: self.current_tok.col,
+            message: msg.to_string(),
+            labels,
+        }
+    }
+
+    // a construct opened at `at` that close ends
+    fn open(&mut self, at: (usize, usize), hashword: &'static str, keyword: Option<&'static str>) {
+        self.opened.push(Opener { at, hashword, keyword });
+    }
+
+    // the #MKAY that ends the construct opened last; without it the error points
+    // back at where that construct was opened
+    fn close(&mut self) -> Result<(), CompileError> {
+        let opener = self.opened.pop();
+        if matches!(self.current_tok.kind, TokenKind::HashWord("#MKAY")) {
+            self.next_token()?;
+            return Ok(());
+        }
+        let Some(opener) = opener else {
+            return self.match_hashword("#MKAY");
+        };
+        let what = match opener.keyword {
+            Some(keyword) => format!("{} {}", opener.hashword, keyword),
+            None => opener.hashword.to_string(),
+        };
+        let label = Label { line: opener.at.0, col: opener.at.1, message: format!("{} opened here", what) };
+        Err(self.syntax_error_with(
+            &format!("Expected '#MKAY' to close {} but found {:?}", what, self.current_tok.kind),
+            vec![label],
+        ))
+    }
+
+    // grammar added in `since` is an error in documents of an older version
+    fn require(&self, since: LanguageVersion, feature: &str) -> Result<(), CompileError> {
+        if self.version < since {
+            return Err(self.syntax_error(&format!(
+                "{} needs #HAI {} or newer, this document is #HAI {}",
+                feature, since, self.version
+            )));
+        }
+        Ok(())
     }
 
     //  checking that current token matches expected hashword
-    fn match_hashword(&mut self, expected: &str) {
-        if let TokenKind::HashWord(ref hw) = self.current_tok.kind {
-            if hw == expected {
-                self.next_token();
-                return;
-            }
+    fn match_hashword(&mut self, expected: &str) -> Result<(), CompileError> {
+        if let TokenKind::HashWord(hw) = self.current_tok.kind
+            && hw == expected
+        {
+            self.next_token()?;
+            return Ok(());
         }
-        self.syntax_error(&format!("Expected '{}' but found {:?}", expected, self.current_tok.kind));
+        Err(self.syntax_error(&format!("Expected '{}' but found {:?}", expected, self.current_tok.kind)))
     }
 
     // Checking that current token matches expected keyword
-    fn match_keyword(&mut self, expected: &str) {
-        if let TokenKind::Keyword(ref kw) = self.current_tok.kind {
-            if kw == expected {
-                self.next_token();
-                return;
+    fn match_keyword(&mut self, expected: &str) -> Result<(), CompileError> {
+        if let TokenKind::Keyword(kw) = self.current_tok.kind
+            && kw == expected
+        {
+            self.next_token()?;
+            return Ok(());
+        }
+        Err(self.syntax_error(&format!("Expected keyword '{}' but found {:?}", expected, self.current_tok.kind)))
+    }
+
+    // a quoted YARN value and the #MKAY after it, taken exactly as written
+    fn yarn(&mut self) -> Result<Option<String>, CompileError> {
+        if !matches!(self.current_tok.kind, TokenKind::Yarn(_)) {
+            return Ok(None);
+        }
+        self.require(LanguageVersion::V1_2, "A YARN literal")?;
+        let TokenKind::Yarn(yarn) = self.next_token()?.kind else {
+            unreachable!("checked for a YARN above");
+        };
+        self.close()?;
+        Ok(Some(yarn))
+    }
+
+    // a token a section that is still open can't hold: the end of the document,
+    // and for sections that can't nest, the next section
+    fn ends_open_section(&self, nests: bool) -> bool {
+        match self.current_tok.kind {
+            TokenKind::Eof | TokenKind::HashWord("#KTHXBYE") => true,
+            TokenKind::HashWord("#MAEK") => !nests,
+            _ => false,
+        }
+    }
+
+    // a forgotten #OIC: the error is kept and the section ends here, so parsing
+    // can go on and find more
+    fn unclosed(&mut self, what: &str, start: (usize, usize)) {
+        let error = self.syntax_error(&format!(
+            "Unclosed {} started at line {}, col {} - missing #OIC",
+            what, start.0, start.1
+        ));
+        self.errors.push(error);
+    }
+
+    // where the section being parsed starts, see section
+    fn section_start(&self) -> (usize, usize) {
+        self.positions.sections.last().copied().unwrap_or((1, 1))
+    }
+
+    // the first token from the current one on that isn't a newline, without moving on
+    fn peek_past_newlines(&mut self) -> Result<&Token, CompileError> {
+        let mut ahead = 0;
+        while matches!(self.peek_token(ahead)?.kind, TokenKind::Newline) {
+            ahead += 1;
+        }
+        self.peek_token(ahead)
+    }
+
+    // the token after the last one read from the source
+    fn read_token(&mut self) -> Result<Token, CompileError> {
+        match &mut self.tokens {
+            TokenSource::Lexer(lexer) => {
+                let token = lexer.next_lossless()?;
+                if let Some(cst) = &mut self.cst_state {
+                    cst.ahead.push_back((token.leading, token.text));
+                }
+                Ok(token.token)
             }
+            // past the last token stays at the end of the file
+            TokenSource::Tokens(tokens) => Ok(tokens.next().unwrap_or_else(|| {
+                let last = match self.lookahead.back() {
+                    Some(Ok(token)) => token,
+                    _ => &self.current_tok,
+                };
+                Token { kind: TokenKind::Eof, line: last.line, col: last.col }
+            })),
         }
-        self.syntax_error(&format!("Expected keyword '{}' but found {:?}", expected, self.current_tok.kind));
     }
 
-    // Skip optional newlines
-    fn skip_newlines(&mut self) {
-        while matches!(self.current_tok.kind, TokenKind::Newline) {
-            self.next_token();
+    // a node of the concrete tree around what `parse` reads, of the kind of
+    // the AST node it gives
+    fn node(&mut self, parse: impl FnOnce(&mut Self) -> Result<ASTNode<'t>, CompileError>) -> Result<ASTNode<'t>, CompileError> {
+        let Some(checkpoint) = self.cst_state.as_ref().map(|cst| cst.builder.checkpoint()) else {
+            return parse(self);
+        };
+        let node = parse(self)?;
+        if let (Some(cst), Some(kind)) = (&mut self.cst_state, NodeKind::of(&node)) {
+            cst.builder.wrap(checkpoint, kind);
         }
+        Ok(node)
     }
 
-    // consume a newline
-    fn match_newline(&mut self) {
-        if matches!(self.current_tok.kind, TokenKind::Newline) {
-            self.next_token();
-        } else {
-            self.syntax_error("Expected newline");
+    // Skip optional newlines
+    fn skip_newlines(&mut self) -> Result<(), CompileError> {
+        while matches!(self.current_tok.kind, TokenKind::Newline) {
+            self.next_token()?;
         }
+        Ok(())
     }
 
-    // grammar: <program> ::= #HAI <body> #KTHXBYE
-    fn program(&mut self) -> ASTNode {
-        self.match_hashword("#HAI");
-        self.skip_newlines();
+    // grammar: <program> ::= #HAI [<version>] <body> #KTHXBYE
+    fn program(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let hai_line = self.current_tok.line;
+        self.match_hashword("#HAI")?;
+        self.version_pragma(hai_line)?;
+        self.skip_newlines()?;
         
-        let body = self.body();
+        let body = self.body()?;
         
-        self.skip_newlines();
-        self.match_hashword("#KTHXBYE");
-        self.skip_newlines();  // will still end program if there is extra white space at end
+        self.skip_newlines()?;
+        self.match_hashword("#KTHXBYE")?;
+        self.skip_newlines()?;  // will still end program if there is extra white space at end
         
         // Check for EOF
         if !matches!(self.current_tok.kind, TokenKind::Eof) {
-            self.syntax_error("Unexpected tokens after #KTHXBYE");
+            return Err(self.syntax_error("Unexpected tokens after #KTHXBYE"));
         }
         
-        ASTNode::Program { children: body }
+        Ok(ASTNode::Program { children: body })
+    }
+
+    // a version right after #HAI on the same line; anything else is the first text
+    fn version_pragma(&mut self, hai_line: usize) -> Result<(), CompileError> {
+        let TokenKind::Text(text) = &self.current_tok.kind else {
+            return Ok(());
+        };
+        if self.current_tok.line != hai_line {
+            return Ok(());
+        }
+        let Some(version) = LanguageVersion::parse(text) else {
+            return Ok(());
+        };
+        if version > LanguageVersion::LATEST {
+            return Err(self.syntax_error(&format!(
+                "This document needs LOLCODE {}, but this compiler only supports up to {}",
+                version,
+                LanguageVersion::LATEST
+            )));
+        }
+        self.version = version;
+        self.next_token()?;
+        Ok(())
+    }
+
+    // a #SHHH pragma, remembered with the index of the construct after it
+    fn shhh(&mut self, next_index: usize, pending: &mut Vec<(&'static str, usize)>) -> Result<bool, CompileError> {
+        let TokenKind::Shhh(code) = self.current_tok.kind else {
+            return Ok(false);
+        };
+        pending.push((code, next_index));
+        self.next_token()?;
+        Ok(true)
+    }
+
+    // once the constructs are in the arena: pragmas before the first one cover all
+    // of them, the others the construct right after
+    fn attach_shhh(&mut self, pending: Vec<(&'static str, usize)>, nodes: &'t [ASTNode<'t>]) {
+        for (code, index) in pending {
+            let covered = if index == 0 { nodes } else { nodes.get(index..=index).unwrap_or(&[]) };
+            for node in covered {
+                self.suppressions.nodes.push((node, code));
+            }
+        }
     }
 
     // <body> ::= { <section> | <content> }
-    fn body(&mut self) -> Vec<ASTNode> {
+    fn body(&mut self) -> Result<&'t [ASTNode<'t>], CompileError> {
         let mut nodes = Vec::new();
+        let mut pending = Vec::new();
         
         loop {
-            self.skip_newlines();
+            self.skip_newlines()?;
+            
+            // before the first construct a pragma covers the whole document
+            if self.shhh(nodes.len(), &mut pending)? {
+                if nodes.is_empty() {
+                    self.suppressions.document.extend(pending.drain(..).map(|(code, _)| code));
+                }
+                continue;
+            }
             
             // Check for end of program
-            if let TokenKind::HashWord(ref hw) = self.current_tok.kind {
+            if let TokenKind::HashWord(hw) = self.current_tok.kind {
                 if hw == "#KTHXBYE" {
                     break;
                 }
                 if hw == "#MAEK" {
                     // Section
-                    nodes.push(self.section());
+                    nodes.push(self.node(Self::section)?);
                     continue;
                 }
                 // Check for variable declarations at top level
                 if hw == "#I HAZ" {
-                    nodes.push(self.variable_declaration());
-                    self.skip_newlines();
-                    // Check for assignment that follows
-                    if let TokenKind::HashWord(ref hw2) = self.current_tok.kind {
-                        if hw2 == "#IT IZ" {
-                            nodes.push(self.variable_assignment());
-                        }
+                    nodes.push(self.node(Self::variable_declaration)?);
+                    // an assignment that follows, past any newlines
+                    if matches!(self.peek_past_newlines()?.kind, TokenKind::HashWord("#IT IZ")) {
+                        self.skip_newlines()?;
+                        nodes.push(self.node(Self::variable_assignment)?);
                     }
                     continue;
                 }
             if hw == "#LEMME SEE" {
-                nodes.push(self.variable_reference());
+                nodes.push(self.node(Self::variable_reference)?);
+                continue;
+            }
+            if hw == "#IT IZ" {
+                nodes.push(self.node(Self::variable_assignment)?);
+                continue;
+            }
+            if hw == "#IZ DEFINED" {
+                nodes.push(self.node(|p| p.if_defined(Self::paragraf_content))?);
                 continue;
             }
                 // some other hashword
             if hw == "#GIMMEH" {
-                nodes.push(self.styled_text());
+                nodes.push(self.node(Self::styled_text)?);
                 continue;
                 }
             }
@@ -161,67 +686,103 @@ impl<'a> LolcodeParser<'a> {
             
             // Deals w/ text or other
             match &self.current_tok.kind {
-                TokenKind::Text(t) => {
-                    let text = t.clone();
-                    self.next_token();
+                TokenKind::Text(t) | TokenKind::Address(t) => {
+                    let text = self.arena.alloc_str(t);
+                    self.next_token()?;
                     nodes.push(ASTNode::Text { content: text });
                 }
                 TokenKind::VarDef(v) => {
-                    let var = v.clone();
-                    self.next_token();
+                    let var = self.arena.alloc_str(v);
+                    self.next_token()?;
                     nodes.push(ASTNode::Text { content: var });
                 }
                 _ => {
-                    self.next_token();
+                    self.next_token()?;
                 }
             }
         }
         
-        nodes
+        let nodes = self.arena.alloc_nodes(nodes);
+        self.attach_shhh(pending, nodes);
+        Ok(nodes)
+    }
+
+    // sections nest through paragrafs, so this is the one place the parser
+    // (and later every traversal of the tree) can recurse without bound
+    fn section(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let index = self.positions.sections.len();
+        let start = (self.current_tok.line, self.current_tok.col);
+        self.positions.sections.push(start);
+        // nested sections end first, so the end is filled in once it is known
+        self.positions.section_ends.push(start);
+        self.depth += 1;
+        if self.depth > self.max_nesting {
+            return Err(self.syntax_error(&format!(
+                "Sections are nested more than {} levels deep",
+                self.max_nesting
+            )));
+        }
+        let node = self.section_kind()?;
+        self.positions.section_ends[index] = self.previous_at;
+        self.depth -= 1;
+        Ok(node)
     }
 
     // grammar: <section> ::= <head_section> | <paragraf_section> | <list_section>
-    fn section(&mut self) -> ASTNode {
-        if let TokenKind::HashWord(ref hw) = self.current_tok.kind {
-            match hw.as_str() {
+    fn section_kind(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        if let TokenKind::HashWord(hw) = self.current_tok.kind {
+            match hw {
                 "#MAEK" => {
-                    self.next_token();
-                    self.skip_newlines();
+                    self.next_token()?;
+                    self.skip_newlines()?;
                     
-                    if let TokenKind::Keyword(ref kw) = self.current_tok.kind {
-                        match kw.as_str() {
+                    if let TokenKind::Keyword(kw) = self.current_tok.kind {
+                        // a PARAGRAF can hold PARAGRAFs and LISTs, the other sections only
+                        // go at the top level
+                        if matches!(kw, "HEAD" | "META") && self.paragrafs > 0 {
+                            return Err(self.syntax_error(&format!(
+                                "A {} section can't be inside a PARAGRAF, only at the top level of the document",
+                                kw
+                            )));
+                        }
+                        match kw {
                             "HEAD" => return self.head_section(),
                             "PARAGRAF" => return self.paragraf_section(),
                             "LIST" => return self.list_section(),
-                            _ => self.syntax_error(&format!("Unknown section type '{}'", kw)),
+                            "META" => {
+                                self.require(LanguageVersion::V1_1, "#MAEK META")?;
+                                return self.meta_section();
+                            }
+                            _ => return Err(self.syntax_error(&format!("Unknown section type '{}'", kw))),
                         }
                     } else {
-                        self.syntax_error("Expected section type after #MAEK");
+                        return Err(self.syntax_error("Expected section type after #MAEK"));
                     }
                 }
-                _ => self.syntax_error(&format!("Expected #MAEK to start a section, found '{}'", hw)),
+                _ => return Err(self.syntax_error(&format!("Expected #MAEK to start a section, found '{}'", hw))),
             }
         }
-        self.syntax_error("Expected section");
+        Err(self.syntax_error("Expected section"))
     }
 
     // grammar:  <head_section> ::= #MAEK HEAD <head_content> #OIC
-    fn head_section(&mut self) -> ASTNode {
-        self.match_keyword("HEAD");
-        self.skip_newlines();
+    fn head_section(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let start = self.section_start();
+        self.match_keyword("HEAD")?;
+        self.skip_newlines()?;
         
         let mut children = Vec::new();
         
         // Parse head content until #OIC
         loop {
-            self.skip_newlines();
+            self.skip_newlines()?;
             
-            if let TokenKind::HashWord(ref hw) = self.current_tok.kind {
+            if let TokenKind::HashWord(hw) = self.current_tok.kind {
                 if hw == "#OIC" {
                     break;
                 }
                 if hw == "#GIMMEH" {
-                    children.push(self.head_content());
+                    children.push(self.node(Self::head_content)?);
                     continue;
                 }
             }
@@ -230,26 +791,81 @@ impl<'a> LolcodeParser<'a> {
             if !matches!(self.current_tok.kind, TokenKind::Newline) {
                 break;
             }
-            self.next_token();
+            self.next_token()?;
         }
         
-        self.match_hashword("#OIC");
+        if self.ends_open_section(false) {
+            self.unclosed("HEAD section", start);
+        } else {
+            self.match_hashword("#OIC")?;
+        }
         
-        ASTNode::HeadSection { children }
+        Ok(ASTNode::HeadSection { children: self.arena.alloc_nodes(children) })
+    }
+
+    // grammar: <meta_section> ::= #MAEK META { #I HAZ <key> #IT IZ (<value> | <yarn>) #MKAY } #OIC
+    fn meta_section(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let start = self.section_start();
+        self.match_keyword("META")?;
+        self.skip_newlines()?;
+
+        let mut entries = Vec::new();
+
+        while !matches!(self.current_tok.kind, TokenKind::HashWord(hw) if hw == "#OIC") {
+            if self.ends_open_section(false) {
+                self.unclosed("META section", start);
+                return Ok(ASTNode::MetaSection { entries });
+            }
+            self.match_hashword("#I HAZ")?;
+            let key = match &self.current_tok.kind {
+                TokenKind::VarDef(name) => name.to_ascii_lowercase(),
+                _ => return Err(self.syntax_error("Expected metadata name after #I HAZ")),
+            };
+            self.next_token()?;
+            self.skip_newlines()?;
+
+            // values keep their spacing, descriptions are whole sentences
+            let at = (self.current_tok.line, self.current_tok.col);
+            self.match_hashword("#IT IZ")?;
+            self.open(at, "#IT IZ", None);
+            let value = match self.yarn()? {
+                Some(yarn) => yarn,
+                None => self.spaced_body()?,
+            };
+            entries.push((key, value));
+            self.skip_newlines()?;
+        }
+
+        self.match_hashword("#OIC")?;
+
+        Ok(ASTNode::MetaSection { entries })
     }
 
-    // grammar: <head_content> ::= #GIMMEH TITLE <text> #MKAY
-    fn head_content(&mut self) -> ASTNode {
-        self.match_hashword("#GIMMEH");
-        self.match_keyword("TITLE");
+    // grammar: <head_content> ::= #GIMMEH TITLE ({ <text> | <variable_reference> } | <yarn>) #MKAY
+    fn head_content(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let at = (self.current_tok.line, self.current_tok.col);
+        self.match_hashword("#GIMMEH")?;
+        self.match_keyword("TITLE")?;
+        self.open(at, "#GIMMEH", Some("TITLE"));
+        self.positions.elements.push(at);
+        if let Some(yarn) = self.yarn()? {
+            let text = ASTNode::Text { content: self.arena.alloc_str(&yarn) };
+            return Ok(ASTNode::Title { content: self.arena.alloc_nodes(vec![text]) });
+        }
         
+        let mut content = Vec::new();
         let mut title_text = String::new();
         
-        // collect text until #MKAY
+        // collect text until #MKAY, the words between references make one text each
         loop {
             match &self.current_tok.kind {
-                TokenKind::HashWord(hw) if hw == "#MKAY" => break,
-                TokenKind::Text(t) => {
+                TokenKind::HashWord(hw) if *hw == "#MKAY" => break,
+                TokenKind::HashWord(hw) if *hw == "#LEMME SEE" => {
+                    self.push_title_text(&mut title_text, &mut content);
+                    content.push(self.node(Self::variable_reference)?);
+                    continue;
+                }
+                TokenKind::Text(t) | TokenKind::Address(t) => {
                     if !title_text.is_empty() {
                         title_text.push(' ');
                     }
@@ -265,263 +881,621 @@ impl<'a> LolcodeParser<'a> {
                     // Skip newlines in title
                 }
                 _ => {
-                    self.syntax_error(&format!("Unexpected token in TITLE: {:?}", self.current_tok.kind));
+                    return Err(self.syntax_error(&format!("Unexpected token in TITLE: {:?}", self.current_tok.kind)));
                 }
             }
-            self.next_token();
+            self.next_token()?;
         }
         
-        self.match_hashword("#MKAY");
+        self.close()?;
+        self.push_title_text(&mut title_text, &mut content);
         
-        ASTNode::Title { content: title_text.trim().to_string() }
+        Ok(ASTNode::Title { content: self.arena.alloc_nodes(content) })
+    }
+
+    // the title words collected so far become a text node
+    fn push_title_text(&mut self, title_text: &mut String, content: &mut Vec<ASTNode<'t>>) {
+        let text = title_text.trim();
+        if !text.is_empty() {
+            content.push(ASTNode::Text { content: self.arena.alloc_str(text) });
+        }
+        title_text.clear();
     }
 
     // gtammar: <paragraf_section> ::= #MAEK PARAGRAF <paragraf_content> #OIC
-    fn paragraf_section(&mut self) -> ASTNode {
-        self.match_keyword("PARAGRAF");
-        self.skip_newlines();
+    fn paragraf_section(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let start = self.section_start();
+        self.match_keyword("PARAGRAF")?;
+        self.skip_newlines()?;
         
         let mut children = Vec::new();
+        let mut pending = Vec::new();
         
-        while !matches!(self.current_tok.kind, TokenKind::HashWord(ref hw) if hw == "#OIC") {
-            children.push(self.paragraf_content());
-            self.skip_newlines();
+        // a paragraf can hold sections, so only the end of the document closes one
+        self.paragrafs += 1;
+        while !matches!(self.current_tok.kind, TokenKind::HashWord(hw) if hw == "#OIC") {
+            if self.ends_open_section(true) {
+                break;
+            }
+            if !self.shhh(children.len(), &mut pending)? {
+                children.push(self.paragraf_content()?);
+            }
+            self.skip_newlines()?;
         }
+        self.paragrafs -= 1;
         
-        self.match_hashword("#OIC");
+        if self.ends_open_section(true) {
+            self.unclosed("PARAGRAF section", start);
+        } else {
+            self.match_hashword("#OIC")?;
+        }
         
-        ASTNode::ParagrafSection { children }
+        let children = self.arena.alloc_nodes(children);
+        self.attach_shhh(pending, children);
+        Ok(ASTNode::ParagrafSection { children })
     }
 
     // grammar: <paragraf_content> ::= <variable_decl> | <variable_assign> | <styled_text> | <text>
-    fn paragraf_content(&mut self) -> ASTNode {
+    //                               | <section> | <if_defined>
+    fn paragraf_content(&mut self) -> Result<ASTNode<'t>, CompileError> {
         match &self.current_tok.kind {
             TokenKind::HashWord(hw) => {
-                match hw.as_str() {
-                    "#I HAZ" => self.variable_declaration(),
-                    "#IT IZ" => self.variable_assignment(),
-                    "#LEMME SEE" => self.variable_reference(),
-                    "#GIMMEH" => self.styled_text(),
-                    "#MAEK" => self.section(),
-                    _ => self.syntax_error(&format!("Unexpected hashword in paragraf: {}", hw)),
+                match *hw {
+                    "#I HAZ" => self.node(Self::variable_declaration),
+                    "#IT IZ" => self.node(Self::variable_assignment),
+                    "#LEMME SEE" => self.node(Self::variable_reference),
+                    "#GIMMEH" => self.node(Self::styled_text),
+                    "#MAEK" => self.node(Self::section),
+                    "#IZ DEFINED" => self.node(|p| p.if_defined(Self::paragraf_content)),
+                    _ => Err(self.syntax_error(&format!("Unexpected hashword in paragraf: {}", hw))),
                 }
             }
-            TokenKind::Text(t) => {
-                let text = t.clone();
-                self.next_token();
-                ASTNode::Text { content: text }
+            TokenKind::Text(t) | TokenKind::Address(t) => {
+                let text = self.arena.alloc_str(t);
+                self.next_token()?;
+                Ok(ASTNode::Text { content: text })
             }
             TokenKind::VarDef(v) => {
-                let var = v.clone();
-                self.next_token();
-                ASTNode::Text { content: var }
+                let var = self.arena.alloc_str(v);
+                self.next_token()?;
+                Ok(ASTNode::Text { content: var })
             }
             TokenKind::Newline => {
-                self.next_token();
-                ASTNode::Newline
+                self.next_token()?;
+                Ok(ASTNode::Newline)
             }
-            _ => self.syntax_error("Unexpected token in paragraf content"),
+            _ => Err(self.syntax_error("Unexpected token in paragraf content")),
         }
     }
 
     // grammar:  <variable_decl> ::= #I HAZ <varname>
-    fn variable_declaration(&mut self) -> ASTNode {
-        self.match_hashword("#I HAZ");
+    fn variable_declaration(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let start = (self.current_tok.line, self.current_tok.col);
+        self.match_hashword("#I HAZ")?;
         
         if let TokenKind::VarDef(name) = &self.current_tok.kind {
-            let var_name = name.clone();
-            self.next_token();
-            ASTNode::VariableDeclaration { name: var_name }
+            let var_name = self.interner.intern(name);
+            let at = (self.current_tok.line, self.current_tok.col);
+            let end = (at.0, at.1 + name.chars().count());
+            self.positions.declarations.push(VariableSpan { start, end, name: Some(at) });
+            self.next_token()?;
+            Ok(ASTNode::VariableDeclaration { name: var_name })
         } else {
-            self.syntax_error("Expected variable name after #I HAZ");
+            Err(self.syntax_error("Expected variable name after #I HAZ"))
         }
     }
 
-    // grammar: <variable_assign> ::= #IT IZ <value> #MKAY
-    fn variable_assignment(&mut self) -> ASTNode {
-        self.match_hashword("#IT IZ");
+    // grammar: <variable_assign> ::= #IT IZ [<varname> R] (<value> | <yarn>) #MKAY
+    // without a name it assigns to the variable declared right before it
+    fn variable_assignment(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let mut span = VariableSpan { start: (self.current_tok.line, self.current_tok.col), ..VariableSpan::default() };
+        self.match_hashword("#IT IZ")?;
+        self.open(span.start, "#IT IZ", None);
+        let mut name = None;
+        if let TokenKind::VarDef(target) = &self.current_tok.kind {
+            name = Some(self.interner.intern(target));
+            span.name = Some((self.current_tok.line, self.current_tok.col));
+            self.next_token()?;
+        }
+        if let Some(yarn) = self.yarn()? {
+            self.push_assignment(span);
+            return Ok(ASTNode::VariableAssignment { name, value: self.arena.alloc_str(&yarn) });
+        }
         
         let mut value = String::new();
         
         // last variable assigned, need semantic to deal with scoping here
-        while !matches!(self.current_tok.kind, TokenKind::HashWord(ref hw) if hw == "#MKAY") {
+        while !matches!(self.current_tok.kind, TokenKind::HashWord(hw) if hw == "#MKAY") {
             match &self.current_tok.kind {
-                TokenKind::Text(t) => value.push_str(t),
+                TokenKind::VarVal(t) | TokenKind::Text(t) | TokenKind::Address(t) => value.push_str(t),
                 TokenKind::VarDef(v) => value.push_str(v),
                 _ => break,
             }
-            self.next_token();
+            self.next_token()?;
         }
         
-        self.match_hashword("#MKAY");
+        self.close()?;
+        self.push_assignment(span);
         
-        ASTNode::VariableAssignment { 
-            name: String::new(), // Need semantic analyzer here
-            value: value.trim().to_string() 
-        }
+        Ok(ASTNode::VariableAssignment { 
+            name, // without one the semantic analyzer finds the variable
+            value: self.arena.alloc_str(value.trim()),
+        })
+    }
+
+    // an assignment ends at the #MKAY just matched
+    fn push_assignment(&mut self, mut span: VariableSpan) {
+        let (line, col) = self.previous_at;
+        span.end = (line, col + "#MKAY".len());
+        self.positions.assignments.push(span);
     }
 
     // grammar:  <variable_reference> ::= #LEMME SEE <varname> #MKAY
-    fn variable_reference(&mut self) -> ASTNode {
-        self.match_hashword("#LEMME SEE");
+    fn variable_reference(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let name = self.referenced_name()?;
+        self.close_reference()?;
+        Ok(ASTNode::VariableReference { name })
+    }
+
+    // a variable reference up to the #MKAY that closes it
+    fn referenced_name(&mut self) -> Result<Symbol, CompileError> {
+        let start = (self.current_tok.line, self.current_tok.col);
+        self.match_hashword("#LEMME SEE")?;
+        self.open(start, "#LEMME SEE", None);
         
         if let TokenKind::VarDef(name) = &self.current_tok.kind {
-            let var_name = name.clone();
-            self.next_token();
-            self.match_hashword("#MKAY");
-            ASTNode::VariableReference { name: var_name }
+            let var_name = self.interner.intern(name);
+            let at = (self.current_tok.line, self.current_tok.col);
+            self.positions.references.push(VariableSpan { start, end: start, name: Some(at) });
+            self.next_token()?;
+            Ok(var_name)
         } else {
-            self.syntax_error("Expected variable name after #LEMME SEE");
+            Err(self.syntax_error("Expected variable name after #LEMME SEE"))
         }
     }
 
+    // the #MKAY after referenced_name, returns where the reference ends
+    fn close_reference(&mut self) -> Result<(usize, usize), CompileError> {
+        let end = (self.current_tok.line, self.current_tok.col + "#MKAY".len());
+        self.close()?;
+        if let Some(reference) = self.positions.references.last_mut() {
+            reference.end = end;
+        }
+        Ok(end)
+    }
+
     // grammar: <styled_text> ::= #GIMMEH <style> <text> #MKAY
-    fn styled_text(&mut self) -> ASTNode {
-        self.match_hashword("#GIMMEH");
+    fn styled_text(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let at = (self.current_tok.line, self.current_tok.col);
+        self.match_hashword("#GIMMEH")?;
         
-        if let TokenKind::Keyword(style) = &self.current_tok.kind {
-            let style_type = style.clone();
-            self.next_token();
+        if let TokenKind::Keyword(style_type) = self.current_tok.kind {
+            self.next_token()?;
             
             // NEWLINE is special - doesn't need content or #MKAY
             if style_type == "NEWLINE" {
-                return ASTNode::Newline;
-            }
-            
-           // SOUNDZ and VIDZ take URLs
-        if style_type == "SOUNDZ" || style_type == "VIDZ" {
-            let mut url = String::new();
-    
-        // Collect the actual URL until #MKAY tag
-        while !matches!(self.current_tok.kind, TokenKind::HashWord(ref hw) if hw == "#MKAY") {
-            match &self.current_tok.kind {
-                TokenKind::Text(t) => {
-                url.push_str(t);
-                self.next_token();
+                return Ok(ASTNode::Newline);
             }
-            TokenKind::VarDef(v) => {
-                url.push_str(v);
-                self.next_token();
+            self.open(at, "#GIMMEH", Some(style_type));
+            // other styles only leave text behind, which has no element of its own
+            if matches!(style_type, "BOLD" | "ITALICS" | "LINKZ" | "SOUNDZ" | "VIDZ" | "PIKCHUR") {
+                self.positions.elements.push(at);
             }
-            TokenKind::Address(a) => {
-                url.push_str(a);
-                self.next_token();
+            
+            // LINKZ points at another page or a URL
+            if style_type == "LINKZ" {
+                self.require(LanguageVersion::V1_1, "LINKZ")?;
+                return self.link();
             }
-            TokenKind::Newline => {
-                // Skip newlines in URLs
-                self.next_token();
+
+            // SOUNDZ, VIDZ and PIKCHUR take URLs
+            if style_type == "SOUNDZ" || style_type == "VIDZ" || style_type == "PIKCHUR" {
+                if style_type == "PIKCHUR" {
+                    self.require(LanguageVersion::V1_1, "PIKCHUR")?;
+                }
+                return self.media(style_type);
             }
-            _ => break,
-        }
-    }
-    
-    self.match_hashword("#MKAY");
-    
-    return if style_type == "SOUNDZ" {
-        ASTNode::Sound { url: url.trim().to_string() }
-    } else {
-        ASTNode::Video { url: url.trim().to_string() }
-    };
-}
-            
+
             //vector to hold italic/bold text
             let mut content = Vec::new();
             
-            while !matches!(self.current_tok.kind, TokenKind::HashWord(ref hw) if hw == "#MKAY") {
+            while !matches!(self.current_tok.kind, TokenKind::HashWord(hw) if hw == "#MKAY") {
                 match &self.current_tok.kind {
-                    TokenKind::HashWord(hw) if hw == "#LEMME SEE" => {
+                    TokenKind::HashWord(hw) if *hw == "#LEMME SEE" => {
                         // variable reference inside styled
-                        content.push(self.variable_reference());
+                        content.push(self.node(Self::variable_reference)?);
             
         }
-                    TokenKind::Text(t) => {
-                        content.push(ASTNode::Text { content: t.clone() });
-                        self.next_token();
+                    TokenKind::Text(t) | TokenKind::Address(t) | TokenKind::Yarn(t) => {
+                        if matches!(self.current_tok.kind, TokenKind::Yarn(_)) {
+                            self.require(LanguageVersion::V1_2, "A YARN literal")?;
+                        }
+                        content.push(ASTNode::Text { content: self.arena.alloc_str(t) });
+                        self.next_token()?;
                     }
                     TokenKind::VarDef(v) => {
-                        content.push(ASTNode::Text { content: v.clone() });
-                        self.next_token();
+                        content.push(ASTNode::Text { content: self.arena.alloc_str(v) });
+                        self.next_token()?;
                     }
                     _ => break,
                 }
                 
             }
             
-            self.match_hashword("#MKAY");
+            self.close()?;
             
-            match style_type.as_str() {
-                "BOLD" => ASTNode::Bold { content },
-                "ITALICS" => ASTNode::Italics { content },
-                _ => ASTNode::Text { content: format!("{} text", style_type) },
+            Ok(match style_type {
+                "BOLD" => ASTNode::Bold { content: self.arena.alloc_nodes(content) },
+                "ITALICS" => ASTNode::Italics { content: self.arena.alloc_nodes(content) },
+                _ => ASTNode::Text { content: self.arena.alloc_str(&format!("{} text", style_type)) },
+            })
+        } else {
+            Err(self.syntax_error("Expected style keyword after #GIMMEH"))
+        }
+    }
+
+    // grammar: <media> ::= #GIMMEH (SOUNDZ | VIDZ | PIKCHUR) <url> [WIT <attribute> {AN <attribute>}] #MKAY
+    //          <url>   ::= { <text> | <variable_reference> }        (pieces that touch)
+    fn media(&mut self, style_type: &str) -> Result<ASTNode<'t>, CompileError> {
+        let mut url_references = Vec::new();
+        let body = self.spaced_body_with(Some(&mut url_references))?;
+        // the URL runs to the first space, the references in it included
+        let url_end = body.find(char::is_whitespace).unwrap_or(body.len());
+        if url_references.iter().any(|(offset, _)| *offset > url_end) {
+            return Err(self.syntax_error(&format!(
+                "A variable reference in {} has to be part of the URL, not its attributes",
+                style_type
+            )));
+        }
+        let url = self.arena.alloc_str(&body[..url_end]);
+        let mut attributes = self.media_attributes(style_type, body[url_end..].split_whitespace().collect())?;
+        attributes.url_references = url_references;
+        let attributes = Box::new(attributes);
+
+        Ok(match style_type {
+            "SOUNDZ" => ASTNode::Sound { url, attributes },
+            "PIKCHUR" => ASTNode::Image { url, attributes },
+            _ => ASTNode::Video { url, attributes },
+        })
+    }
+
+    // grammar: <link> ::= #GIMMEH LINKZ <page name | url> [<text>] #MKAY
+    fn link(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let body = self.spaced_body()?;
+        let mut words = body.split_whitespace();
+        let target = match words.next() {
+            Some(t) => self.arena.alloc_str(t),
+            None => return Err(self.syntax_error("Expected page name or URL after LINKZ")),
+        };
+
+        // without any text the link shows its target
+        let text = words.collect::<Vec<_>>().join(" ");
+        let text = if text.is_empty() { target } else { self.arena.alloc_str(&text) };
+
+        Ok(ASTNode::Link { target, text })
+    }
+
+    // Collect the words of a body until #MKAY.
+    // The lexer splits URLs and punctuated text into separate tokens, so pieces that touch
+    // in the source are joined back together and anything separated by whitespace gets a space.
+    fn spaced_body(&mut self) -> Result<String, CompileError> {
+        self.spaced_body_with(None)
+    }
+
+    // spaced_body that also takes variable references when given somewhere to put them;
+    // a reference is left out of the body and recorded with the byte offset it goes in at
+    fn spaced_body_with(&mut self, mut references: Option<&mut Vec<(usize, Symbol)>>) -> Result<String, CompileError> {
+        let mut body = String::new();
+        // (line, col) just past the previous piece
+        let mut last_end = None;
+        // a reference takes no room in the body but still needs a space after it
+        let mut referenced = false;
+
+        while !matches!(self.current_tok.kind, TokenKind::HashWord(hw) if hw == "#MKAY") {
+            let piece = match &self.current_tok.kind {
+                TokenKind::Text(t) | TokenKind::VarDef(t) | TokenKind::Address(t) | TokenKind::VarVal(t) => t.as_str(),
+                TokenKind::Newline => {
+                    last_end = None;
+                    self.next_token()?;
+                    continue;
+                }
+                TokenKind::HashWord(hw) if *hw == "#LEMME SEE" => {
+                    let Some(references) = references.as_deref_mut() else {
+                        break;
+                    };
+                    if (!body.is_empty() || referenced) && last_end != Some((self.current_tok.line, self.current_tok.col)) {
+                        body.push(' ');
+                    }
+                    referenced = true;
+                    let name = self.referenced_name()?;
+                    last_end = Some(self.close_reference()?);
+                    references.push((body.len(), name));
+                    continue;
+                }
+                _ => break,
+            };
+
+            if (!body.is_empty() || referenced) && last_end != Some((self.current_tok.line, self.current_tok.col)) {
+                body.push(' ');
             }
+            last_end = Some((self.current_tok.line, self.current_tok.col + piece.chars().count()));
+            body.push_str(piece);
+            self.next_token()?;
+        }
+
+        self.close()?;
+        Ok(body)
+    }
+
+    // grammar: <attributes> ::= WIT <attribute> {AN <attribute>}
+    //          <attribute>  ::= POSTER <url> | AUTOPLAY | LOOP | MUTED     (VIDZ)
+    //                         | CAPTIONS <url> <lang> [<label>]          (VIDZ)
+    //                         | LOOP | PRELOAD (NONE | METADATA | AUTO)  (SOUNDZ)
+    //                         | WIDTH <n> | HEIGHT <n>                   (PIKCHUR)
+    //                         | SRCSET <url> <descriptor>                (PIKCHUR)
+    //                         | ALT <text>                               (PIKCHUR, must be last)
+    //                         | LAZY                                     (all)
+    fn media_attributes(&self, style_type: &str, words: Vec<&str>) -> Result<MediaAttributes, CompileError> {
+        let mut attributes = MediaAttributes::default();
+        let mut words = words.into_iter();
+
+        match words.next() {
+            None => return Ok(attributes),
+            Some(w) if w.eq_ignore_ascii_case("WIT") => self.require(LanguageVersion::V1_1, "WIT")?,
+            Some(w) => return Err(self.syntax_error(&format!(
+                "Expected 'WIT' or '#MKAY' after {} URL but found '{}'",
+                style_type, w
+            ))),
+        }
+
+        loop {
+            let name = match words.next() {
+                Some(w) => w.to_ascii_uppercase(),
+                None => return Err(self.syntax_error(&format!("Expected attribute after 'WIT' in {}", style_type))),
+            };
+
+            match (style_type, name.as_str()) {
+                ("VIDZ", "POSTER") => match words.next() {
+                    Some(url) => attributes.poster = Some(url.to_string()),
+                    None => return Err(self.syntax_error("Expected URL after POSTER")),
+                },
+                ("VIDZ", "AUTOPLAY") => attributes.autoplay = true,
+                ("VIDZ", "LOOP") => attributes.looping = true,
+                ("VIDZ", "MUTED") => attributes.muted = true,
+                ("VIDZ", "CAPTIONS") => match (words.next(), words.next()) {
+                    (Some(src), Some(lang)) => attributes.captions.push(CaptionTrack {
+                        src: src.to_string(),
+                        lang: lang.to_string(),
+                        label: None,
+                    }),
+                    _ => return Err(self.syntax_error("Expected captions URL and language after CAPTIONS")),
+                },
+                ("PIKCHUR", "WIDTH") => attributes.width = Some(self.pixel_count(words.next(), "WIDTH")?),
+                ("PIKCHUR", "HEIGHT") => attributes.height = Some(self.pixel_count(words.next(), "HEIGHT")?),
+                ("PIKCHUR", "SRCSET") => match (words.next(), words.next()) {
+                    (Some(src), Some(descriptor)) => {
+                        attributes.srcset.push(format!("{} {}", src, descriptor))
+                    }
+                    _ => return Err(self.syntax_error("Expected URL and size (e.g. 2x or 800w) after SRCSET")),
+                },
+                // alt text can contain spaces, so it takes the rest of the body
+                ("PIKCHUR", "ALT") => {
+                    attributes.alt = Some(words.collect::<Vec<_>>().join(" "));
+                    return Ok(attributes);
+                }
+                (_, "LAZY") => attributes.lazy = true,
+                ("SOUNDZ", "LOOP") => attributes.looping = true,
+                ("SOUNDZ", "PRELOAD") => match words.next().map(|w| w.to_ascii_lowercase()) {
+                    Some(mode) if matches!(mode.as_str(), "none" | "metadata" | "auto") => {
+                        attributes.preload = Some(mode)
+                    }
+                    Some(mode) => return Err(self.syntax_error(&format!(
+                        "PRELOAD must be NONE, METADATA or AUTO but found '{}'",
+                        mode
+                    ))),
+                    None => return Err(self.syntax_error("Expected NONE, METADATA or AUTO after PRELOAD")),
+                },
+                _ => return Err(self.syntax_error(&format!("Unknown {} attribute '{}'", style_type, name))),
+            }
+
+            match words.next() {
+                None => return Ok(attributes),
+                Some(w) if w.eq_ignore_ascii_case("AN") => {}
+                // the optional caption label is the only word that can come before AN
+                Some(label) if name == "CAPTIONS" => {
+                    if let Some(track) = attributes.captions.last_mut() {
+                        track.label = Some(label.to_string());
+                    }
+                    match words.next() {
+                        None => return Ok(attributes),
+                        Some(w) if w.eq_ignore_ascii_case("AN") => {}
+                        Some(w) => return Err(self.syntax_error(&format!("Expected 'AN' between attributes but found '{}'", w))),
+                    }
+                }
+                Some(w) => return Err(self.syntax_error(&format!("Expected 'AN' between attributes but found '{}'", w))),
+            }
+        }
+    }
+
+    // a WIDTH/HEIGHT value has to be a whole number of pixels
+    fn pixel_count(&self, word: Option<&str>, attribute: &str) -> Result<u32, CompileError> {
+        match word.map(|w| w.trim_end_matches("px").parse::<u32>()) {
+            Some(Ok(n)) => Ok(n),
+            _ => Err(self.syntax_error(&format!("Expected a number of pixels after {}", attribute))),
+        }
+    }
+
+    // grammar: <if_defined> ::= #IZ DEFINED <flag> { <content> } #OIC
+    // where <content> is what the enclosing section holds
+    fn if_defined(&mut self, content: fn(&mut Self) -> Result<ASTNode<'t>, CompileError>) -> Result<ASTNode<'t>, CompileError> {
+        // nests like a section, and counts towards the same limit
+        self.depth += 1;
+        if self.depth > self.max_nesting {
+            return Err(self.syntax_error(&format!(
+                "Sections are nested more than {} levels deep",
+                self.max_nesting
+            )));
+        }
+        let start = (self.current_tok.line, self.current_tok.col);
+        self.match_hashword("#IZ DEFINED")?;
+        let TokenKind::VarDef(flag) = &self.current_tok.kind else {
+            return Err(self.syntax_error("Expected flag name after #IZ DEFINED"));
+        };
+        let flag = self.arena.alloc_str(flag);
+        self.next_token()?;
+        self.skip_newlines()?;
+
+        let mut children = Vec::new();
+        while !matches!(self.current_tok.kind, TokenKind::HashWord(hw) if hw == "#OIC") {
+            if self.ends_open_section(true) {
+                break;
+            }
+            match self.current_tok.kind {
+                TokenKind::HashWord("#IZ DEFINED") => children.push(self.node(|p| p.if_defined(content))?),
+                _ => children.push(self.node(content)?),
+            }
+            self.skip_newlines()?;
+        }
+        if self.ends_open_section(true) {
+            self.unclosed("#IZ DEFINED", start);
         } else {
-            self.syntax_error("Expected style keyword after #GIMMEH");
+            self.match_hashword("#OIC")?;
         }
+        self.depth -= 1;
+
+        Ok(ASTNode::IfDefined { flag, children: self.arena.alloc_nodes(children) })
     }
 
     // grammar: <list_section> ::= #MAEK LIST <list_items> #OIC
-    fn list_section(&mut self) -> ASTNode {
-        self.match_keyword("LIST");
-        self.skip_newlines();
+    fn list_section(&mut self) -> Result<ASTNode<'t>, CompileError> {
+        let start = self.section_start();
+        self.match_keyword("LIST")?;
+        self.skip_newlines()?;
         
         let mut items = Vec::new();
+        let mut pending = Vec::new();
         
-        while !matches!(self.current_tok.kind, TokenKind::HashWord(ref hw) if hw == "#OIC") {
-            items.push(self.list_item());
-            self.skip_newlines();
+        while !matches!(self.current_tok.kind, TokenKind::HashWord(hw) if hw == "#OIC") {
+            if self.ends_open_section(false) {
+                break;
+            }
+            if matches!(self.current_tok.kind, TokenKind::HashWord("#IZ DEFINED")) {
+                items.push(self.node(|p| p.if_defined(Self::list_item))?);
+            } else if !self.shhh(items.len(), &mut pending)? {
+                items.push(self.node(Self::list_item)?);
+            }
+            self.skip_newlines()?;
         }
         
-        self.match_hashword("#OIC");
+        if self.ends_open_section(false) {
+            self.unclosed("LIST section", start);
+        } else {
+            self.match_hashword("#OIC")?;
+        }
         
-        ASTNode::ListSection { children: items }
+        let children = self.arena.alloc_nodes(items);
+        self.attach_shhh(pending, children);
+        Ok(ASTNode::ListSection { children })
     }
 
     // grammar: <list_item> ::= #GIMMEH ITEM <text> #MKAY
-    fn list_item(&mut self) -> ASTNode {
-    self.match_hashword("#GIMMEH");
-    self.match_keyword("ITEM");
+    fn list_item(&mut self) -> Result<ASTNode<'t>, CompileError> {
+    let at = (self.current_tok.line, self.current_tok.col);
+    self.match_hashword("#GIMMEH")?;
+    self.match_keyword("ITEM")?;
+    self.open(at, "#GIMMEH", Some("ITEM"));
+    self.positions.elements.push(at);
     
     let mut content = Vec::new();
     
-    while !matches!(self.current_tok.kind, TokenKind::HashWord(ref hw) if hw == "#MKAY") {
+    while !matches!(self.current_tok.kind, TokenKind::HashWord(hw) if hw == "#MKAY") {
         match &self.current_tok.kind {
-            TokenKind::HashWord(hw) if hw == "#LEMME SEE" => {
-                content.push(self.variable_reference());
+            TokenKind::HashWord(hw) if *hw == "#LEMME SEE" => {
+                content.push(self.node(Self::variable_reference)?);
             }
-            TokenKind::Text(t) => {
-                content.push(ASTNode::Text { content: t.clone() });
-                self.next_token();
+            TokenKind::Text(t) | TokenKind::Address(t) => {
+                content.push(ASTNode::Text { content: self.arena.alloc_str(t) });
+                self.next_token()?;
             }
             TokenKind::VarDef(v) => {
-                content.push(ASTNode::Text { content: v.clone() });
-                self.next_token();
+                content.push(ASTNode::Text { content: self.arena.alloc_str(v) });
+                self.next_token()?;
             }
             _ => break,
         }
     }
     
-    self.match_hashword("#MKAY");
+    self.close()?;
     
-    ASTNode::Item { content }
+    Ok(ASTNode::Item { content: self.arena.alloc_nodes(content) })
 }
 }
 
-impl<'a> Parser for LolcodeParser<'a> {
-    fn parse(&mut self) {
-        // parsing from top level grammar rule
-        let tree = self.program();
-        self.parse_tree = Some(tree);
+impl Parser for LolcodeParser<'_, '_> {
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "parse", skip_all))]
+    fn parse(&mut self) -> Result<(), CompileError> {
+        if let Some(e) = self.first_error.take() {
+            self.errors.push(e.clone());
+            return Err(e);
+        }
+        // parsing from top level grammar rule; errors recovered from along the
+        // way still fail the parse
+        match self.program() {
+            Ok(tree) if self.errors.is_empty() => {
+                self.parse_tree = Some(self.arena.alloc(tree));
+                // the end of the file, with the trivia before it, closes the concrete tree
+                if let Some(mut cst) = self.cst_state.take() {
+                    let (leading, text) = cst.current;
+                    cst.builder.token(leading, self.current_tok.kind.clone(), text);
+                    self.cst = Some(cst.builder.finish());
+                }
+            }
+            Ok(_) => return Err(self.errors[0].clone()),
+            Err(e) => {
+                self.errors.push(e);
+                return Err(self.errors[0].clone());
+            }
+        }
         
-        println!("Parsing successful!");
+        #[cfg(feature = "tracing")]
+        tracing::debug!("parse tree built");
+        log::info!("Parsing successful!");
+        Ok(())
     }
 
-    fn next_token(&mut self) -> Token {
-        let tok = self.lexer.get_next_token();
-        self.current_tok = tok.clone();
-        tok
+    fn next_token(&mut self) -> Result<Token, CompileError> {
+        let tok = match self.lookahead.pop_front() {
+            Some(tok) => tok?,
+            None => self.read_token()?,
+        };
+        self.previous_at = (self.current_tok.line, self.current_tok.col);
+        if let Some(cst) = &mut self.cst_state {
+            let next = cst.ahead.pop_front().unwrap_or_default();
+            let (leading, text) = std::mem::replace(&mut cst.current, next);
+            cst.builder.token(leading, self.current_tok.kind.clone(), text);
+        }
+        Ok(std::mem::replace(&mut self.current_tok, tok))
     }
 
     fn current_token(&self) -> &Token {
         &self.current_tok
     }
+
+    fn peek_token(&mut self, ahead: usize) -> Result<&Token, CompileError> {
+        if ahead == 0 {
+            return Ok(&self.current_tok);
+        }
+        while self.lookahead.len() < ahead {
+            // nothing past a lexical error is read until it is reached
+            if let Some(Err(e)) = self.lookahead.back() {
+                return Err(e.clone());
+            }
+            let tok = self.read_token();
+            self.lookahead.push_back(tok);
+        }
+        match &self.lookahead[ahead - 1] {
+            Ok(tok) => Ok(tok),
+            Err(e) => Err(e.clone()),
+        }
+    }
 }
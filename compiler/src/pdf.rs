@@ -0,0 +1,77 @@
+//! PDF output.
+//!
+//! PDFs are made the way a person would make them by hand: the page is
+//! generated as HTML with print styles and then printed by a headless
+//! Chrome/Chromium/Edge, which lays it out exactly like the browser preview.
+
+use std::path::Path;
+use std::process::Command;
+
+/// Print styles added to the page when the target is PDF.
+///
+/// Players can't play on paper, so media elements are hidden and links show
+/// where they point.
+pub const PRINT_CSS: &str = "\
+@page { size: A4; margin: 2cm; }
+body { font-family: Georgia, 'Times New Roman', serif; font-size: 12pt; line-height: 1.5; }
+h1 { page-break-after: avoid; }
+p, li, img { page-break-inside: avoid; }
+img { max-width: 100%; }
+video, audio, iframe, .lol-embed { display: none; }
+a[href^=\"http\"]::after { content: \" (\" attr(href) \")\"; font-size: 90%; }
+";
+
+/// Browsers that can print to PDF from the command line, in the order tried.
+#[cfg(target_os = "windows")]
+const BROWSERS: &[&str] = &[
+    "chrome",
+    "msedge",
+    "C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe",
+    "C:\\Program Files (x86)\\Microsoft\\Edge\\Application\\msedge.exe",
+];
+#[cfg(target_os = "macos")]
+const BROWSERS: &[&str] = &[
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    "/Applications/Chromium.app/Contents/MacOS/Chromium",
+    "/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge",
+];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const BROWSERS: &[&str] = &["google-chrome", "chromium", "chromium-browser", "microsoft-edge"];
+
+/// Prints an HTML file to PDF with the first headless browser that works.
+pub fn render_pdf(html_path: &Path, pdf_path: &Path) -> Result<(), String> {
+    let html_path = html_path
+        .canonicalize()
+        .map_err(|e| format!("Failed to find '{}': {}", html_path.display(), e))?;
+    // windows paths come back as \\?\C:\... and need a third slash in the URL
+    let path = html_path.to_string_lossy().replace("\\\\?\\", "").replace('\\', "/");
+    let url = if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    };
+
+    for browser in BROWSERS {
+        let status = Command::new(browser)
+            .arg("--headless")
+            .arg("--disable-gpu")
+            .arg("--no-pdf-header-footer")
+            .arg(format!("--print-to-pdf={}", pdf_path.display()))
+            .arg(&url)
+            .output();
+
+        // a missing browser is not an error until every one has been tried
+        if let Ok(output) = status
+            && output.status.success()
+            && pdf_path.exists()
+        {
+            return Ok(());
+        }
+    }
+
+    Err(format!(
+        "no headless browser found to print '{}' (tried {}); open it in a browser and print to PDF instead",
+        html_path.display(),
+        BROWSERS.join(", ")
+    ))
+}
@@ -0,0 +1,350 @@
+//! The compilation pipeline with observer hooks.
+//!
+//! `Pipeline` runs a document through lexing, parsing, the link and lint
+//! passes, semantic analysis and code generation. Tools that want to watch a
+//! compile (dump tokens, collect statistics, post-process output) register a
+//! `PipelineObserver` instead of copying the pipeline.
+
+use crate::arena::AstArena;
+use crate::builtins::BuildTime;
+use crate::classic;
+use crate::diagnostics::{self, CompileError, Diagnostic, Suppressions};
+use crate::intern::{Interner, Symbol};
+use crate::lexer::{LexicalAnalyzer, Lexer};
+use crate::links;
+use crate::lint::{self, LintOptions};
+use crate::parser::{ASTNode, DEFAULT_MAX_NESTING, LolcodeParser, Parser, Positions};
+use crate::semantic::{LolcodeSemanticAnalyzer, OutputOptions};
+use crate::symbols::SymbolTable;
+use crate::token::{Token, TokenKind};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::exit;
+use std::rc::Rc;
+
+/// Receives the result of each stage as the pipeline runs.
+///
+/// Every method has an empty default, so observers only implement the stages
+/// they care about.
+pub trait PipelineObserver {
+    /// Called before the source is lexed.
+    fn before_lexing(&mut self, _input_filename: &str) {}
+
+    /// Called with every token of the source, `Eof` included.
+    fn after_lexing(&mut self, _tokens: &[Token]) {}
+
+    /// Called with the parse tree.
+    fn after_parsing(&mut self, _tree: &ASTNode) {}
+
+    /// Called with the global variables once semantic analysis passed, and the
+    /// interner that maps their symbols back to names.
+    fn after_semantic(&mut self, _symbols: &HashMap<Symbol, Option<String>>, _interner: &Interner) {}
+
+    /// Called with the generated output before it is written.
+    fn after_codegen(&mut self, _output: &str) {}
+}
+
+/// A shared observer, for callers that want to read its state after the run
+/// or keep it across several pipelines.
+impl<T: PipelineObserver> PipelineObserver for Rc<RefCell<T>> {
+    fn before_lexing(&mut self, input_filename: &str) {
+        self.borrow_mut().before_lexing(input_filename);
+    }
+
+    fn after_lexing(&mut self, tokens: &[Token]) {
+        self.borrow_mut().after_lexing(tokens);
+    }
+
+    fn after_parsing(&mut self, tree: &ASTNode) {
+        self.borrow_mut().after_parsing(tree);
+    }
+
+    fn after_semantic(&mut self, symbols: &HashMap<Symbol, Option<String>>, interner: &Interner) {
+        self.borrow_mut().after_semantic(symbols, interner);
+    }
+
+    fn after_codegen(&mut self, output: &str) {
+        self.borrow_mut().after_codegen(output);
+    }
+}
+
+/// Settings and observers for compiling documents.
+pub struct Pipeline {
+    /// Options for the generated output
+    pub options: OutputOptions,
+    /// Levels for the lint rules
+    pub lint_options: LintOptions,
+    /// Check media links before compiling
+    pub check_links: bool,
+    /// Page name -> href of every page LINKZ can point at
+    pub page_links: HashMap<String, String>,
+    /// Where the page's output is relative to the site root, for its URL
+    /// below `options.base_url`
+    pub page_href: Option<String>,
+    /// Warnings from the link checker and lints, left for the caller to report
+    /// so builds of many pages can print them together
+    pub diagnostics: Vec<Diagnostic>,
+    /// Deepest section nesting a document may have
+    pub max_nesting: usize,
+    /// Treat variable names that differ only in case as the same variable
+    pub case_insensitive: bool,
+    /// Read classic LOLCODE 1.2 programs instead of the document language
+    pub classic: bool,
+    /// Global variables given from outside the document
+    pub defines: HashMap<String, String>,
+    /// What the built-in date and time variables show
+    pub build_time: BuildTime,
+    /// Only warn about a HEAD section after the document's content
+    pub allow_late_head: bool,
+    /// Generate the output and write it next to the source; builds that only
+    /// want other artifacts turn this off and stop after semantic analysis
+    pub write_output: bool,
+    /// Variable names of the last document run, for resolving the symbols in
+    /// the tree it returned
+    pub interner: Interner,
+    /// The first TITLE of the last document run as it was written, with the
+    /// values of its variable references
+    pub title: Option<String>,
+    /// Scopes and variables of the last document run
+    pub symbols: SymbolTable,
+    observers: Vec<Box<dyn PipelineObserver>>,
+    // whether errors go into `diagnostics` instead of stopping the process
+    collect_errors: bool,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pipeline {
+    /// Creates a pipeline with default options and no observers.
+    pub fn new() -> Self {
+        Self {
+            options: OutputOptions::default(),
+            lint_options: LintOptions::default(),
+            check_links: false,
+            page_links: HashMap::new(),
+            page_href: None,
+            diagnostics: Vec::new(),
+            max_nesting: DEFAULT_MAX_NESTING,
+            case_insensitive: false,
+            classic: false,
+            defines: HashMap::new(),
+            build_time: BuildTime::now(),
+            allow_late_head: false,
+            write_output: true,
+            interner: Interner::new(),
+            title: None,
+            symbols: SymbolTable::default(),
+            observers: Vec::new(),
+            collect_errors: false,
+        }
+    }
+
+    /// Registers an observer. Observers are called in the order they were added.
+    pub fn add_observer(&mut self, observer: Box<dyn PipelineObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Compiles one document and writes its output next to `input_filename`,
+    /// unless `write_output` is off.
+    ///
+    /// Returns the parse tree, allocated in `arena`, so callers can pull more out
+    /// of it (metadata, links). An error in the source is reported and ends the
+    /// process with its exit status.
+    pub fn run<'t>(&mut self, source: &str, input_filename: &str, arena: &'t AstArena<'t>) -> &'t ASTNode<'t> {
+        self.collect_errors = false;
+        match self.run_stages(source, input_filename, arena) {
+            Ok(tree) => tree,
+            // errors have already stopped the process
+            Err(code) => exit(code),
+        }
+    }
+
+    /// Like `run`, but an error in the source goes into `diagnostics` along
+    /// with the warnings, and the exit status it would have stopped with comes
+    /// back instead, so a build of many documents can go on with the next one.
+    pub fn try_run<'t>(&mut self, source: &str, input_filename: &str, arena: &'t AstArena<'t>) -> Result<&'t ASTNode<'t>, i32> {
+        self.collect_errors = true;
+        self.run_stages(source, input_filename, arena)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "compile", skip_all, fields(input = input_filename)))]
+    fn run_stages<'t>(&mut self, source: &str, input_filename: &str, arena: &'t AstArena<'t>) -> Result<&'t ASTNode<'t>, i32> {
+        for observer in &mut self.observers {
+            observer.before_lexing(input_filename);
+        }
+
+        //classic programs have a front end of their own and no document tokens
+        let (tree, interner, shhh, positions) = if self.classic {
+            for observer in &mut self.observers {
+                observer.after_lexing(&[]);
+            }
+            let (tree, interner) = classic::parse(source, arena).map_err(|e| self.fail(&e))?;
+            (tree, interner, Suppressions::default(), Positions::default())
+        } else {
+            self.lex_and_parse(source, arena)?
+        };
+        for observer in &mut self.observers {
+            observer.after_parsing(tree);
+        }
+
+        //optional link checking pass, dead links are only warnings
+        let mut found = Vec::new();
+        if self.check_links {
+            let base_dir = Path::new(input_filename).parent().unwrap_or(Path::new("."));
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("check_links").entered();
+            found.extend(links::check_links(tree, base_dir, &shhh));
+        }
+
+        //lint rules, stop here if any of them are set to error
+        //classic programs have no way to give a title
+        let mut lint_options = self.lint_options.clone();
+        if self.classic {
+            lint_options.titles = None;
+        }
+        found.extend(lint::lint_source(source, &lint_options));
+        found.extend(lint::lint(tree, &lint_options, &shhh));
+        shhh.filter(&mut found);
+        self.diagnostics.append(&mut found);
+        if diagnostics::has_errors(&self.diagnostics) {
+            if !self.collect_errors {
+                diagnostics::report(&self.diagnostics);
+                exit(diagnostics::EXIT_FAILURE);
+            }
+            return Err(diagnostics::EXIT_FAILURE);
+        }
+
+        //Task 3: Semantic Analysis
+        let mut analyzer = LolcodeSemanticAnalyzer::new();
+        analyzer.options = self.options.clone();
+        analyzer.page_links = self.page_links.clone();
+        analyzer.page_href = self.page_href.clone();
+        analyzer.interner = interner;
+        analyzer.case_insensitive = self.case_insensitive;
+        analyzer.defines = self.defines.clone();
+        analyzer.build_time = self.build_time;
+        analyzer.allow_late_head = self.allow_late_head;
+        analyzer.positions = positions;
+        analyzer.source_len = source.len();
+        analyzer.check_tree(tree).map_err(|e| self.fail(&e))?;
+        shhh.filter(&mut analyzer.warnings);
+        self.diagnostics.append(&mut analyzer.warnings);
+        self.symbols = std::mem::take(&mut analyzer.symbols);
+        for observer in &mut self.observers {
+            observer.after_semantic(analyzer.get_current_scope(), &analyzer.interner);
+        }
+
+        if !self.write_output {
+            self.interner = std::mem::take(&mut analyzer.interner);
+            return Ok(tree);
+        }
+
+        //Task 4: Code Generation
+        //without observers the output streams straight to its file
+        if self.observers.is_empty() {
+            analyzer.write_output(tree, input_filename);
+            self.interner = std::mem::take(&mut analyzer.interner);
+            self.title = analyzer.first_title.take();
+            return Ok(tree);
+        }
+        let output = analyzer.generate_output(tree);
+        for observer in &mut self.observers {
+            observer.after_codegen(&output);
+        }
+        analyzer.emit_output(&output, input_filename);
+        self.interner = std::mem::take(&mut analyzer.interner);
+        self.title = analyzer.first_title.take();
+
+        Ok(tree)
+    }
+
+    // Tasks 1 and 2 for the document language; returns the tree with what the
+    // analyzer needs from the parser
+    fn lex_and_parse<'t>(
+        &mut self,
+        source: &str,
+        arena: &'t AstArena<'t>,
+    ) -> Result<(&'t ASTNode<'t>, Interner, Suppressions<'t>, Positions), i32> {
+        //Task 1: Lexical Analysis
+        //lexer exits on invalid tokens, so reaching Eof means they were all valid.
+        //tokens take several times the size of the source, so they are only kept
+        //while observers look at them
+        #[cfg(feature = "tracing")]
+        let lex_span = tracing::info_span!("lex").entered();
+        let mut lexer = Lexer::new(source);
+        let keep_tokens = !self.observers.is_empty();
+        let mut tokens = Vec::new();
+        let mut token_count = 0;
+        loop {
+            let tok = lexer.get_next_token().map_err(|e| self.fail(&e))?;
+            let done = tok.kind == TokenKind::Eof;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(line = tok.line, col = tok.col, kind = ?tok.kind, "token");
+            token_count += 1;
+            if keep_tokens {
+                tokens.push(tok);
+            }
+            if done {
+                break;
+            }
+        }
+        log::debug!("Lexed {} tokens", token_count);
+        #[cfg(feature = "tracing")]
+        {
+            tracing::debug!(tokens = token_count, "lexing finished");
+            drop(lex_span);
+        }
+        for observer in &mut self.observers {
+            observer.after_lexing(&tokens);
+        }
+        drop(tokens);
+
+        //Task 2: Syntax Analysis
+        let mut parser = LolcodeParser::new(source, arena);
+        parser.max_nesting = self.max_nesting;
+        //every error the parser recovered from is reported, the first decides the exit status
+        if let Err(first) = parser.parse() {
+            for error in &parser.errors {
+                if self.collect_errors {
+                    self.diagnostics.extend(error.to_diagnostics());
+                } else {
+                    error.report();
+                }
+            }
+            if !self.collect_errors {
+                exit(first.exit_code());
+            }
+            return Err(first.exit_code());
+        }
+        let Some(tree) = parser.parse_tree.take() else {
+            if !self.collect_errors {
+                eprintln!("Error: No parse tree generated");
+                exit(diagnostics::EXIT_SYNTAX);
+            }
+            self.diagnostics.push(Diagnostic::error("syntax-error", "No parse tree generated".to_string()));
+            return Err(diagnostics::EXIT_SYNTAX);
+        };
+        Ok((
+            tree,
+            std::mem::take(&mut parser.interner),
+            std::mem::take(&mut parser.suppressions),
+            std::mem::take(&mut parser.positions),
+        ))
+    }
+
+    // the command line compiler reports an error in the source and gives up;
+    // `try_run` keeps it and returns the exit status
+    fn fail(&mut self, error: &CompileError) -> i32 {
+        if !self.collect_errors {
+            error.report();
+            exit(error.exit_code());
+        }
+        self.diagnostics.extend(error.to_diagnostics());
+        error.exit_code()
+    }
+}
@@ -0,0 +1,77 @@
+//! The whole pipeline as one call, for web playgrounds and other embedders.
+//!
+//! `compile_snippet` runs every stage `Pipeline::run` does on a source held in
+//! memory and hands back what came out instead of printing it: the HTML, if
+//! there is any, and every warning and error as a `Diagnostic`. Nothing is
+//! written, opened or exited.
+//!
+//! ```text
+//! let result = lolcompiler::playground::compile_snippet(source);
+//! for diagnostic in &result.diagnostics {
+//!     show(diagnostic.severity, &diagnostic.message);
+//! }
+//! if let Some(html) = result.html {
+//!     preview(&html);
+//! }
+//! ```
+
+use crate::arena::AstArena;
+use crate::diagnostics::{self, Diagnostic};
+use crate::lint::{self, LintOptions};
+use crate::parser::{LolcodeParser, Parser};
+use crate::semantic::LolcodeSemanticAnalyzer;
+
+/// What compiling a snippet gave.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnippetOutput {
+    /// The generated page, or `None` when an error stopped compilation
+    pub html: Option<String>,
+    /// Warnings, and the errors that stopped compilation, in the order the
+    /// stages found them
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Compiles `source` to HTML with the default options.
+///
+/// Every syntax error the parser recovered from is in `diagnostics`, not just
+/// the first. A lint at level `error` stops compilation like it stops a build.
+pub fn compile_snippet(source: &str) -> SnippetOutput {
+    let mut diagnostics = Vec::new();
+    let arena = AstArena::new();
+
+    let mut parser = LolcodeParser::new(source, &arena);
+    if parser.parse().is_err() {
+        for error in &parser.errors {
+            diagnostics.extend(error.to_diagnostics());
+        }
+        return SnippetOutput { html: None, diagnostics };
+    }
+    // a successful parse always leaves a tree
+    let Some(tree) = parser.parse_tree else {
+        return SnippetOutput { html: None, diagnostics };
+    };
+
+    let lint_options = LintOptions::default();
+    diagnostics.extend(lint::lint_source(source, &lint_options));
+    diagnostics.extend(lint::lint(tree, &lint_options, &parser.suppressions));
+    parser.suppressions.filter(&mut diagnostics);
+    if diagnostics::has_errors(&diagnostics) {
+        return SnippetOutput { html: None, diagnostics };
+    }
+
+    let mut analyzer = LolcodeSemanticAnalyzer::new();
+    analyzer.options.open_browser = false;
+    analyzer.interner = std::mem::take(&mut parser.interner);
+    analyzer.positions = std::mem::take(&mut parser.positions);
+    analyzer.source_len = source.len();
+    let checked = analyzer.check_tree(tree);
+    parser.suppressions.filter(&mut analyzer.warnings);
+    diagnostics.append(&mut analyzer.warnings);
+    if let Err(error) = checked {
+        diagnostics.extend(error.to_diagnostics());
+        return SnippetOutput { html: None, diagnostics };
+    }
+
+    let html = analyzer.generate_output(tree);
+    SnippetOutput { html: Some(html), diagnostics }
+}
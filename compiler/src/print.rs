@@ -0,0 +1,220 @@
+//! Prints a parse tree back as LOLCODE source.
+//!
+//! The output is canonical rather than a copy of the original file: one construct
+//! per line, no indentation, attributes in a fixed order, values and titles
+//! quoted as YARN literals and comments gone.
+//! Parsing the printed source gives an equal tree, except for the placeholder
+//! text the parser leaves for a `#GIMMEH TITLE` or `#GIMMEH ITEM` outside its
+//! section.
+
+use crate::intern::Interner;
+use crate::lexer;
+use crate::parser::{ASTNode, MediaAttributes};
+use unicode_ident::is_xid_continue;
+
+/// Prints `tree` as source. Variable names are resolved with `interner`.
+pub fn to_source(tree: &ASTNode, interner: &Interner) -> String {
+    let mut out = String::new();
+    print_node(tree, interner, &mut out);
+    out
+}
+
+fn print_node(node: &ASTNode, interner: &Interner, out: &mut String) {
+    match node {
+        ASTNode::Program { children } => {
+            out.push_str("#HAI\n");
+            print_lines(children, interner, out);
+            out.push_str("#KTHXBYE\n");
+        }
+        ASTNode::HeadSection { children } => print_section("HEAD", children, interner, out),
+        ASTNode::ParagrafSection { children } => print_section("PARAGRAF", children, interner, out),
+        ASTNode::ListSection { children } => print_section("LIST", children, interner, out),
+        ASTNode::MetaSection { entries } => {
+            out.push_str("#MAEK META\n");
+            for (key, value) in entries {
+                out.push_str("#I HAZ ");
+                out.push_str(key);
+                out.push('\n');
+                bracket(out, "#IT IZ", &yarn(value));
+                out.push('\n');
+            }
+            out.push_str("#OIC");
+        }
+        ASTNode::VariableDeclaration { name } => {
+            out.push_str("#I HAZ ");
+            out.push_str(interner.resolve(*name));
+        }
+        ASTNode::VariableAssignment { name: None, value } => bracket(out, "#IT IZ", &yarn(value)),
+        ASTNode::VariableAssignment { name: Some(name), value } => {
+            bracket(out, "#IT IZ", &format!("{} R {}", interner.resolve(*name), yarn(value)))
+        }
+        ASTNode::VariableReference { name } => bracket(out, "#LEMME SEE", interner.resolve(*name)),
+        // plain titles keep their spacing as a YARN, references need the word form
+        ASTNode::Title { content: [ASTNode::Text { content }] } => bracket(out, "#GIMMEH TITLE", &yarn(content)),
+        ASTNode::Title { content } => bracket(out, "#GIMMEH TITLE", &inline(content, interner)),
+        ASTNode::Text { content } => out.push_str(&escape(content)),
+        ASTNode::Bold { content } => bracket(out, "#GIMMEH BOLD", &inline(content, interner)),
+        ASTNode::Italics { content } => bracket(out, "#GIMMEH ITALICS", &inline(content, interner)),
+        ASTNode::Item { content } => bracket(out, "#GIMMEH ITEM", &inline(content, interner)),
+        ASTNode::Newline => out.push_str("#GIMMEH NEWLINE"),
+        ASTNode::Sound { url, attributes } => bracket(out, "#GIMMEH SOUNDZ", &media(url, attributes, interner)),
+        ASTNode::Video { url, attributes } => bracket(out, "#GIMMEH VIDZ", &media(url, attributes, interner)),
+        ASTNode::Image { url, attributes } => bracket(out, "#GIMMEH PIKCHUR", &media(url, attributes, interner)),
+        ASTNode::Link { target, text } => {
+            // a link without text shows its target
+            let body = if text == target { target.to_string() } else { format!("{} {}", target, text) };
+            bracket(out, "#GIMMEH LINKZ", &escape(&body));
+        }
+        ASTNode::IfDefined { flag, children } => {
+            out.push_str("#IZ DEFINED ");
+            out.push_str(flag);
+            out.push('\n');
+            print_lines(children, interner, out);
+            out.push_str("#OIC");
+        }
+    }
+}
+
+fn print_section(kind: &str, children: &[ASTNode], interner: &Interner, out: &mut String) {
+    out.push_str("#MAEK ");
+    out.push_str(kind);
+    out.push('\n');
+    print_lines(children, interner, out);
+    out.push_str("#OIC");
+}
+
+// every child of a section gets its own line, so text never runs into the next node
+fn print_lines(children: &[ASTNode], interner: &Interner, out: &mut String) {
+    for child in children {
+        print_node(child, interner, out);
+        out.push('\n');
+    }
+}
+
+// `<open> <body> #MKAY`, without a double space when the body is empty
+fn bracket(out: &mut String, open: &str, body: &str) {
+    out.push_str(open);
+    out.push(' ');
+    if !body.is_empty() {
+        out.push_str(body);
+        out.push(' ');
+    }
+    out.push_str("#MKAY");
+}
+
+// styled text and list items keep their pieces on one line
+fn inline(content: &[ASTNode], interner: &Interner) -> String {
+    let mut line = String::new();
+    for piece in content {
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        print_node(piece, interner, &mut line);
+    }
+    line
+}
+
+fn media(url: &str, attributes: &MediaAttributes, interner: &Interner) -> String {
+    // references go back in where they were, touching the URL text around them
+    let mut url = url.to_string();
+    for (offset, name) in attributes.url_references.iter().rev() {
+        url.insert_str(*offset, &format!("#LEMME SEE {} #MKAY", interner.resolve(*name)));
+    }
+
+    let mut words = Vec::new();
+    if let Some(poster) = &attributes.poster {
+        words.push(format!("POSTER {}", poster));
+    }
+    if attributes.autoplay {
+        words.push("AUTOPLAY".to_string());
+    }
+    if attributes.looping {
+        words.push("LOOP".to_string());
+    }
+    if attributes.muted {
+        words.push("MUTED".to_string());
+    }
+    for track in &attributes.captions {
+        match &track.label {
+            Some(label) => words.push(format!("CAPTIONS {} {} {}", track.src, track.lang, label)),
+            None => words.push(format!("CAPTIONS {} {}", track.src, track.lang)),
+        }
+    }
+    if let Some(preload) = &attributes.preload {
+        words.push(format!("PRELOAD {}", preload.to_ascii_uppercase()));
+    }
+    if let Some(width) = attributes.width {
+        words.push(format!("WIDTH {}", width));
+    }
+    if let Some(height) = attributes.height {
+        words.push(format!("HEIGHT {}", height));
+    }
+    for candidate in &attributes.srcset {
+        words.push(format!("SRCSET {}", candidate));
+    }
+    if attributes.lazy {
+        words.push("LAZY".to_string());
+    }
+    // ALT takes the rest of the body, so it has to come last
+    if let Some(alt) = &attributes.alt {
+        words.push(format!("ALT {}", escape(alt)).trim_end().to_string());
+    }
+
+    if words.is_empty() {
+        url
+    } else {
+        format!("{} WIT {}", url, words.join(" AN "))
+    }
+}
+
+// backslashes before `#`, backslashes and keywords that start a word, so the
+// lexer reads the text back as text. URLs are read as written and stay as they are.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut word_start = true;
+    let mut url_end = 0;
+    for (i, c) in text.char_indices() {
+        if word_start && is_url(&text[i..]) {
+            url_end = text[i..].find(char::is_whitespace).map_or(text.len(), |end| i + end);
+        }
+        if i >= url_end {
+            if c == '#' || c == '\\' {
+                escaped.push('\\');
+            } else if word_start {
+                let word: String = text[i..].chars().take_while(|c| is_xid_continue(*c)).collect();
+                if lexer::keyword(&word.to_ascii_uppercase()).is_some() {
+                    escaped.push('\\');
+                }
+            }
+        }
+        escaped.push(c);
+        word_start = c.is_whitespace();
+    }
+    escaped
+}
+
+// values and titles are quoted so every character survives
+fn yarn(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// `scheme://` at the start of `text`, like the lexer's URL check
+fn is_url(text: &str) -> bool {
+    let scheme = text
+        .char_indices()
+        .find(|(i, c)| !(c.is_ascii_alphabetic() || (*i > 0 && (c.is_ascii_digit() || matches!(c, '+' | '-' | '.')))))
+        .map_or(text.len(), |(i, _)| i);
+    scheme > 0 && text[scheme..].starts_with("://")
+}
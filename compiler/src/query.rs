@@ -0,0 +1,118 @@
+//! Path-style selectors for picking nodes out of a parse tree.
+//!
+//! A selector names kinds of nodes, as `ASTNode::kind_name` gives them, joined
+//! like in CSS: `a > b` is a `b` right below an `a`, `a b` a `b` anywhere below
+//! one, and `*` any kind of node.
+//!
+//! ```text
+//! program > paragraf > bold     bold text straight in a top-level paragraf
+//! list item                     every list item, however deeply nested
+//! head title                    the document title
+//! paragraf > *                  everything right inside a paragraf
+//! ```
+//!
+//! For anything a kind can't say, `ASTNode::find_all` takes a closure.
+
+use crate::parser::ASTNode;
+
+/// Every kind of node a selector can name.
+pub const KINDS: [&str; 19] = [
+    "program", "head", "meta", "paragraf", "list", "declaration", "assignment", "reference", "title", "text",
+    "bold", "italics", "item", "newline", "sound", "video", "image", "link", "if_defined",
+];
+
+/// A parsed selector, see the module docs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    // `None` for `*`
+    kind: Option<&'static str>,
+    // joined to the step before by `>` rather than a space
+    child: bool,
+}
+
+impl Selector {
+    /// Reads a selector such as `program > paragraf > bold`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut steps = Vec::new();
+        let mut child = false;
+        // `a>b` needs no spaces around the `>`
+        let spaced = text.replace('>', " > ");
+        for word in spaced.split_whitespace() {
+            if word == ">" {
+                if steps.is_empty() || child {
+                    return Err(format!("'>' needs a kind of node on each side in '{}'", text));
+                }
+                child = true;
+                continue;
+            }
+            let kind = match word {
+                "*" => None,
+                _ => match KINDS.iter().find(|kind| kind.eq_ignore_ascii_case(word)) {
+                    Some(kind) => Some(*kind),
+                    None => {
+                        return Err(format!("unknown kind of node '{}', expected one of: {}", word, KINDS.join(", ")));
+                    }
+                },
+            };
+            steps.push(Step { kind, child });
+            child = false;
+        }
+        if steps.is_empty() || child {
+            return Err(format!("'{}' doesn't end with a kind of node", text));
+        }
+        Ok(Self { steps })
+    }
+
+    /// The nodes of `tree` the selector picks, in document order.
+    pub fn select<'t>(&self, tree: &'t ASTNode<'t>) -> Vec<&'t ASTNode<'t>> {
+        let mut found = Vec::new();
+        let mut path = Vec::new();
+        self.collect(tree, &mut path, &mut found);
+        found
+    }
+
+    /// True if the selector picks the last node of `path`, which runs from the
+    /// root of the tree down to it.
+    pub fn matches(&self, path: &[&ASTNode]) -> bool {
+        matches_steps(&self.steps, path)
+    }
+
+    fn collect<'t>(&self, node: &'t ASTNode<'t>, path: &mut Vec<&'t ASTNode<'t>>, found: &mut Vec<&'t ASTNode<'t>>) {
+        path.push(node);
+        if self.matches(path) {
+            found.push(node);
+        }
+        for child in node.children() {
+            self.collect(child, path, found);
+        }
+        path.pop();
+    }
+}
+
+/// The nodes of `tree` that `selector` picks, see `Selector`.
+pub fn select<'t>(tree: &'t ASTNode<'t>, selector: &str) -> Result<Vec<&'t ASTNode<'t>>, String> {
+    Ok(Selector::parse(selector)?.select(tree))
+}
+
+// whether the last step picks the last node of the path and the steps before
+// it pick its ancestors
+fn matches_steps(steps: &[Step], path: &[&ASTNode]) -> bool {
+    let (Some((step, earlier)), Some((node, above))) = (steps.split_last(), path.split_last()) else {
+        return steps.is_empty();
+    };
+    if step.kind.is_some_and(|kind| kind != node.kind_name()) {
+        return false;
+    }
+    if earlier.is_empty() {
+        return true;
+    }
+    if step.child {
+        return matches_steps(earlier, above);
+    }
+    (1..=above.len()).rev().any(|len| matches_steps(earlier, &above[..len]))
+}
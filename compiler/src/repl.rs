@@ -0,0 +1,153 @@
+//! An interactive session, for `lolcompiler repl`.
+//!
+//! Each entry is a piece of a document, usually one section, compiled on its
+//! own as if it were everything between `#HAI` and `#KTHXBYE`:
+//!
+//! ```text
+//! let mut session = Session::new();
+//! session.eval("#I HAZ NAME\n#IT IZ Ceiling Cat #MKAY")?;
+//! let entry = session.eval("#MAEK PARAGRAF\nhai #LEMME SEE NAME #MKAY\n#OIC")?;
+//! assert_eq!(entry.html, "<p>\nhai Ceiling Cat</p>\n");
+//! ```
+//!
+//! Global variables an entry leaves with a value are still there in later
+//! entries, like defines: they can be read, and declared again to give them a
+//! new value. An entry that fails leaves the session as it was.
+
+use crate::arena::AstArena;
+use crate::diagnostics::{CompileError, Diagnostic};
+use crate::json;
+use crate::parser::{LolcodeParser, Parser};
+use crate::semantic::LolcodeSemanticAnalyzer;
+use crate::stages;
+use crate::token::{Token, TokenKind};
+use std::collections::HashMap;
+
+// put in front of an entry that doesn't start with #HAI, on its first line
+const WRAPPER: &str = "#HAI ";
+
+/// Variables kept between the entries of an interactive session.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    /// Global variables earlier entries left with a value, name -> value
+    pub variables: HashMap<String, String>,
+    /// Treat variable names that differ only in case as the same variable
+    pub case_insensitive: bool,
+}
+
+/// What one entry compiled to.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The entry's tokens, without the `#HAI` and `#KTHXBYE` it was wrapped in
+    pub tokens: Vec<Token>,
+    /// The parse tree as JSON, see `json::ast`
+    pub ast: String,
+    /// The HTML of the entry's content, without the page around it
+    pub html: String,
+    /// Warnings from semantic analysis
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Session {
+    /// Creates a session without any variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles one entry and keeps the global variables it leaves.
+    ///
+    /// An entry that starts with `#HAI` is compiled as it is; any other is
+    /// wrapped in `#HAI` and `#KTHXBYE`. Positions in tokens and errors are
+    /// the entry's own either way.
+    pub fn eval(&mut self, entry: &str) -> Result<Entry, CompileError> {
+        let entry = entry.trim_end();
+        let wrapped = !entry.trim_start().starts_with("#HAI");
+        let source = if wrapped { format!("{}{}\n#KTHXBYE\n", WRAPPER, entry) } else { entry.to_string() };
+
+        let mut tokens = stages::lex(&source).map_err(|e| match e {
+            CompileError::Lexical { line: 1, col, message } if wrapped => CompileError::Lexical {
+                line: 1,
+                col: col.saturating_sub(WRAPPER.len()).max(1),
+                message,
+            },
+            e => e,
+        })?;
+        // the parser takes its positions from the tokens, so moving them back
+        // fixes the first line for every later stage
+        if wrapped {
+            for token in tokens.iter_mut().skip(1).take_while(|token| token.line == 1) {
+                token.col -= WRAPPER.len();
+            }
+        }
+        let arena = AstArena::new();
+        let mut parser = LolcodeParser::from_tokens(tokens.clone(), &arena);
+        parser.parse()?;
+        let Some(tree) = parser.parse_tree else {
+            return Err(CompileError::Syntax {
+                line: 1,
+                col: 1,
+                message: "No parse tree generated".to_string(),
+                labels: Vec::new(),
+            });
+        };
+
+        let mut analyzer = LolcodeSemanticAnalyzer::new();
+        analyzer.options.open_browser = false;
+        analyzer.interner = std::mem::take(&mut parser.interner);
+        analyzer.positions = std::mem::take(&mut parser.positions);
+        analyzer.case_insensitive = self.case_insensitive;
+        analyzer.defines = self.variables.clone();
+        analyzer.check_tree(tree)?;
+        let page = analyzer.generate_output(tree);
+
+        // codegen ends back in the global scope
+        for (name, value) in analyzer.get_current_scope() {
+            if let Some(value) = value {
+                self.variables.insert(analyzer.interner.resolve(*name).to_string(), value.clone());
+            }
+        }
+
+        if wrapped {
+            let lines = entry.lines().count().max(1);
+            tokens.remove(0);
+            tokens.retain(|token| token.line <= lines && token.kind != TokenKind::Eof);
+        }
+        // a later entry may still read a value this one assigned
+        analyzer.warnings.retain(|warning| warning.code != "unused-value");
+        Ok(Entry {
+            tokens,
+            ast: json::ast(tree, &analyzer.interner),
+            html: page_body(&page).to_string(),
+            warnings: std::mem::take(&mut analyzer.warnings),
+        })
+    }
+}
+
+/// True once `text` has opened a section or `#IZ DEFINED` and closed every
+/// one it opened, so an interactive session can read an entry over several
+/// lines. Other entries go on until an empty line. Text that doesn't lex is
+/// complete too, so its error is shown.
+pub fn is_complete(text: &str) -> bool {
+    let Ok(tokens) = stages::lex(text) else {
+        return true;
+    };
+    let mut depth = 0i32;
+    let mut opened = false;
+    for token in &tokens {
+        match token.kind {
+            TokenKind::HashWord("#MAEK" | "#IZ DEFINED") => {
+                depth += 1;
+                opened = true;
+            }
+            TokenKind::HashWord("#OIC") => depth -= 1,
+            _ => {}
+        }
+    }
+    opened && depth <= 0
+}
+
+// what is between <body> and </body> of a generated page
+fn page_body(page: &str) -> &str {
+    let body = page.split_once("<body>\n").map_or(page, |(_, body)| body);
+    body.rsplit_once("</body>").map_or(body, |(body, _)| body)
+}
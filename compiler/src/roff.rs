@@ -0,0 +1,149 @@
+//! roff (man page) output.
+//!
+//! `--target man` writes a `.1` page that can be installed with the system's
+//! man pages. The first TITLE becomes the `.TH` header, later titles become
+//! `.SH` section headings, paragraphs become `.PP` blocks and lists become
+//! bulleted `.IP` items. A META `date` fills in the header's date field.
+//!
+//! Works on a tree resolved by `LolcodeSemanticAnalyzer::resolve_tree`.
+
+use crate::meta;
+use crate::parser::ASTNode;
+
+/// Renders a resolved tree as man page source.
+pub fn render(tree: &ASTNode) -> String {
+    let meta = meta::document_meta(tree);
+    let mut renderer = RoffRenderer::default();
+
+    let name = meta.title().unwrap_or("LOLCODE").to_ascii_uppercase();
+    renderer.out.push_str(&format!(
+        ".TH \"{}\" \"1\" \"{}\"\n",
+        escape(&name).replace('"', "\\(dq"),
+        meta.get("date").unwrap_or("")
+    ));
+    // the header already shows the first title
+    renderer.skip_title = meta.title().is_some() && meta.get("title").is_none();
+
+    renderer.node(tree);
+    renderer.flush_line();
+    renderer.out
+}
+
+/// Escapes text so roff doesn't treat it as requests or escapes.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\e")
+}
+
+#[derive(Default)]
+struct RoffRenderer {
+    out: String,
+    // inline text of the current output line
+    line: String,
+    skip_title: bool,
+}
+
+impl RoffRenderer {
+    fn flush_line(&mut self) {
+        let line = self.line.trim();
+        if !line.is_empty() {
+            // a line starting with . or ' would be read as a request
+            if line.starts_with('.') || line.starts_with('\'') {
+                self.out.push_str("\\&");
+            }
+            self.out.push_str(line);
+            self.out.push('\n');
+        }
+        self.line.clear();
+    }
+
+    fn push_word(&mut self, text: &str) {
+        if !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        self.line.push_str(text);
+    }
+
+    fn request(&mut self, request: &str) {
+        self.flush_line();
+        self.out.push_str(request);
+        self.out.push('\n');
+    }
+
+    fn inline(&mut self, children: &[ASTNode]) -> String {
+        let saved = std::mem::take(&mut self.line);
+        for child in children.iter() {
+            self.node(child);
+        }
+        std::mem::replace(&mut self.line, saved).trim().to_string()
+    }
+
+    fn node(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Program { children } | ASTNode::HeadSection { children } => {
+                for child in children.iter() {
+                    self.node(child);
+                }
+            }
+            ASTNode::ParagrafSection { children } => {
+                self.request(".PP");
+                for (i, child) in children.iter().enumerate() {
+                    self.node(child);
+                    // what follows a nested paragraf is a paragraph of its own
+                    if matches!(child, ASTNode::ParagrafSection { .. }) && i + 1 < children.len() {
+                        self.request(".PP");
+                    }
+                }
+                self.flush_line();
+            }
+            ASTNode::ListSection { children } => {
+                self.flush_line();
+                for child in children.iter() {
+                    self.node(child);
+                }
+                self.request(".PP");
+            }
+            ASTNode::Item { content } => {
+                let item = self.inline(content);
+                self.request(".IP \\(bu 2");
+                self.line = item;
+                self.flush_line();
+            }
+            ASTNode::Title { content } => {
+                if self.skip_title {
+                    self.skip_title = false;
+                } else {
+                    self.request(&format!(".SH \"{}\"", escape(&meta::title_text(content).to_ascii_uppercase())));
+                }
+            }
+            // most words have nothing to escape
+            ASTNode::Text { content } if !content.contains('\\') => self.push_word(content),
+            ASTNode::Text { content } => self.push_word(&escape(content)),
+            ASTNode::Bold { content } => {
+                let text = self.inline(content);
+                self.push_word(&format!("\\fB{}\\fR", text));
+            }
+            ASTNode::Italics { content } => {
+                let text = self.inline(content);
+                self.push_word(&format!("\\fI{}\\fR", text));
+            }
+            ASTNode::Newline => self.request(".br"),
+            ASTNode::Link { target, text } if target == text => {
+                self.push_word(&format!("\\fI{}\\fR", escape(target)))
+            }
+            ASTNode::Link { target, text } => {
+                self.push_word(&format!("{} <\\fI{}\\fR>", escape(text), escape(target)))
+            }
+            ASTNode::Image { url, attributes } => self.push_word(&format!(
+                "[image: {}]",
+                escape(attributes.alt.as_deref().unwrap_or(url))
+            )),
+            ASTNode::Video { url, .. } => self.push_word(&format!("[video: \\fI{}\\fR]", escape(url))),
+            ASTNode::Sound { url, .. } => self.push_word(&format!("[audio: \\fI{}\\fR]", escape(url))),
+            ASTNode::MetaSection { .. }
+            | ASTNode::VariableDeclaration { .. }
+            | ASTNode::VariableAssignment { .. }
+            | ASTNode::VariableReference { .. }
+            | ASTNode::IfDefined { .. } => {}
+        }
+    }
+}
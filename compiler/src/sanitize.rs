@@ -0,0 +1,303 @@
+//! What a variable's value may put into HTML.
+//!
+//! The document's own text is written into the page as it is, markup and all,
+//! since its author wrote it. A value can come from further away, a `--define`
+//! or the build environment, and ends up wherever the variable is referenced,
+//! media URLs included. So HTML output escapes every value by default, and
+//! `--raw-values <tags>` lets values keep the tags listed, stripped of their
+//! attributes, while the rest is still escaped.
+//!
+//! Other targets escape for their own formats and take values as they are.
+//!
+//! `--paranoid` is for compiling documents nobody has looked at, e.g. ones
+//! users submit to a shared server: the document's own text is escaped too,
+//! so no markup from the source reaches the page, and every URL with a
+//! `javascript:`, `vbscript:` or `data:` scheme is dropped. `violations` lists
+//! what in a page breaks those rules, for checking the output of that mode.
+
+use crate::slides;
+use crate::xml::escape_xml;
+
+/// URL schemes that run code when the URL is followed.
+const SCRIPT_SCHEMES: [&str; 2] = ["javascript:", "vbscript:"];
+
+/// URL schemes no URL may have with `--paranoid`.
+const PARANOID_SCHEMES: [&str; 3] = ["javascript:", "vbscript:", "data:"];
+
+/// Attributes that hold a URL.
+const URL_ATTRIBUTES: [&str; 5] = ["href", "src", "poster", "srcset", "action"];
+
+/// Elements the HTML output is made of; anything else in a page came from the
+/// source. The only scripts are the reveal.js ones of slides.
+const OUTPUT_ELEMENTS: [&str; 23] = [
+    "html", "head", "meta", "title", "style", "link", "body", "section", "p", "ul", "li", "h1", "b", "i", "br", "a",
+    "img", "audio", "video", "track", "div", "iframe", "object",
+];
+
+/// How variable values are substituted into HTML.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ValuePolicy {
+    /// `<`, `>`, `&` and `"` are escaped, so a value is only ever text
+    #[default]
+    EscapeAll,
+    /// Tags with these names (lowercase) stay, without their attributes;
+    /// everything else is escaped. Entities like `&amp;` stay too
+    AllowTags(Vec<String>),
+}
+
+impl ValuePolicy {
+    /// Reads the comma-separated tag names of `--raw-values`, e.g. `b,i,br`;
+    /// `None` if one isn't a tag name.
+    pub fn allow(list: &str) -> Option<Self> {
+        let mut tags = Vec::new();
+        for tag in list.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+            if !is_tag_name(tag) {
+                return None;
+            }
+            tags.push(tag.to_ascii_lowercase());
+        }
+        Some(Self::AllowTags(tags))
+    }
+
+    /// A value as the content of an element.
+    pub fn text(&self, value: &str) -> String {
+        match self {
+            Self::EscapeAll => escape_xml(value),
+            Self::AllowTags(tags) => allow_tags(value, tags),
+        }
+    }
+
+    /// A value inside a quoted attribute, such as the URL of a media embed;
+    /// `starts_url` when it comes first in the URL. Tags mean nothing there, so
+    /// it is always escaped, and a value that would make the URL run a script
+    /// is left out.
+    pub fn url(&self, value: &str, starts_url: bool) -> String {
+        if starts_url && has_scheme(value, &SCRIPT_SCHEMES) {
+            return String::new();
+        }
+        escape_xml(value)
+    }
+}
+
+/// A URL for an attribute of `--paranoid` output: escaped, and empty if it
+/// has a scheme that can run code or carry a document of its own.
+pub fn paranoid_url(url: &str) -> String {
+    if has_scheme(url, &PARANOID_SCHEMES) {
+        return String::new();
+    }
+    escape_xml(url)
+}
+
+/// What in an HTML page `--paranoid` output must never have: an element the
+/// compiler doesn't write, an inline event handler (`onload=`, ...) or
+/// document (`srcdoc=`), or a
+/// URL attribute with a `javascript:`, `vbscript:` or `data:` scheme. Each
+/// comes back as a message with the tag it was found in.
+pub fn violations(html: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        // comments (source maps) and the doctype aren't elements
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let rest_of_tag = rest.strip_prefix('/').unwrap_or(rest);
+        let name_len = rest_of_tag.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest_of_tag.len());
+        if name_len == 0 {
+            continue;
+        }
+        let name = rest_of_tag[..name_len].to_ascii_lowercase();
+        let (attributes, len) = tag_attributes(&rest_of_tag[name_len..]);
+        let tag = &rest[..(rest.len() - rest_of_tag.len()) + name_len + len];
+        let after = &rest_of_tag[name_len + len..];
+        // a closing </script> is only there if its opening tag was
+        let reveal = name == "script"
+            && (rest.starts_with('/')
+                || attributes.iter().any(|(attribute, value)| attribute == "src" && value.starts_with(&slides::reveal_cdn()))
+                || after.strip_prefix('>').is_some_and(|body| {
+                    [slides::REVEAL_INIT, slides::DECK_SCRIPT].iter().any(|script| {
+                        body.strip_prefix(script).is_some_and(|end| end.starts_with("</script>"))
+                    })
+                }));
+        if !OUTPUT_ELEMENTS.contains(&name.as_str()) && !reveal {
+            found.push(format!("element '{}' in <{}>", name, tag));
+        }
+        for (attribute, value) in attributes {
+            if attribute.starts_with("on") {
+                found.push(format!("event handler '{}' in <{}>", attribute, tag));
+            } else if attribute == "srcdoc" {
+                found.push(format!("inline document in <{}>", tag));
+            } else if URL_ATTRIBUTES.contains(&attribute.as_str())
+                && value.split(',').any(|url| has_scheme(url, &PARANOID_SCHEMES))
+            {
+                found.push(format!("{} URL with a forbidden scheme in <{}>", attribute, tag));
+            }
+        }
+        rest = after;
+    }
+    found
+}
+
+// the attributes of a tag, names lowercased, from after its name, and how far
+// the tag goes
+fn tag_attributes(text: &str) -> (Vec<(String, String)>, usize) {
+    let mut attributes = Vec::new();
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] == b'>' {
+            return (attributes, i);
+        }
+        let name_start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'=' | b'>' | b'/') {
+            i += 1;
+        }
+        let name = text[name_start..i].to_ascii_lowercase();
+        let mut value = "";
+        if i < bytes.len() && bytes[i] == b'=' {
+            i += 1;
+            let quote = bytes.get(i).copied().filter(|q| matches!(q, b'"' | b'\''));
+            let value_start = i + usize::from(quote.is_some());
+            let value_end = match quote {
+                Some(q) => text[value_start..].find(char::from(q)).map_or(text.len(), |end| value_start + end),
+                None => text[value_start..]
+                    .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                    .map_or(text.len(), |end| value_start + end),
+            };
+            value = &text[value_start..value_end];
+            i = (value_end + usize::from(quote.is_some())).min(text.len());
+        }
+        attributes.push((name, value.to_string()));
+    }
+}
+
+// whether `url` starts with one of `schemes`; browsers skip whitespace and
+// control characters in a scheme, and entities are decoded for the check
+fn has_scheme(url: &str, schemes: &[&str]) -> bool {
+    let url = url.replace("&#58;", ":").replace("&colon;", ":");
+    let scheme: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .take(16)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    schemes.iter().any(|forbidden| scheme.starts_with(forbidden))
+}
+
+fn is_tag_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic()) && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// `value` with the allowed tags rebuilt bare and everything else escaped
+fn allow_tags(value: &str, tags: &[String]) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(c) = rest.chars().next() {
+        if c == '<'
+            && let Some((tag, len)) = allowed_tag(rest, tags)
+        {
+            out.push_str(&tag);
+            rest = &rest[len..];
+            continue;
+        }
+        if c == '&'
+            && let Some(len) = entity_len(rest)
+        {
+            out.push_str(&rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+// a tag at the start of `text` whose name is allowed, rebuilt without
+// attributes, and how long it was
+fn allowed_tag(text: &str, tags: &[String]) -> Option<(String, usize)> {
+    let end = text.find('>')?;
+    let inside = &text[1..end];
+    // a `<` before the `>` means this one never closed
+    if inside.contains('<') {
+        return None;
+    }
+    let (closing, inside) = match inside.strip_prefix('/') {
+        Some(inside) => (true, inside),
+        None => (false, inside),
+    };
+    let name_len = inside.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(inside.len());
+    let name = inside[..name_len].to_ascii_lowercase();
+    if !is_tag_name(&name) || !tags.contains(&name) {
+        return None;
+    }
+    let tag = if closing { format!("</{}>", name) } else { format!("<{}>", name) };
+    Some((tag, end + 1))
+}
+
+// length of a character reference like `&amp;`, `&#39;` or `&#x27;` at the
+// start of `text`
+fn entity_len(text: &str) -> Option<usize> {
+    let end = text.find(';')?;
+    let body = &text[1..end];
+    let valid = match body.strip_prefix('#') {
+        Some(number) => match number.strip_prefix(['x', 'X']) {
+            Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()),
+        },
+        None => is_tag_name(body),
+    };
+    valid.then_some(end + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT_SCHEMES: [&str; 2] = ["javascript:", "vbscript:"];
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn has_scheme_sees_through_case_and_whitespace() {
+        assert!(has_scheme("JavaScript:alert(1)", &SCRIPT_SCHEMES));
+        assert!(has_scheme("  jav\tas\ncript:alert(1)", &SCRIPT_SCHEMES));
+        assert!(has_scheme("java\u{1}script:alert(1)", &SCRIPT_SCHEMES));
+        assert!(has_scheme("javascript&#58;alert(1)", &SCRIPT_SCHEMES));
+        assert!(has_scheme("JAVASCRIPT&colon;alert(1)", &SCRIPT_SCHEMES));
+        assert!(!has_scheme("https://example.com/javascript:", &SCRIPT_SCHEMES));
+        assert!(!has_scheme("javascript.html", &SCRIPT_SCHEMES));
+    }
+
+    #[test]
+    fn allow_tags_drops_attributes() {
+        let allowed = tags(&["b", "br"]);
+        assert_eq!(allow_tags("<b onclick=\"x()\">hi</b>", &allowed), "<b>hi</b>");
+        assert_eq!(allow_tags("<B class=x>hi</B >", &allowed), "<b>hi</b>");
+        assert_eq!(allow_tags("a<br style='x'/>b", &allowed), "a<br>b");
+        assert_eq!(allow_tags("<i>hi</i>", &allowed), "&lt;i&gt;hi&lt;/i&gt;");
+        assert_eq!(allow_tags("<bdo>x</bdo>", &allowed), "&lt;bdo&gt;x&lt;/bdo&gt;");
+    }
+
+    #[test]
+    fn allow_tags_escapes_unclosed_tags() {
+        let allowed = tags(&["b"]);
+        assert_eq!(allow_tags("1 < 2", &allowed), "1 &lt; 2");
+        assert_eq!(allow_tags("<b", &allowed), "&lt;b");
+        assert_eq!(allow_tags("<b <img src=x onerror=y()>", &allowed), "&lt;b &lt;img src=x onerror=y()&gt;");
+        assert_eq!(allow_tags("&amp; & &#39;", &allowed), "&amp; &amp; &#39;");
+    }
+}
@@ -3,9 +3,43 @@
 //! This module performs semantic analysis including variable scope checking, usage validation,
 //! and HTML code generation. It implements a scope stack to handle nested scoping rules
 //! and ensures variables are declared before use and assigned before reference.
+//!
+//! A variable can be assigned any number of times; every reference shows the value
+//! of the last assignment before it. `#IT IZ` without a name assigns to the
+//! variable declared last in the scopes that are still open.
+//!
+//! Every PARAGRAF and LIST opens a scope, however deeply they nest. A section
+//! sees the variables of the sections around it, and a declaration inside it
+//! hides an outer variable of the same name until the section ends. In HTML a
+//! PARAGRAF nested in another one is an aside, written as a `<section>`.
 
-use crate::parser::ASTNode;
+use crate::ansi;
+use crate::arena::AstArena;
+use crate::asciidoc;
+use crate::builtins::BuildTime;
+use crate::doctype::Doctype;
+use crate::diagnostics::{CompileError, Diagnostic, EXIT_IO, Label};
+use crate::gemtext;
+use crate::integrity;
+use crate::intern::{Interner, Symbol};
+use crate::media;
+use crate::meta::{self, DocumentMeta};
+use crate::output::{self, ChangedFileWriter};
+use crate::parser::{ASTNode, MediaAttributes, Positions, VariableSpan};
+use crate::pdf;
+use crate::roff;
+use crate::sanitize::{self, ValuePolicy};
+use crate::site::{self, OutputNaming};
+use crate::slides;
+use crate::symbols::{ScopeInfo, SymbolInfo, SymbolTable, UseKind, VariableInfo};
+use crate::target::Target;
+use crate::xml;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
 /// Trait defining the interface for semantic analysis.
@@ -17,11 +51,16 @@ pub trait SemanticAnalyzer {
 
 /// Represents a single scope level with its own symbol table.
 /// 
-/// Each scope maintains a mapping of variable names to their optional values.
+/// Each scope maintains a mapping of interned variable names to their optional values.
 // scope level with its own symbol table
 #[derive(Debug, Clone)]
 struct Scope {
-    variables: HashMap<String, Option<String>>, // variable -> value 
+    variables: HashMap<Symbol, Option<String>>, // variable -> value 
+    // variables whose value hasn't been read yet, in the order they were assigned
+    unread: Vec<Symbol>,
+    // references to variables of this scope made before they had a value, with
+    // where the reference is
+    waiting: Vec<(Symbol, String)>,
 }
 
 impl Scope {
@@ -29,22 +68,193 @@ impl Scope {
     fn new() -> Self {
         Self {
             variables: HashMap::new(),
+            unread: Vec::new(),
+            waiting: Vec::new(),
         }
     }
 }
 
+/// Options that change the generated HTML.
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    /// Don't fetch any embedded media until the reader asks for it
+    pub lazy_media: bool,
+    /// Open the generated page in the browser when done
+    pub open_browser: bool,
+    /// What kind of document to produce
+    pub target: Target,
+    /// Put a comment with the `.lol` line and column before each element of
+    /// HTML output
+    pub source_map: bool,
+    /// Give each element of HTML output a `data-lol-line` attribute with the
+    /// line it comes from, for previews that scroll along with the editor
+    pub debug_annotations: bool,
+    /// How the output file is named after the source
+    pub naming: OutputNaming,
+    /// How variable values are substituted into HTML output
+    pub value_policy: ValuePolicy,
+    /// Nonce stamped on the `<style>` and `<script>` elements the compiler
+    /// adds, for a strict Content-Security-Policy
+    pub csp_nonce: Option<String>,
+    /// Give assets loaded from a CDN a Subresource Integrity hash
+    pub subresource_integrity: bool,
+    /// Load reveal.js from its CDN for slides instead of writing the
+    /// built-in player into the deck
+    pub reveal_cdn: bool,
+    /// Escape the document's own text as well as variable values and drop
+    /// `javascript:`, `vbscript:` and `data:` URLs, for documents nobody has
+    /// reviewed (see `sanitize`)
+    pub paranoid: bool,
+    /// Write HTML output as XHTML, which XML tools can read: void elements
+    /// closed with `/>`, boolean attributes with a value and the XHTML
+    /// namespace on `<html>`
+    pub xhtml: bool,
+    /// The version of HTML pages are written for; slides are always HTML5
+    pub doctype: Doctype,
+    /// Add Open Graph and Twitter card tags to the head, for link previews
+    pub social_cards: bool,
+    /// Where the site is published, ending with `/`: pages link to their
+    /// canonical URL below it and relative URLs are made absolute
+    pub base_url: Option<String>,
+    /// HTML written as it is at the end of `<head>`, such as a web-font loader
+    pub head_snippet: Option<String>,
+    /// HTML written as it is at the end of `<body>`, such as an analytics tag
+    pub body_snippet: Option<String>,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            lazy_media: false,
+            open_browser: true,
+            target: Target::Html,
+            source_map: false,
+            debug_annotations: false,
+            naming: OutputNaming::default(),
+            value_policy: ValuePolicy::default(),
+            csp_nonce: None,
+            subresource_integrity: false,
+            reveal_cdn: false,
+            paranoid: false,
+            xhtml: false,
+            doctype: Doctype::default(),
+            social_cards: false,
+            base_url: None,
+            head_snippet: None,
+            body_snippet: None,
+        }
+    }
+}
+
+impl OutputOptions {
+    /// The `--inject-head`/`--inject-body` snippet, with the CSP nonce on its
+    /// scripts and styles. Nothing in it comes from the document, so it is
+    /// written unescaped even under `--paranoid`.
+    pub fn snippet(&self, snippet: Option<&str>) -> String {
+        match (snippet, &self.csp_nonce) {
+            (Some(snippet), Some(nonce)) => integrity::stamp_nonce(snippet, nonce),
+            (Some(snippet), None) => snippet.to_string(),
+            (None, _) => String::new(),
+        }
+    }
+
+    /// The version of HTML the page is written for; reveal.js needs HTML5.
+    pub fn html_version(&self) -> Doctype {
+        if self.target == Target::Slides { Doctype::Html5 } else { self.doctype }
+    }
+
+    /// True when the page is written in XML syntax, for `--xhtml` or XHTML 1.0.
+    pub fn xml_syntax(&self) -> bool {
+        self.xhtml || self.html_version() == Doctype::Xhtml10
+    }
+
+    /// The `<html>` start tag of a page.
+    pub fn html_start_tag(&self) -> &'static str {
+        if self.xml_syntax() { "<html xmlns=\"http://www.w3.org/1999/xhtml\">" } else { "<html>" }
+    }
+
+    /// What closes a void element like `<br>` after its attributes.
+    pub fn void_end(&self) -> &'static str {
+        if self.xml_syntax() { " />" } else { ">" }
+    }
+
+    /// A boolean attribute like `controls`, with a leading space; XHTML
+    /// spells it out as `controls="controls"`.
+    pub fn boolean_attribute(&self, name: &str) -> String {
+        if self.xml_syntax() { format!(" {}=\"{}\"", name, name) } else { format!(" {}", name) }
+    }
+}
+
 /// Concrete semantic analyzer implementation with scope support.
 /// 
 /// Maintains a stack of scopes (local scopes on top, global at bottom),
 /// tracks variable declarations and assignments, and generates HTML output.
 // Concrete semantic analyzer implementation with scope support
 pub struct LolcodeSemanticAnalyzer {
-    // Stack of scopes:local scopes at top, then global
+    // global scope, always there
+    global: Scope,
+    // Stack of local scopes opened inside the global one, innermost at top
     scope_stack: Vec<Scope>,
-    // see if currently inside of variable assignment
-    current_assignment: Option<String>,
+    // the variable a bare #IT IZ assigns to, the one declared last
+    current_assignment: Option<Symbol>,
+    // set when codegen emits a video/iframe that needs the responsive css
+    uses_embeds: bool,
+    // `first_title` as plain text rather than HTML, for the social cards
+    first_title_text: Option<String>,
     // tracks errors on vector
     errors: Vec<String>,
+    // symbol index -> the symbol it resolves as, only filled when case is ignored
+    folded: Vec<Symbol>,
+    /// Warnings from the last `check_tree`, left for the caller to report
+    pub warnings: Vec<Diagnostic>,
+    /// Treat `Name` and `NAME` as the same variable
+    pub case_insensitive: bool,
+    /// Options for the HTML output
+    pub options: OutputOptions,
+    /// Page name -> href of every page LINKZ can point at
+    pub page_links: HashMap<String, String>,
+    /// Where the page's output is relative to the site root, e.g.
+    /// `guides/intro.html`, for its URL below `options.base_url`
+    pub page_href: Option<String>,
+    /// Global variables given from outside the document, e.g. with `--define`.
+    /// Variables the document declares itself hide them.
+    pub defines: HashMap<String, String>,
+    /// What the built-in date and time variables show
+    pub build_time: BuildTime,
+    /// Report a HEAD section after the document's content as a warning
+    /// instead of an error
+    pub allow_late_head: bool,
+    /// Where sections and variable references start, from the parser, to point
+    /// at problems. Without them problems are reported without a position.
+    pub positions: Positions,
+    // variable references the semantic check has passed, to find the next one
+    // in `positions`
+    references_seen: usize,
+    // the same for sections, declarations and assignments
+    sections_seen: usize,
+    declarations_seen: usize,
+    assignments_seen: usize,
+    // what the symbol table will hold about the scopes the check has open,
+    // the global one at the bottom
+    open_scopes: Vec<ScopeInfo>,
+    /// Every scope of the last checked document and the variables declared in it
+    pub symbols: SymbolTable,
+    /// Text of the first TITLE the last output pass wrote, with the values of
+    /// its variable references
+    pub first_title: Option<String>,
+    /// URL of the first image the last output pass wrote, with the values of
+    /// its variable references
+    pub first_image: Option<String>,
+    /// Names behind the variable symbols, taken over from the parser
+    pub interner: Interner,
+    /// Length of the source in bytes, when the caller knows it. Pages come out
+    /// about as long as their source, so `generate_output` sizes its buffer
+    /// from it once instead of growing it as the page is written.
+    pub source_len: usize,
+    // node address -> where the element starts in the source, for source maps
+    element_positions: HashMap<usize, (usize, usize)>,
+    // META lang of the document the last output pass wrote, for its file name
+    lang: Option<String>,
 }
 
 impl LolcodeSemanticAnalyzer {
@@ -53,9 +263,34 @@ impl LolcodeSemanticAnalyzer {
     /// Initializes with a single global scope and no errors.
     pub fn new() -> Self {
         Self {
-            scope_stack: vec![Scope::new()], // Start with global scope
+            global: Scope::new(), // Start with global scope
+            scope_stack: Vec::new(),
             current_assignment: None,
+            uses_embeds: false,
+            first_title_text: None,
             errors: Vec::new(),
+            folded: Vec::new(),
+            warnings: Vec::new(),
+            case_insensitive: false,
+            options: OutputOptions::default(),
+            page_links: HashMap::new(),
+            page_href: None,
+            defines: HashMap::new(),
+            build_time: BuildTime::now(),
+            allow_late_head: false,
+            positions: Positions::default(),
+            references_seen: 0,
+            sections_seen: 0,
+            declarations_seen: 0,
+            assignments_seen: 0,
+            open_scopes: Vec::new(),
+            symbols: SymbolTable::default(),
+            first_title: None,
+            first_image: None,
+            element_positions: HashMap::new(),
+            lang: None,
+            interner: Interner::new(),
+            source_len: 0,
         }
     }
 
@@ -65,6 +300,34 @@ impl LolcodeSemanticAnalyzer {
         self.errors.push(msg);
     }
 
+    /// The symbol a variable name resolves as: itself, or with `case_insensitive`
+    /// the first spelling of it that appears in the document.
+    fn key(&self, name: Symbol) -> Symbol {
+        self.folded.get(name.index()).copied().unwrap_or(name)
+    }
+
+    /// Maps every spelling of a name to its first one when case is ignored.
+    /// Returns each extra spelling with the one it was folded into.
+    fn fold_names(&mut self) -> Vec<(Symbol, Symbol)> {
+        self.folded.clear();
+        let mut collisions = Vec::new();
+        if !self.case_insensitive {
+            return collisions;
+        }
+        let mut first: HashMap<String, Symbol> = HashMap::new();
+        for (index, name) in self.interner.names().enumerate() {
+            let Some(symbol) = self.interner.symbol_at(index) else {
+                continue;
+            };
+            let canonical = *first.entry(name.to_lowercase()).or_insert(symbol);
+            if canonical != symbol {
+                collisions.push((symbol, canonical));
+            }
+            self.folded.push(canonical);
+        }
+        collisions
+    }
+
     /// Enters a new scope by pushing it onto the scope stack.
     // new scope (push onto stack)
     fn enter_scope(&mut self) {
@@ -74,68 +337,335 @@ impl LolcodeSemanticAnalyzer {
     /// Exits the current scope by popping it from the stack.
     // exit current scope (pop)
     fn exit_scope(&mut self) {
-        if self.scope_stack.len() > 1 {
-            self.scope_stack.pop();
+        // the global scope is never popped
+        if let Some(scope) = self.scope_stack.pop() {
+            self.forget_assignment_target(&scope);
+        }
+    }
+
+    // a bare #IT IZ can't assign to a variable whose scope is gone
+    fn forget_assignment_target(&mut self, scope: &Scope) {
+        if self.current_assignment.is_some_and(|name| scope.variables.contains_key(&self.key(name))) {
+            self.current_assignment = None;
         }
     }
 
     /// Gets a mutable reference to the current (top) scope.
     // get current scope (top of stack)
     fn current_scope(&mut self) -> &mut Scope {
-        self.scope_stack.last_mut().unwrap()
+        self.scope_stack.last_mut().unwrap_or(&mut self.global)
     }
 
     /// Looks up a variable in the scope stack.
     /// 
-    /// Searches from innermost to outermost scope.
+    /// Searches from innermost to outermost scope, then the defines and the
+    /// built-in variables.
     // look for variable in current scope
-    fn lookup_variable(&self, name: &str) -> Option<Option<String>> {
+    fn lookup_variable(&self, name: Symbol) -> Option<Option<String>> {
+        let key = self.key(name);
         // Search closest to furthest
-        for scope in self.scope_stack.iter().rev() {
-            if let Some(value) = scope.variables.get(name) {
+        for scope in self.scope_stack.iter().rev().chain(std::iter::once(&self.global)) {
+            if let Some(value) = scope.variables.get(&key) {
                 return Some(value.clone());
             }
         }
-        None
+        let spelling = self.interner.resolve(name);
+        if let Some(value) = self.define(spelling) {
+            return Some(Some(value.clone()));
+        }
+        self.build_time.value(spelling, self.case_insensitive).map(Some)
+    }
+
+    /// Checks a variable reference during analysis: the variable has to be
+    /// declared and have a value by then.
+    fn check_reference(&mut self, name: Symbol) {
+        self.record_reference(self.references_seen, name);
+        let position = self.next_reference_position();
+        self.mark_read(name);
+        match self.lookup_variable(name) {
+            None => {
+                self.semantic_error(format!(
+                    "{}Variable '{}' is used but never declared",
+                    position,
+                    self.interner.resolve(name)
+                ));
+            }
+            Some(None) => {
+                // an error either way, which one depends on what comes later
+                self.wait_for_value(name, position);
+            }
+            Some(Some(_)) => {
+                // Variable is declared and assigned
+            }
+        }
+    }
+
+    /// Marks the value of a variable as read, in the scope it resolves to.
+    fn mark_read(&mut self, name: Symbol) {
+        let key = self.key(name);
+        for scope in self.scope_stack.iter_mut().rev().chain(std::iter::once(&mut self.global)) {
+            if scope.variables.contains_key(&key) {
+                scope.unread.retain(|unread| *unread != key);
+                return;
+            }
+        }
+    }
+
+    /// True if the variable has a value nothing has read yet.
+    fn is_unread(&self, name: Symbol) -> bool {
+        let key = self.key(name);
+        self.scope_stack
+            .iter()
+            .rev()
+            .chain(std::iter::once(&self.global))
+            .find(|scope| scope.variables.contains_key(&key))
+            .is_some_and(|scope| scope.unread.contains(&key))
+    }
+
+    /// Remembers a reference to a declared variable that has no value yet. If
+    /// a value comes later it was used too early, otherwise never assigned.
+    fn wait_for_value(&mut self, name: Symbol, position: String) {
+        let key = self.key(name);
+        for scope in self.scope_stack.iter_mut().rev().chain(std::iter::once(&mut self.global)) {
+            if scope.variables.contains_key(&key) {
+                scope.waiting.push((key, position));
+                return;
+            }
+        }
+    }
+
+    /// Enters the scope of a PARAGRAF or LIST during the semantic check.
+    fn enter_checked_scope(&mut self, kind: &'static str) {
+        let section = self.sections_seen;
+        self.sections_seen += 1;
+        self.enter_scope();
+        self.open_scopes.push(ScopeInfo::new(
+            kind,
+            self.positions.sections.get(section).copied(),
+            self.positions.section_ends.get(section).copied(),
+        ));
+    }
+
+    /// Leaves a scope during the semantic check, warning about every value
+    /// assigned in it that nothing read.
+    fn exit_checked_scope(&mut self) {
+        if let Some(mut scope) = self.scope_stack.pop() {
+            self.forget_assignment_target(&scope);
+            self.report_scope(&mut scope);
+            if let Some(info) = self.open_scopes.pop() {
+                let info = self.close_scope_info(info, &scope);
+                if let Some(parent) = self.open_scopes.last_mut() {
+                    parent.children.push(info);
+                }
+            }
+        }
+    }
+
+    // the values the variables of a scope have when it ends
+    fn close_scope_info(&self, mut info: ScopeInfo, scope: &Scope) -> ScopeInfo {
+        for variable in &mut info.variables {
+            let value = self
+                .interner
+                .get(&variable.name)
+                .and_then(|name| scope.variables.get(&self.key(name)));
+            variable.value = value.cloned().flatten();
+        }
+        info
+    }
+
+    // the parser recorded the positions of content under an #IZ DEFINED that is
+    // off too, so it is counted to stay in step with them
+    fn skip_positions(&mut self, nodes: &[ASTNode]) {
+        for node in nodes {
+            match node {
+                ASTNode::VariableReference { .. } => self.references_seen += 1,
+                ASTNode::VariableDeclaration { .. } => self.declarations_seen += 1,
+                ASTNode::VariableAssignment { .. } => self.assignments_seen += 1,
+                ASTNode::MetaSection { .. } => self.sections_seen += 1,
+                ASTNode::HeadSection { children }
+                | ASTNode::ParagrafSection { children }
+                | ASTNode::ListSection { children } => {
+                    self.sections_seen += 1;
+                    self.skip_positions(children);
+                }
+                ASTNode::Program { children } | ASTNode::IfDefined { children, .. } => self.skip_positions(children),
+                ASTNode::Title { content }
+                | ASTNode::Bold { content }
+                | ASTNode::Italics { content }
+                | ASTNode::Item { content } => self.skip_positions(content),
+                ASTNode::Sound { attributes, .. } | ASTNode::Video { attributes, .. } | ASTNode::Image { attributes, .. } => {
+                    self.references_seen += attributes.url_references.len();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // a declaration goes in the symbol table with where it is
+    fn record_declaration(&mut self, name: Symbol) {
+        let span = self.positions.declarations.get(self.declarations_seen).copied();
+        self.declarations_seen += 1;
+        let declared_at = span.map(|span| span.start);
+        let name = self.interner.resolve(name).to_string();
+        // its value is only known once the scope ends, see check_tree
+        if let Some(span) = span {
+            self.record_use(UseKind::Declaration, name.clone(), span, declared_at, None);
+        }
+        if let Some(info) = self.open_scopes.last_mut()
+            && !info.variables.iter().any(|variable| variable.name == name)
+        {
+            info.variables.push(VariableInfo { name, value: None, declared_at });
+        }
+    }
+
+    // an assignment goes in the symbol table with the value it gives; returns
+    // where it is, if that is known
+    fn record_assignment(&mut self, name: Option<Symbol>, value: &str) -> Option<VariableSpan> {
+        let span = self.positions.assignments.get(self.assignments_seen).copied();
+        self.assignments_seen += 1;
+        if let (Some(span), Some(name)) = (span, name) {
+            let declared_at = self.declaration_site(name);
+            let spelling = self.interner.resolve(name).to_string();
+            self.record_use(UseKind::Assignment, spelling, span, declared_at, Some(value.to_string()));
+        }
+        span
+    }
+
+    // a reference goes in the symbol table with the value it shows
+    fn record_reference(&mut self, index: usize, name: Symbol) {
+        if let Some(span) = self.positions.references.get(index).copied() {
+            let declared_at = self.declaration_site(name);
+            let value = self.lookup_variable(name).flatten();
+            self.record_use(UseKind::Reference, self.interner.resolve(name).to_string(), span, declared_at, value);
+        }
+    }
+
+    fn record_use(
+        &mut self,
+        kind: UseKind,
+        name: String,
+        span: VariableSpan,
+        declared_at: Option<(usize, usize)>,
+        value: Option<String>,
+    ) {
+        self.symbols.uses.push(SymbolInfo { name, kind, span: (span.start, span.end), name_at: span.name, declared_at, value });
+    }
+
+    // where the variable a name resolves to here is declared, if the document declares it
+    fn declaration_site(&self, name: Symbol) -> Option<(usize, usize)> {
+        let key = self.key(name);
+        self.open_scopes.iter().rev().find_map(|scope| {
+            scope
+                .variables
+                .iter()
+                .find(|variable| self.interner.get(&variable.name).is_some_and(|declared| self.key(declared) == key))
+                .map(|variable| variable.declared_at)
+        })?
+    }
+
+    // what is left in a scope once the check is done with it
+    fn report_scope(&mut self, scope: &mut Scope) {
+        for (name, position) in std::mem::take(&mut scope.waiting) {
+            self.semantic_error(format!(
+                "{}Variable '{}' is used but never assigned a value",
+                position,
+                self.interner.resolve(name)
+            ));
+        }
+        self.warn_unread(std::mem::take(&mut scope.unread));
+    }
+
+    /// "line 3, col 5: " for the next variable reference, if its position is known.
+    fn next_reference_position(&mut self) -> String {
+        let position = self.positions.references.get(self.references_seen).map(|span| &span.start);
+        self.references_seen += 1;
+        position_prefix(position)
+    }
+
+    fn warn_unread(&mut self, unread: Vec<Symbol>) {
+        for name in unread {
+            self.warnings.push(Diagnostic::warning("unused-value", format!(
+                "Variable '{}' is assigned a value that is never read",
+                self.interner.resolve(name)
+            )));
+        }
+    }
+
+    /// True if `flag` is one of the defines, for `#IZ DEFINED`.
+    fn is_defined(&self, flag: &str) -> bool {
+        self.define(flag).is_some()
+    }
+
+    /// Looks up a define, in any case when case is ignored.
+    fn define(&self, name: &str) -> Option<&String> {
+        if let Some(value) = self.defines.get(name) {
+            return Some(value);
+        }
+        if !self.case_insensitive {
+            return None;
+        }
+        let folded = name.to_lowercase();
+        self.defines.iter().find(|(defined, _)| defined.to_lowercase() == folded).map(|(_, value)| value)
     }
 
     /// Declares a variable in the current scope.
     /// 
     /// Reports an error if the variable is already declared in the current scope.
     // Declare a variable in current scope
-    fn declare_variable(&mut self, name: String) {
-        let scope = self.current_scope();
+    fn declare_variable(&mut self, name: Symbol) {
+        let key = self.key(name);
+        let scope = self.scope_stack.last_mut().unwrap_or(&mut self.global);
         
         // Check if variable already exists in current scope 
-        if scope.variables.contains_key(&name) {
-            self.semantic_error(format!(
-                "Variable '{}' is already declared in this scope",
-                name
-            ));
-        } else {
-            scope.variables.insert(name, None); // None = declared but not assigned
+        match scope.variables.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(None); // None = declared but not assigned
+            }
+            Entry::Occupied(_) => {
+                let msg = format!(
+                    "Variable '{}' is already declared in this scope",
+                    self.interner.resolve(name)
+                );
+                self.semantic_error(msg);
+            }
         }
     }
 
     /// Declares a variable during code generation without error checking.
     // Declare a variable in current scope 
-    fn declare_variable_codegen(&mut self, name: String) {
+    fn declare_variable_codegen(&mut self, name: Symbol) {
+        let name = self.key(name);
         let scope = self.current_scope();
         scope.variables.insert(name, None);
     }
 
-    /// Assigns a value to a previously declared variable.
+    /// Assigns a value to a previously declared variable. False if no scope
+    /// declares it.
     // Assign value to a variable
-    fn assign_variable(&mut self, name: &str, value: String) {
+    fn assign_variable(&mut self, name: Symbol, value: String) -> bool {
         // Find the variable in current or parent scopes and assign the value
-        for scope in self.scope_stack.iter_mut().rev() {
-            if scope.variables.contains_key(name) {
-                scope.variables.insert(name.to_string(), Some(value));
-                return;
+        let key = self.key(name);
+        for scope in self.scope_stack.iter_mut().rev().chain(std::iter::once(&mut self.global)) {
+            if let Some(slot) = scope.variables.get_mut(&key) {
+                *slot = Some(value);
+                if !scope.unread.contains(&key) {
+                    scope.unread.push(key);
+                }
+                // references that came earlier didn't get this value
+                let (early, waiting) = std::mem::take(&mut scope.waiting).into_iter().partition(|(waiter, _)| *waiter == key);
+                scope.waiting = waiting;
+                for (_, position) in early {
+                    self.semantic_error(format!(
+                        "{}Variable '{}' is used before it is assigned a value",
+                        position,
+                        self.interner.resolve(name)
+                    ));
+                }
+                return true;
             }
         }
-        //Error if not found
-        self.semantic_error(format!("Cannot assign to undeclared variable '{}'", name));
+        // the caller reports it, with the assignment's position
+        false
     }
 
     /// Traverses the AST and checks for semantic errors.
@@ -147,117 +677,162 @@ impl LolcodeSemanticAnalyzer {
         match node {
             ASTNode::Program { children } => {
                 // top level first
-                for child in children {
+                for child in children.iter() {
                     self.traverse(child);
                 }
             }
 
             ASTNode::HeadSection { children } => {
                 // Head sections don't create new scope
-                for child in children {
+                self.sections_seen += 1;
+                for child in children.iter() {
                     self.traverse(child);
                 }
             }
 
             ASTNode::ParagrafSection { children } => {
                 // Enter new scope for paragraf section
-                self.enter_scope();
-                for child in children {
+                self.enter_checked_scope("PARAGRAF");
+                for child in children.iter() {
                     self.traverse(child);
                 }
-                self.exit_scope();
+                self.exit_checked_scope();
             }
 
             ASTNode::ListSection { children } => {
                 // Enter new scope for list section
-                self.enter_scope();
-                for child in children {
+                self.enter_checked_scope("LIST");
+                for child in children.iter() {
                     self.traverse(child);
                 }
-                self.exit_scope();
+                self.exit_checked_scope();
             }
 
             // Variable declaration: #I HAZ varname
             ASTNode::VariableDeclaration { name } => {
-                self.declare_variable(name.clone());
-                self.current_assignment = Some(name.clone());
-            }
-
-            // Variable assignment: #IT IZ value #MKAY
-            ASTNode::VariableAssignment { name: _, value } => {
-                // Mark the most recently declared variable as assigned with its value
-                if let Some(var_name) = self.current_assignment.clone() {
-                    self.assign_variable(&var_name, value.clone());
-                    self.current_assignment = None;
-                }
+                self.declare_variable(*name);
+                self.record_declaration(*name);
+                self.current_assignment = Some(*name);
             }
 
-            // Variable reference: #LEMME SEE varname #MKAY
-            ASTNode::VariableReference { name } => {
-                match self.lookup_variable(name) {
-                    None => {
-                        self.semantic_error(format!(
-                            "Variable '{}' is used but never declared",
-                            name
-                        ));
+            // Variable assignment: #IT IZ [varname R] value #MKAY
+            ASTNode::VariableAssignment { name, value } => {
+                // without a name the most recently declared variable gets the value
+                let span = self.record_assignment(name.or(self.current_assignment), value);
+                if let Some(var_name) = name.or(self.current_assignment) {
+                    if self.is_unread(var_name) {
+                        self.warnings.push(Diagnostic::warning("unused-value", format!(
+                            "Variable '{}' is assigned again before its value is read",
+                            self.interner.resolve(var_name)
+                        )));
                     }
-                    Some(None) => {
+                    if !self.assign_variable(var_name, value.to_string()) {
+                        let position = span.map(|span| span.name.unwrap_or(span.start));
                         self.semantic_error(format!(
-                            "Variable '{}' is used but never assigned a value",
-                            name
+                            "{}Cannot assign to undeclared variable '{}'",
+                            position_prefix(position.as_ref()),
+                            self.interner.resolve(var_name)
                         ));
                     }
-                    Some(Some(_)) => {
-                        // Variable is declared and assigned
-                    }
+                } else {
+                    self.warnings.push(Diagnostic::warning("unused-value", format!(
+                        "The value '{}' isn't assigned to any variable: #IT IZ without a name needs an #I HAZ before it",
+                        value
+                    )));
                 }
             }
 
+            // Variable reference: #LEMME SEE varname #MKAY
+            ASTNode::VariableReference { name } => self.check_reference(*name),
+
             // content in bold/italic
-            ASTNode::Bold { content } => {
-                for child in content {
+            ASTNode::Title { content } | ASTNode::Bold { content } => {
+                for child in content.iter() {
                     self.traverse(child);
                 }
             }
 
             ASTNode::Italics { content } => {
-                for child in content {
+                for child in content.iter() {
                     self.traverse(child);
                 }
             }
 
             ASTNode::Item { content } => {
-                for child in content {
+                for child in content.iter() {
                     self.traverse(child);
                 }
             }
 
+            // content for a flag that isn't defined is left out, like it isn't there
+            ASTNode::IfDefined { flag, children } => {
+                if self.is_defined(flag) {
+                    for child in children.iter() {
+                        self.traverse(child);
+                    }
+                } else {
+                    self.skip_positions(children);
+                }
+            }
+
             // nothing in leaf nodes
-            ASTNode::Title { .. } => {}
             ASTNode::Text { .. } => {}
             ASTNode::Newline => {}
-            ASTNode::Sound { .. } => {}
-            ASTNode::Video { .. } => {}
+
+            // media URLs can be built from variables
+            ASTNode::Sound { attributes, .. } | ASTNode::Video { attributes, .. } | ASTNode::Image { attributes, .. } => {
+                for (_, name) in &attributes.url_references {
+                    self.check_reference(*name);
+                }
+            }
+
+            // dates in the META block have to be usable by the feed
+            ASTNode::MetaSection { entries } => {
+                self.sections_seen += 1;
+                for (key, value) in entries {
+                    if key == "date" && !meta::is_valid_date(value) {
+                        self.semantic_error(format!(
+                            "META date '{}' must be written as YYYY-MM-DD",
+                            value
+                        ));
+                    }
+                }
+            }
+
+            // links inside the project have to point at a page that is being built
+            ASTNode::Link { target, .. } => {
+                if !site::is_external(target) && !self.page_links.contains_key(*target) {
+                    self.semantic_error(format!(
+                        "Link to page '{}' which does not exist",
+                        target
+                    ));
+                }
+            }
         }
     }
 
-    /// Prints all accumulated semantic errors and exits if any were found.
-    /// Print all semantic errors and exit if any found
-    fn report_errors(&self) {
-        if !self.errors.is_empty() {
-            eprintln!("=== Semantic Analysis Errors ===");
-            for error in &self.errors {
-                eprintln!("Semantic error: {}", error);
-            }
-            eprintln!("================================");
-            exit(1);
+    /// Hands back all accumulated semantic errors, if any were found.
+    fn take_errors(&mut self) -> Result<(), CompileError> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        #[cfg(feature = "tracing")]
+        for error in &self.errors {
+            tracing::error!("semantic error: {}", error);
         }
+        Err(CompileError::Semantic { errors: std::mem::take(&mut self.errors) })
+    }
+}
+
+impl Default for LolcodeSemanticAnalyzer {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl SemanticAnalyzer for LolcodeSemanticAnalyzer {
     fn analyze(&mut self) {
-        println!("Starting semantic analysis...");
+        log::info!("Starting semantic analysis...");
     }
 
     fn check_variables(&mut self) {
@@ -272,175 +847,1009 @@ impl LolcodeSemanticAnalyzer {
     /// 1. Semantic validation pass to check for errors
     /// 2. Code generation pass to produce HTML with variable substitution
     // analyze parse tree
-    pub fn analyze_tree(&mut self, tree: &ASTNode, input_filename: &str) {
-        println!("Starting semantic analysis...");
+    pub fn analyze_tree(&mut self, tree: &ASTNode, input_filename: &str) -> Result<(), CompileError> {
+        self.check_tree(tree)?;
+        self.write_output(tree, input_filename);
+        Ok(())
+    }
+
+    /// Runs the semantic validation pass and returns every error it found.
+    /// 
+    /// Afterwards `get_current_scope` holds the global variables of the document
+    /// and `symbols` every scope with the variables declared in it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "semantic", skip_all))]
+    pub fn check_tree(&mut self, tree: &ASTNode) -> Result<(), CompileError> {
+        log::info!("Starting semantic analysis...");
+        self.warnings.clear();
+        for (spelling, first) in self.fold_names() {
+            self.warnings.push(Diagnostic::warning("case-collision", format!(
+                "'{}' and '{}' are the same variable since case is ignored",
+                self.interner.resolve(spelling),
+                self.interner.resolve(first)
+            )));
+        }
         
         //Traverse tree and check semantics
+        self.references_seen = 0;
+        self.sections_seen = 0;
+        self.declarations_seen = 0;
+        self.assignments_seen = 0;
+        self.open_scopes = vec![ScopeInfo::new("document", None, None)];
+        self.symbols = SymbolTable::default();
+        self.symbols.case_insensitive = self.case_insensitive;
         self.traverse(tree);
+        let mut global = std::mem::replace(&mut self.global, Scope::new());
+        self.report_scope(&mut global);
+        if let Some(info) = self.open_scopes.pop() {
+            self.symbols.global = self.close_scope_info(info, &global);
+        }
+        self.global = global;
+        let mut final_values = HashMap::new();
+        collect_final_values(&self.symbols.global, &mut final_values);
+        for symbol in &mut self.symbols.uses {
+            if symbol.kind == UseKind::Declaration {
+                symbol.value = final_values.get(&symbol.span.0).cloned().flatten();
+            }
+        }
+        self.check_head_placement(tree, &mut 0, &mut false, &mut None, true);
         
-        // Report any errors found
-        self.report_errors();
-        
-        println!("Semantic analysis completed successfully!");
+        // Hand back any errors found
+        self.take_errors()?;
         
-        // Task 4: Generate HTML code
-        println!("Generating HTML output...");
+        log::info!("Semantic analysis completed successfully!");
+        Ok(())
+    }
+
+    /// Reports HEAD sections that come after content: a PARAGRAF, a LIST or text
+    /// at the top level. META sections and variables may come before a HEAD.
+    ///
+    /// `sections` counts the sections seen so far, to find a section's place in
+    /// `positions`; sections under an `#IZ DEFINED` that is off are counted
+    /// but not checked. `content_start` is where the first body section is,
+    /// when the content starts with one, for a label on the diagnostic.
+    fn check_head_placement(
+        &mut self,
+        node: &ASTNode,
+        sections: &mut usize,
+        content_seen: &mut bool,
+        content_start: &mut Option<(usize, usize)>,
+        included: bool,
+    ) {
+        let is_section = matches!(
+            node,
+            ASTNode::HeadSection { .. } | ASTNode::MetaSection { .. } | ASTNode::ParagrafSection { .. } | ASTNode::ListSection { .. }
+        );
+        let index = *sections;
+        if is_section {
+            *sections += 1;
+        }
+        match node {
+            ASTNode::HeadSection { .. } if included && *content_seen => {
+                let mut diagnostic = Diagnostic::warning(
+                    "late-head",
+                    "HEAD section after the document's content; HEAD has to come first".to_string(),
+                );
+                diagnostic.span = self.positions.sections.get(index).copied();
+                if let Some((line, col)) = *content_start {
+                    diagnostic.labels.push(Label { line, col, message: "the content starts here".to_string() });
+                }
+                if self.allow_late_head {
+                    self.warnings.push(diagnostic);
+                } else {
+                    self.semantic_error(diagnostic.located_message());
+                }
+            }
+            ASTNode::HeadSection { .. }
+            | ASTNode::MetaSection { .. }
+            | ASTNode::VariableDeclaration { .. }
+            | ASTNode::VariableAssignment { .. }
+            | ASTNode::Newline
+            | ASTNode::Program { .. }
+            | ASTNode::IfDefined { .. } => {}
+            ASTNode::ParagrafSection { .. } | ASTNode::ListSection { .. } if included && !*content_seen => {
+                *content_seen = true;
+                *content_start = self.positions.sections.get(index).copied();
+            }
+            _ if included => *content_seen = true,
+            _ => {}
+        }
+        match node {
+            ASTNode::Program { children } | ASTNode::ParagrafSection { children } | ASTNode::ListSection { children } => {
+                for child in children.iter() {
+                    self.check_head_placement(child, sections, content_seen, content_start, included);
+                }
+            }
+            ASTNode::IfDefined { flag, children } => {
+                let included = included && self.is_defined(flag);
+                for child in children.iter() {
+                    self.check_head_placement(child, sections, content_seen, content_start, included);
+                }
+            }
+            // sections only nest in paragrafs
+            _ => {}
+        }
+    }
+
+    /// The variable declared or referenced at a source position in the last
+    /// checked document, with where it is declared and the value it has there.
+    pub fn symbol_at(&self, line: usize, col: usize) -> Option<SymbolInfo> {
+        self.symbols.symbol_at(line, col)
+    }
+
+    /// Returns the variables of the scope on top of the stack.
+    /// Names are interned; resolve them with `interner`.
+    pub fn get_current_scope(&self) -> &HashMap<Symbol, Option<String>> {
+        &self.scope_stack.last().unwrap_or(&self.global).variables
+    }
+
+    /// Runs the code generation pass for the selected target and returns the output.
+    pub fn generate_output(&mut self, tree: &ASTNode) -> String {
+        // the page frame and its styles come on top of the content
+        let capacity = if self.options.target.is_html() { self.source_len + 4096 } else { 0 };
+        let mut output = Vec::with_capacity(capacity);
+        // writing into a Vec can't fail, and every backend writes strings
+        if let Ok(Some(rendered)) = self.codegen(tree, &mut output) {
+            return rendered;
+        }
+        into_string(output)
+    }
+
+    /// Runs the code generation pass for the selected target and writes the output
+    /// to `out` as it is produced, so large pages never sit in memory as a whole.
+    pub fn generate_output_to<W: Write>(&mut self, tree: &ASTNode, out: &mut W) -> io::Result<()> {
+        match self.codegen(tree, out)? {
+            Some(rendered) => out.write_all(rendered.as_bytes()),
+            None => Ok(()),
+        }
+    }
+
+    // HTML targets are written to `out` node by node; the others render the
+    // whole resolved tree to a string, which is handed back as it is so
+    // `generate_output` doesn't copy it
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "codegen", skip_all, fields(target = self.options.target.name())))]
+    fn codegen<W: Write>(&mut self, tree: &ASTNode, out: &mut W) -> io::Result<Option<String>> {
+        // Task 4: Generate HTML code (or whichever target was asked for)
+        log::info!("Generating {} output...", self.options.target.name());
         
         // Reset scopes for HTML generation traversal
-        self.scope_stack = vec![Scope::new()];
-        self.current_assignment = None;
+        self.reset_codegen();
+        // the file name can depend on the page's language
+        if self.options.naming.uses_lang() {
+            self.lang = meta::document_meta(tree).get("lang").map(str::to_string);
+        }
         
-        // Re-traverse to generate HTML (this time populating scopes with values)
-        let html = self.generate_html_with_traversal(tree);
+        // Re-traverse to generate output (this time populating scopes with values)
+        let arena = AstArena::new();
+        let rendered = match self.options.target {
+            Target::Html | Target::Pdf | Target::Slides => {
+                if self.options.source_map || self.options.debug_annotations {
+                    self.element_positions = self.positions.element_starts(tree);
+                }
+                self.write_html(tree, out)?;
+                return Ok(None);
+            }
+            Target::Gemtext => gemtext::render(self.resolve_tree(tree, &arena)),
+            Target::Ansi => ansi::render(self.resolve_tree(tree, &arena)),
+            Target::Man => roff::render(self.resolve_tree(tree, &arena)),
+            Target::AsciiDoc => asciidoc::render(self.resolve_tree(tree, &arena)),
+            Target::Xml => xml::render(self.resolve_tree(tree, &arena)),
+        };
+        Ok(Some(rendered))
+    }
+
+    /// Delivers generated output: writes the file next to the input, prints a
+    /// PDF for the PDF target and opens web pages in the browser.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "emit", skip_all, fields(input = input_filename, bytes = output.len())))]
+    pub fn emit_output(&mut self, output: &str, input_filename: &str) {
+        // terminal output is the preview, nothing to write or open
+        if self.options.target == Target::Ansi {
+            print!("{}", output);
+            return;
+        }
         
         // Write to output file
-        let output_filename = self.write_html_file(&html, input_filename);
+        let output_filename = self.write_output_file(output, input_filename, self.options.target.extension());
+        self.finish_output(&output_filename);
+    }
+
+    /// Generates the output straight into its file (or the terminal for ANSI)
+    /// without holding it in memory, then delivers it like `emit_output`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "emit", skip_all, fields(input = input_filename)))]
+    pub fn write_output(&mut self, tree: &ASTNode, input_filename: &str) {
+        if self.options.target == Target::Ansi {
+            let stdout = io::stdout();
+            self.generate_output_to(tree, &mut stdout.lock()).unwrap_or_else(|e| {
+                eprintln!("Failed to write terminal output: {}", e);
+                exit(EXIT_IO);
+            });
+            return;
+        }
+
+        //an unchanged page is left alone so file watchers don't see a new file;
+        //the name can depend on the page's language
+        if self.options.naming.uses_lang() {
+            self.lang = meta::document_meta(tree).get("lang").map(str::to_string);
+        }
+        let output_filename = self.output_path(input_filename);
+        let written = ChangedFileWriter::create(&output_filename).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            self.generate_output_to(tree, &mut out)?;
+            out.into_inner().map_err(|e| e.into_error())?.finish()
+        });
+        let changed = written.unwrap_or_else(|e| {
+            eprintln!("Failed to write {} file: {}", self.options.target.extension(), e);
+            exit(EXIT_IO);
+        });
+        if !changed {
+            log::debug!("Unchanged, not rewritten: {}", output_filename.display());
+        }
+        self.finish_output(&output_filename.to_string_lossy());
+    }
+
+    // report the written file, then print it to PDF or open it in the browser
+    fn finish_output(&self, output_filename: &str) {
+        log::info!("{} generated successfully: {}", self.options.target.name(), output_filename);
         
-        println!("HTML generated successfully: {}", output_filename);
+        // PDF target prints the page instead of opening it
+        if self.options.target == Target::Pdf {
+            let html_path = Path::new(output_filename);
+            let pdf_path = html_path.with_extension("pdf");
+            pdf::render_pdf(html_path, &pdf_path).unwrap_or_else(|e| {
+                eprintln!("Failed to generate PDF: {}", e);
+                exit(EXIT_IO);
+            });
+            log::info!("PDF generated successfully: {}", pdf_path.display());
+            return;
+        }
         
         // Open in browser
-        self.open_in_browser(&output_filename);
+        if self.options.open_browser && self.options.target.is_html() {
+            self.open_in_browser(output_filename);
+        }
+    }
+
+    /// Resets the scopes so the tree can be walked again for output.
+    fn reset_codegen(&mut self) {
+        self.global = Scope::new();
+        self.scope_stack.clear();
+        self.current_assignment = None;
+        self.first_title = None;
+        self.first_title_text = None;
+        self.first_image = None;
+        // codegen without check_tree first still needs the folded names
+        if self.case_insensitive && self.folded.len() != self.interner.len() {
+            self.fold_names();
+        }
+        self.uses_embeds = false;
+    }
+
+    /// What a variable reference shows here: the variable's value, or a marker
+    /// when it has none.
+    fn reference_value(&self, name: Symbol) -> String {
+        match self.lookup_variable(name) {
+            Some(Some(value)) => value,
+            _ => format!("[undefined: {}]", self.interner.resolve(name)),
+        }
+    }
+
+    /// What a variable reference shows in HTML: its value, put in the way
+    /// `options.value_policy` says.
+    fn html_value(&self, name: Symbol) -> String {
+        match self.lookup_variable(name) {
+            Some(Some(value)) if self.options.paranoid => xml::escape_xml(&value),
+            Some(Some(value)) => self.options.value_policy.text(&value),
+            _ => format!("[undefined: {}]", self.interner.resolve(name)),
+        }
+    }
+
+    /// Text the document wrote, for HTML: as it is, or escaped with `--paranoid`.
+    /// In XML syntax its markup stays, but a stray `&` or `<` is escaped so
+    /// the page still parses.
+    fn html_text<'s>(&self, text: &'s str) -> Cow<'s, str> {
+        if self.options.paranoid {
+            Cow::Owned(xml::escape_xml(text))
+        } else if self.options.xml_syntax() {
+            xml::escape_stray_markup(text)
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Text the document wrote, for an HTML attribute: always escaped, so a `"`
+    /// can't end the attribute. Only element text is passed through as it is.
+    fn html_attribute<'s>(&self, text: &'s str) -> Cow<'s, str> {
+        if text.contains(['&', '<', '>', '"']) {
+            Cow::Owned(xml::escape_xml(text))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// A relative URL as an absolute one below `options.base_url`, when there
+    /// is one; other URLs as they are.
+    fn resolved_url<'s>(&self, url: &'s str) -> Cow<'s, str> {
+        match &self.options.base_url {
+            Some(base) if site::is_relative_url(url) => {
+                Cow::Owned(site::absolute_url(base, self.page_href.as_deref().unwrap_or(""), url))
+            }
+            _ => Cow::Borrowed(url),
+        }
+    }
+
+    /// A URL the document wrote, for an HTML attribute: escaped, and without a
+    /// script or data scheme with `--paranoid`.
+    fn html_url<'s>(&self, url: &'s str) -> Cow<'s, str> {
+        let resolved = self.resolved_url(url);
+        if self.options.paranoid {
+            return Cow::Owned(sanitize::paranoid_url(&resolved));
+        }
+        match resolved {
+            Cow::Borrowed(url) => self.html_attribute(url),
+            Cow::Owned(resolved) => Cow::Owned(self.html_attribute(&resolved).into_owned()),
+        }
+    }
+
+    /// The HTML text of a TITLE with the values its variable references have here.
+    fn title_text(&self, content: &[ASTNode]) -> String {
+        let mut title = String::new();
+        let mut first = true;
+        let mut push = |word: &str| {
+            if !first {
+                title.push(' ');
+            }
+            first = false;
+            title.push_str(word);
+        };
+        for piece in content {
+            match piece {
+                ASTNode::Text { content } => push(&self.html_text(content)),
+                ASTNode::VariableReference { name } => push(&self.html_value(*name)),
+                _ => {}
+            }
+        }
+        title
+    }
+
+    // the words of a TITLE with the values of its references, unescaped
+    fn plain_title_text(&self, content: &[ASTNode]) -> String {
+        let words: Vec<String> = content
+            .iter()
+            .filter_map(|piece| match piece {
+                ASTNode::Text { content } => Some(content.to_string()),
+                ASTNode::VariableReference { name } => Some(self.reference_value(*name)),
+                _ => None,
+            })
+            .collect();
+        words.join(" ")
+    }
+
+    /// The URL of a media embed with the values of its variable references put in.
+    fn media_url(&self, url: &str, attributes: &MediaAttributes) -> String {
+        let mut built = url.to_string();
+        // from the back, so the offsets before each one stay where they are
+        for (offset, name) in attributes.url_references.iter().rev() {
+            built.insert_str(*offset, &self.reference_value(*name));
+        }
+        built
+    }
+
+    /// The URL of a media embed for an HTML attribute, with the values put in
+    /// the way `options.value_policy` says.
+    fn html_media_url(&self, url: &str, attributes: &MediaAttributes) -> String {
+        // the whole URL is checked and escaped, values and all
+        if self.options.paranoid {
+            return sanitize::paranoid_url(&self.resolved_url(&self.media_url(url, attributes)));
+        }
+        if let Cow::Owned(resolved) = self.resolved_url(&self.media_url(url, attributes)) {
+            return xml::escape_xml(&resolved);
+        }
+        // the values are escaped by the policy, the URL around them as attributes are
+        let mut built = String::with_capacity(url.len());
+        let mut written = 0;
+        for (offset, name) in &attributes.url_references {
+            built.push_str(&self.html_attribute(&url[written..*offset]));
+            written = *offset;
+            match self.lookup_variable(*name) {
+                Some(Some(value)) => built.push_str(&self.options.value_policy.url(&value, *offset == 0)),
+                _ => built.push_str(&self.reference_value(*name)),
+            }
+        }
+        built.push_str(&self.html_attribute(&url[written..]));
+        built
+    }
+
+    /// The children with the content of defined `#IZ DEFINED` in place of it.
+    fn included<'a, 't>(&self, children: &'a [ASTNode<'t>]) -> Vec<&'a ASTNode<'t>> {
+        let mut included = Vec::new();
+        for child in children {
+            match child {
+                ASTNode::IfDefined { flag, children } => {
+                    if self.is_defined(flag) {
+                        included.extend(self.included(children));
+                    }
+                }
+                _ => included.push(child),
+            }
+        }
+        included
+    }
+
+    /// Returns a copy of the tree with every variable reference replaced by its value.
+    /// 
+    /// Declarations and assignments are dropped, so backends other than HTML can
+    /// render the tree without knowing about scopes. Links to other pages get
+    /// their resolved href as the target. The new tree is allocated in `arena`.
+    pub fn resolve_tree<'t>(&mut self, tree: &ASTNode, arena: &'t AstArena<'t>) -> &'t ASTNode<'t> {
+        self.reset_codegen();
+        let resolved = self
+            .resolve_node(tree, arena)
+            .unwrap_or(ASTNode::Program { children: &[] });
+        arena.alloc(resolved)
+    }
+
+    // resolve a list of children in order, dropping the ones that disappear
+    fn resolve_children<'t>(&mut self, children: &[ASTNode], arena: &'t AstArena<'t>) -> &'t [ASTNode<'t>] {
+        let mut resolved = Vec::with_capacity(children.len());
+        self.resolve_into(children, arena, &mut resolved);
+        arena.alloc_nodes(resolved)
+    }
+
+    // the content of a defined #IZ DEFINED takes its place among the children
+    fn resolve_into<'t>(&mut self, children: &[ASTNode], arena: &'t AstArena<'t>, resolved: &mut Vec<ASTNode<'t>>) {
+        for child in children {
+            match child {
+                ASTNode::IfDefined { flag, children } => {
+                    if self.is_defined(flag) {
+                        self.resolve_into(children, arena, resolved);
+                    }
+                }
+                _ => resolved.extend(self.resolve_node(child, arena)),
+            }
+        }
+    }
+
+    // same scope handling as the HTML traversal
+    fn resolve_node<'t>(&mut self, node: &ASTNode, arena: &'t AstArena<'t>) -> Option<ASTNode<'t>> {
+        let resolved = match node {
+            ASTNode::Program { children } => ASTNode::Program { children: self.resolve_children(children, arena) },
+            ASTNode::HeadSection { children } => ASTNode::HeadSection { children: self.resolve_children(children, arena) },
+            ASTNode::ParagrafSection { children } => {
+                self.enter_scope();
+                let children = self.resolve_children(children, arena);
+                self.exit_scope();
+                ASTNode::ParagrafSection { children }
+            }
+            ASTNode::ListSection { children } => {
+                self.enter_scope();
+                let children = self.resolve_children(children, arena);
+                self.exit_scope();
+                ASTNode::ListSection { children }
+            }
+            ASTNode::Bold { content } => ASTNode::Bold { content: self.resolve_children(content, arena) },
+            ASTNode::Italics { content } => ASTNode::Italics { content: self.resolve_children(content, arena) },
+            ASTNode::Item { content } => ASTNode::Item { content: self.resolve_children(content, arena) },
+            ASTNode::Link { target, text } => ASTNode::Link {
+                target: arena.alloc_str(self.page_links.get(*target).map(String::as_str).unwrap_or(target)),
+                text: arena.alloc_str(text),
+            },
+            ASTNode::VariableDeclaration { name } => {
+                self.current_assignment = Some(*name);
+                self.declare_variable_codegen(*name);
+                return None;
+            }
+            ASTNode::VariableAssignment { name, value } => {
+                if let Some(var_name) = name.or(self.current_assignment) {
+                    self.assign_variable(var_name, value.to_string());
+                }
+                return None;
+            }
+            ASTNode::VariableReference { name } => {
+                ASTNode::Text { content: arena.alloc_str(&self.reference_value(*name)) }
+            }
+            // spliced into the parent by resolve_into
+            ASTNode::IfDefined { .. } => return None,
+            ASTNode::MetaSection { entries } => ASTNode::MetaSection { entries: entries.clone() },
+            ASTNode::Title { content } => {
+                let content = self.resolve_children(content, arena);
+                if self.first_title.is_none() {
+                    self.first_title = Some(meta::title_text(content));
+                }
+                ASTNode::Title { content }
+            }
+            ASTNode::Text { content } => ASTNode::Text { content: arena.alloc_str(content) },
+            ASTNode::Newline => ASTNode::Newline,
+            ASTNode::Sound { url, attributes } => {
+                let (url, attributes) = self.resolve_media(url, attributes, arena);
+                ASTNode::Sound { url, attributes }
+            }
+            ASTNode::Video { url, attributes } => {
+                let (url, attributes) = self.resolve_media(url, attributes, arena);
+                ASTNode::Video { url, attributes }
+            }
+            ASTNode::Image { url, attributes } => {
+                let (url, attributes) = self.resolve_media(url, attributes, arena);
+                ASTNode::Image { url, attributes }
+            }
+        };
+        Some(resolved)
+    }
+
+    // a media URL with its variables filled in, which the resolved tree no longer has
+    fn resolve_media<'t>(
+        &self,
+        url: &str,
+        attributes: &MediaAttributes,
+        arena: &'t AstArena<'t>,
+    ) -> (&'t str, Box<MediaAttributes>) {
+        let url = arena.alloc_str(&self.media_url(url, attributes));
+        let mut attributes = Box::new(attributes.clone());
+        attributes.url_references.clear();
+        (url, attributes)
     }
 
     /// Generates HTML by re-traversing the tree and maintaining scope.
     /// 
     /// During this pass, variables are populated with their values and
-    /// substituted into the HTML output.
+    /// substituted into the HTML output, which is written to `out` piece by piece.
     // Generate HTML by re-traversing the tree and maintaining scope
-    fn generate_html_with_traversal(&mut self, node: &ASTNode) -> String {
+    fn write_html<W: Write>(&mut self, node: &ASTNode, out: &mut W) -> io::Result<()> {
         match node {
+            ASTNode::Program { children } if self.options.target == Target::Slides => {
+                // each top-level section is a slide, loose content in between gets its own
+                let mut slides = Vec::new();
+                let mut loose = Vec::new();
+                for child in self.included(children) {
+                    if matches!(
+                        child,
+                        ASTNode::HeadSection { .. } | ASTNode::ParagrafSection { .. } | ASTNode::ListSection { .. }
+                    ) {
+                        push_slide(&mut slides, std::mem::take(&mut loose));
+                        let mut slide = Vec::new();
+                        self.write_html(child, &mut slide)?;
+                        slides.push(into_string(slide));
+                    } else {
+                        self.write_html(child, &mut loose)?;
+                    }
+                }
+                push_slide(&mut slides, loose);
+
+                // the first title was written with the slides, references filled in
+                let meta = meta::document_meta(node);
+                let mut head = self.head_meta_tags(&meta);
+                if self.uses_embeds {
+                    let nonce = integrity::nonce_attribute(self.options.csp_nonce.as_deref());
+                    head.push_str(&format!("<style{}>\n{}</style>\n", nonce, media::RESPONSIVE_EMBED_CSS));
+                }
+                let title = meta
+                    .get("title")
+                    .or(self.first_title.as_deref())
+                    .unwrap_or("LOLCODE Markdown")
+                    .to_string();
+                // the first title is HTML already, but a META title is the document's text
+                let title = match meta.get("title") {
+                    Some(_) => self.html_text(&title).into_owned(),
+                    None => title,
+                };
+                out.write_all(slides::reveal_document(&title, &head, &slides, &self.options).as_bytes())
+            }
+
             ASTNode::Program { children } => {
-                let mut body_content = String::new();
-                for child in children {
-                    body_content.push_str(&self.generate_html_with_traversal(child));
+                // the body is written first, so the head knows what is in it
+                let mut body = Vec::with_capacity(self.source_len * 2);
+                for child in children.iter() {
+                    self.write_html(child, &mut body)?;
                 }
-                
-                format!(
-                    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"UTF-8\">\n<title>LOLCODE Markdown</title>\n</head>\n<body>\n{}</body>\n</html>",
-                    body_content
-                )
+
+                let version = self.options.html_version();
+                // the charset attribute of <meta> is new in HTML5
+                let charset = if version.is_legacy() {
+                    "http-equiv=\"Content-Type\" content=\"text/html; charset=UTF-8\""
+                } else {
+                    "charset=\"UTF-8\""
+                };
+                write!(
+                    out,
+                    "{}\n{}\n<head>\n<meta {}{}\n<title>LOLCODE Markdown</title>\n",
+                    version.declaration(),
+                    self.options.html_start_tag(),
+                    charset,
+                    self.options.void_end()
+                )?;
+                out.write_all(self.head_meta_tags(&meta::document_meta(node)).as_bytes())?;
+
+                // only pages with embeds get the wrapper styles; the head is written
+                // before the body, so look for them up front. HTML 4 has no wrappers
+                let nonce = integrity::nonce_attribute(self.options.csp_nonce.as_deref());
+                let style_type = if version.is_legacy() { " type=\"text/css\"" } else { "" };
+                if media::has_embeds(node) && !version.is_legacy() {
+                    write!(out, "<style{}{}>\n{}</style>\n", style_type, nonce, media::RESPONSIVE_EMBED_CSS)?;
+                }
+                if self.options.target == Target::Pdf {
+                    write!(out, "<style{}{}>\n{}</style>\n", style_type, nonce, pdf::PRINT_CSS)?;
+                }
+
+                out.write_all(self.options.snippet(self.options.head_snippet.as_deref()).as_bytes())?;
+                out.write_all(b"</head>\n<body>\n")?;
+                out.write_all(&body)?;
+                out.write_all(self.options.snippet(self.options.body_snippet.as_deref()).as_bytes())?;
+                out.write_all(b"</body>\n</html>")
             }
 
             ASTNode::HeadSection { children } => {
-                let mut content = String::new();
-                for child in children {
-                    content.push_str(&self.generate_html_with_traversal(child));
+                for child in children.iter() {
+                    self.write_html(child, out)?;
+                }
+                Ok(())
+            }
+
+            ASTNode::IfDefined { flag, children } => {
+                if self.is_defined(flag) {
+                    for child in children.iter() {
+                        self.write_html(child, out)?;
+                    }
                 }
-                content
+                Ok(())
             }
 
             ASTNode::ParagrafSection { children } => {
+                self.write_source_position(node, out)?;
                 self.enter_scope();
                 
-                let mut content = String::new();
+                // a <p> can't hold another one or a list: nested sections close it,
+                // and the content after them opens a new one
+                let children = self.included(children);
+                let line = self.line_attribute(node);
+                let mut open = children.is_empty();
+                if open {
+                    writeln!(out, "<p{}>", line)?;
+                }
                 for child in children {
-                    content.push_str(&self.generate_html_with_traversal(child));
+                    let block = matches!(child, ASTNode::ParagrafSection { .. } | ASTNode::ListSection { .. });
+                    if block && open {
+                        out.write_all(b"</p>\n")?;
+                    } else if !block && !open {
+                        writeln!(out, "<p{}>", line)?;
+                    }
+                    open = !block;
+                    if let ASTNode::ParagrafSection { .. } = child {
+                        // HTML 4 has no <section>
+                        let (open, close) = if self.options.html_version().is_legacy() {
+                            ("<div class=\"section\">\n", "</div>\n")
+                        } else {
+                            ("<section>\n", "</section>\n")
+                        };
+                        out.write_all(open.as_bytes())?;
+                        self.write_html(child, out)?;
+                        out.write_all(close.as_bytes())?;
+                    } else {
+                        self.write_html(child, out)?;
+                    }
                 }
                 
                 self.exit_scope();
                 
-                format!("<p>\n{}</p>\n", content)
+                if open {
+                    out.write_all(b"</p>\n")?;
+                }
+                Ok(())
             }
 
             ASTNode::ListSection { children } => {
+                self.write_source_position(node, out)?;
                 self.enter_scope();
                 
-                let mut items = String::new();
-                for child in children {
-                    items.push_str(&self.generate_html_with_traversal(child));
+                writeln!(out, "<ul{}>", self.line_attribute(node))?;
+                for child in children.iter() {
+                    self.write_html(child, out)?;
                 }
                 
                 self.exit_scope();
                 
-                format!("<ul>\n{}</ul>\n", items)
+                out.write_all(b"</ul>\n")
             }
 
             ASTNode::Title { content } => {
-                format!("<h1>{}</h1>\n", content)
+                self.write_source_position(node, out)?;
+                let title = self.title_text(content);
+                writeln!(out, "<h1{}>{}</h1>", self.line_attribute(node), title)?;
+                if self.first_title.is_none() {
+                    self.first_title = Some(title);
+                    self.first_title_text = Some(self.plain_title_text(content));
+                }
+                Ok(())
             }
 
             ASTNode::Text { content } => {
-                format!("{} ", content)
+                out.write_all(self.html_text(content).as_bytes())?;
+                out.write_all(b" ")
             }
 
             ASTNode::Bold { content } => {
-                let mut inner = String::new();
-                for child in content {
-                    inner.push_str(&self.generate_html_with_traversal(child));
+                self.write_source_position(node, out)?;
+                write!(out, "<b{}>", self.line_attribute(node))?;
+                for child in content.iter() {
+                    self.write_html(child, out)?;
                 }
-                format!("<b>{}</b>", inner)
+                out.write_all(b"</b>")
             }
 
             ASTNode::Italics { content } => {
-                let mut inner = String::new();
-                for child in content {
-                    inner.push_str(&self.generate_html_with_traversal(child));
+                self.write_source_position(node, out)?;
+                write!(out, "<i{}>", self.line_attribute(node))?;
+                for child in content.iter() {
+                    self.write_html(child, out)?;
                 }
-                format!("<i>{}</i>", inner)
+                out.write_all(b"</i>")
             }
 
             ASTNode::Item { content } => {
-                let mut item_html = String::new();
-                for child in content {
-                    item_html.push_str(&self.generate_html_with_traversal(child));
+                self.write_source_position(node, out)?;
+                write!(out, "<li{}>", self.line_attribute(node))?;
+                for child in content.iter() {
+                    self.write_html(child, out)?;
                 }
-                format!("<li>{}</li>\n", item_html)
+                out.write_all(b"</li>\n")
             }
 
             ASTNode::Newline => {
-                "<br>\n".to_string()
+                writeln!(out, "<br{}", self.options.void_end())
             }
 
-            ASTNode::Sound { url } => {
-                format!("<audio controls src=\"{}\"></audio>\n", url)
+            ASTNode::Sound { url, attributes } => {
+                self.write_source_position(node, out)?;
+                let url = self.html_media_url(url, attributes);
+                if self.options.html_version().is_legacy() {
+                    return self.write_media_object(node, &url, out);
+                }
+                write!(out, "<audio{}{} src=\"{}\"", self.line_attribute(node), self.options.boolean_attribute("controls"), url)?;
+                if attributes.looping {
+                    out.write_all(self.options.boolean_attribute("loop").as_bytes())?;
+                }
+                if let Some(preload) = &attributes.preload {
+                    write!(out, " preload=\"{}\"", preload)?;
+                } else if attributes.lazy || self.options.lazy_media {
+                    out.write_all(b" preload=\"none\"")?;
+                }
+                out.write_all(b"></audio>\n")
+            }
+
+            ASTNode::Video { url, attributes } => {
+                self.write_source_position(node, out)?;
+                self.uses_embeds = true;
+                let url = self.html_media_url(url, attributes);
+                if self.options.html_version().is_legacy() {
+                    let embed = media::detect_video_host(&url)
+                        .map(|(host, id)| media::embed_url(host, &self.html_attribute(&id), attributes));
+                    return self.write_media_object(node, embed.as_deref().unwrap_or(&url), out);
+                }
+
+                // hosted players can't be played from a <video> tag
+                if let Some((host, id)) = media::detect_video_host(&url) {
+                    let lazy = if attributes.lazy || self.options.lazy_media {
+                        " loading=\"lazy\""
+                    } else {
+                        ""
+                    };
+                    // the embed URL comes escaped, only the id is from the document
+                    let embed = media::embed_url(host, &self.html_attribute(&id), attributes);
+                    return writeln!(
+                        out,
+                        "<div class=\"lol-embed\"{}><iframe src=\"{}\" allow=\"autoplay; fullscreen; picture-in-picture\"{}{}></iframe></div>",
+                        self.line_attribute(node),
+                        embed,
+                        self.options.boolean_attribute("allowfullscreen"),
+                        lazy
+                    );
+                }
+
+                write!(
+                    out,
+                    "<div class=\"lol-embed\"{}><video{} src=\"{}\"",
+                    self.line_attribute(node),
+                    self.options.boolean_attribute("controls"),
+                    url
+                )?;
+                if let Some(poster) = &attributes.poster {
+                    write!(out, " poster=\"{}\"", self.html_url(poster))?;
+                }
+                if attributes.autoplay {
+                    out.write_all(self.options.boolean_attribute("autoplay").as_bytes())?;
+                }
+                if attributes.looping {
+                    out.write_all(self.options.boolean_attribute("loop").as_bytes())?;
+                }
+                if attributes.muted {
+                    out.write_all(self.options.boolean_attribute("muted").as_bytes())?;
+                }
+                if attributes.lazy || self.options.lazy_media {
+                    out.write_all(b" preload=\"none\"")?;
+                }
+                out.write_all(b">")?;
+                // captions go inside the video as <track> children, the first one on by default
+                for (i, track) in attributes.captions.iter().enumerate() {
+                    write!(
+                        out,
+                        "<track kind=\"captions\" src=\"{}\" srclang=\"{}\" label=\"{}\"{}{}",
+                        self.html_url(&track.src),
+                        self.html_attribute(&track.lang),
+                        self.html_attribute(track.label.as_deref().unwrap_or(&track.lang)),
+                        if i == 0 { self.options.boolean_attribute("default") } else { String::new() },
+                        self.options.void_end()
+                    )?;
+                }
+                out.write_all(b"</video></div>\n")
+            }
+
+            ASTNode::Image { url, attributes } => {
+                self.write_source_position(node, out)?;
+                if self.first_image.is_none() {
+                    self.first_image = Some(self.media_url(url, attributes));
+                }
+                let url = self.html_media_url(url, attributes);
+                write!(out, "<img{} src=\"{}\"", self.line_attribute(node), url)?;
+                let legacy = self.options.html_version().is_legacy();
+                if let Some(alt) = &attributes.alt {
+                    write!(out, " alt=\"{}\"", self.html_attribute(alt))?;
+                } else if legacy {
+                    // HTML 4 requires alt on every image
+                    out.write_all(b" alt=\"\"")?;
+                }
+                // explicit dimensions let the browser reserve space before the image loads
+                if let Some(width) = attributes.width {
+                    write!(out, " width=\"{}\"", width)?;
+                }
+                if let Some(height) = attributes.height {
+                    write!(out, " height=\"{}\"", height)?;
+                }
+                if !attributes.srcset.is_empty() && !legacy {
+                    let srcset: Vec<_> = attributes.srcset.iter().map(|source| self.html_url(source)).collect();
+                    write!(out, " srcset=\"{}\"", srcset.join(", "))?;
+                }
+                if (attributes.lazy || self.options.lazy_media) && !legacy {
+                    out.write_all(b" loading=\"lazy\"")?;
+                }
+                writeln!(out, "{}", self.options.void_end())
             }
 
-            ASTNode::Video { url } => {
-                format!("<video controls src=\"{}\"></video>\n", url)
+            // metadata isn't part of the page body
+            ASTNode::MetaSection { .. } => Ok(()),
+
+            ASTNode::Link { target, text } => {
+                self.write_source_position(node, out)?;
+                let href = self.page_links.get(*target).map(String::as_str).unwrap_or(target);
+                write!(out, "<a{} href=\"{}\">{}</a> ", self.line_attribute(node), self.html_url(href), self.html_text(text))
             }
 
             ASTNode::VariableDeclaration { name } => {
-                self.current_assignment = Some(name.clone());
-                self.declare_variable_codegen(name.clone());
-                String::new()
+                self.current_assignment = Some(*name);
+                self.declare_variable_codegen(*name);
+                Ok(())
             }
             
-            ASTNode::VariableAssignment { value, .. } => {
-                if let Some(var_name) = self.current_assignment.clone() {
-                    self.assign_variable(&var_name, value.clone());
-                    self.current_assignment = None;
+            ASTNode::VariableAssignment { name, value } => {
+                if let Some(var_name) = name.or(self.current_assignment) {
+                    self.assign_variable(var_name, value.to_string());
                 }
-                String::new()
+                Ok(())
             }
             
             ASTNode::VariableReference { name } => {
-                match self.lookup_variable(name) {
-                    Some(Some(value)) => value,
-                    _ => format!("[undefined: {}]", name)
-                }
+                out.write_all(self.html_value(*name).as_bytes())
             }
         }
     }
 
-    /// Writes HTML content to an output file.
-    // Write HTML to output file
-    fn write_html_file(&self, html: &str, input_filename: &str) -> String {
-        use std::fs;
-        use std::path::Path;
-        
-        // Create output filename by replacing .lol with .html
-        let path = Path::new(input_filename);
-        let output_filename = path.with_extension("html");
+    // the <meta name> tags for the description, keywords and author from META,
+    // with social cards on the Open Graph and Twitter card tags, and with a base
+    // URL the page's canonical URL
+    fn head_meta_tags(&self, meta: &DocumentMeta) -> String {
+        let mut tags = String::new();
+        if let (Some(base), Some(href)) = (&self.options.base_url, &self.page_href) {
+            tags.push_str(&format!(
+                "<link rel=\"canonical\" href=\"{}\"{}\n",
+                xml::escape_xml(&site::absolute_url(base, "", href)),
+                self.options.void_end()
+            ));
+        }
+        let mut push = |attribute: &str, name: &str, content: &str| {
+            tags.push_str(&format!(
+                "<meta {}=\"{}\" content=\"{}\"{}\n",
+                attribute,
+                name,
+                xml::escape_xml(content),
+                self.options.void_end()
+            ));
+        };
+        for (name, content) in meta.head_entries() {
+            push("name", name, &content);
+        }
+        if !self.options.social_cards {
+            return tags;
+        }
+
+        // a META image wins over the first one on the page; --paranoid still
+        // drops script and data URLs
+        let image = meta.get("image").map(str::to_string).or_else(|| self.first_image.clone());
+        let image = image.map(|image| self.resolved_url(&image).into_owned());
+        let image = image.filter(|image| !self.options.paranoid || !sanitize::paranoid_url(image).is_empty());
+        // the body is written by now, so the first title has its references'
+        // values, which the tree's doesn't
+        let title = meta.get("title").or(self.first_title_text.as_deref());
+        let description = meta.get("description");
+        push("property", "og:type", "website");
+        for (name, content) in [("title", title), ("description", description), ("image", image.as_deref())] {
+            if let Some(content) = content {
+                push("property", &format!("og:{}", name), content);
+            }
+        }
+        let card = if image.is_some() { "summary_large_image" } else { "summary" };
+        push("name", "twitter:card", card);
+        for (name, content) in [("title", title), ("description", description), ("image", image.as_deref())] {
+            if let Some(content) = content {
+                push("name", &format!("twitter:{}", name), content);
+            }
+        }
+        tags
+    }
+
+    // sound and video for HTML 4, which has no <audio>, <video> or <iframe>:
+    // an <object> the browser plays if it can, with a link to the media inside
+    fn write_media_object<W: Write>(&self, node: &ASTNode, url: &str, out: &mut W) -> io::Result<()> {
+        writeln!(
+            out,
+            "<object{} data=\"{}\"><a href=\"{}\">{}</a></object>",
+            self.line_attribute(node),
+            url,
+            url,
+            url
+        )
+    }
+
+    // with source maps on, a comment saying where in the .lol file an element
+    // came from goes in front of it
+    fn write_source_position<W: Write>(&self, node: &ASTNode, out: &mut W) -> io::Result<()> {
+        if !self.options.source_map {
+            return Ok(());
+        }
+        match self.element_positions.get(&(node as *const ASTNode as usize)) {
+            Some((line, col)) => write!(out, "<!-- lol {}:{} -->", line, col),
+            None => Ok(()),
+        }
+    }
+
+    // ` data-lol-line="N"` for an element's tag when debug annotations are on
+    fn line_attribute(&self, node: &ASTNode) -> LineAttribute {
+        if !self.options.debug_annotations {
+            return LineAttribute(None);
+        }
+        LineAttribute(self.element_positions.get(&(node as *const ASTNode as usize)).map(|(line, _)| *line))
+    }
+
+    /// Writes generated content to an output file next to the input.
+    // Write output file, named after the input the way `options.naming` says
+    fn write_output_file(&self, content: &str, input_filename: &str, extension: &str) -> String {
+        let output_filename = self.output_path(input_filename);
         
-        // Write HTML to file
-        fs::write(&output_filename, html).unwrap_or_else(|e| {
-            eprintln!("Failed to write HTML file: {}", e);
-            exit(1);
+        // Write output to file, unless it already holds exactly this
+        let changed = output::write_if_changed(&output_filename, content.as_bytes()).unwrap_or_else(|e| {
+            eprintln!("Failed to write {} file: {}", extension, e);
+            exit(EXIT_IO);
         });
+        if !changed {
+            log::debug!("Unchanged, not rewritten: {}", output_filename.display());
+        }
         
         output_filename.to_string_lossy().to_string()
     }
 
+    /// Where the output for `input_filename` goes, once an output pass has
+    /// seen the document.
+    pub fn output_path(&self, input_filename: &str) -> PathBuf {
+        self.options
+            .naming
+            .output_path(Path::new(input_filename), self.options.target.extension(), self.lang.as_deref())
+    }
+
     /// Opens the HTML file in the default browser.
     // Open HTML file in browser
     fn open_in_browser(&self, filename: &str) {
@@ -478,4 +1887,51 @@ impl LolcodeSemanticAnalyzer {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// "line 3, col 5: " in front of a message, or nothing without a position
+fn position_prefix(position: Option<&(usize, usize)>) -> String {
+    match position {
+        Some((line, col)) => format!("line {}, col {}: ", line, col),
+        None => String::new(),
+    }
+}
+
+// declaration position -> the value the variable ends its scope with
+fn collect_final_values(scope: &ScopeInfo, values: &mut HashMap<(usize, usize), Option<String>>) {
+    for variable in &scope.variables {
+        if let Some(declared_at) = variable.declared_at {
+            values.insert(declared_at, variable.value.clone());
+        }
+    }
+    for child in &scope.children {
+        collect_final_values(child, values);
+    }
+}
+
+// add the loose content collected between sections as a slide of its own
+fn push_slide(slides: &mut Vec<String>, loose: Vec<u8>) {
+    let loose = into_string(loose);
+    if !loose.trim().is_empty() {
+        slides.push(loose);
+    }
+}
+
+// generated HTML back as a string, without copying it when it is valid UTF-8
+// (which it always is, every backend writes strings)
+fn into_string(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+// the `data-lol-line` attribute of an element, written straight into its tag
+// instead of being built as a string first
+struct LineAttribute(Option<usize>);
+
+impl fmt::Display for LineAttribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(line) => write!(f, " data-lol-line=\"{}\"", line),
+            None => Ok(()),
+        }
+    }
+}
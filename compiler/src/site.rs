@@ -0,0 +1,418 @@
+//! Multi-page projects.
+//!
+//! When the compiler is given several `.lol` files or a directory, every source
+//! becomes a page with a logical name: its path relative to the project root,
+//! without the `.lol` extension and with `/` separators (e.g. `guides/intro`).
+//! `#GIMMEH LINKZ <name> #MKAY` refers to pages by that name and is resolved
+//! to the generated file at build time.
+
+use crate::arena::AstArena;
+use crate::cache::{self, BuildCache};
+use crate::glob;
+use crate::ignore::{self, IgnoreFile};
+use crate::meta;
+use crate::parser::{LolcodeParser, Parser};
+use crate::target::Target;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Default limit on the size of one source file, 64 MB.
+///
+/// Compiling takes roughly seven times the size of the source in memory
+/// (a 100 MB page built in about 6 s with a 670 MB peak), and pages build in
+/// parallel, so this keeps a stray huge file from exhausting memory.
+pub const DEFAULT_MAX_INPUT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// One source file in the project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// Logical name used by LINKZ
+    pub name: String,
+    /// Path of the `.lol` source
+    pub source: PathBuf,
+}
+
+impl Page {
+    /// Path of the generated HTML file.
+    pub fn output(&self) -> PathBuf {
+        self.source.with_extension("html")
+    }
+}
+
+/// How generated files are named after their source: `{stem}.{ext}`, with the
+/// target's extension, unless set otherwise.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputNaming {
+    /// Extension to use instead of the target's, without the dot (`htm`, `xhtml`)
+    pub extension: Option<String>,
+    /// File name template: `{stem}` is the source's file name without `.lol`,
+    /// `{ext}` the extension and `{lang}` the page's META `lang`. A page
+    /// without a `lang` leaves out `{lang}` and a `.` next to it, so
+    /// `{stem}.{lang}.{ext}` gives `index.html` for it.
+    pub template: Option<String>,
+}
+
+impl OutputNaming {
+    /// True when names depend on the language of the page.
+    pub fn uses_lang(&self) -> bool {
+        self.template.as_deref().is_some_and(|template| template.contains("{lang}"))
+    }
+
+    /// The file name of the output for a source whose name without `.lol` is
+    /// `stem`.
+    pub fn file_name(&self, stem: &str, target_extension: &str, lang: Option<&str>) -> String {
+        let extension = self.extension.as_deref().unwrap_or(target_extension);
+        let Some(template) = &self.template else {
+            return format!("{}.{}", stem, extension);
+        };
+        let template = match lang {
+            Some(lang) => template.replace("{lang}", lang),
+            None => [".{lang}", "{lang}.", "{lang}"]
+                .iter()
+                .find(|placeholder| template.contains(*placeholder))
+                .map_or_else(|| template.clone(), |placeholder| template.replace(placeholder, "")),
+        };
+        template.replace("{stem}", stem).replace("{ext}", extension)
+    }
+
+    /// Path of the output next to `source`.
+    pub fn output_path(&self, source: &Path, target_extension: &str, lang: Option<&str>) -> PathBuf {
+        let stem = source.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+        source.with_file_name(self.file_name(&stem, target_extension, lang))
+    }
+
+    /// Path of a page's output relative to the project root, with `/`
+    /// separators, e.g. `guides/intro.html`.
+    pub fn href(&self, name: &str, target_extension: &str, lang: Option<&str>) -> String {
+        let (dirs, stem) = match name.rsplit_once('/') {
+            Some((dirs, stem)) => (format!("{}/", dirs), stem),
+            None => (String::new(), name),
+        };
+        format!("{}{}", dirs, self.file_name(stem, target_extension, lang))
+    }
+}
+
+/// Reads a source file, refusing files larger than `max_size` bytes before
+/// any of it is loaded.
+pub fn read_source(path: &Path, max_size: u64) -> Result<String, String> {
+    let too_large = |size: u64| {
+        format!(
+            "Input too large: '{}' is {:.1} MB, the limit is {:.1} MB",
+            path.display(),
+            size as f64 / (1024.0 * 1024.0),
+            max_size as f64 / (1024.0 * 1024.0)
+        )
+    };
+    let failed = |e: std::io::Error| format!("Failed to read '{}': {}", path.display(), e);
+
+    let file = File::open(path).map_err(failed)?;
+    let size = file.metadata().map_err(failed)?.len();
+    if size > max_size {
+        return Err(too_large(size));
+    }
+
+    //the size can change under us (or not be known for pipes), so never read past the limit
+    let mut source = String::new();
+    file.take(max_size + 1).read_to_string(&mut source).map_err(failed)?;
+    if source.len() as u64 > max_size {
+        return Err(too_large(source.len() as u64));
+    }
+    Ok(source)
+}
+
+/// Collects the pages for the given command line inputs.
+///
+/// A directory contributes every `.lol` file below it that its `.lolignore`
+/// files don't skip (see `ignore`), named relative to that directory. A pattern such as `docs/**/*.lol` contributes the `.lol` files it
+/// matches (see `glob`), named relative to the directory before its first
+/// wildcard; a file that exists under the pattern's exact name is taken as it
+/// is. A single file is named relative to its own directory.
+pub fn collect_pages(inputs: &[String]) -> Result<Vec<Page>, String> {
+    let mut pages: Vec<Page> = Vec::new();
+
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut sources = Vec::new();
+            find_sources(path, &mut Vec::new(), &mut sources)
+                .map_err(|e| format!("Failed to read directory '{}': {}", input, e))?;
+            for source in sources {
+                let name = logical_name(path, &source);
+                pages.push(Page { name, source });
+            }
+        } else if glob::is_pattern(input) && !path.exists() {
+            let base = glob::base_dir(input);
+            let mut sources = glob::expand(input).map_err(|e| format!("Failed to expand '{}': {}", input, e))?;
+            sources.retain(|source| source.extension().and_then(|s| s.to_str()) == Some("lol"));
+            if sources.is_empty() {
+                return Err(format!("no .lol files match '{}'", input));
+            }
+            for source in sources {
+                let name = logical_name(&base, &source);
+                pages.push(Page { name, source });
+            }
+        } else {
+            if path.extension().and_then(|s| s.to_str()) != Some("lol") {
+                return Err(format!("input file '{}' must have a .lol extension", input));
+            }
+            let root = path.parent().unwrap_or(Path::new(""));
+            let name = logical_name(root, path);
+            pages.push(Page {
+                name,
+                source: path.to_path_buf(),
+            });
+        }
+    }
+
+    // two pages with the same name would make links ambiguous
+    let mut seen: HashMap<&str, &Path> = HashMap::new();
+    for page in &pages {
+        if let Some(other) = seen.insert(&page.name, &page.source) {
+            return Err(format!(
+                "'{}' and '{}' both define the page '{}'",
+                other.display(),
+                page.source.display(),
+                page.name
+            ));
+        }
+    }
+
+    Ok(pages)
+}
+
+/// The project root: the first input if it is a directory, the directory
+/// before the first wildcard of a pattern, otherwise the directory of the first
+/// input file. Project-wide files such as the feed go here.
+pub fn project_root(inputs: &[String]) -> PathBuf {
+    let input = inputs.first().map(String::as_str).unwrap_or(".");
+    let first = Path::new(input);
+    if glob::is_pattern(input) && !first.exists() {
+        glob::base_dir(input)
+    } else if first.is_dir() {
+        first.to_path_buf()
+    } else {
+        first.parent().unwrap_or(Path::new("")).to_path_buf()
+    }
+}
+
+/// Recursively finds `.lol` files below a directory, in sorted order, leaving
+/// out what the ignore files of the directories above (`ignores`) and its own
+/// skip.
+fn find_sources(dir: &Path, ignores: &mut Vec<IgnoreFile>, sources: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let own = IgnoreFile::load(dir)?;
+    let has_own = own.is_some();
+    ignores.extend(own);
+
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        let is_dir = path.is_dir();
+        if ignore::is_ignored(ignores, &path, is_dir) {
+            log::debug!("Ignored {}", path.display());
+            continue;
+        }
+        if is_dir {
+            find_sources(&path, ignores, sources)?;
+        } else if path.extension().and_then(|s| s.to_str()) == Some("lol") {
+            sources.push(path);
+        }
+    }
+
+    if has_own {
+        ignores.pop();
+    }
+    Ok(())
+}
+
+/// Builds the logical name of `source` relative to `root`.
+fn logical_name(root: &Path, source: &Path) -> String {
+    let relative = source.strip_prefix(root).unwrap_or(source).with_extension("");
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Returns true for link targets that point outside the project.
+pub fn is_external(target: &str) -> bool {
+    target.contains("://") || target.starts_with("mailto:")
+}
+
+/// Returns true for URLs relative to the page they are on: no scheme, and not
+/// from the root of the site (`/`) or a fragment or query of the page itself.
+pub fn is_relative_url(url: &str) -> bool {
+    let scheme_len = url.find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))).unwrap_or(url.len());
+    let has_scheme = scheme_len > 0 && url.starts_with(|c: char| c.is_ascii_alphabetic()) && url[scheme_len..].starts_with(':');
+    !url.is_empty() && !has_scheme && !url.starts_with(['/', '#', '?'])
+}
+
+/// `url`, relative to the page whose output is at `page_href` (relative to the
+/// site root), as an absolute URL below `base`, which ends with `/`. `.` and
+/// `..` are taken out; `..` never goes above `base`.
+pub fn absolute_url(base: &str, page_href: &str, url: &str) -> String {
+    let (path, rest) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+    let mut segments: Vec<&str> = match page_href.rsplit_once('/') {
+        Some((dir, _)) => dir.split('/').filter(|segment| !segment.is_empty()).collect(),
+        None => Vec::new(),
+    };
+    let parts: Vec<&str> = path.split('/').collect();
+    for (i, part) in parts.iter().enumerate() {
+        match *part {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(part),
+        }
+        // a path ending in `.` or `..` names a directory
+        if i == parts.len() - 1 && matches!(*part, "." | "..") {
+            segments.push("");
+        }
+    }
+    format!("{}{}{}", base, segments.join("/"), rest)
+}
+
+/// Builds the LINKZ table for one page: every page name mapped to the href of
+/// its generated file, relative to where the output of the page doing the
+/// linking is written. The hrefs come from the output paths rather than the
+/// page names, which are relative to each page's own input, so pages from
+/// different inputs (`a/x.lol b/y.lol`) link to each other too. `langs` holds
+/// the META `lang` of the pages that have one, for names that use it.
+pub fn page_links(
+    pages: &[Page],
+    from: &Page,
+    naming: &OutputNaming,
+    extension: &str,
+    langs: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let output = |page: &Page| naming.output_path(&page.source, extension, langs.get(&page.name).map(String::as_str));
+    let from_output = output(from);
+    pages
+        .iter()
+        .map(|page| (page.name.clone(), relative_href(&from_output, &output(page))))
+        .collect()
+}
+
+/// Extensions of the files a build can write next to a source besides its
+/// output: the binary AST and what `--emit` adds.
+const ARTIFACT_EXTENSIONS: [&str; 4] = ["lolast", "tokens", "ast.json", "symbols.json"];
+
+/// The files a build of these pages wrote that still exist. A project build
+/// records everything it writes in the manifest at `root`, so when `cache` is
+/// that manifest and these are the project's pages its files are the ones
+/// listed there that are below `root` and have an extension a build writes,
+/// and the manifest itself. Otherwise only each page's output
+/// for `target`, named the way `naming` names it, is taken (and the PDF's
+/// HTML), so a file of another type that happens to share a page's name is
+/// never mistaken for an output.
+pub fn generated_files(
+    pages: &[Page],
+    root: &Path,
+    naming: &OutputNaming,
+    langs: &HashMap<String, String>,
+    target: Target,
+    cache: Option<&BuildCache>,
+) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    match cache {
+        // only project builds write a manifest
+        Some(cache) if pages.len() > 1 => {
+            // the manifest is just a file in the tree; don't trust it further
+            // than a build could have written
+            let trusted = cache.outputs.iter().filter(|output| {
+                output.strip_prefix(root).is_ok_and(cache::stays_inside) && is_build_output(output, naming)
+            });
+            files.extend(trusted.cloned());
+            files.push(root.join(cache::CACHE_FILE));
+        }
+        // terminal output is never a file
+        _ if target == Target::Ansi => {}
+        _ => {
+            for page in pages {
+                let lang = langs.get(&page.name).map(String::as_str);
+                let output = naming.output_path(&page.source, target.extension(), lang);
+                if target == Target::Pdf {
+                    files.push(output.with_extension("pdf"));
+                }
+                files.push(output);
+            }
+        }
+    }
+    files.sort();
+    files.dedup();
+    files.retain(|file| file.is_file());
+    files
+}
+
+// whether a file has an extension some build writes: a target's that gets a
+// file, `naming`'s own, the PDF's or an artifact's
+fn is_build_output(path: &Path, naming: &OutputNaming) -> bool {
+    let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+        return false;
+    };
+    let targets = Target::ALL.into_iter().filter(|target| *target != Target::Ansi).map(|target| target.extension());
+    let mut extensions = targets.chain(naming.extension.as_deref()).chain(["pdf"]).chain(ARTIFACT_EXTENSIONS);
+    extensions.any(|extension| name.len() > extension.len() + 1 && name.ends_with(&format!(".{}", extension)))
+}
+
+/// The META `lang` of every page that has one, for output names that use it.
+/// Pages that can't be read or parsed are left out; their build reports why.
+pub fn page_langs(pages: &[Page], max_size: u64) -> HashMap<String, String> {
+    let mut langs = HashMap::new();
+    for page in pages {
+        let Ok(source) = read_source(&page.source, max_size) else {
+            continue;
+        };
+        let arena = AstArena::new();
+        let mut parser = LolcodeParser::new(&source, &arena);
+        if parser.parse().is_err() {
+            continue;
+        }
+        if let Some(lang) = parser.parse_tree.and_then(|tree| meta::document_meta(tree).get("lang").map(str::to_string)) {
+            langs.insert(page.name.clone(), lang);
+        }
+    }
+    langs
+}
+
+/// Relative href from the output file `from` to the output file `to`:
+/// `site/guides/intro.html` -> `site/index.html` gives `../index.html`.
+fn relative_href(from: &Path, to: &Path) -> String {
+    let from = normalized(from);
+    let to = normalized(to);
+    let from_dirs = &from[..from.len().saturating_sub(1)];
+
+    // skip the directories both pages share
+    let common = from_dirs
+        .iter()
+        .zip(&to)
+        .take_while(|(a, b)| a == b)
+        .count()
+        .min(to.len().saturating_sub(1));
+
+    let mut href = "../".repeat(from_dirs.len() - common);
+    href.push_str(&to[common..].join("/"));
+    href
+}
+
+// the components of `path` from the file system root, `.` and `..` taken out
+fn normalized(path: &Path) -> Vec<String> {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut parts = Vec::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop();
+            }
+            other => parts.push(other.as_os_str().to_string_lossy().into_owned()),
+        }
+    }
+    parts
+}
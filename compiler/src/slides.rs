@@ -0,0 +1,113 @@
+//! Slide deck output.
+//!
+//! With `--target slides` every top-level HEAD, PARAGRAF and LIST section
+//! becomes one slide, and loose content between sections gets a slide of its
+//! own. The result is a single self-contained HTML file: the styles and the
+//! script that steps through the slides are written into it, so a deck works
+//! offline and loads nothing from anywhere else. The markup is the one
+//! reveal.js uses, and `--reveal-cdn` loads reveal.js from a CDN instead of
+//! the built-in player, for its themes and transitions.
+
+use crate::integrity;
+use crate::semantic::OutputOptions;
+
+/// reveal.js version loaded from the CDN.
+const REVEAL_VERSION: &str = "5.1.0";
+
+/// The script that starts reveal.js.
+pub(crate) const REVEAL_INIT: &str = "Reveal.initialize({ hash: true });";
+
+/// Where the reveal.js files are loaded from with `--reveal-cdn`.
+pub(crate) fn reveal_cdn() -> String {
+    format!("https://cdn.jsdelivr.net/npm/reveal.js@{}/dist", REVEAL_VERSION)
+}
+
+/// Styles of the built-in player: one slide on the screen at a time, centred
+/// the way reveal.js's white theme lays them out.
+const DECK_CSS: &str = "\
+html, body { margin: 0; height: 100%; overflow: hidden; background: #fff; color: #222; }
+body { font-family: \"Source Sans Pro\", Helvetica, sans-serif; font-size: 40px; }
+.reveal, .reveal .slides { width: 100%; height: 100%; }
+.reveal .slides > section { display: none; box-sizing: border-box; height: 100%; padding: 1em 2em; overflow: auto; text-align: center; }
+.reveal .slides > section.present { display: flex; flex-direction: column; justify-content: center; align-items: center; }
+.reveal h1 { font-size: 2.5em; margin: 0 0 0.5em; }
+.reveal ul { text-align: left; }
+.reveal img, .reveal video, .reveal iframe { max-width: 95%; max-height: 70vh; }
+";
+
+/// Script of the built-in player: the arrow keys, space, Page Up/Down,
+/// Home/End and clicks step through the slides, and the URL remembers the
+/// slide as `#/<n>` like reveal.js does, so a reload stays on it.
+pub(crate) const DECK_SCRIPT: &str = "\
+(function () {
+  var slides = document.querySelectorAll(\".reveal .slides > section\");
+  var current = 0;
+  function show(index) {
+    current = Math.max(0, Math.min(slides.length - 1, index));
+    for (var i = 0; i !== slides.length; i++) {
+      slides[i].className = i === current ? \"present\" : \"\";
+    }
+    history.replaceState(null, \"\", \"#/\" + current);
+  }
+  var steps = { ArrowRight: 1, ArrowDown: 1, PageDown: 1, \" \": 1, ArrowLeft: -1, ArrowUp: -1, PageUp: -1 };
+  document.addEventListener(\"keydown\", function (event) {
+    if (event.key === \"Home\") {
+      show(0);
+    } else if (event.key === \"End\") {
+      show(slides.length - 1);
+    } else if (steps[event.key]) {
+      show(current + steps[event.key]);
+    } else {
+      return;
+    }
+    event.preventDefault();
+  });
+  document.addEventListener(\"click\", function (event) {
+    if (!event.target.closest(\"a, audio, video, iframe\")) {
+      show(current + (event.clientX * 3 >= window.innerWidth ? 1 : -1));
+    }
+  });
+  show(parseInt(location.hash.slice(2), 10) || 0);
+})();
+";
+
+/// Wraps already generated slide bodies in a presentation, with `head` (meta
+/// tags and styles) at the end of its head. The styles and scripts carry the
+/// CSP nonce of `options` when there is one. With `reveal_cdn` the deck loads
+/// reveal.js instead of the built-in player, and with `subresource_integrity`
+/// those files are pinned by their hash (see `integrity`).
+pub fn reveal_document(title: &str, head: &str, slides: &[String], options: &OutputOptions) -> String {
+    let nonce = integrity::nonce_attribute(options.csp_nonce.as_deref());
+    let (html, end) = (options.html_start_tag(), options.void_end());
+    let (head_snippet, body_snippet) = (options.snippet(options.head_snippet.as_deref()), options.snippet(options.body_snippet.as_deref()));
+
+    let mut sections = String::with_capacity(slides.iter().map(|slide| slide.len() + 20).sum());
+    for slide in slides {
+        sections.push_str("<section>\n");
+        sections.push_str(slide);
+        sections.push_str("</section>\n");
+    }
+
+    let (styles, script) = if options.reveal_cdn {
+        let cdn = reveal_cdn();
+        let pinned = |file: &str| if options.subresource_integrity { integrity::integrity_attributes(&format!("{}/{}", cdn, file)) } else { String::new() };
+        let (reveal_css, theme_css, reveal_js) = (pinned("reveal.css"), pinned("theme/white.css"), pinned("reveal.js"));
+        (
+            format!(
+                "<link rel=\"stylesheet\" href=\"{cdn}/reveal.css\"{nonce}{reveal_css}{end}\n\
+<link rel=\"stylesheet\" href=\"{cdn}/theme/white.css\"{nonce}{theme_css}{end}\n"
+            ),
+            format!("<script src=\"{cdn}/reveal.js\"{nonce}{reveal_js}></script>\n<script{nonce}>{REVEAL_INIT}</script>\n"),
+        )
+    } else {
+        (format!("<style{nonce}>\n{DECK_CSS}</style>\n"), format!("<script{nonce}>{DECK_SCRIPT}</script>\n"))
+    };
+
+    format!(
+        "<!DOCTYPE html>\n{html}\n<head>\n<meta charset=\"UTF-8\"{end}\n\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\"{end}\n\
+<title>{title}</title>\n\
+{styles}{head}{head_snippet}</head>\n<body>\n<div class=\"reveal\">\n<div class=\"slides\">\n{sections}</div>\n</div>\n\
+{script}{body_snippet}</body>\n</html>"
+    )
+}
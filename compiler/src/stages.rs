@@ -0,0 +1,103 @@
+//! One compiler stage at a time.
+//!
+//! `Pipeline` runs every stage and delivers the output; these functions run a
+//! single one in memory so benchmarks and tools can measure or replace it. Each
+//! stage takes what the one before returned:
+//!
+//! ```text
+//! let tokens = stages::lex(source)?;
+//! let (tree, interner) = stages::parse(tokens, &arena)?;
+//! let mut analyzer = stages::analyze(tree, interner)?;
+//! let html = stages::codegen(tree, &mut analyzer);
+//! ```
+//!
+//! Nothing is printed, written or exited; errors in the source come back as a
+//! `CompileError`.
+
+use crate::arena::AstArena;
+use crate::cst::SyntaxNode;
+use crate::diagnostics::CompileError;
+use crate::intern::Interner;
+use crate::lexer::{LexicalAnalyzer, Lexer};
+use crate::parser::{ASTNode, LolcodeParser, Parser};
+use crate::semantic::LolcodeSemanticAnalyzer;
+use crate::token::{LosslessToken, Token, TokenKind};
+
+/// Lexes the whole source. The last token is always `Eof`.
+pub fn lex(source: &str) -> Result<Vec<Token>, CompileError> {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.get_next_token()?;
+        let done = token.kind == TokenKind::Eof;
+        tokens.push(token);
+        if done {
+            return Ok(tokens);
+        }
+    }
+}
+
+/// Lexes the whole source keeping whitespace and comments, see
+/// `Lexer::lossless`. The last token is always `Eof`.
+pub fn lex_lossless(source: &str) -> Result<Vec<LosslessToken<'_>>, CompileError> {
+    let mut lexer = Lexer::lossless(source);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_lossless()?;
+        let done = token.token.kind == TokenKind::Eof;
+        tokens.push(token);
+        if done {
+            return Ok(tokens);
+        }
+    }
+}
+
+/// Parses tokens from `lex` into a tree allocated in `arena`, with the default
+/// nesting limit. Returns the tree and the interner holding its variable names.
+pub fn parse<'t>(tokens: Vec<Token>, arena: &'t AstArena<'t>) -> Result<(&'t ASTNode<'t>, Interner), CompileError> {
+    let mut parser = LolcodeParser::from_tokens(tokens, arena);
+    parser.parse()?;
+    match parser.parse_tree {
+        Some(tree) => Ok((tree, parser.interner)),
+        // parse only succeeds once it built the tree
+        None => Err(CompileError::Syntax {
+            line: 1,
+            col: 1,
+            message: "No parse tree generated".to_string(),
+            labels: Vec::new(),
+        }),
+    }
+}
+
+/// Parses the source into its concrete syntax tree, see `cst`.
+pub fn cst(source: &str) -> Result<SyntaxNode<'_>, CompileError> {
+    let arena = AstArena::new();
+    let mut parser = LolcodeParser::with_cst(source, &arena);
+    parser.parse()?;
+    parser.cst.take().ok_or_else(|| CompileError::Syntax {
+        line: 1,
+        col: 1,
+        message: "No parse tree generated".to_string(),
+        labels: Vec::new(),
+    })
+}
+
+/// Runs semantic analysis on a parsed tree and returns the analyzer, ready
+/// for `codegen`.
+///
+/// The analyzer knows no other pages, so LINKZ to a page name is an error here;
+/// set `page_links` on a `LolcodeSemanticAnalyzer` and call `check_tree` to
+/// analyze pages of a project.
+pub fn analyze(tree: &ASTNode, interner: Interner) -> Result<LolcodeSemanticAnalyzer, CompileError> {
+    let mut analyzer = LolcodeSemanticAnalyzer::new();
+    analyzer.options.open_browser = false;
+    analyzer.interner = interner;
+    analyzer.check_tree(tree)?;
+    Ok(analyzer)
+}
+
+/// Generates the output for the analyzer's target from a tree that passed
+/// `analyze`. Can be called repeatedly with the same analyzer.
+pub fn codegen(tree: &ASTNode, analyzer: &mut LolcodeSemanticAnalyzer) -> String {
+    analyzer.generate_output(tree)
+}
@@ -0,0 +1,338 @@
+//! The symbol table of a checked document, for tools.
+//!
+//! `LolcodeSemanticAnalyzer::check_tree` leaves one in `symbols`: the global
+//! scope and every scope opened inside it, nested like the sections that open
+//! them, with the variables declared in each. Positions are (line, column) in
+//! the source; trees that didn't come from the document parser have none.
+//! `to_json` writes the table out for tools that aren't written in Rust,
+//! `symbol_at` finds the variable under a position, for editor hovers, and
+//! `references_at` and `find_references` list everywhere a variable is used.
+//! Uses belong to the declaration their scope resolves them to, so a variable
+//! hidden by another of the same name in a nested section is kept apart.
+//! `rename` builds on that to give the edits that rename one variable, and
+//! `apply_edits` makes them; `check_source` gets the table of a source without
+//! generating any output.
+
+use crate::arena::AstArena;
+use crate::diagnostics::CompileError;
+use crate::json;
+use crate::lexer;
+use crate::parser::{LolcodeParser, Parser};
+use crate::semantic::LolcodeSemanticAnalyzer;
+
+/// A variable declared in a scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableInfo {
+    /// The name as the declaration spells it
+    pub name: String,
+    /// The value it has when its scope ends, None if it never got one
+    pub value: Option<String>,
+    /// Where its `#I HAZ` is
+    pub declared_at: Option<(usize, usize)>,
+}
+
+/// A scope and the scopes opened inside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeInfo {
+    /// What opens the scope: "document" for the global scope, "PARAGRAF" or "LIST"
+    pub kind: &'static str,
+    /// The `#MAEK` of the section that opens it
+    pub start: Option<(usize, usize)>,
+    /// The `#OIC` of the section that opens it
+    pub end: Option<(usize, usize)>,
+    /// Variables declared in this scope, in source order
+    pub variables: Vec<VariableInfo>,
+    /// Scopes opened directly inside this one, in source order
+    pub children: Vec<ScopeInfo>,
+}
+
+impl ScopeInfo {
+    /// An empty scope.
+    pub fn new(kind: &'static str, start: Option<(usize, usize)>, end: Option<(usize, usize)>) -> Self {
+        Self { kind, start, end, variables: Vec::new(), children: Vec::new() }
+    }
+
+    fn write_json(&self, out: &mut String) {
+        out.push_str(&format!(
+            "{{\"kind\":{},\"start\":{},\"end\":{},\"variables\":[",
+            json::string(self.kind),
+            json_position(self.start),
+            json_position(self.end)
+        ));
+        for (i, variable) in self.variables.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&format!(
+                "{{\"name\":{},\"value\":{},\"declared_at\":{}}}",
+                json::string(&variable.name),
+                variable.value.as_deref().map_or_else(|| "null".to_string(), json::string),
+                json_position(variable.declared_at)
+            ));
+        }
+        out.push_str("],\"children\":[");
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            child.write_json(out);
+        }
+        out.push_str("]}");
+    }
+}
+
+/// How a variable is used at some place in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UseKind {
+    /// `#I HAZ <name>`
+    Declaration,
+    /// `#IT IZ [<name> R] <value> #MKAY`
+    Assignment,
+    /// `#LEMME SEE <name> #MKAY`
+    Reference,
+}
+
+/// A variable declaration, assignment or reference in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolInfo {
+    /// The variable's name as it is written there, or as it is declared for
+    /// an assignment without a name
+    pub name: String,
+    pub kind: UseKind,
+    /// Where the construct starts, and just past its end
+    pub span: ((usize, usize), (usize, usize)),
+    /// Where the name is written; None for an assignment without a name
+    pub name_at: Option<(usize, usize)>,
+    /// Where the variable is declared; None for defines and built-in variables
+    pub declared_at: Option<(usize, usize)>,
+    /// The value the variable has there: at a reference the last one assigned
+    /// before it, at an assignment the one it gives, at a declaration the one
+    /// it ends its scope with
+    pub value: Option<String>,
+}
+
+/// Every scope of a checked document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolTable {
+    /// The document's global scope, holding all the others
+    pub global: ScopeInfo,
+    /// Every declaration and reference with a known position, in document order
+    pub uses: Vec<SymbolInfo>,
+    /// Names that differ only in case are the same variable
+    pub case_insensitive: bool,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self { global: ScopeInfo::new("document", None, None), uses: Vec::new(), case_insensitive: false }
+    }
+}
+
+/// A replacement of the source text between two positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    /// First (line, column) replaced
+    pub start: (usize, usize),
+    /// Just past the last one replaced
+    pub end: (usize, usize),
+    /// What goes in its place
+    pub text: String,
+}
+
+impl SymbolTable {
+    /// The table as JSON: the global scope as an object with `kind`, `start`,
+    /// `end`, `variables` (each with `name`, `value` and `declared_at`) and
+    /// `children`, the same kind of object for every nested scope. Positions
+    /// are `[line, column]` or `null`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.global.write_json(&mut out);
+        out
+    }
+
+    /// The variable declared or referenced at a source position, with where it
+    /// is declared and the value it has there.
+    pub fn symbol_at(&self, line: usize, col: usize) -> Option<SymbolInfo> {
+        self.uses
+            .iter()
+            .find(|symbol| symbol.span.0 <= (line, col) && (line, col) < symbol.span.1)
+            .cloned()
+    }
+
+    /// Every use of the variable at a source position, its declaration included,
+    /// in document order. Empty when there is no variable there.
+    pub fn references_at(&self, line: usize, col: usize) -> Vec<SymbolInfo> {
+        match self.symbol_at(line, col) {
+            Some(symbol) => self.uses_of(symbol.declared_at, &symbol.name),
+            None => Vec::new(),
+        }
+    }
+
+    /// Every use of the variables called `name`: one list for each declaration
+    /// of that name, and one for a define or built-in variable used without
+    /// one, each in document order.
+    pub fn find_references(&self, name: &str) -> Vec<Vec<SymbolInfo>> {
+        let mut found: Vec<Vec<SymbolInfo>> = self
+            .uses
+            .iter()
+            .filter(|symbol| symbol.kind == UseKind::Declaration && self.same_name(&symbol.name, name))
+            .map(|declaration| self.uses_of(declaration.declared_at, name))
+            .collect();
+        let undeclared = self.uses_of(None, name);
+        if !undeclared.is_empty() {
+            found.push(undeclared);
+        }
+        found
+    }
+
+    // the uses that resolve to one declaration; without one, the uses of the
+    // define or built-in variable `name`
+    fn uses_of(&self, declared_at: Option<(usize, usize)>, name: &str) -> Vec<SymbolInfo> {
+        self.uses
+            .iter()
+            .filter(|symbol| match declared_at {
+                Some(_) => symbol.declared_at == declared_at,
+                None => symbol.declared_at.is_none() && self.same_name(&symbol.name, name),
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The edits that rename the variable at a source position to `new_name`:
+    /// its declaration and every assignment and reference that resolves to it,
+    /// and nothing else of the same name.
+    ///
+    /// Fails when there is no declared variable there, when `new_name` isn't a
+    /// variable name, or when the new name would change what some use resolves
+    /// to: another variable of that name in a scope that holds uses of this
+    /// one, or uses of another variable of that name inside this one's scope.
+    pub fn rename(&self, line: usize, col: usize, new_name: &str) -> Result<Vec<TextEdit>, String> {
+        let symbol = self
+            .symbol_at(line, col)
+            .ok_or_else(|| format!("There is no variable at line {}, col {}", line, col))?;
+        let declared_at = symbol.declared_at.ok_or_else(|| {
+            format!("'{}' is a define or built-in variable, not one the document declares", symbol.name)
+        })?;
+        if !lexer::is_variable_name(new_name) {
+            return Err(format!("'{}' isn't a valid variable name", new_name));
+        }
+        let uses = self.uses_of(Some(declared_at), &symbol.name);
+        let scope = self.scope_declaring(declared_at).unwrap_or(&self.global);
+
+        //every other variable the new name could be confused with, by its scope
+        let mut others: Vec<(Option<(usize, usize)>, &ScopeInfo)> = self
+            .uses
+            .iter()
+            .filter(|other| other.kind == UseKind::Declaration && other.declared_at != Some(declared_at))
+            .filter(|other| self.same_name(&other.name, new_name))
+            .map(|other| {
+                let other_scope = other.declared_at.and_then(|at| self.scope_declaring(at));
+                (other.declared_at, other_scope.unwrap_or(&self.global))
+            })
+            .collect();
+        others.push((None, &self.global));
+        for (other_declared_at, other_scope) in others {
+            let other_uses = self.uses_of(other_declared_at, new_name);
+            let hides_ours = within(other_scope, scope) && uses.iter().any(|u| covers(other_scope, u.span.0));
+            let hidden_by_ours = within(scope, other_scope) && other_uses.iter().any(|u| covers(scope, u.span.0));
+            let same_scope = other_declared_at.is_some() && std::ptr::eq(scope, other_scope);
+            if hides_ours || hidden_by_ours || same_scope {
+                let other = match other_declared_at {
+                    Some((line, col)) => format!("the '{}' declared at line {}, col {}", new_name, line, col),
+                    None => format!("the define or built-in variable '{}'", new_name),
+                };
+                return Err(format!("Renaming '{}' to '{}' would mix it up with {}", symbol.name, new_name, other));
+            }
+        }
+
+        Ok(uses
+            .iter()
+            .filter_map(|u| {
+                let (line, col) = u.name_at?;
+                Some(TextEdit { start: (line, col), end: (line, col + u.name.chars().count()), text: new_name.to_string() })
+            })
+            .collect())
+    }
+
+    // the scope a variable declared at that position belongs to
+    fn scope_declaring(&self, declared_at: (usize, usize)) -> Option<&ScopeInfo> {
+        fn find(scope: &ScopeInfo, declared_at: (usize, usize)) -> Option<&ScopeInfo> {
+            if scope.variables.iter().any(|v| v.declared_at == Some(declared_at)) {
+                return Some(scope);
+            }
+            scope.children.iter().find_map(|child| find(child, declared_at))
+        }
+        find(&self.global, declared_at)
+    }
+
+    fn same_name(&self, a: &str, b: &str) -> bool {
+        if self.case_insensitive { a.eq_ignore_ascii_case(b) } else { a == b }
+    }
+}
+
+// whether `inner` lies inside `outer` (or is it)
+fn within(inner: &ScopeInfo, outer: &ScopeInfo) -> bool {
+    match (inner.start, inner.end, outer.start, outer.end) {
+        (_, _, None, _) => true,
+        (Some(start), Some(end), Some(outer_start), Some(outer_end)) => outer_start <= start && end <= outer_end,
+        _ => false,
+    }
+}
+
+// whether a position lies inside a scope
+fn covers(scope: &ScopeInfo, position: (usize, usize)) -> bool {
+    match (scope.start, scope.end) {
+        (Some(start), Some(end)) => start <= position && position <= end,
+        (None, _) => true,
+        _ => false,
+    }
+}
+
+/// Parses and checks `source` with the settings of `analyzer` (defines, page
+/// links, case sensitivity) and returns its symbol table. No output is
+/// generated.
+pub fn check_source(source: &str, analyzer: &mut LolcodeSemanticAnalyzer) -> Result<SymbolTable, CompileError> {
+    let arena = AstArena::new();
+    let mut parser = LolcodeParser::new(source, &arena);
+    parser.parse()?;
+    let tree = parser.parse_tree.take().ok_or_else(|| CompileError::Syntax {
+        line: 1,
+        col: 1,
+        message: "No parse tree generated".to_string(),
+        labels: Vec::new(),
+    })?;
+    analyzer.interner = std::mem::take(&mut parser.interner);
+    analyzer.positions = std::mem::take(&mut parser.positions);
+    analyzer.check_tree(tree)?;
+    Ok(std::mem::take(&mut analyzer.symbols))
+}
+
+/// Applies edits to the source they were made for. Positions are counted the
+/// way the lexer counts them: lines and columns from 1, columns in characters,
+/// a leading BOM not counted. An edit that overlaps an earlier one is skipped.
+pub fn apply_edits(source: &str, edits: &[TextEdit]) -> String {
+    let mut edits: Vec<&TextEdit> = edits.iter().collect();
+    edits.sort_by_key(|edit| edit.start);
+
+    let offsets = lexer::byte_offsets(source, edits.iter().flat_map(|edit| [edit.start, edit.end]).collect());
+
+    let mut out = String::with_capacity(source.len());
+    let mut copied = 0;
+    for edit in edits {
+        let (Some(&start), Some(&end)) = (offsets.get(&edit.start), offsets.get(&edit.end)) else {
+            continue;
+        };
+        if start < copied || end < start {
+            continue;
+        }
+        out.push_str(&source[copied..start]);
+        out.push_str(&edit.text);
+        copied = end;
+    }
+    out.push_str(&source[copied..]);
+    out
+}
+
+fn json_position(position: Option<(usize, usize)>) -> String {
+    position.map_or_else(|| "null".to_string(), |(line, col)| format!("[{},{}]", line, col))
+}
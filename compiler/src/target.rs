@@ -0,0 +1,89 @@
+//! Output targets.
+//!
+//! HTML is the default. Other targets are picked with `--target <name>` and
+//! decide what file the compiler produces from the checked tree.
+
+/// The kind of document the compiler produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    /// A web page
+    #[default]
+    Html,
+    /// Print-ready HTML converted to PDF by a headless browser
+    Pdf,
+    /// A self-contained presentation in reveal.js markup, one slide per section
+    Slides,
+    /// text/gemini for Gemini capsules
+    Gemtext,
+    /// ANSI-styled text; the command line prints it to the terminal, while
+    /// `compile_file` saves it as a `.txt` file
+    Ansi,
+    /// A roff man page
+    Man,
+    /// AsciiDoc for Antora and other AsciiDoc toolchains
+    AsciiDoc,
+    /// Structured XML of the document
+    Xml,
+}
+
+impl Target {
+    /// Every target.
+    pub const ALL: [Target; 8] = [
+        Target::Html,
+        Target::Pdf,
+        Target::Slides,
+        Target::Gemtext,
+        Target::Ansi,
+        Target::Man,
+        Target::AsciiDoc,
+        Target::Xml,
+    ];
+
+    /// Parses a target name given on the command line.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "html" => Some(Target::Html),
+            "pdf" => Some(Target::Pdf),
+            "slides" | "reveal" => Some(Target::Slides),
+            "gemtext" | "gemini" | "gmi" => Some(Target::Gemtext),
+            "ansi" | "terminal" => Some(Target::Ansi),
+            "man" | "roff" => Some(Target::Man),
+            "asciidoc" | "adoc" => Some(Target::AsciiDoc),
+            "xml" => Some(Target::Xml),
+            _ => None,
+        }
+    }
+
+    /// Name used in progress messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Target::Html | Target::Pdf | Target::Slides => "HTML",
+            Target::Gemtext => "Gemtext",
+            Target::Ansi => "terminal",
+            Target::Man => "man page",
+            Target::AsciiDoc => "AsciiDoc",
+            Target::Xml => "XML",
+        }
+    }
+
+    /// Extension of the generated file (PDFs are printed from the HTML file,
+    /// ANSI text only gets a file from `compile_file`).
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Target::Html | Target::Pdf | Target::Slides => "html",
+            Target::Gemtext => "gmi",
+            Target::Ansi => "txt",
+            Target::Man => "1",
+            Target::AsciiDoc => "adoc",
+            Target::Xml => "xml",
+        }
+    }
+
+    /// True for targets whose output is a web page.
+    pub fn is_html(&self) -> bool {
+        matches!(self, Target::Html | Target::Pdf | Target::Slides)
+    }
+
+    /// Names accepted by `parse`, for usage messages.
+    pub const NAMES: &'static str = "html, pdf, slides, gemtext, ansi, man, asciidoc, xml";
+}
@@ -0,0 +1,155 @@
+//! Per-stage timing statistics for `--timings`.
+//!
+//! `Timings` is a pipeline observer that measures the wall-clock time between
+//! stage hooks and counts what each stage produced: tokens for the lexer, AST
+//! nodes for the parser, variables for semantic analysis and bytes for code
+//! generation. It keeps one line per document and running totals so a large
+//! batch can be summarized at the end; printing them, and where, is up to the
+//! caller. Parallel builds give every page its own `Timings` and `merge` them
+//! afterwards, since the stage clock is per document.
+
+use crate::intern::{Interner, Symbol};
+use crate::parser::ASTNode;
+use crate::pipeline::PipelineObserver;
+use crate::token::Token;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Names of the measured stages, in pipeline order.
+const STAGES: [&str; 4] = ["lexing", "parsing", "semantic", "codegen"];
+
+/// What each stage's count measures.
+const UNITS: [&str; 4] = ["tokens", "nodes", "variables", "bytes"];
+
+/// Time and output size of one stage.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StageStats {
+    pub time: Duration,
+    pub count: usize,
+}
+
+/// Collects stage statistics across every document compiled with it.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    /// Source file of the document being compiled
+    input: String,
+    /// When the last stage finished (or the document started)
+    last: Option<Instant>,
+    /// Stats of the document being compiled
+    current: [StageStats; 4],
+    /// Sums over every finished document
+    pub totals: [StageStats; 4],
+    /// Number of finished documents
+    pub documents: usize,
+    /// One line with the stats of each finished document, in the order they
+    /// finished (or were merged)
+    pub lines: Vec<String>,
+}
+
+impl Timings {
+    /// Creates an empty set of timings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the end of a stage, timed from the end of the previous one.
+    ///
+    /// Link checking and linting run between parsing and semantic analysis and
+    /// are counted as part of the semantic stage.
+    fn finish_stage(&mut self, stage: usize, count: usize) {
+        let now = Instant::now();
+        let time = self.last.map(|last| now - last).unwrap_or_default();
+        self.last = Some(now);
+        self.current[stage] = StageStats { time, count };
+    }
+
+    /// Adds the totals of timings collected separately, e.g. on another thread.
+    pub fn merge(&mut self, other: &Timings) {
+        for (total, stats) in self.totals.iter_mut().zip(&other.totals) {
+            total.time += stats.time;
+            total.count += stats.count;
+        }
+        self.documents += other.documents;
+        self.lines.extend(other.lines.iter().cloned());
+    }
+
+    /// The totals over every document, with the share of time per stage, one
+    /// line per stage.
+    pub fn report_totals(&self) -> String {
+        let total: Duration = self.totals.iter().map(|s| s.time).sum();
+        let mut report = format!("Timings for {} document(s):\n", self.documents);
+        for (i, stats) in self.totals.iter().enumerate() {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                stats.time.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            report.push_str(&format!(
+                "  {:<8} {:>10.3} ms {:>5.1}% {:>10} {}\n",
+                STAGES[i],
+                millis(stats.time),
+                share,
+                stats.count,
+                UNITS[i]
+            ));
+        }
+        report.push_str(&format!("  {:<8} {:>10.3} ms\n", "total", millis(total)));
+        report
+    }
+}
+
+impl PipelineObserver for Timings {
+    fn before_lexing(&mut self, input_filename: &str) {
+        self.input = input_filename.to_string();
+        self.current = Default::default();
+        self.last = Some(Instant::now());
+    }
+
+    fn after_lexing(&mut self, tokens: &[Token]) {
+        self.finish_stage(0, tokens.len());
+    }
+
+    fn after_parsing(&mut self, tree: &ASTNode) {
+        self.finish_stage(1, count_nodes(tree));
+    }
+
+    fn after_semantic(&mut self, symbols: &HashMap<Symbol, Option<String>>, _interner: &Interner) {
+        self.finish_stage(2, symbols.len());
+    }
+
+    fn after_codegen(&mut self, output: &str) {
+        self.finish_stage(3, output.len());
+
+        let line: Vec<String> = self
+            .current
+            .iter()
+            .enumerate()
+            .map(|(i, s)| format!("{} {:.3} ms ({} {})", STAGES[i], millis(s.time), s.count, UNITS[i]))
+            .collect();
+        self.lines.push(format!("Timings for {}: {}", self.input, line.join(", ")));
+
+        for (total, stats) in self.totals.iter_mut().zip(&self.current) {
+            total.time += stats.time;
+            total.count += stats.count;
+        }
+        self.documents += 1;
+    }
+}
+
+/// Counts the nodes of a parse tree, the root included.
+pub fn count_nodes(node: &ASTNode) -> usize {
+    let children = match node {
+        ASTNode::Program { children }
+        | ASTNode::HeadSection { children }
+        | ASTNode::ParagrafSection { children }
+        | ASTNode::ListSection { children }
+        | ASTNode::IfDefined { children, .. } => children,
+        ASTNode::Bold { content } | ASTNode::Italics { content } | ASTNode::Item { content } => content,
+        _ => return 1,
+    };
+    1 + children.iter().map(count_nodes).sum::<usize>()
+}
+
+fn millis(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0
+}
@@ -10,25 +10,29 @@
 /// 
 /// * `HashWord` - Keywords prefixed with `#` (e.g., `#HAI`, `#KTHXBYE`, `#I HAZ`)
 /// * `Keyword` - Language keywords without `#` prefix (e.g., `HEAD`, `TITLE`, `PARAGRAF`)
-/// * `Address` - URL addresses for multimedia content
+/// * `Address` - A URL, from `scheme://` to the next whitespace, `#fragment` included
 /// * `Text` - Plain text content that isn't a keyword
 /// * `VarDef` - Variable definition identifier
-/// * `VarVal` - Variable value content
+/// * `VarVal` - Unquoted value after `#IT IZ`, the rest of the line up to `#MKAY`
+/// * `Yarn` - Quoted string literal after `#IT IZ` or `TITLE`, escapes already applied
+/// * `Shhh` - `#SHHH <code>` pragma with the diagnostic code it silences
 /// * `Newline` - Explicit newline token
 /// * `Eof` - End of file marker
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     //words leading with hashtag
-    HashWord(String),
+    HashWord(&'static str),
 
     // keywords that dont start with a hashtag
-    Keyword(String),
+    Keyword(&'static str),
 
     // text that isn't a defined keyword in the grammar
     Address(String),
     Text(String),      
     VarDef(String),   
     VarVal(String),
+    Yarn(String),
+    Shhh(&'static str),
     Newline,
     Eof,
 }
@@ -41,4 +45,36 @@ pub struct Token {
     pub kind: TokenKind,
     pub line: usize,
     pub col: usize,
-}
\ No newline at end of file
+}
+/// Source text the lexer reads past between tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trivia<'a> {
+    /// Spaces, tabs, zero-width characters, a byte order mark, or text that is
+    /// blank once trimmed
+    Whitespace(&'a str),
+    /// An `#OBTW ... #TLDR` comment block, both hashtag words included
+    Comment(&'a str),
+}
+
+impl<'a> Trivia<'a> {
+    /// The source text, exactly as written.
+    pub fn text(&self) -> &'a str {
+        match self {
+            Trivia::Whitespace(text) | Trivia::Comment(text) => text,
+        }
+    }
+}
+
+/// A token from a lossless lexer, with the source around it kept.
+///
+/// Writing out every token's `leading` trivia and then its `text`, up to and
+/// including `Eof`, gives back the source byte for byte.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LosslessToken<'a> {
+    pub token: Token,
+    /// Whitespace and comments between the previous token and this one
+    pub leading: Vec<Trivia<'a>>,
+    /// The source the token was read from; text tokens include the spacing
+    /// trimmed off their value, `Eof` is empty
+    pub text: &'a str,
+}
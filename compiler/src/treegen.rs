@@ -0,0 +1,398 @@
+//! Random valid parse trees for property tests (feature `arbitrary`).
+//!
+//! `arbitrary_tree` turns fuzzer or proptest input into a tree the parser could
+//! have built and the semantic analyzer accepts: every variable is declared and
+//! assigned before it is referenced, no name is declared twice in one scope, a
+//! HEAD section only comes first, META dates are real dates and links are
+//! external. Printing it with `print::to_source` and parsing the result gives an
+//! equal tree back, which makes round-trip and invariant tests for passes and
+//! backends short to write:
+//!
+//! ```text
+//! // with proptest and proptest-arbitrary-interop
+//! proptest! {
+//!     #[test]
+//!     fn html_never_empty(doc in arb::<ArbitraryDocument>()) {
+//!         let html = compile(&doc.source);
+//!         prop_assert!(html.contains("<body>"));
+//!     }
+//! }
+//! ```
+
+use crate::arena::AstArena;
+use crate::intern::{Interner, Symbol};
+use crate::parser::{ASTNode, CaptionTrack, MediaAttributes};
+use crate::print;
+use arbitrary::{Arbitrary, Unstructured};
+
+/// Deepest section nesting generated.
+const MAX_DEPTH: usize = 4;
+
+/// Most children generated for one section or styled text.
+const MAX_CHILDREN: u32 = 6;
+
+// single words lex as one token; none of them is a keyword
+const WORDS: [&str; 12] = [
+    "cat", "cheez", "burger", "hai", "kitteh", "lol", "nom", "oh", "srsly", "wut", "x1", "yarn",
+];
+
+// text that starts with something other than a letter lexes as one token up to
+// the end of the line or the next `#`, spaces and all
+const PHRASES: [&str; 8] = [
+    "1 cat",
+    "42",
+    "(yes) maybe",
+    "... and then",
+    "100% cheez",
+    "\"quoted\" words",
+    "<b>not bold</b>",
+    "& co",
+];
+
+const NAMES: [&str; 6] = ["a", "b", "count", "kitty", "name", "total"];
+
+const URLS: [&str; 5] = [
+    "https://example.com/cat.png",
+    "https://example.com/v/123",
+    "media/song.mp3",
+    "./clip.mp4",
+    "https://example.com/a?b=c",
+];
+
+const LINKS: [&str; 3] = ["https://example.com", "https://lolcode.org/spec", "mailto:cat@example.com"];
+
+const FLAGS: [&str; 3] = ["draft", "internal", "preview"];
+
+const META_KEYS: [&str; 4] = ["author", "description", "summary", "tags"];
+
+/// A valid document as source text, for `Arbitrary`-driven tests.
+#[derive(Debug, Clone)]
+pub struct ArbitraryDocument {
+    pub source: String,
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryDocument {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let arena = AstArena::new();
+        let mut interner = Interner::new();
+        let tree = arbitrary_tree(u, &arena, &mut interner)?;
+        Ok(Self {
+            source: print::to_source(tree, &interner),
+        })
+    }
+}
+
+/// Builds a valid `Program` tree from `u`, allocated in `arena`.
+///
+/// Variable names are interned in `interner` in the order they appear, so the
+/// tree compares equal to the one parsed back from its printed source.
+pub fn arbitrary_tree<'t>(
+    u: &mut Unstructured<'_>,
+    arena: &'t AstArena<'t>,
+    interner: &mut Interner,
+) -> arbitrary::Result<&'t ASTNode<'t>> {
+    let mut generator = TreeGenerator {
+        u,
+        arena,
+        interner,
+        scopes: vec![Vec::new()],
+        depth: 0,
+    };
+    let children = generator.body()?;
+    Ok(arena.alloc(ASTNode::Program { children }))
+}
+
+struct TreeGenerator<'g, 'u, 't> {
+    u: &'g mut Unstructured<'u>,
+    arena: &'t AstArena<'t>,
+    interner: &'g mut Interner,
+    // names declared in each open scope, global first
+    scopes: Vec<Vec<Symbol>>,
+    // sections currently open
+    depth: usize,
+}
+
+impl<'t> TreeGenerator<'_, '_, 't> {
+    fn pick<T: Copy>(&mut self, choices: &[T]) -> arbitrary::Result<T> {
+        Ok(*self.u.choose(choices)?)
+    }
+
+    fn count(&mut self) -> arbitrary::Result<u32> {
+        self.u.int_in_range(0..=MAX_CHILDREN)
+    }
+
+    fn words(&mut self) -> arbitrary::Result<String> {
+        let mut words = Vec::new();
+        for _ in 0..self.u.int_in_range(1..=4)? {
+            words.push(self.pick(&WORDS)?);
+        }
+        Ok(words.join(" "))
+    }
+
+    // a word or a phrase, which lexes as a single token
+    fn text(&mut self) -> arbitrary::Result<&'t str> {
+        let text = if self.u.arbitrary()? { self.pick(&WORDS)? } else { self.pick(&PHRASES)? };
+        Ok(self.arena.alloc_str(text))
+    }
+
+    // the semantic analyzer wants HEAD sections before any content, so one is
+    // only generated at the very start
+    fn body(&mut self) -> arbitrary::Result<&'t [ASTNode<'t>]> {
+        let mut nodes = Vec::new();
+        if self.u.arbitrary()? {
+            nodes.push(self.head()?);
+        }
+        for _ in 0..self.count()? {
+            match self.u.int_in_range(0..=4)? {
+                0 => nodes.push(self.section()?),
+                1 => self.declaration(&mut nodes)?,
+                2 => nodes.extend(self.reference()?),
+                3 => nodes.push(self.styled()?),
+                _ => nodes.push(ASTNode::Text { content: self.text()? }),
+            }
+        }
+        Ok(self.arena.alloc_nodes(nodes))
+    }
+
+    fn section(&mut self) -> arbitrary::Result<ASTNode<'t>> {
+        if self.depth >= MAX_DEPTH {
+            return Ok(ASTNode::Text { content: self.text()? });
+        }
+        self.depth += 1;
+        // META only goes at the top level, inside a paragraf it is another paragraf
+        let node = match self.u.int_in_range(0..=3)? {
+            0 | 1 if self.depth == 1 => self.meta()?,
+            2 => self.scoped(Self::list)?,
+            _ => self.scoped(Self::paragraf)?,
+        };
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    // paragrafs and lists open a scope in the semantic analyzer
+    fn scoped(&mut self, build: fn(&mut Self) -> arbitrary::Result<ASTNode<'t>>) -> arbitrary::Result<ASTNode<'t>> {
+        self.scopes.push(Vec::new());
+        let node = build(self);
+        self.scopes.pop();
+        node
+    }
+
+    fn head(&mut self) -> arbitrary::Result<ASTNode<'t>> {
+        let mut titles = Vec::new();
+        for _ in 0..self.u.int_in_range(0..=2)? {
+            let title = if self.u.arbitrary()? { self.words()? } else { self.pick(&PHRASES)?.to_string() };
+            let text = ASTNode::Text { content: self.arena.alloc_str(&title) };
+            titles.push(ASTNode::Title { content: self.arena.alloc_nodes(vec![text]) });
+        }
+        Ok(ASTNode::HeadSection { children: self.arena.alloc_nodes(titles) })
+    }
+
+    fn meta(&mut self) -> arbitrary::Result<ASTNode<'t>> {
+        let mut entries = Vec::new();
+        for _ in 0..self.u.int_in_range(0..=3)? {
+            if self.u.arbitrary()? {
+                let date = format!(
+                    "{:04}-{:02}-{:02}",
+                    self.u.int_in_range(1990..=2030)?,
+                    self.u.int_in_range(1..=12)?,
+                    self.u.int_in_range(1..=28)?
+                );
+                entries.push(("date".to_string(), date));
+            } else {
+                let key = self.pick(&META_KEYS)?.to_string();
+                entries.push((key, self.words()?));
+            }
+        }
+        Ok(ASTNode::MetaSection { entries })
+    }
+
+    fn paragraf(&mut self) -> arbitrary::Result<ASTNode<'t>> {
+        let mut children = Vec::new();
+        for _ in 0..self.count()? {
+            match self.u.int_in_range(0..=6)? {
+                0 => children.push(self.section()?),
+                1 => self.declaration(&mut children)?,
+                2 => children.extend(self.reference()?),
+                3 => children.push(self.styled()?),
+                4 => children.push(ASTNode::Newline),
+                5 => children.push(self.if_defined()?),
+                _ => children.push(ASTNode::Text { content: self.text()? }),
+            }
+        }
+        Ok(ASTNode::ParagrafSection { children: self.arena.alloc_nodes(children) })
+    }
+
+    // no declarations inside, the flag may be off and take them away
+    fn if_defined(&mut self) -> arbitrary::Result<ASTNode<'t>> {
+        let flag = self.arena.alloc_str(self.pick(&FLAGS)?);
+        let mut children = Vec::new();
+        for _ in 0..self.count()? {
+            match self.u.int_in_range(0..=2)? {
+                0 => children.extend(self.reference()?),
+                1 => children.push(self.styled()?),
+                _ => children.push(ASTNode::Text { content: self.text()? }),
+            }
+        }
+        Ok(ASTNode::IfDefined { flag, children: self.arena.alloc_nodes(children) })
+    }
+
+    fn list(&mut self) -> arbitrary::Result<ASTNode<'t>> {
+        let mut items = Vec::new();
+        for _ in 0..self.count()? {
+            items.push(ASTNode::Item { content: self.inline()? });
+        }
+        Ok(ASTNode::ListSection { children: self.arena.alloc_nodes(items) })
+    }
+
+    // a declaration is always followed by its assignment, like the parser expects at the top level;
+    // a name already declared in this scope is assigned again by name instead
+    fn declaration(&mut self, nodes: &mut Vec<ASTNode<'t>>) -> arbitrary::Result<()> {
+        let name = self.pick(&NAMES)?;
+        let Some(scope) = self.scopes.last_mut() else {
+            return Ok(());
+        };
+        // only names that end up in the tree are interned, to keep the parser's order
+        if let Some(symbol) = self.interner.get(name)
+            && scope.contains(&symbol)
+        {
+            nodes.push(ASTNode::VariableAssignment { name: Some(symbol), value: self.text()? });
+            return Ok(());
+        }
+        let symbol = self.interner.intern(name);
+        scope.push(symbol);
+        nodes.push(ASTNode::VariableDeclaration { name: symbol });
+        nodes.push(ASTNode::VariableAssignment { name: None, value: self.text()? });
+        Ok(())
+    }
+
+    // a reference to some variable in scope, if there is one
+    fn reference(&mut self) -> arbitrary::Result<Option<ASTNode<'t>>> {
+        let visible: Vec<Symbol> = self.scopes.iter().flatten().copied().collect();
+        if visible.is_empty() {
+            return Ok(None);
+        }
+        let name = self.pick(&visible)?;
+        Ok(Some(ASTNode::VariableReference { name }))
+    }
+
+    fn styled(&mut self) -> arbitrary::Result<ASTNode<'t>> {
+        Ok(match self.u.int_in_range(0..=6)? {
+            0 => ASTNode::Bold { content: self.inline()? },
+            1 => ASTNode::Italics { content: self.inline()? },
+            2 => ASTNode::Newline,
+            3 => {
+                let target = self.arena.alloc_str(self.pick(&LINKS)?);
+                let text = if self.u.arbitrary()? { target } else { self.arena.alloc_str(&self.words()?) };
+                ASTNode::Link { target, text }
+            }
+            4 => {
+                let (url, attributes) = self.media(Self::sound_attributes)?;
+                ASTNode::Sound { url, attributes }
+            }
+            5 => {
+                let (url, attributes) = self.media(Self::video_attributes)?;
+                ASTNode::Video { url, attributes }
+            }
+            _ => {
+                let (url, attributes) = self.media(Self::image_attributes)?;
+                ASTNode::Image { url, attributes }
+            }
+        })
+    }
+
+    // a URL and its attributes; the URL may start with a variable holding its base
+    fn media(
+        &mut self,
+        attributes: fn(&mut Self) -> arbitrary::Result<Box<MediaAttributes>>,
+    ) -> arbitrary::Result<(&'t str, Box<MediaAttributes>)> {
+        let url = self.url()?;
+        let mut attributes = attributes(self)?;
+        if self.u.ratio(1, 4)?
+            && let Some(ASTNode::VariableReference { name }) = self.reference()?
+        {
+            attributes.url_references.push((0, name));
+            let file = url.rsplit('/').next().unwrap_or(url);
+            return Ok((self.arena.alloc_str(&format!("/{}", file)), attributes));
+        }
+        Ok((url, attributes))
+    }
+
+    // words and references on one line; a phrase runs to the next `#`, so it
+    // can only come last or right before a reference
+    fn inline(&mut self) -> arbitrary::Result<&'t [ASTNode<'t>]> {
+        let mut content = Vec::new();
+        let mut after_phrase = false;
+        for _ in 0..self.count()? {
+            if after_phrase || self.u.ratio(1, 4)? {
+                if let Some(reference) = self.reference()? {
+                    content.push(reference);
+                    after_phrase = false;
+                }
+                continue;
+            }
+            let text = self.text()?;
+            after_phrase = PHRASES.contains(&text);
+            content.push(ASTNode::Text { content: text });
+        }
+        Ok(self.arena.alloc_nodes(content))
+    }
+
+    fn url(&mut self) -> arbitrary::Result<&'t str> {
+        Ok(self.arena.alloc_str(self.pick(&URLS)?))
+    }
+
+    fn sound_attributes(&mut self) -> arbitrary::Result<Box<MediaAttributes>> {
+        let mut attributes = MediaAttributes {
+            looping: self.u.arbitrary()?,
+            lazy: self.u.arbitrary()?,
+            ..MediaAttributes::default()
+        };
+        if self.u.arbitrary()? {
+            attributes.preload = Some(self.pick(&["none", "metadata", "auto"])?.to_string());
+        }
+        Ok(Box::new(attributes))
+    }
+
+    fn video_attributes(&mut self) -> arbitrary::Result<Box<MediaAttributes>> {
+        let mut attributes = MediaAttributes {
+            autoplay: self.u.arbitrary()?,
+            looping: self.u.arbitrary()?,
+            muted: self.u.arbitrary()?,
+            lazy: self.u.arbitrary()?,
+            ..MediaAttributes::default()
+        };
+        if self.u.arbitrary()? {
+            attributes.poster = Some(self.pick(&URLS)?.to_string());
+        }
+        for _ in 0..self.u.int_in_range(0..=2)? {
+            let label = if self.u.arbitrary()? { Some(self.pick(&WORDS)?.to_string()) } else { None };
+            attributes.captions.push(CaptionTrack {
+                src: self.pick(&URLS)?.to_string(),
+                lang: self.pick(&["en", "fr", "de"])?.to_string(),
+                label,
+            });
+        }
+        Ok(Box::new(attributes))
+    }
+
+    fn image_attributes(&mut self) -> arbitrary::Result<Box<MediaAttributes>> {
+        let mut attributes = MediaAttributes {
+            lazy: self.u.arbitrary()?,
+            ..MediaAttributes::default()
+        };
+        if self.u.arbitrary()? {
+            attributes.width = Some(self.u.int_in_range(1..=2000)?);
+        }
+        if self.u.arbitrary()? {
+            attributes.height = Some(self.u.int_in_range(1..=2000)?);
+        }
+        for _ in 0..self.u.int_in_range(0..=2)? {
+            let descriptor = self.pick(&["2x", "800w"])?;
+            attributes.srcset.push(format!("{} {}", self.pick(&URLS)?, descriptor));
+        }
+        if self.u.arbitrary()? {
+            attributes.alt = Some(self.words()?);
+        }
+        Ok(Box::new(attributes))
+    }
+}
@@ -0,0 +1,253 @@
+//! XML output.
+//!
+//! `--target xml` writes the document as plain structured XML so publishing
+//! toolchains can read compiler output without scraping HTML. The element
+//! names follow the grammar (`<paragraf>`, `<list>`, `<item>`, ...), with the
+//! META block as `<meta>` entries and media attributes as XML attributes.
+//!
+//! Works on a tree resolved by `LolcodeSemanticAnalyzer::resolve_tree`, so
+//! variable references already hold their values.
+
+use crate::meta;
+use crate::parser::ASTNode;
+use std::borrow::Cow;
+
+/// Escapes text for use inside XML elements and attributes.
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    push_escaped(&mut escaped, text);
+    escaped
+}
+
+// appends `text` escaped, copying the runs between special characters whole
+fn push_escaped(out: &mut String, text: &str) {
+    let mut plain = 0;
+    for (i, byte) in text.bytes().enumerate() {
+        let entity = match byte {
+            b'&' => "&amp;",
+            b'<' => "&lt;",
+            b'>' => "&gt;",
+            b'"' => "&quot;",
+            _ => continue,
+        };
+        out.push_str(&text[plain..i]);
+        out.push_str(entity);
+        plain = i + 1;
+    }
+    out.push_str(&text[plain..]);
+}
+
+/// Escapes what would keep markup from parsing as XML and leaves the markup:
+/// a `&` that doesn't start a character reference or one of XML's five
+/// entities, and a `<` that doesn't start a tag, comment or processing
+/// instruction. For document text in XHTML output, which passes its tags through.
+pub fn escape_stray_markup(text: &str) -> Cow<'_, str> {
+    let mut out = String::new();
+    let mut plain = 0;
+    for (i, byte) in text.bytes().enumerate() {
+        let rest = &text[i + 1..];
+        let entity = match byte {
+            b'&' if !starts_reference(rest) => "&amp;",
+            b'<' if !rest.starts_with(|c: char| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?')) => "&lt;",
+            _ => continue,
+        };
+        out.push_str(&text[plain..i]);
+        out.push_str(entity);
+        plain = i + 1;
+    }
+    if plain == 0 {
+        return Cow::Borrowed(text);
+    }
+    out.push_str(&text[plain..]);
+    Cow::Owned(out)
+}
+
+// whether what follows a `&` is `amp;`, `#38;`, `#x26;` and the like
+fn starts_reference(rest: &str) -> bool {
+    let Some((name, _)) = rest.split_once(';') else {
+        return false;
+    };
+    if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit())
+    } else if let Some(decimal) = name.strip_prefix('#') {
+        !decimal.is_empty() && decimal.chars().all(|c| c.is_ascii_digit())
+    } else {
+        matches!(name, "amp" | "lt" | "gt" | "quot" | "apos")
+    }
+}
+
+/// Renders a resolved tree as an XML document.
+pub fn render(tree: &ASTNode) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    node(tree, 0, &mut out);
+    out
+}
+
+fn push_indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+// `<name>text</name>` on a line of its own
+fn text_element(name: &str, text: &str, depth: usize, out: &mut String) {
+    push_indent(out, depth);
+    out.push('<');
+    out.push_str(name);
+    out.push('>');
+    push_escaped(out, text);
+    out.push_str("</");
+    out.push_str(name);
+    out.push_str(">\n");
+}
+
+fn container(name: &str, children: &[ASTNode], depth: usize, out: &mut String) {
+    push_indent(out, depth);
+    if children.is_empty() {
+        out.push('<');
+        out.push_str(name);
+        out.push_str("/>\n");
+        return;
+    }
+    out.push('<');
+    out.push_str(name);
+    out.push_str(">\n");
+    for child in children.iter() {
+        node(child, depth + 1, out);
+    }
+    push_indent(out, depth);
+    out.push_str("</");
+    out.push_str(name);
+    out.push_str(">\n");
+}
+
+// `<name key="value".../>` for the pairs that are set, left open when `open`
+fn media_element(name: &str, pairs: &[(&str, Option<&str>)], open: bool, depth: usize, out: &mut String) {
+    push_indent(out, depth);
+    out.push('<');
+    out.push_str(name);
+    for (key, value) in pairs {
+        if let Some(value) = value {
+            out.push(' ');
+            out.push_str(key);
+            out.push_str("=\"");
+            push_escaped(out, value);
+            out.push('"');
+        }
+    }
+    out.push_str(if open { ">\n" } else { "/>\n" });
+}
+
+fn flag(set: bool) -> Option<&'static str> {
+    set.then_some("true")
+}
+
+fn node(node_ref: &ASTNode, depth: usize, out: &mut String) {
+    match node_ref {
+        ASTNode::Program { children } => container("document", children, depth, out),
+        ASTNode::HeadSection { children } => container("head", children, depth, out),
+        ASTNode::ParagrafSection { children } => container("paragraf", children, depth, out),
+        ASTNode::ListSection { children } => container("list", children, depth, out),
+        ASTNode::Bold { content } => container("bold", content, depth, out),
+        ASTNode::Italics { content } => container("italics", content, depth, out),
+        ASTNode::Item { content } => container("item", content, depth, out),
+        ASTNode::MetaSection { entries } => {
+            push_indent(out, depth);
+            out.push_str("<meta>\n");
+            for (key, value) in entries {
+                push_indent(out, depth + 1);
+                out.push_str("<entry key=\"");
+                push_escaped(out, key);
+                out.push_str("\">");
+                push_escaped(out, value);
+                out.push_str("</entry>\n");
+            }
+            push_indent(out, depth);
+            out.push_str("</meta>\n");
+        }
+        ASTNode::Title { content } => text_element("title", &meta::title_text(content), depth, out),
+        ASTNode::Text { content } => text_element("text", content, depth, out),
+        ASTNode::Newline => {
+            push_indent(out, depth);
+            out.push_str("<newline/>\n");
+        }
+        ASTNode::Link { target, text } => {
+            push_indent(out, depth);
+            out.push_str("<link target=\"");
+            push_escaped(out, target);
+            out.push_str("\">");
+            push_escaped(out, text);
+            out.push_str("</link>\n");
+        }
+        ASTNode::Image { url, attributes: a } => {
+            let width = a.width.map(|w| w.to_string());
+            let height = a.height.map(|h| h.to_string());
+            let srcset = (!a.srcset.is_empty()).then(|| a.srcset.join(", "));
+            media_element(
+                "image",
+                &[
+                    ("src", Some(url)),
+                    ("alt", a.alt.as_deref()),
+                    ("width", width.as_deref()),
+                    ("height", height.as_deref()),
+                    ("srcset", srcset.as_deref()),
+                    ("lazy", flag(a.lazy)),
+                ],
+                false,
+                depth,
+                out,
+            );
+        }
+        ASTNode::Sound { url, attributes: a } => media_element(
+            "sound",
+            &[
+                ("src", Some(url)),
+                ("loop", flag(a.looping)),
+                ("preload", a.preload.as_deref()),
+                ("lazy", flag(a.lazy)),
+            ],
+            false,
+            depth,
+            out,
+        ),
+        ASTNode::Video { url, attributes: a } => {
+            let captioned = !a.captions.is_empty();
+            media_element(
+                "video",
+                &[
+                    ("src", Some(url)),
+                    ("poster", a.poster.as_deref()),
+                    ("autoplay", flag(a.autoplay)),
+                    ("loop", flag(a.looping)),
+                    ("muted", flag(a.muted)),
+                    ("lazy", flag(a.lazy)),
+                ],
+                captioned,
+                depth,
+                out,
+            );
+            if captioned {
+                for track in &a.captions {
+                    media_element(
+                        "captions",
+                        &[
+                            ("src", Some(&track.src)),
+                            ("lang", Some(&track.lang)),
+                            ("label", track.label.as_deref()),
+                        ],
+                        false,
+                        depth + 1,
+                        out,
+                    );
+                }
+                push_indent(out, depth);
+                out.push_str("</video>\n");
+            }
+        }
+        // variables and #IZ DEFINED are gone after resolving
+        ASTNode::VariableDeclaration { .. }
+        | ASTNode::VariableAssignment { .. }
+        | ASTNode::VariableReference { .. }
+        | ASTNode::IfDefined { .. } => {}
+    }
+}
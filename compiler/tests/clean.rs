@@ -0,0 +1,86 @@
+//! `lolcompiler clean` removes what builds wrote and leaves the files that
+//! only share a source's name.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const PAGE: &str = "#HAI 1.2\n#SHHH missing-title\n#OBTW #TLDR\n#KTHXBYE\n";
+
+// a fresh directory with two pages and hand-written data files next to one
+fn project(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lolcompiler-clean-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("data.lol"), PAGE).unwrap();
+    fs::write(dir.join("other.lol"), PAGE).unwrap();
+    fs::write(dir.join("data.xml"), "<data/>\n").unwrap();
+    fs::write(dir.join("data.adoc"), "= Data\n").unwrap();
+    dir
+}
+
+fn lolcompiler(args: &[&str], dir: &Path) {
+    let status = Command::new(env!("CARGO_BIN_EXE_lolcompiler"))
+        .args(args)
+        .arg(dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "lolcompiler {:?} failed", args);
+}
+
+#[test]
+fn clean_after_build_keeps_hand_written_files() {
+    let dir = project("built");
+    lolcompiler(&["--quiet"], &dir);
+    assert!(dir.join("data.html").is_file());
+    assert!(dir.join(".lolcache").is_file());
+
+    lolcompiler(&["clean", "--quiet"], &dir);
+    assert!(!dir.join("data.html").exists());
+    assert!(!dir.join("other.html").exists());
+    assert!(!dir.join(".lolcache").exists());
+    assert!(dir.join("data.xml").is_file());
+    assert!(dir.join("data.adoc").is_file());
+    assert!(dir.join("data.lol").is_file());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn clean_without_cache_only_takes_the_target() {
+    let dir = project("unbuilt");
+    fs::write(dir.join("data.html"), "<p>old</p>\n").unwrap();
+
+    lolcompiler(&["clean", "--quiet"], &dir);
+    assert!(!dir.join("data.html").exists());
+    assert!(dir.join("data.xml").is_file());
+    assert!(dir.join("data.adoc").is_file());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn clean_ignores_manifest_entries_outside_the_project() {
+    let dir = project("tampered");
+    lolcompiler(&["--quiet"], &dir);
+    let outside = std::env::temp_dir().join(format!("lolcompiler-clean-outside-{}", std::process::id()));
+    fs::create_dir_all(&outside).unwrap();
+    fs::write(outside.join("keep.html"), "<p>keep</p>\n").unwrap();
+    fs::write(dir.join("notes.txt"), "keep\n").unwrap();
+
+    // what a manifest that came with a checkout could list
+    let manifest = dir.join(".lolcache");
+    let mut text = fs::read_to_string(&manifest).unwrap();
+    let outside_name = outside.file_name().unwrap().to_string_lossy();
+    text.push_str(&format!("output ../{}/keep.html\n", outside_name));
+    text.push_str(&format!("output {}\n", outside.join("keep.html").display()));
+    text.push_str("output notes.txt\n");
+    text.push_str("output data.lol\n");
+    fs::write(&manifest, text).unwrap();
+
+    lolcompiler(&["clean", "--quiet"], &dir);
+    assert!(outside.join("keep.html").is_file());
+    assert!(dir.join("notes.txt").is_file());
+    assert!(dir.join("data.lol").is_file());
+    assert!(!dir.join("data.html").exists());
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_dir_all(&outside).unwrap();
+}
@@ -0,0 +1,29 @@
+//! The documents in `tests/golden` (and, with `--social-cards` and `--xhtml`,
+//! in `tests/social_cards` and `tests/xhtml`) against the output they are
+//! expected to compile to.
+
+use lolcompiler::golden::GoldenRun;
+use std::path::Path;
+
+#[test]
+fn golden_documents() {
+    let report = GoldenRun::new().check_dir(Path::new("tests/golden")).expect("golden directory is readable");
+    assert!(report.is_ok(), "{}", report);
+}
+
+#[test]
+fn social_card_documents() {
+    let mut run = GoldenRun::new();
+    run.options.social_cards = true;
+    let report = run.check_dir(Path::new("tests/social_cards")).expect("social card directory is readable");
+    assert!(report.is_ok(), "{}", report);
+}
+
+// checked with `xmllint --noout` when blessed: XHTML output has to parse as XML
+#[test]
+fn xhtml_documents() {
+    let mut run = GoldenRun::new();
+    run.options.xhtml = true;
+    let report = run.check_dir(Path::new("tests/xhtml")).expect("xhtml directory is readable");
+    assert!(report.is_ok(), "{}", report);
+}
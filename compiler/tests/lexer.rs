@@ -0,0 +1,16 @@
+//! Which hashtag words the lexer accepts.
+
+use std::process::Command;
+
+// the keywords are hashtag words too, so the first word the lexer rejects in
+// Test4 is `#END`, not `#HEAD`
+#[test]
+fn keywords_are_hashtag_words() {
+    let output = Command::new(env!("CARGO_BIN_EXE_lolcompiler"))
+        .arg("samples/Test4.lol")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr);
+    assert!(stderr.contains("line 5, col 5: Unrecognized hashtag word '#END'"), "{}", stderr);
+}
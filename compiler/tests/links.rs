@@ -0,0 +1,33 @@
+//! LINKZ between pages of a project point at where the other page's output is,
+//! whichever input each page came from.
+
+use std::fs;
+use std::process::Command;
+
+fn page(link: &str) -> String {
+    format!("#HAI\n#SHHH missing-title\n#MAEK PARAGRAF\n#GIMMEH LINKZ {} #MKAY\n#OIC\n#KTHXBYE\n", link)
+}
+
+#[test]
+fn links_between_input_roots() {
+    let dir = std::env::temp_dir().join(format!("lolcompiler-links-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("a")).unwrap();
+    fs::create_dir_all(dir.join("b/deep")).unwrap();
+    fs::write(dir.join("a/x.lol"), page("deep/y")).unwrap();
+    fs::write(dir.join("b/deep/y.lol"), page("x")).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_lolcompiler"))
+        .arg("--quiet")
+        .arg(dir.join("a/x.lol"))
+        .arg(dir.join("b"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let x = fs::read_to_string(dir.join("a/x.html")).unwrap();
+    assert!(x.contains("href=\"../b/deep/y.html\""), "{}", x);
+    let y = fs::read_to_string(dir.join("b/deep/y.html")).unwrap();
+    assert!(y.contains("href=\"../../a/x.html\""), "{}", y);
+    fs::remove_dir_all(&dir).unwrap();
+}
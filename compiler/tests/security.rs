@@ -0,0 +1,23 @@
+//! The documents in `security` compile, with `--paranoid`, to the output they
+//! are expected to, and no target lets their markup through unescaped.
+
+use lolcompiler::golden::GoldenRun;
+use std::path::Path;
+
+fn paranoid_run() -> GoldenRun {
+    let mut run = GoldenRun::new();
+    run.options.paranoid = true;
+    run
+}
+
+#[test]
+fn security_documents() {
+    let report = paranoid_run().check_dir(Path::new("security")).expect("security directory is readable");
+    assert!(report.is_ok(), "{}", report);
+}
+
+#[test]
+fn security_documents_escape_everything() {
+    let escapes = paranoid_run().check_paranoid(Path::new("security")).expect("security directory is readable");
+    assert!(escapes.is_empty(), "{}", escapes.join("\n"));
+}
@@ -0,0 +1,42 @@
+//! Slide decks are one file that loads nothing from elsewhere, unless
+//! `--reveal-cdn` asks for reveal.js.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const DECK: &str = "#HAI\n#MAEK HEAD\n#GIMMEH TITLE Deck #MKAY\n#OIC\n#MAEK PARAGRAF\nHi\n#OIC\n#KTHXBYE\n";
+
+fn build_deck(name: &str, args: &[&str]) -> String {
+    let dir = std::env::temp_dir().join(format!("lolcompiler-slides-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("deck.lol");
+    fs::write(&source, DECK).unwrap();
+    // --json keeps the build from opening the deck in a browser
+    let output = Command::new(env!("CARGO_BIN_EXE_lolcompiler"))
+        .args(["--json", "--target", "slides"])
+        .args(args)
+        .arg(&source)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let html = fs::read_to_string(Path::new(&dir).join("deck.html")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    html
+}
+
+#[test]
+fn deck_is_self_contained() {
+    let html = build_deck("offline", &[]);
+    assert!(!html.contains("http"), "{}", html);
+    assert!(html.contains("<style>"));
+    assert_eq!(html.matches("<section>").count(), 2, "{}", html);
+}
+
+#[test]
+fn reveal_cdn_loads_reveal_js() {
+    let html = build_deck("cdn", &["--reveal-cdn"]);
+    assert!(html.contains("https://cdn.jsdelivr.net/npm/reveal.js@"), "{}", html);
+    assert!(html.contains("Reveal.initialize"));
+}